@@ -0,0 +1,70 @@
+//! Benchmarks of the stages a shot goes through, to catch performance
+//! regressions in the parser and the simulation. Run with
+//! `cargo bench --features bench`.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use graphwars::bench::{Board, compile, parse, simulate};
+use std::hint::black_box;
+
+/// Equations of growing size, to see how each stage scales with them
+const EQUATIONS: &[(&str, &str)] = &[
+    ("line", "x/4"),
+    ("polynomial", "x^3/100-2x^2/25+x/3-1"),
+    ("trig", "3sin(x/2)+2sin(3x+1)/5"),
+    (
+        "nested",
+        "max(sqrt(abs(x)+1)*ln(x^2+2), mod(x,3)-log10(abs(sin(x))+1)/2)",
+    ),
+];
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for &(name, equation) in EQUATIONS {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            equation,
+            |b, equation| b.iter(|| parse(black_box(equation))),
+        );
+    }
+    group.finish();
+}
+
+fn bench_compile(c: &mut Criterion) {
+    let board = Board::generate(42, 4, 2, 2);
+    let mut group = c.benchmark_group("compile");
+    for &(name, equation) in EQUATIONS {
+        let parsed = parse(equation).unwrap();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &parsed,
+            |b, parsed| b.iter(|| compile(black_box(parsed), &board)),
+        );
+    }
+    group.finish();
+}
+
+fn bench_simulate(c: &mut Criterion) {
+    // An empty board, where shots cross all of it, and a crowded one
+    let boards = [
+        ("open", Board::generate(1, 1, 0, 0)),
+        ("crowded", Board::generate(1234, 6, 6, 3)),
+    ];
+    let mut group = c.benchmark_group("simulate");
+    for (board_name, board) in &boards {
+        for &(name, equation) in EQUATIONS {
+            let Some(compiled) = compile(&parse(equation).unwrap(), board)
+            else {
+                continue;
+            };
+            group.bench_with_input(
+                BenchmarkId::new(*board_name, name),
+                &compiled,
+                |b, compiled| b.iter(|| simulate(black_box(compiled), board)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_compile, bench_simulate);
+criterion_main!(benches);
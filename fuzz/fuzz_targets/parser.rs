@@ -0,0 +1,14 @@
+#![no_main]
+#![feature(let_chains)]
+
+use libfuzzer_sys::fuzz_target;
+
+// The parser depends on nothing else in the game, so it is included directly
+// rather than through the game's library, which would pull in Bevy
+#[allow(dead_code)]
+#[path = "../../src/parse.rs"]
+mod parse;
+
+fuzz_target!(|input: &str| {
+    parse::parse_and_eval_arbitrary(input);
+});
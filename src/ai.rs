@@ -0,0 +1,422 @@
+//! Computer-controlled players
+
+use crate::consts::*;
+use crate::models::{Obstacle, PlayPhase};
+use crate::parse::{ParsedFunction, Restriction};
+use crate::sim::{ShotStepper, Target, aim};
+use bevy::math::Vec2;
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+use rand::Rng;
+
+/// Soldiers of every opponent of the current player
+fn enemy_targets(state: &PlayPhase) -> Vec<Target> {
+    (state.opponents())
+        .flat_map(|i| i.soldiers())
+        .map(Target::from)
+        .collect()
+}
+
+/// Pick the slope of the straight shot from the current player's active
+/// soldier that destroys the most enemy soldiers, taking obstacles and the
+/// field into account
+pub fn choose_slope(state: &PlayPhase) -> f32 {
+    let from = state.current_player().current_soldier().graph_location();
+    let targets = enemy_targets(state);
+    // Aim straight at each enemy first, so those win ties, then fan out in
+    // between in case obstacles or the field get in the way
+    let aimed = targets
+        .iter()
+        .filter(|target| target.location.x - from.x > GRAPH_RES)
+        .map(|target| {
+            (target.location.y - from.y) / (target.location.x - from.x)
+        });
+    let fan = (0..AI_FAN_LINES).map(|i| {
+        let t = (i as f32 + 0.5) / AI_FAN_LINES as f32;
+        ((t - 0.5) * std::f32::consts::PI).tan()
+    });
+    let mut best = (0, 0.);
+    for slope in aimed.chain(fan) {
+        let hits = ShotStepper::line(from, slope, targets.clone())
+            .with_obstacles(state.obstacles().to_vec())
+            .with_field(state.field().to_vec())
+            .with_max_length(state.max_shot_length())
+            .trace()
+            .hits
+            .len();
+        if hits > best.0 {
+            best = (hits, slope);
+        }
+    }
+    best.1
+}
+
+/// Search for a shot for the current player's active soldier at a difficulty
+/// level, by trying random changes to the coefficients of the best shot so
+/// far, starting from the best straight line. Shots are scored by the enemy
+/// soldiers they destroy, then by how close they pass to the nearest one
+/// left standing, and have to follow the match's rules. Lower levels try
+/// fewer shots and aim the one they pick less carefully.
+///
+/// The search is taken a batch of shots at a time, so it can be spread over
+/// several frames, and is over early once a shot destroys every enemy
+/// soldier.
+pub struct ShotSearch {
+    level: u8,
+    from: Vec2,
+    /// Number of coefficients of the polynomial shots tried, which is the
+    /// highest degree the match allows
+    degree: usize,
+    best: Vec<f32>,
+    best_score: Option<(usize, f32)>,
+    enemies: usize,
+    batches_left: usize,
+}
+
+impl ShotSearch {
+    /// Start searching from the best straight line
+    pub fn new(state: &PlayPhase, level: u8) -> Self {
+        let from = state.current_player().current_soldier().graph_location();
+        let degree = match state.restriction() {
+            Restriction::LinesOnly => 1,
+            Restriction::MaxDegree(max) => AI_MAX_DEGREE.min(max as usize),
+            _ => AI_MAX_DEGREE,
+        }
+        .max(1);
+        let best = vec![choose_slope(state)];
+        Self {
+            level,
+            from,
+            degree,
+            best_score: score_shot(state, &polynomial_equation(from.x, &best)),
+            best,
+            enemies: (state.opponents()).map(|i| i.soldiers().len()).sum(),
+            batches_left: (AI_CANDIDATES_PER_LEVEL * level as usize)
+                .div_ceil(AI_SEARCH_BATCH),
+        }
+    }
+
+    /// Whether there is nothing left to try, as the level's shots have all
+    /// been tried or one destroys every enemy soldier
+    pub fn is_done(&self) -> bool {
+        self.batches_left == 0
+            || self
+                .best_score
+                .is_some_and(|(hits, _)| hits == self.enemies)
+    }
+
+    /// Try a batch of `AI_SEARCH_BATCH` changes, scored across the compute
+    /// task pool. Returns whether the search is done.
+    pub fn step(&mut self, state: &PlayPhase, rng: &mut impl Rng) -> bool {
+        if self.is_done() {
+            return true;
+        }
+        self.batches_left -= 1;
+        let batch = (0..AI_SEARCH_BATCH)
+            .map(|_| {
+                let mut coefficients = self.best.clone();
+                coefficients.resize(self.degree, 0.);
+                for (power, coefficient) in coefficients.iter_mut().enumerate()
+                {
+                    let scale = AI_PERTURBATION
+                        * AI_PERTURBATION_FALLOFF.powi(power as i32);
+                    *coefficient += rng.gen_range(-scale..=scale);
+                }
+                coefficients
+            })
+            .collect::<Vec<_>>();
+        let pool = ComputeTaskPool::get_or_init(TaskPool::default);
+        let from = self.from;
+        let scores = pool.scope(|scope| {
+            for coefficients in &batch {
+                scope.spawn(async move {
+                    score_shot(
+                        state,
+                        &polynomial_equation(from.x, coefficients),
+                    )
+                });
+            }
+        });
+        for (coefficients, score) in batch.into_iter().zip(scores) {
+            if score > self.best_score {
+                (self.best, self.best_score) = (coefficients, score);
+            }
+        }
+        self.is_done()
+    }
+
+    /// The best shot found so far, aimed with the level's error
+    pub fn finish(mut self, rng: &mut impl Rng) -> String {
+        let error = AI_AIM_ERROR
+            * (MAX_AI_LEVEL.saturating_sub(self.level)) as f32
+            / (MAX_AI_LEVEL - 1) as f32;
+        self.best[0] += rng.gen_range(-error..=error);
+        polynomial_equation(self.from.x, &self.best)
+    }
+}
+
+/// Enemy soldiers the shot destroys and how far it passes from the nearest
+/// one left, negated so that higher is better, or nothing if it can't be
+/// shot under the match's rules
+fn score_shot(state: &PlayPhase, equation: &str) -> Option<(usize, f32)> {
+    let from = state.current_player().current_soldier().graph_location();
+    let parsed = equation.parse::<ParsedFunction>().ok()?;
+    state.check_equation(&parsed).ok()?;
+    let function = aim(parsed, from).ok()?;
+    let targets = enemy_targets(state);
+    let trace = ShotStepper::new(function, from.x, targets.clone())
+        .with_obstacles(state.obstacles().to_vec())
+        .with_field(state.field().to_vec())
+        .with_max_length(state.max_shot_length())
+        .trace();
+    let closest = (targets.iter())
+        .filter(|target| !trace.hits.iter().any(|hit| hit.id == target.id))
+        .flat_map(|target| {
+            trace
+                .points
+                .iter()
+                .map(|point| point.distance(target.location))
+        })
+        .fold(f32::INFINITY, f32::min);
+    Some((trace.hits.len(), -closest))
+}
+
+/// Equation of a straight shot with the given slope
+pub fn line_equation(slope: f32) -> String {
+    format!("{slope:.3}x")
+}
+
+/// Outcome of searching for a single shot that hits every target
+pub struct Search {
+    /// Equation of the first shot found to hit every target, if any
+    pub equation: Option<String>,
+    /// Number of candidate shots traced by the search
+    pub effort: usize,
+}
+
+/// Search for one shot from `from` that hits all of the `targets`, trying
+/// polynomials of growing degree: lines through each target, then parabolas
+/// through each pair of targets, and so on up to `AI_MAX_DEGREE`. Every
+/// candidate is parsed and traced like a typed-in shot, so it has to get
+/// past the `obstacles` too.
+pub fn search_single_shot(
+    from: Vec2,
+    targets: &[Target],
+    obstacles: &[Obstacle],
+) -> Search {
+    let mut effort = 0;
+    for degree in 1..=AI_MAX_DEGREE.min(targets.len()) {
+        for subset in 1..1u32 << targets.len() {
+            if subset.count_ones() as usize != degree {
+                continue;
+            }
+            let points = (targets.iter().enumerate())
+                .filter(|(i, _)| subset & (1 << i) != 0)
+                .map(|(_, target)| target.location - from)
+                .collect::<Vec<_>>();
+            let Some(coefficients) = fit_polynomial(&points) else {
+                continue;
+            };
+            let equation = polynomial_equation(from.x, &coefficients);
+            effort += 1;
+            let Ok(parsed) = equation.parse::<ParsedFunction>() else {
+                continue;
+            };
+            let Ok(function) = aim(parsed, from) else {
+                continue;
+            };
+            let trace = ShotStepper::new(function, from.x, targets.to_vec())
+                .with_obstacles(obstacles.to_vec())
+                .trace();
+            if trace.hits.len() == targets.len() {
+                return Search {
+                    equation: Some(equation),
+                    effort,
+                };
+            }
+        }
+    }
+    Search {
+        equation: None,
+        effort,
+    }
+}
+
+/// Coefficients `c` of the polynomial `c[0]x + c[1]x² + ...` through each of
+/// the `points`, of degree `points.len()`. There is none if two points share
+/// an x coordinate, or lie behind the shooter at the origin.
+fn fit_polynomial(points: &[Vec2]) -> Option<Vec<f32>> {
+    if points.iter().any(|point| point.x <= GRAPH_RES) {
+        return None;
+    }
+    // Gaussian elimination on the rows [x, x², ..., y]
+    let degree = points.len();
+    let mut rows = (points.iter())
+        .map(|point| {
+            let mut row = (1..=degree as i32)
+                .map(|power| point.x.powi(power))
+                .collect::<Vec<_>>();
+            row.push(point.y);
+            row
+        })
+        .collect::<Vec<_>>();
+    for column in 0..degree {
+        let pivot = (column..degree).max_by(|&a, &b| {
+            rows[a][column].abs().total_cmp(&rows[b][column].abs())
+        })?;
+        if rows[pivot][column].abs() < f32::EPSILON {
+            return None;
+        }
+        rows.swap(column, pivot);
+        for row in 0..degree {
+            if row != column {
+                let factor = rows[row][column] / rows[column][column];
+                for i in column..=degree {
+                    rows[row][i] -= factor * rows[column][i];
+                }
+            }
+        }
+    }
+    Some((0..degree).map(|i| rows[i][degree] / rows[i][i]).collect())
+}
+
+/// Equation of the polynomial `c[0](x - x0) + c[1](x - x0)² + ...`
+fn polynomial_equation(x0: f32, coefficients: &[f32]) -> String {
+    let base = if x0 < 0. {
+        format!("(x + {:.3})", -x0)
+    } else {
+        format!("(x - {x0:.3})")
+    };
+    let mut equation = String::new();
+    for (power, coefficient) in (1..).zip(coefficients) {
+        let sign = if *coefficient < 0. { "-" } else { "+" };
+        if !equation.is_empty() {
+            equation.push_str(&format!(" {sign} "));
+        } else if *coefficient < 0. {
+            equation.push('-');
+        }
+        equation.push_str(&format!("{:.5}{base}", coefficient.abs()));
+        if power > 1 {
+            equation.push_str(&format!("^{power}"));
+        }
+    }
+    equation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ParsedFunction;
+    use crate::sim::aim;
+    use bevy::math::Vec2;
+
+    #[test]
+    fn test_line_equation() {
+        for slope in [0., 1.5, -0.25] {
+            let parsed =
+                line_equation(slope).parse::<ParsedFunction>().unwrap();
+            let from = Vec2::new(-3., 2.);
+            let trace = ShotStepper::new(
+                aim(parsed, from).unwrap(),
+                from.x,
+                Vec::new(),
+            )
+            .trace();
+            let end = trace.points.last().unwrap();
+            let expected = from.y + slope * (end.x - from.x);
+            assert!((end.y - expected).abs() < 0.01);
+        }
+    }
+
+    fn target(number: u8, x: f32, y: f32) -> Target {
+        Target {
+            id: crate::models::SoldierId {
+                player: crate::models::PlayerSelect::Player2,
+                number,
+            },
+            location: Vec2::new(x, y),
+        }
+    }
+
+    #[test]
+    fn test_search_single_shot() {
+        let from = Vec2::new(-8., -2.);
+        // All on one line, found straight away
+        let line = [target(0, -4., 0.), target(1, 0., 2.), target(2, 4., 4.)];
+        let search = search_single_shot(from, &line, &[]);
+        assert!(search.equation.is_some());
+        assert_eq!(search.effort, 1);
+        // Needs a cubic, so every line and parabola is tried first
+        let cubic = [target(0, -5., 3.), target(1, 0., -4.), target(2, 5., 5.)];
+        let search = search_single_shot(from, &cubic, &[]);
+        assert!(search.equation.is_some());
+        assert_eq!(search.effort, 7);
+        // Nothing can hit a target behind the shooter
+        let behind = [target(0, -9., 0.)];
+        assert!(search_single_shot(from, &behind, &[]).equation.is_none());
+    }
+
+    #[test]
+    fn test_shot_search() {
+        use crate::models::GameState;
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let mut state = GameState::default();
+        let setup = state.setup_state_mut().unwrap();
+        setup.computer = Some(MAX_AI_LEVEL);
+        setup.players[1].soldier_num = std::num::NonZeroU8::new(4).unwrap();
+        state.start_playing().unwrap();
+        let state = state.playing_state().unwrap();
+        assert_eq!(state.players()[1].ai_level, Some(MAX_AI_LEVEL));
+        assert!(state.players()[1].ai);
+        assert!(!state.current_player().ai);
+        // The search starts from the best line and only keeps better shots,
+        // and aims without error at the highest level
+        let from = state.current_player().current_soldier().graph_location();
+        let line = polynomial_equation(from.x, &[choose_slope(state)]);
+        let line_score = score_shot(state, &line).unwrap();
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut search = ShotSearch::new(state, MAX_AI_LEVEL);
+        while !search.step(state, &mut rng) {}
+        assert!(search.is_done());
+        let equation = search.finish(&mut rng);
+        let score = score_shot(state, &equation).unwrap();
+        assert!(score >= line_score);
+    }
+
+    #[test]
+    fn test_free_for_all() {
+        use crate::models::{GameState, PlayerConfig, PlayerSelect};
+
+        let mut state = GameState::default();
+        let setup = state.setup_state_mut().unwrap();
+        setup
+            .players
+            .extend([PlayerConfig::new(3), PlayerConfig::new(4)]);
+        state.start_playing().unwrap();
+        let state = state.playing_state_mut().unwrap();
+        assert_eq!(state.players().len(), 4);
+        // Every other player's soldiers are targets, and players 3 and 4
+        // share the halves of players 1 and 2
+        assert_eq!(enemy_targets(state).len(), 3);
+        let player_3 = state.player(PlayerSelect::Player3).soldiers()[0].id();
+        assert!(state.soldiers().any(|i| i.id() == player_3));
+        assert!(
+            state.player(PlayerSelect::Player3).soldiers()[0]
+                .graph_location()
+                .x
+                <= 0.
+        );
+        // Eliminated players are skipped, and the last one standing wins
+        let player_2 = state.player(PlayerSelect::Player2).soldiers()[0].id();
+        assert!(state.damage_soldier(player_2));
+        assert_eq!(state.next_player(), PlayerSelect::Player3);
+        assert_eq!(state.get_winner(), None);
+        assert!(state.damage_soldier(player_3));
+        state.next_turn();
+        assert_eq!(state.turn(), PlayerSelect::Player4);
+        let player_1 = state.player(PlayerSelect::Player1).soldiers()[0].id();
+        assert!(state.damage_soldier(player_1));
+        assert_eq!(state.get_winner(), Some(PlayerSelect::Player4));
+    }
+}
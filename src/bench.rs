@@ -0,0 +1,71 @@
+//! Entry points for benchmarking the engine, only compiled with the `bench`
+//! feature. The benchmarks in `benches/` time each stage a shot goes
+//! through: parsing the typed equation (tokenizer, shunting yard and tree),
+//! compiling it into a function aimed from the shooter, and stepping the shot
+//! across the board. Run them with `cargo bench --features bench`.
+
+use crate::models::*;
+use crate::parse::ParsedFunction;
+use crate::sim::{FieldModifier, ShotStepper, Target, aim};
+use bevy::math::Vec2;
+
+/// An equation parsed into its expression tree
+pub struct Parsed(ParsedFunction);
+
+/// A parsed equation turned into a function that starts at the shooter
+pub struct Compiled(Function);
+
+/// A board generated from a seed (see `SeededBoard`), shot at by the first
+/// soldier of player 1
+pub struct Board {
+    shooter: Vec2,
+    targets: Vec<Target>,
+    obstacles: Vec<Obstacle>,
+    field: Vec<FieldModifier>,
+}
+
+impl Board {
+    pub fn generate(
+        seed: u64,
+        soldiers: u8,
+        obstacles: u8,
+        gravity_wells: u8,
+    ) -> Board {
+        let SeededBoard {
+            soldiers: [player_1, player_2],
+            obstacles,
+            field,
+        } = SeededBoard::generate(seed, soldiers, obstacles, gravity_wells);
+        Board {
+            shooter: player_1[0].graph_location(),
+            targets: player_2.iter().map(Target::from).collect(),
+            obstacles,
+            field,
+        }
+    }
+}
+
+/// Parse `equation`, or `None` if it doesn't parse
+pub fn parse(equation: &str) -> Option<Parsed> {
+    equation.parse().ok().map(Parsed)
+}
+
+/// Aim the equation from the board's shooter, as is done before each shot,
+/// or `None` if it can't be shot from there
+pub fn compile(parsed: &Parsed, board: &Board) -> Option<Compiled> {
+    aim(parsed.0.clone(), board.shooter).ok().map(Compiled)
+}
+
+/// Step the shot across the board until it ends, returning the number of
+/// points of its path
+pub fn simulate(compiled: &Compiled, board: &Board) -> usize {
+    let trace = ShotStepper::new(
+        compiled.0.clone(),
+        board.shooter.x,
+        board.targets.clone(),
+    )
+    .with_obstacles(board.obstacles.clone())
+    .with_field(board.field.clone())
+    .trace();
+    trace.points.len()
+}
@@ -0,0 +1,212 @@
+//! Relay server for playing over the internet without port forwarding. Both
+//! players connect out to the relay and join the same room, after which the
+//! relay forwards everything one sends to the other, without looking at it.
+//!
+//! A client joins by sending `JOIN <room code>` on a line of its own. The
+//! relay answers `WAITING` while the room is empty, and `PAIRED` (to both)
+//! once the second player arrives. A player who leaves while waiting is
+//! taken out of the room, so nobody is paired with them.
+//!
+//! Run with `cargo run --bin relay [addr]`.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Address the relay listens on unless another is given
+const DEFAULT_ADDR: &str = "0.0.0.0:7077";
+
+/// Longest room code accepted, in bytes
+const MAX_ROOM_CODE: usize = 32;
+
+/// Players waiting for somebody to join their room, by room code
+type Rooms = Arc<Mutex<HashMap<String, Waiting>>>;
+
+/// A player waiting in a room
+struct Waiting {
+    /// Number of the player's connection, to tell them apart from another
+    /// player waiting in the same room later
+    id: usize,
+    stream: TcpStream,
+}
+
+fn main() -> std::io::Result<()> {
+    let addr = std::env::args().nth(1);
+    let listener = TcpListener::bind(addr.as_deref().unwrap_or(DEFAULT_ADDR))?;
+    println!("Relay listening on {}", listener.local_addr()?);
+    serve(listener, Rooms::default());
+    Ok(())
+}
+
+/// Accept players forever, each handled on its own thread
+fn serve(listener: TcpListener, rooms: Rooms) {
+    for (id, stream) in listener.incoming().enumerate() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let rooms = rooms.clone();
+        thread::spawn(move || {
+            if let Err(e) = join(stream, id, &rooms) {
+                eprintln!("Player dropped before being paired: {e}");
+            }
+        });
+    }
+}
+
+/// Read the room a player asks for, then either wait in it or pair them with
+/// the player already waiting there. `id` is the number of the connection.
+fn join(
+    mut stream: TcpStream,
+    id: usize,
+    rooms: &Rooms,
+) -> std::io::Result<()> {
+    let line = read_join_line(&mut stream)?;
+    let Some(code) = line
+        .trim_end()
+        .strip_prefix("JOIN ")
+        .filter(|code| !code.is_empty() && code.len() <= MAX_ROOM_CODE)
+    else {
+        writeln!(stream, "ERROR expected JOIN <room code>")?;
+        return stream.shutdown(Shutdown::Both);
+    };
+    let waiting = {
+        let mut rooms = rooms.lock().unwrap_or_else(|e| e.into_inner());
+        // In case the player waiting left without it being noticed yet, the
+        // newcomer waits in their place if they can't be told they were paired
+        let other = (rooms.remove(code))
+            .map(|other| other.stream)
+            .filter(|mut other| writeln!(other, "PAIRED").is_ok());
+        if other.is_none() {
+            let stream = stream.try_clone()?;
+            rooms.insert(code.to_string(), Waiting { id, stream });
+        }
+        other
+    };
+    let Some(other) = waiting else {
+        writeln!(stream, "WAITING")?;
+        watch(&stream, code, id, rooms);
+        return Ok(());
+    };
+    writeln!(stream, "PAIRED")?;
+    forward(stream, other);
+    Ok(())
+}
+
+/// Wait until a player waiting in the room `code` either sends something or
+/// leaves, taking them out of the room if they left. Once paired, what they
+/// send is forwarded, so it is only peeked at here.
+fn watch(stream: &TcpStream, code: &str, id: usize, rooms: &Rooms) {
+    if matches!(stream.peek(&mut [0]), Ok(n) if n > 0) {
+        return;
+    }
+    let mut rooms = rooms.lock().unwrap_or_else(|e| e.into_inner());
+    if rooms.get(code).is_some_and(|i| i.id == id) {
+        rooms.remove(code);
+    }
+}
+
+/// Read the first line a player sends, a byte at a time so nothing they send
+/// after it is lost before it can be forwarded
+fn read_join_line(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    // Room for "JOIN ", the code and a line ending
+    while line.len() < MAX_ROOM_CODE + 8 {
+        if stream.read(&mut byte)? == 0 || byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Copy everything each player sends to the other, until either of them
+/// disconnects
+fn forward(a: TcpStream, b: TcpStream) {
+    let pipe = |mut from: TcpStream, mut to: TcpStream| {
+        thread::spawn(move || {
+            let _ = std::io::copy(&mut from, &mut to);
+            let _ = from.shutdown(Shutdown::Both);
+            let _ = to.shutdown(Shutdown::Both);
+        })
+    };
+    match (a.try_clone(), b.try_clone()) {
+        (Ok(a_reader), Ok(b_reader)) => {
+            pipe(a_reader, b);
+            pipe(b_reader, a);
+        }
+        _ => {
+            let _ = a.shutdown(Shutdown::Both);
+            let _ = b.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    fn connect(addr: std::net::SocketAddr, room: &str) -> BufReader<TcpStream> {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        writeln!(stream, "JOIN {room}").unwrap();
+        BufReader::new(stream)
+    }
+
+    fn read_line(reader: &mut BufReader<TcpStream>) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line.trim_end().to_string()
+    }
+
+    #[test]
+    fn test_relay() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, Rooms::default()));
+
+        let mut host = connect(addr, "abc");
+        assert_eq!(read_line(&mut host), "WAITING");
+        let mut guest = connect(addr, "abc");
+        assert_eq!(read_line(&mut host), "PAIRED");
+        assert_eq!(read_line(&mut guest), "PAIRED");
+
+        writeln!(host.get_mut(), "2x+1").unwrap();
+        assert_eq!(read_line(&mut guest), "2x+1");
+        writeln!(guest.get_mut(), "sin(x)").unwrap();
+        assert_eq!(read_line(&mut host), "sin(x)");
+
+        // Once one side leaves, the other is disconnected too
+        drop(guest);
+        let mut rest = Vec::new();
+        host.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+
+        let mut stranger = connect(addr, "");
+        assert!(read_line(&mut stranger).starts_with("ERROR"));
+    }
+
+    #[test]
+    fn test_left_while_waiting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let rooms = Rooms::default();
+        let served = rooms.clone();
+        thread::spawn(move || serve(listener, served));
+
+        let mut host = connect(addr, "abc");
+        assert_eq!(read_line(&mut host), "WAITING");
+        drop(host);
+        // The room is emptied, rather than pairing the next player with
+        // nobody
+        let empty = (0..100).any(|_| {
+            thread::sleep(std::time::Duration::from_millis(10));
+            rooms.lock().unwrap().is_empty()
+        });
+        assert!(empty);
+        let mut guest = connect(addr, "abc");
+        assert_eq!(read_line(&mut guest), "WAITING");
+    }
+}
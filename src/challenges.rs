@@ -0,0 +1,258 @@
+//! Weekly challenges: a playlist of puzzles picked deterministically for
+//! each ISO week, so every player gets the same ones
+
+use crate::consts::*;
+use crate::models::{GameState, PlayerSelect};
+use crate::puzzle::{Difficulty, Puzzle, generate};
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// A week as numbered by ISO 8601: weeks start on Monday, and week 1 is the
+/// one with the year's first Thursday
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct IsoWeek {
+    pub year: i64,
+    pub week: u32,
+}
+
+impl IsoWeek {
+    /// The week containing the given day, counted from 1970-01-01
+    pub fn containing(days: i64) -> Self {
+        let thursday = days - weekday(days) + 3;
+        let year = year_of(thursday);
+        let week = (thursday - jan_1(year)) / 7 + 1;
+        IsoWeek {
+            year,
+            week: week as u32,
+        }
+    }
+
+    pub fn current() -> Self {
+        let days = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() / 86400);
+        Self::containing(days as i64)
+    }
+
+    /// Day the week starts on, counted from 1970-01-01
+    fn monday(&self) -> i64 {
+        let jan_4 = jan_1(self.year) + 3;
+        jan_4 - weekday(jan_4) + 7 * (i64::from(self.week) - 1)
+    }
+
+    pub fn previous(&self) -> Self {
+        Self::containing(self.monday() - 7)
+    }
+
+    fn seed(&self) -> u64 {
+        ((self.year as u64) << 8) | u64::from(self.week)
+    }
+}
+
+impl std::fmt::Display for IsoWeek {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-W{:02}", self.year, self.week)
+    }
+}
+
+/// Day of the week of a day counted from 1970-01-01, from 0 for Monday. That
+/// day was a Thursday.
+fn weekday(days: i64) -> i64 {
+    (days + 3).rem_euclid(7)
+}
+
+/// January 1st of `year`, counted in days from 1970-01-01
+fn jan_1(year: i64) -> i64 {
+    let y = year - 1;
+    365 * y + y.div_euclid(4) - y.div_euclid(100) + y.div_euclid(400) - 719162
+}
+
+/// Year of a day counted from 1970-01-01
+fn year_of(days: i64) -> i64 {
+    let mut year = 1970 + days.div_euclid(366);
+    while jan_1(year + 1) <= days {
+        year += 1;
+    }
+    year
+}
+
+/// One of the puzzles of a week's challenge
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeId {
+    pub week: IsoWeek,
+    /// Position of the puzzle in the week's playlist
+    pub index: usize,
+}
+
+/// The week's playlist: a puzzle of each difficulty, the same for everybody
+pub fn weekly_puzzles(week: IsoWeek) -> Vec<Puzzle> {
+    let mut rng = StdRng::seed_from_u64(week.seed());
+    (Difficulty::ALL.into_iter().enumerate())
+        .map(|(index, difficulty)| Puzzle {
+            challenge: Some(ChallengeId { week, index }),
+            ..generate(&mut rng, difficulty)
+        })
+        .collect()
+}
+
+/// Challenge puzzles each player has solved, by player name, which persist
+/// between launches
+#[derive(
+    Resource, Serialize, Deserialize, Clone, Debug, PartialEq, Default,
+)]
+#[serde(default)]
+pub struct Challenges {
+    pub players: BTreeMap<String, BTreeMap<IsoWeek, BTreeSet<usize>>>,
+}
+
+impl Challenges {
+    fn path() -> PathBuf {
+        crate::util::data_dir().join("challenges.ron")
+    }
+
+    /// Read the challenges file, starting without any solved if it is
+    /// missing or can't be parsed
+    pub fn load() -> Challenges {
+        let Ok(contents) = std::fs::read_to_string(Self::path()) else {
+            return Challenges::default();
+        };
+        ron::from_str(&contents).unwrap_or_else(|e| {
+            log::warn!("Failed to parse challenges, starting over: {e}");
+            Challenges::default()
+        })
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents =
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+                .map_err(std::io::Error::other)?;
+        std::fs::write(path, contents)
+    }
+
+    /// Mark a challenge as solved by `player`. Returns whether it is the
+    /// first time they solved it.
+    pub fn complete(&mut self, player: &str, id: ChallengeId) -> bool {
+        let weeks = self.players.entry(player.to_string()).or_default();
+        weeks.entry(id.week).or_default().insert(id.index)
+    }
+
+    /// Puzzles of the week's challenge solved by `player`
+    pub fn solved(&self, player: &str, week: IsoWeek) -> usize {
+        (self.players.get(player))
+            .and_then(|weeks| weeks.get(&week))
+            .map_or(0, BTreeSet::len)
+    }
+
+    pub fn is_solved(&self, player: &str, id: ChallengeId) -> bool {
+        (self.players.get(player))
+            .and_then(|weeks| weeks.get(&id.week))
+            .is_some_and(|solved| solved.contains(&id.index))
+    }
+}
+
+/// Mark challenge puzzles as solved when their match is won
+pub fn record_challenges(
+    state: Res<GameState>,
+    mut challenges: ResMut<Challenges>,
+) {
+    let Some(finished) = state.finished_state() else {
+        return;
+    };
+    let Some(id) = finished.puzzle.as_ref().and_then(|i| i.challenge) else {
+        return;
+    };
+    if finished.winner == PlayerSelect::Player1 {
+        let name = &finished.winner_name;
+        // Only mark the challenges as changed (and save them) the first time
+        if challenges.bypass_change_detection().complete(name, id) {
+            challenges.set_changed();
+        }
+    }
+}
+
+/// Write the solved challenges to disk whenever they change
+pub fn save_challenges(challenges: Res<Challenges>) {
+    if let Err(e) = challenges.save() {
+        log::warn!("Failed to save challenges: {e}");
+    }
+}
+
+/// Badge showing how many of a week's puzzles were solved
+pub fn badge(solved: usize) -> String {
+    let total = Difficulty::ALL.len();
+    let solved = solved.min(total);
+    "★".repeat(solved) + &"☆".repeat(total - solved)
+}
+
+/// The current week and the ones before it shown in the calendar, newest
+/// first
+pub fn calendar() -> Vec<IsoWeek> {
+    std::iter::successors(Some(IsoWeek::current()), |week| {
+        Some(week.previous())
+    })
+    .take(CHALLENGE_CALENDAR_WEEKS)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso_week() {
+        // 1970-01-01, a Thursday
+        assert_eq!(IsoWeek::containing(0), IsoWeek {
+            year: 1970,
+            week: 1
+        });
+        // 2021-01-03 is a Sunday still in the last week of 2020
+        assert_eq!(IsoWeek::containing(18630), IsoWeek {
+            year: 2020,
+            week: 53
+        });
+        // 2024-12-30 is a Monday already in the first week of 2025
+        let week = IsoWeek::containing(20087);
+        assert_eq!(week, IsoWeek {
+            year: 2025,
+            week: 1
+        });
+        assert_eq!(week.previous(), IsoWeek {
+            year: 2024,
+            week: 52
+        });
+        assert_eq!(week.to_string(), "2025-W01");
+    }
+
+    #[test]
+    fn test_weekly_puzzles() {
+        let week = IsoWeek {
+            year: 2026,
+            week: 42,
+        };
+        let puzzles = weekly_puzzles(week);
+        let again = weekly_puzzles(week);
+        assert_eq!(puzzles.len(), Difficulty::ALL.len());
+        for (puzzle, same) in puzzles.iter().zip(&again) {
+            assert_eq!(puzzle.code(), same.code());
+        }
+        let mut challenges = Challenges::default();
+        let id = puzzles[1].challenge.unwrap();
+        assert!(challenges.complete("a", id));
+        assert!(!challenges.complete("a", id));
+        assert!(challenges.is_solved("a", id));
+        assert_eq!(challenges.solved("a", week), 1);
+        assert_eq!(challenges.solved("b", week), 0);
+        let saved = ron::to_string(&challenges).unwrap();
+        assert_eq!(ron::from_str::<Challenges>(&saved).unwrap(), challenges);
+    }
+}
@@ -4,33 +4,668 @@ use std::time::Duration;
 /// Radius of soldiers in pixels
 pub const SOLDIER_RADIUS: f32 = 12.;
 
+/// Size of soldier skin sprites in pixels. This is only cosmetic, hits are
+/// still checked against `SOLDIER_RADIUS`.
+pub const SOLDIER_SPRITE_SIZE: f32 = SOLDIER_RADIUS * 2.5;
+
+/// Most players a free-for-all match can have
+pub const MAX_PLAYERS: usize = 4;
+
+/// Colors of each player's soldiers, in turn order
+pub const PLAYER_COLORS: [Color; MAX_PLAYERS] = [
+    Color::srgb(0., 0., 1.),
+    Color::srgb(1., 0., 0.),
+    Color::srgb(0., 0.6, 0.),
+    Color::srgb(1., 0.55, 0.),
+];
+
 /// Color with which to outline the active soldier
 pub const ACTIVE_SOLDIER_OUTLINE_COLOR: Color = Color::srgb(0., 1., 0.);
 
+/// Thickness of the outline of the active soldier in pixels
+pub const ACTIVE_SOLDIER_OUTLINE_WIDTH: f32 = 2.;
+
 /// Steps in x to take when graphing
 pub const GRAPH_RES: f32 = 0.01;
 
+/// Functions whose samples are kept, so previewing and then shooting the
+/// same equation evaluates it once
+pub const SAMPLE_CACHE_TABLES: usize = 4;
+
 /// Speed to graph at (units/sec)
 pub const GRAPHING_SPEED: f32 = 20.;
 
 /// The function to use before the player customises it
 pub const DEFAULT_FUNCTION: &str = "x";
 
+/// Most candidate equations a soldier can keep at once
+pub const EQUATION_SLOTS: usize = 3;
+
 /// The slope to require over a step to consider a graph discontinuous
 pub const DISCONTINUITY_THRESHOLD: f32 = 15.;
 
+/// How far ahead (in graph units along x) to look for a hit when slowing
+/// down the shot. At the slowed speed this is about 0.3s before the hit.
+pub const SLOWMO_LOOKAHEAD: f32 = 1.5;
+
+/// Minimum time (in real seconds) to keep the slow motion and zoom going once
+/// an upcoming hit is spotted
+pub const SLOWMO_HOLD: f32 = 0.8;
+
+/// Factor applied to the graphing speed right before a hit
+pub const SLOWMO_FACTOR: f32 = 0.25;
+
+/// How much the camera zooms in right before a hit
+pub const SLOWMO_ZOOM: f32 = 1.15;
+
+/// How much each line scrolled zooms the free camera in or out
+pub const FREE_CAMERA_ZOOM_STEP: f32 = 1.1;
+
+/// Most the free camera can zoom in
+pub const FREE_CAMERA_MAX_ZOOM: f32 = 4.;
+
+/// Pixels of smooth scrolling that count as one line scrolled
+pub const FREE_CAMERA_PIXELS_PER_LINE: f32 = 40.;
+
+/// Fraction of the way the camera moves towards a hit it zooms in on
+pub const SLOWMO_ZOOM_PULL: f32 = 0.3;
+
+/// How quickly the camera eases towards where it should be (1/sec)
+pub const CAMERA_EASE_SPEED: f32 = 6.;
+
+/// Magnification of the picture-in-picture inset
+pub const PIP_ZOOM: f32 = 3.;
+
+/// Size of the picture-in-picture inset in logical pixels
+pub const PIP_SIZE: f32 = 180.;
+
+/// Distance of the picture-in-picture inset from the window edges
+pub const PIP_MARGIN: f32 = 10.;
+
+/// Smallest size (in logical pixels) the picture-in-picture inset may shrink
+/// to before it is hidden
+pub const PIP_MIN_SIZE: f32 = 48.;
+
+/// Size (in pixels) of the area around the board that presentation mode fits
+/// into the window
+pub const PRESENTATION_VIEW_SIZE: f32 = 480.;
+
+/// Text size of the player nameplates in presentation mode
+pub const NAMEPLATE_TEXT_SIZE: f32 = 40.;
+
+/// Chroma-key green behind the board in overlay mode
+pub const OVERLAY_BACKGROUND_COLOR: Color =
+    Color::srgb(0., 177. / 255., 64. / 255.);
+
+/// Width of the shots and other lines in overlay mode, in pixels
+pub const OVERLAY_LINE_WIDTH: f32 = 6.;
+
+/// Text size of the player nameplates in overlay mode
+pub const OVERLAY_NAMEPLATE_TEXT_SIZE: f32 = 56.;
+
+/// Seconds the menu has to be left alone before a demo match starts
+pub const DEMO_IDLE_SECONDS: f32 = 60.;
+
+/// How much faster than normal demo matches are played
+pub const DEMO_SPEED: f32 = 2.;
+
+/// Number of soldiers of each player in demo matches
+pub const DEMO_SOLDIERS: u8 = 3;
+
+/// Number of obstacles in demo matches
+pub const DEMO_OBSTACLES: u8 = 2;
+
+/// Text size of the banner shown during demo matches
+pub const DEMO_BANNER_TEXT_SIZE: f32 = 28.;
+
+/// Seconds a finished match stays on screen in kiosk mode before going back
+/// to the setup screen
+pub const KIOSK_RESTART_SECONDS: f32 = 20.;
+
+/// Initial size (in logical pixels) of the separate equation input window
+pub const INPUT_WINDOW_SIZE: (f32, f32) = (480., 160.);
+
+/// Render layer of the camera of the input window, which nothing else is on
+pub const INPUT_WINDOW_LAYER: usize = 1;
+
+/// Lowest frame cap that can be set, so a bad settings file can't make the
+/// game unusable
+pub const MIN_FRAME_CAP: u32 = 15;
+
+/// How long the window has to stay put after being moved or resized before
+/// its placement is saved, so dragging it doesn't write the settings on every
+/// frame
+pub const WINDOW_PLACEMENT_SAVE_DELAY: Duration = Duration::from_millis(500);
+
+/// Frame cap while the battery saver is saving power
+pub const BATTERY_SAVER_FRAME_CAP: u32 = 20;
+
+/// How long nothing has to animate before the battery saver kicks in, so it
+/// doesn't flicker on and off between animations
+pub const BATTERY_SAVER_DELAY: Duration = Duration::from_secs(1);
+
+/// Window sizes offered in the settings, in logical pixels
+pub const RESOLUTIONS: [(u32, u32); 5] = [
+    (1024, 768),
+    (1280, 720),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+];
+
+/// Remaining seconds in a turn at which a time warning is announced, and
+/// below which the turn timer pulses
+pub const TURN_WARNING_SECONDS: u64 = 10;
+
+/// Remaining seconds in a turn at which a beep is played, if turned on
+pub const TURN_BEEP_SECONDS: [f32; 5] = [10., 5., 3., 2., 1.];
+
+/// Pitch of the low-time beeps, in hertz
+pub const TURN_BEEP_PITCH: f32 = 880.;
+
+/// Length of the low-time beeps
+pub const TURN_BEEP_LENGTH: Duration = Duration::from_millis(120);
+
+/// Pitch of the chime reminding players that their turn has started, in
+/// hertz
+pub const TURN_CHIME_PITCH: f32 = 660.;
+
+/// Length of the turn reminder chime
+pub const TURN_CHIME_LENGTH: Duration = Duration::from_millis(400);
+
+/// How long an announcement banner stays up in seconds
+pub const ANNOUNCEMENT_DURATION: f32 = 3.;
+
+/// Longest a shot is animated for. Shots that would take longer, such as
+/// nearly flat ones crawling across the board, are finished at once so they
+/// can't stall the match.
+pub const MAX_GRAPHING_TIME: Duration = Duration::from_secs(15);
+
 /// How long to wait after graphing to start the next turn
 pub const AFTER_GRAPH_PAUSE: Duration = Duration::from_secs(1);
 
-/// Size of explosion sprite in pixels
+/// Color of the graph being shot
+pub const TRAIL_COLOR: Color = Color::srgb(1., 0., 0.);
+
+/// Length (in pixels, along the graph) over which the trail fades out
+pub const TRAIL_FADE_LENGTH: f32 = 150.;
+
+/// Alpha of the oldest part of the trail, so the whole shot stays visible
+pub const TRAIL_MIN_ALPHA: f32 = 0.25;
+
+/// Radius of the glowing head of the graph being shot in pixels
+pub const COMET_HEAD_RADIUS: f32 = 6.;
+
+/// Number of rings used to draw the glow around the head of the shot
+pub const COMET_GLOW_RINGS: u32 = 4;
+
+/// Angular speed of the pulsing of the head of the shot (radians/sec)
+pub const COMET_PULSE_SPEED: f32 = 12.;
+
+/// Color of the graph being shot in high-contrast mode
+pub const HIGH_CONTRAST_TRAIL_COLOR: Color = Color::BLACK;
+
+/// Length of the dashes of player 2's graphs in high-contrast mode, in pixels
+pub const DASH_LENGTH: f32 = 8.;
+
+/// Length of the gaps between dashes in pixels
+pub const DASH_GAP: f32 = 6.;
+
+/// Distance between the stripes on player 2's soldiers in high-contrast mode,
+/// in pixels
+pub const STRIPE_SPACING: f32 = 5.;
+
+/// Color of the stripes on player 2's soldiers
+pub const STRIPE_COLOR: Color = Color::WHITE;
+
+/// How long the equation has to stay unchanged before the practice preview
+/// is recomputed, in seconds
+pub const PREVIEW_DEBOUNCE: f32 = 0.15;
+
+/// How much of the shot the aim preview shows, along its path, in graph
+/// units
+pub const AIM_PREVIEW_LENGTH: f32 = 3.;
+
+/// Lengths of the dashes of the aim preview and of the gaps between them,
+/// in pixels
+pub const AIM_PREVIEW_DASH: f32 = 8.;
+pub const AIM_PREVIEW_GAP: f32 = 6.;
+
+/// Most turns that can be looked back through during play
+pub const HISTORY_TURNS: usize = 10;
+
+/// Furthest the simplified trail of a turn looked back at strays from the
+/// shot, in pixels
+pub const HISTORY_TRAIL_TOLERANCE: f32 = 0.5;
+
+/// Color of the soldiers of a turn being looked back at
+pub const HISTORY_SOLDIER_COLOR: Color = Color::srgba(0., 0., 0., 0.5);
+
+/// Opacity of the trail of a turn being looked back at
+pub const HISTORY_TRAIL_ALPHA: f32 = 0.5;
+
+/// Color of the crosses where a turn being looked back at destroyed soldiers
+pub const HISTORY_KILL_COLOR: Color = Color::srgb(0.8, 0., 0.);
+
+/// Size of the match summary image, in pixels
+pub const SUMMARY_WIDTH: u32 = 640;
+pub const SUMMARY_HEIGHT: u32 = 840;
+
+/// Space around the board and text of the summary image, in pixels
+pub const SUMMARY_MARGIN: f32 = 20.;
+
+/// Distance from the top of the summary image to its board, in pixels
+pub const SUMMARY_BOARD_TOP: f32 = 100.;
+
+/// Pixels per graph unit on the board of the summary image
+pub const SUMMARY_SCALE: f32 = 30.;
+
+pub const SUMMARY_BACKGROUND: Color = Color::WHITE;
+pub const SUMMARY_BOARD_COLOR: Color = Color::srgb(0.9, 0.9, 0.9);
+pub const SUMMARY_TEXT_COLOR: Color = Color::BLACK;
+pub const SUMMARY_TRAIL_ALPHA: f32 = 0.6;
+pub const SUMMARY_TRAIL_WIDTH: f32 = 2.;
+
+/// Latest shots whose trails the summary keeps in full. Older trails are
+/// simplified to within `SUMMARY_TRAIL_TOLERANCE`.
+pub const SUMMARY_DETAILED_TRAILS: usize = 4;
+
+/// Furthest a simplified summary trail strays from the shot, in graph units:
+/// half a pixel of the summary image
+pub const SUMMARY_TRAIL_TOLERANCE: f32 = 0.5 / SUMMARY_SCALE;
+
+/// Most trail points the summary keeps, so long matches don't grow it
+/// without bound. The oldest trails are dropped beyond it.
+pub const SUMMARY_MAX_TRAIL_POINTS: usize = 50_000;
+
+/// Colors of each player's soldiers in the summary image
+pub const SUMMARY_SOLDIER_COLORS: [Color; MAX_PLAYERS] = [
+    Color::srgb(0.1, 0.3, 0.8),
+    Color::srgb(0.2, 0.6, 0.2),
+    Color::srgb(0.8, 0.2, 0.2),
+    Color::srgb(0.9, 0.55, 0.1),
+];
+
+/// Color of the practice preview of the shot
+pub const PREVIEW_COLOR: Color = Color::srgba(0., 0., 0., 0.3);
+
+/// How long an emote stays up, in seconds
+pub const EMOTE_DURATION: f32 = 3.;
+
+/// Most emotes of a player shown at once
+pub const EMOTE_MAX_BUBBLES: usize = 3;
+
+/// Size of the text in emote bubbles
+pub const EMOTE_TEXT_SIZE: f32 = 18.;
+
+/// Longest line of chat, in characters
+pub const CHAT_MAX_LENGTH: usize = 200;
+
+/// Most lines kept in the chat panel
+pub const CHAT_MAX_LINES: usize = 100;
+
+/// Space between the emotes of players sharing a corner of the window, in
+/// free-for-all matches, in points
+pub const EMOTE_ROW_HEIGHT: f32 = 120.;
+
+/// How long texts celebrating multi-kills and records stay up, in seconds
+pub const FLOATING_TEXT_DURATION: f32 = 1.5;
+
+/// How far those texts rise while they fade, in pixels
+pub const FLOATING_TEXT_RISE: f32 = 40.;
+
+pub const FLOATING_TEXT_COLOR: Color = Color::srgb(1., 0.6, 0.);
+
+/// Font size of the "New record!" text
+pub const RECORD_TEXT_SIZE: f32 = 18.;
+
+/// How far above the hit the "New record!" text appears, so it doesn't cover
+/// a multi-kill text, in pixels
+pub const RECORD_TEXT_OFFSET: f32 = 30.;
+
+/// Shortest turns that can be set up, in seconds. Debug builds allow very
+/// short turns to test turns running out.
+#[cfg(debug_assertions)]
+pub const MIN_TURN_SECONDS: u32 = 2;
+#[cfg(not(debug_assertions))]
+pub const MIN_TURN_SECONDS: u32 = 20;
+
+/// Longest turns that can be set up, in seconds
+pub const MAX_TURN_SECONDS: u32 = 300;
+
+/// Most obstacles that can be set up for a generated board
+pub const MAX_OBSTACLES: u8 = 8;
+
+/// Most gravity wells that can be set up
+pub const MAX_GRAVITY_WELLS: u8 = 4;
+
+/// Turn limit suggested when enabling one in the setup
+pub const DEFAULT_MAX_TURNS: u32 = 20;
+
+/// Turns in a row a network player can let run out before they forfeit,
+/// unless changed in the setup
+pub const DEFAULT_IDLE_FORFEIT_TURNS: u8 = 3;
+
+/// Most edits of an equation that can be undone
+pub const EDIT_HISTORY_LENGTH: usize = 100;
+
+/// Smallest size of the keys of the equation palette, big enough to tap
+pub const PALETTE_KEY_SIZE: (f32, f32) = (44., 36.);
+
+/// Height of the equation panel until it is resized, in logical pixels
+pub const INPUT_PANEL_HEIGHT: f32 = 110.;
+
+/// Heights the equation panel can be resized to, in logical pixels
+pub const INPUT_PANEL_HEIGHT_RANGE: (f32, f32) = (60., 500.);
+
+/// Shot range suggested when enabling one in the setup, in graph units. The
+/// board is 20 units across.
+pub const DEFAULT_MAX_SHOT_LENGTH: u32 = 30;
+
+/// Most energy a player can bank, as a number of extra shots' range
+pub const MAX_BANKED_SHOTS: f32 = 1.;
+
+/// Equation budget suggested when enabling one in the setup
+pub const DEFAULT_EQUATION_BUDGET: u32 = 20;
+
+/// Most points (as counted by the equation budget) an equation from another
+/// player can cost. Shots take a bounded number of steps to cross the board,
+/// so this also bounds the work of simulating them.
+pub const REMOTE_MAX_EQUATION_COST: u32 = 100;
+
+/// Turns an equation can't be repeated for, suggested when enabling the
+/// cooldown in the setup
+pub const DEFAULT_EQUATION_COOLDOWN: u8 = 3;
+
+/// Advantage (in soldiers) at which the evaluation bar is about three
+/// quarters full
+pub const EVALUATION_SCALE: f32 = 1.;
+
+/// Width and height of the evaluation bar in pixels
+pub const EVALUATION_BAR_SIZE: (f32, f32) = (200., 10.);
+
+/// Number of cells along each side of the board in the threat overlay
+pub const THREAT_GRID_SIZE: usize = 40;
+
+/// Number of straight shots fanned out from each enemy to find the threatened
+/// cells
+pub const THREAT_FAN_LINES: usize = 180;
+
+/// Color of the cells that enemies can reach in the threat overlay
+pub const THREAT_COLOR: Color = Color::srgba(1., 0.3, 0., 0.25);
+
+/// Range of the radii of obstacles in graph units
+pub const OBSTACLE_RADIUS: (f32, f32) = (0.8, 1.8);
+
+/// Range of the number of sides of polygonal obstacles
+pub const OBSTACLE_SIDES: (u8, u8) = (3, 6);
+
+/// Range of the half lengths of bouncy walls in graph units
+pub const WALL_HALF_LENGTH: (f32, f32) = (1.5, 3.);
+
+/// Thickness of bouncy walls on screen, in pixels
+pub const WALL_THICKNESS: f32 = 4.;
+
+pub const BOUNCY_WALL_COLOR: Color = Color::srgb(0., 0.6, 0.5);
+
+/// Range of the radii of gravity wells in graph units
+pub const WELL_RADIUS: (f32, f32) = (2., 4.);
+
+/// Range of the pull of gravity wells at their center, in graph units of
+/// height per unit travelled
+pub const WELL_STRENGTH: (f32, f32) = (0.5, 2.);
+
+/// Strongest wind, in graph units of height per unit travelled
+pub const WIND_STRENGTH: f32 = 0.6;
+
+/// Length of the wind arrows at the strongest wind, in pixels
+pub const WIND_ARROW_LENGTH: f32 = 60.;
+
+pub const WIND_COLOR: Color = Color::srgba(0., 0.4, 0.8, 0.6);
+
+/// Number of rings drawn for each gravity well
+pub const WELL_RINGS: usize = 4;
+
+pub const WELL_COLOR: Color = Color::srgba(0.5, 0., 0.8, 0.5);
+
+/// How long the active mutators are listed when a match starts, in seconds
+pub const MUTATOR_INTRO_DURATION: f32 = 4.;
+
+/// How long the mutator roulette is shown at the start of a round, in seconds
+pub const ROULETTE_DURATION: f32 = 3.;
+
+/// Part of `ROULETTE_DURATION` spent spinning before showing the picks
+pub const ROULETTE_SPIN_FRACTION: f32 = 0.5;
+
+/// Mutators the roulette goes through per second while spinning
+pub const ROULETTE_SPEED: f32 = 10.;
+
+/// Soldiers in the first wave of a horde match
+pub const HORDE_FIRST_WAVE: u8 = 2;
+
+/// Most soldiers a horde wave can have. Each wave has one more soldier than
+/// the last until it gets there.
+pub const HORDE_MAX_WAVE: u8 = 6;
+
+/// Straight shots the computer tries in between aiming at each enemy
+pub const AI_FAN_LINES: usize = 60;
+
+/// Seconds the computer takes to aim a straight shot, so its turns can be
+/// followed
+pub const AI_THINK_SECONDS: f32 = 1.5;
+
+/// Least seconds a computer opponent spends thinking at the lowest
+/// difficulty, even if its search is over sooner
+pub const AI_MIN_THINK_SECONDS: f32 = 0.8;
+
+/// Seconds added to a computer opponent's least thinking time for each
+/// difficulty level above the lowest, so stronger opponents take longer
+pub const AI_THINK_SECONDS_PER_LEVEL: f32 = 0.3;
+
+/// Most seconds a computer opponent thinks before shooting the best shot it
+/// found so far
+pub const AI_MAX_THINK_SECONDS: f32 = 4.;
+
+/// Radius of the spinner over a computer player's soldier while it thinks,
+/// in pixels
+pub const AI_THINKING_RADIUS: f32 = 8.;
+
+/// Turns per second of the thinking spinner
+pub const AI_THINKING_SPEED: f32 = 1.5;
+
+/// Color of the thinking spinner
+pub const AI_THINKING_COLOR: Color = Color::srgb(0.3, 0.3, 0.3);
+
+/// Largest error in the slope of the computer's shots, so it can miss
+pub const AI_AIM_ERROR: f32 = 0.08;
+
+/// Difficulty levels of computer opponents
+pub const MAX_AI_LEVEL: u8 = 5;
+
+/// Difficulty of a newly picked computer opponent
+pub const DEFAULT_AI_LEVEL: u8 = 3;
+
+/// Shots a computer opponent tries on each turn per level of difficulty
+pub const AI_CANDIDATES_PER_LEVEL: usize = 40;
+
+/// Shots a computer opponent scores at the same time, across the compute
+/// task pool
+pub const AI_SEARCH_BATCH: usize = 20;
+
+/// Largest change to the slope of the computer opponent's best shot when
+/// trying another
+pub const AI_PERTURBATION: f32 = 0.4;
+
+/// Factor the change shrinks by for each higher power of x, as those bend
+/// the shot much more over the width of the board
+pub const AI_PERTURBATION_FALLOFF: f32 = 0.1;
+
+/// Highest degree of the polynomials tried when searching for a single shot
+/// that hits several targets
+pub const AI_MAX_DEGREE: usize = 3;
+
+/// Targets in each generated puzzle
+pub const PUZZLE_TARGETS: usize = 3;
+
+/// Most targets a shared puzzle can have
+pub const PUZZLE_MAX_TARGETS: usize = 8;
+
+/// Most soldiers each team can have in a layout file
+pub const LAYOUT_MAX_SOLDIERS: usize = 8;
+
+/// Most obstacles a layout file can have
+pub const LAYOUT_MAX_OBSTACLES: usize = 16;
+
+/// Most sides a polygonal obstacle of a layout file can have
+pub const LAYOUT_MAX_SIDES: u8 = 12;
+
+/// Weeks shown in the weekly challenge calendar, including the current one
+pub const CHALLENGE_CALENDAR_WEEKS: usize = 8;
+
+/// Most candidate shots the search may need for a puzzle to count as easy,
+/// and as medium
+pub const PUZZLE_EASY_EFFORT: usize = 3;
+pub const PUZZLE_MEDIUM_EFFORT: usize = 6;
+
+/// Puzzles generated looking for one of the requested difficulty, before
+/// settling for any solvable one
+pub const PUZZLE_ATTEMPTS: usize = 200;
+
+/// Rounds of an accuracy drill, each with one target to hit
+pub const DRILL_ROUNDS: usize = 10;
+
+/// Closest a drill target is placed to the shooter, and the distances from
+/// which targets are in the medium and far bands, in graph units
+pub const DRILL_MIN_DISTANCE: f32 = 3.;
+pub const DRILL_MEDIUM_DISTANCE: f32 = 8.;
+pub const DRILL_FAR_DISTANCE: f32 = 14.;
+
+/// Size of the board drills are played on, in pixels
+pub const DRILL_BOARD_SIZE: f32 = 320.;
+
+/// Minimum free space between an obstacle and soldiers or other obstacles,
+/// in graph units
+pub const OBSTACLE_CLEARANCE: f32 = 1.5;
+
+/// Hits a destructible obstacle takes before it crumbles
+pub const OBSTACLE_HP: u32 = 3;
+
+/// Color of obstacles that can't be destroyed
+pub const OBSTACLE_COLOR: Color = Color::srgb(0.3, 0.3, 0.35);
+
+/// Color of undamaged destructible obstacles, which fade towards white as
+/// they take hits
+pub const DESTRUCTIBLE_OBSTACLE_COLOR: Color = Color::srgb(0.55, 0.35, 0.2);
+
+/// How long crumbling obstacles take to disappear, in seconds
+pub const CRUMBLE_DURATION: f32 = 0.6;
+
+/// Size of explosion sprite in pixels, for a single point of damage that
+/// destroys what it hits
 pub const EXPLOSION_SPRITE_SIZE: f32 = 35.;
 
+/// Size of the flash for damage that leaves what was hit standing, relative
+/// to a destroying blast
+pub const CHIP_EXPLOSION_SCALE: f32 = 0.4;
+
+/// Tint of the flash for damage that leaves what was hit standing
+pub const CHIP_EXPLOSION_COLOR: Color = Color::srgb(1., 0.9, 0.5);
+
+/// How long explosions take to fade away
+pub const EXPLOSION_FADE_TIME: Duration = Duration::from_secs(1);
+
+/// Radius of the scorch marks left where soldiers were destroyed, in pixels
+pub const SCORCH_RADIUS: f32 = 7.;
+
+/// Color of the scorch marks left where soldiers were destroyed
+pub const SCORCH_COLOR: Color = Color::srgba(0.15, 0.1, 0.05, 0.35);
+
 /// Original size of explosion sprite image
 pub const EXPLOSION_IMAGE_SIZE: f32 = 128.;
 
+/// Height of the idle bob of soldiers in pixels
+pub const IDLE_BOB_HEIGHT: f32 = 1.5;
+
+/// Angular speed of the idle bob of soldiers (radians/sec)
+pub const IDLE_BOB_SPEED: f32 = 2.5;
+
+/// Distance (in graph units) at which a passing graph makes soldiers flinch
+pub const FLINCH_DISTANCE: f32 = 1.5;
+
+/// How long soldiers flinch for in seconds
+pub const FLINCH_DURATION: f32 = 0.4;
+
+/// Maximum sideways displacement of a flinching soldier in pixels
+pub const FLINCH_SHAKE: f32 = 3.;
+
+/// Jump height of cheering soldiers in pixels
+pub const CHEER_HEIGHT: f32 = 8.;
+
+/// Angular speed of the cheering jumps (radians/sec)
+pub const CHEER_SPEED: f32 = 6.;
+
+/// Address the network lobby starts out with, where the relay listens by
+/// default
+pub const NET_DEFAULT_ADDRESS: &str = "127.0.0.1:7077";
+
+/// How often a host waiting for a network player checks for them
+pub const NET_ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a network match waits for a player whose connection dropped to
+/// come back, unless set otherwise in the lobby, in seconds
+pub const NET_RECONNECT_SECONDS: u32 = 60;
+
+/// How often the connection of a network match is tried again while it is
+/// down
+pub const NET_REDIAL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest message accepted from a network player, in bytes, with room for
+/// the whole match sent to resync the other side
+pub const NET_MAX_MESSAGE_BYTES: u64 = 1024 * 1024;
+
+/// How long each turn of a replay is shown before its shot is taken
+pub const REPLAY_TURN_PAUSE: Duration = Duration::from_millis(1200);
+
+/// Slowest and fastest a replay can be watched, relative to the match
+pub const REPLAY_MIN_SPEED: f32 = 0.25;
+pub const REPLAY_MAX_SPEED: f32 = 8.;
+
+/// Size of the thumbnails of the boards in the replay browser, in pixels
+pub const REPLAY_THUMBNAIL_SIZE: f32 = 60.;
+
+/// Pixels per graph unit of the frames of replays exported as GIFs, and
+/// the margins around the board and the size of the caption above it
+pub const REPLAY_GIF_SCALE: f32 = 15.;
+pub const REPLAY_GIF_MARGIN: f32 = 10.;
+pub const REPLAY_GIF_CAPTION_SIZE: f32 = 16.;
+
+/// How long each turn of a replay exported as a GIF is shown, in
+/// milliseconds
+pub const REPLAY_GIF_FRAME_MS: u32 = 1000;
+
+/// Opacity of the soldiers destroyed in a turn, in its frame of the GIF
+pub const REPLAY_GIF_HIT_ALPHA: f32 = 0.3;
+
+/// How quickly the colors of GIF frames are picked, from 1 (best colors) to
+/// 30 (fastest)
+pub const REPLAY_GIF_SPEED: i32 = 10;
+
+/// Shots of a type a player has to take in a match before the stats say
+/// which type they miss the most
+pub const SHOT_HABIT_MIN_SHOTS: u32 = 3;
+
+/// How far a soldier of a replay may be from where it was recorded, in
+/// graph units, to count as the same
+pub const REPLAY_TOLERANCE: f32 = 0.01;
+
 // Z indices of different elements
 pub const GRID_BACKGROUND_Z: f32 = -10.;
+pub const THREAT_OVERLAY_Z: f32 = -5.;
+pub const SCORCH_Z: f32 = -4.;
+pub const OBSTACLE_Z: f32 = 5.;
 pub const SOLDIER_Z: f32 = 10.;
+pub const ACTIVE_SOLDIER_OUTLINE_Z: f32 = 11.;
 pub const PLAYER_NAME_Z: f32 = 15.;
 pub const SOLDIER_NAME_Z: f32 = 15.;
 pub const EXPLOSION_Z: f32 = 20.;
+pub const FLOATING_TEXT_Z: f32 = 25.;
@@ -0,0 +1,586 @@
+//! Correspondence play: each turn is exported as a code that the player
+//! sends to their opponent over any channel, who imports it to replay the
+//! shot on their own copy of the match and then exports their reply
+//!
+//! Codes aren't signed. Their checksum only catches codes damaged on the
+//! way, as anyone editing a code can compute it again. What keeps a code
+//! honest is that its board has to match the importer's own copy of the
+//! match, and its shot has to be allowed by the match's rules.
+
+use crate::consts::*;
+use crate::models::{GameState, PlayPhase, PlayerSelect, Soldier};
+use crate::net::NetworkSide;
+use crate::parse::{ParsedFunction, RestrictionError};
+use crate::systems::graph_display::StartGraphingEvent;
+use crate::util::fnv1a;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use bevy::prelude::*;
+use thiserror::Error;
+
+/// Version of the turn code format. Bump it whenever the encoding changes,
+/// and teach `migrate` to bring codes of the previous format up to date, so
+/// matches started on an older version of the game can carry on. Whatever
+/// else changes, codes start with the format version and then the game
+/// version.
+const TURN_CODE_VERSION: u8 = 2;
+
+/// Oldest turn code format that can still be migrated
+const OLDEST_TURN_CODE_VERSION: u8 = 1;
+
+/// Version of the game, written into turn codes so that codes which can't be
+/// read can at least say where they came from
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Longest player name or equation a turn code can carry, in bytes
+const MAX_TEXT_LENGTH: usize = 255;
+
+/// The board at the start of a turn: everything the shot depends on.
+/// Locations are kept exactly, so the shot plays out the same for both
+/// players.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Board {
+    pub turn_number: u32,
+    pub turn: PlayerSelect,
+    pub names: [String; 2],
+    /// Ids and locations (in graph units) of each player's living soldiers
+    pub soldiers: [Vec<(u8, Vec2)>; 2],
+    /// Id of the soldier taking the shot
+    pub active_soldier: u8,
+}
+
+impl Board {
+    pub fn of(state: &PlayPhase) -> Board {
+        let player_1 = state.player(PlayerSelect::Player1);
+        let player_2 = state.player(PlayerSelect::Player2);
+        let soldiers = |soldiers: &[Soldier]| {
+            (soldiers.iter())
+                .map(|soldier| (soldier.number(), soldier.graph_location()))
+                .collect()
+        };
+        Board {
+            turn_number: state.turn_number(),
+            turn: state.turn(),
+            names: [player_1.name.clone(), player_2.name.clone()],
+            soldiers: [
+                soldiers(player_1.soldiers()),
+                soldiers(player_2.soldiers()),
+            ],
+            active_soldier: state.current_player().current_soldier().number(),
+        }
+    }
+
+    /// Soldiers of one of the players, to start a match from the board.
+    /// Correspondence matches only have the first two.
+    pub fn player_soldiers(&self, player: PlayerSelect) -> Vec<Soldier> {
+        let Some(soldiers) = self.soldiers.get(player.index()) else {
+            return Vec::new();
+        };
+        (soldiers.iter())
+            .map(|&(id, location)| Soldier::new(player, id, location))
+            .collect()
+    }
+
+    /// Hash of the board, to tell two copies of a match apart at a glance
+    pub fn hash(&self) -> u64 {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes);
+        fnv1a(&bytes)
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.turn_number.to_le_bytes());
+        bytes.push(self.turn.index() as u8 + 1);
+        for name in &self.names {
+            write_text(bytes, name);
+        }
+        for soldiers in &self.soldiers {
+            bytes.push(soldiers.len() as u8);
+            for &(id, location) in soldiers {
+                bytes.push(id);
+                bytes.extend(location.x.to_le_bytes());
+                bytes.extend(location.y.to_le_bytes());
+            }
+        }
+        bytes.push(self.active_soldier);
+    }
+
+    fn read(reader: &mut CodeReader) -> Result<Board, TurnCodeError> {
+        let turn_number = u32::from_le_bytes(reader.array()?);
+        let turn = match reader.byte()? {
+            1 => PlayerSelect::Player1,
+            2 => PlayerSelect::Player2,
+            _ => return Err(TurnCodeError::InvalidBoard),
+        };
+        let names = [reader.text()?, reader.text()?];
+        let mut soldiers = [Vec::new(), Vec::new()];
+        for team in &mut soldiers {
+            for _ in 0..reader.byte()? {
+                let id = reader.byte()?;
+                let x = f32::from_le_bytes(reader.array()?);
+                let y = f32::from_le_bytes(reader.array()?);
+                team.push((id, Vec2::new(x, y)));
+            }
+        }
+        let board = Board {
+            turn_number,
+            turn,
+            names,
+            soldiers,
+            active_soldier: reader.byte()?,
+        };
+        board.validate()?;
+        Ok(board)
+    }
+
+    /// Check that the board could have come up in a match: every soldier is
+    /// on the board with an id of its own, both players have some left, and
+    /// the shooter is one of them
+    fn validate(&self) -> Result<(), TurnCodeError> {
+        for soldiers in &self.soldiers {
+            let on_board = soldiers.iter().all(|(_, location)| {
+                location.is_finite() && location.abs().max_element() <= 10.
+            });
+            let unique = (soldiers.iter().enumerate()).all(|(i, (id, _))| {
+                soldiers[..i].iter().all(|(other, _)| other != id)
+            });
+            if soldiers.is_empty() || !on_board || !unique {
+                return Err(TurnCodeError::InvalidBoard);
+            }
+        }
+        let shooters = self
+            .soldiers
+            .get(self.turn.index())
+            .map_or(&[][..], Vec::as_slice);
+        if !shooters.iter().any(|&(id, _)| id == self.active_soldier)
+            || self.turn_number == 0
+        {
+            return Err(TurnCodeError::InvalidBoard);
+        }
+        Ok(())
+    }
+}
+
+/// A turn of a correspondence match: the board it was taken on and the
+/// equation that was shot
+#[derive(Clone, Debug, PartialEq)]
+pub struct TurnFile {
+    pub match_id: u64,
+    pub board: Board,
+    /// The equation, with any parameters already filled in
+    pub equation: String,
+}
+
+impl TurnFile {
+    /// The turn as a code that can be pasted anywhere. It ends in an unkeyed
+    /// checksum of the rest, so codes that were mangled or cut short on the
+    /// way are turned away. The checksum doesn't stop anyone from editing a
+    /// code on purpose.
+    pub fn code(&self) -> String {
+        let mut bytes = vec![TURN_CODE_VERSION];
+        write_text(&mut bytes, GAME_VERSION);
+        bytes.extend(self.match_id.to_le_bytes());
+        self.board.write(&mut bytes);
+        write_text(&mut bytes, &self.equation);
+        bytes.extend(fnv1a(&bytes).to_le_bytes());
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    pub fn from_code(code: &str) -> Result<TurnFile, TurnCodeError> {
+        let bytes = URL_SAFE_NO_PAD.decode(code.trim())?;
+        let Some((&version, rest)) = bytes.split_first() else {
+            return Err(TurnCodeError::Truncated);
+        };
+        if !(OLDEST_TURN_CODE_VERSION..=TURN_CODE_VERSION).contains(&version) {
+            // Every format from 2 on starts with the game version
+            let made_by = (version >= 2)
+                .then(|| CodeReader(rest).text().ok())
+                .flatten();
+            return Err(TurnCodeError::UnsupportedVersion {
+                format: version,
+                made_by,
+            });
+        }
+        // The version byte has to come before the checksum
+        let Some(split) = bytes.len().checked_sub(8).filter(|&split| split > 0)
+        else {
+            return Err(TurnCodeError::Truncated);
+        };
+        let (payload, checksum) = bytes.split_at(split);
+        if checksum != fnv1a(payload).to_le_bytes() {
+            return Err(TurnCodeError::Checksum);
+        }
+        let payload = migrate(version, payload.to_vec());
+        let mut reader = CodeReader(&payload[1..]);
+        // The game version only matters for codes that can't be read
+        reader.text()?;
+        let match_id = u64::from_le_bytes(reader.array()?);
+        let board = Board::read(&mut reader)?;
+        let equation = reader.text()?;
+        if !reader.0.is_empty() {
+            return Err(TurnCodeError::TrailingData);
+        }
+        // Turned away before it is ever simulated, so a doctored code can't
+        // stall the game
+        let Ok(func) = equation.parse::<ParsedFunction>() else {
+            return Err(TurnCodeError::InvalidEquation);
+        };
+        let cost = func.cost();
+        if cost > REMOTE_MAX_EQUATION_COST {
+            return Err(TurnCodeError::TooComplex {
+                cost,
+                max: REMOTE_MAX_EQUATION_COST,
+            });
+        }
+        Ok(TurnFile {
+            match_id,
+            board,
+            equation,
+        })
+    }
+
+    /// Check that the turn is the opponent's next one in this match, taken
+    /// on the same board as the local copy of the match shows
+    pub fn follows(
+        &self,
+        state: &PlayPhase,
+        correspondence: &Correspondence,
+    ) -> Result<(), TurnCodeError> {
+        if self.match_id != correspondence.match_id {
+            return Err(TurnCodeError::OtherMatch);
+        }
+        let local = Board::of(state);
+        if self.board.turn_number != local.turn_number
+            || self.board.turn != local.turn
+        {
+            return Err(TurnCodeError::WrongTurn {
+                expected: local.turn_number,
+                found: self.board.turn_number,
+            });
+        }
+        // The opponent picks which of their soldiers shoots
+        let local = Board {
+            active_soldier: self.board.active_soldier,
+            ..local
+        };
+        if self.board != local {
+            return Err(TurnCodeError::Desync {
+                local: local.hash(),
+                theirs: self.board.hash(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that the turn can be replayed in this match: it follows on from
+    /// the local copy, and its shot is allowed by the match's rules
+    pub fn check(
+        &self,
+        state: &PlayPhase,
+        correspondence: &Correspondence,
+    ) -> Result<(), TurnCodeError> {
+        self.follows(state, correspondence)?;
+        let func = (self.equation.parse::<ParsedFunction>())
+            .map_err(|_| TurnCodeError::InvalidEquation)?;
+        state.check_equation(&func)?;
+        Ok(())
+    }
+}
+
+/// Why a turn code couldn't be imported
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum TurnCodeError {
+    #[error("Not a turn code")]
+    InvalidCode(#[from] base64::DecodeError),
+    #[error(
+        "Turn code is from {} (format {format}), which this version can't read",
+        game_version_text(made_by)
+    )]
+    UnsupportedVersion {
+        format: u8,
+        /// Version of the game that made the code, if it could be read
+        made_by: Option<String>,
+    },
+    #[error("Turn code is incomplete")]
+    Truncated,
+    #[error("Turn code has unexpected data at the end")]
+    TrailingData,
+    /// The checksum doesn't match, which catches accidental damage but not
+    /// deliberate changes
+    #[error("Turn code was damaged on the way")]
+    Checksum,
+    #[error("Turn code has an impossible board")]
+    InvalidBoard,
+    #[error("Turn code has an equation that can't be parsed")]
+    InvalidEquation,
+    #[error(
+        "Turn code's equation costs {cost} points, over the limit of {max}"
+    )]
+    TooComplex { cost: u32, max: u32 },
+    #[error("Turn code's shot isn't allowed: {0}")]
+    NotAllowed(#[from] RestrictionError),
+    #[error("Turn code belongs to another match")]
+    OtherMatch,
+    #[error("Expected the code of turn {expected}, got turn {found}")]
+    WrongTurn { expected: u32, found: u32 },
+    #[error(
+        "Your boards don't match (yours is {local:016x}, theirs \
+         {theirs:016x})"
+    )]
+    Desync { local: u64, theirs: u64 },
+}
+
+/// State of a correspondence match on this player's side
+#[derive(Clone, Debug)]
+pub struct Correspondence {
+    pub match_id: u64,
+    /// The player on this side; the other one's turns are imported
+    pub local: PlayerSelect,
+    /// Code of the latest turn taken on this side, to send to the opponent
+    pub outgoing: Option<String>,
+    /// Opponent's turn waiting to be replayed
+    pub incoming: Option<TurnFile>,
+    /// Why the last turn to be replayed was turned away, if it was
+    pub rejected: Option<TurnCodeError>,
+    /// Whether the codes travel over a network connection (see `net`)
+    /// instead of being copied by the players
+    pub networked: bool,
+    /// A turn of the local player ran out without a shot, which the
+    /// opponent of a network match still has to be told about
+    pub skipped: bool,
+}
+
+impl Correspondence {
+    /// The state of `side` of a network match as it starts
+    pub fn networked(side: NetworkSide) -> Correspondence {
+        Correspondence {
+            match_id: side.seed,
+            local: side.local,
+            outgoing: None,
+            incoming: None,
+            rejected: None,
+            networked: true,
+            skipped: false,
+        }
+    }
+    /// Record the local player's shot on `board`, to be sent to the opponent
+    pub fn export(&mut self, board: Board, equation: String) {
+        let turn = TurnFile {
+            match_id: self.match_id,
+            board,
+            equation,
+        };
+        self.outgoing = Some(turn.code());
+    }
+}
+
+/// Replay the opponent's imported turn: their soldier takes the shot as soon
+/// as their turn starts. The turn is checked again first, as the turn that
+/// starts a joined match is only checked once the match is set up.
+pub fn replay_turns(
+    mut state: ResMut<GameState>,
+    mut start_graphing_events: EventWriter<StartGraphingEvent>,
+) {
+    let Some(playing_state) = state.playing_state_mut() else {
+        return;
+    };
+    if !playing_state.turn_phase().is_input() {
+        return;
+    }
+    let Some(turn) = playing_state
+        .correspondence_mut()
+        .and_then(|correspondence| correspondence.incoming.take())
+    else {
+        return;
+    };
+    let checked = match playing_state.correspondence() {
+        Some(correspondence) => turn.check(playing_state, correspondence),
+        None => return,
+    };
+    if let Some(correspondence) = playing_state.correspondence_mut() {
+        correspondence.rejected = checked.clone().err();
+    }
+    if let Err(e) = checked {
+        log::info!("Turn code turned away: {e}");
+        return;
+    }
+    let player = playing_state.current_player_mut();
+    player.select_soldier(turn.board.active_soldier);
+    *player.current_soldier_mut().slots.current_mut() = turn.equation.clone();
+    if let Ok(func) = turn.equation.parse() {
+        start_graphing_events.send(StartGraphingEvent(func));
+    }
+}
+
+/// Name of the version of the game that made a file, if it's known
+pub fn game_version_text(made_by: &Option<String>) -> String {
+    match made_by {
+        Some(version) => format!("version {version} of the game"),
+        None => "another version of the game".to_string(),
+    }
+}
+
+/// Bring the payload of a turn code (without its checksum) in an older
+/// format up to the current one, a format at a time
+fn migrate(version: u8, mut payload: Vec<u8>) -> Vec<u8> {
+    if version < 2 {
+        // Format 1 didn't say which version of the game made the code
+        payload.insert(1, 0);
+    }
+    payload
+}
+
+/// Write `text` with its length in front, cut short (between characters) if
+/// it is too long
+fn write_text(bytes: &mut Vec<u8>, text: &str) {
+    let end = (0..=text.len().min(MAX_TEXT_LENGTH))
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0);
+    bytes.push(end as u8);
+    bytes.extend(&text.as_bytes()[..end]);
+}
+
+/// Reads the parts of a decoded turn code in order
+struct CodeReader<'a>(&'a [u8]);
+
+impl CodeReader<'_> {
+    fn byte(&mut self) -> Result<u8, TurnCodeError> {
+        let (&byte, rest) =
+            self.0.split_first().ok_or(TurnCodeError::Truncated)?;
+        self.0 = rest;
+        Ok(byte)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], TurnCodeError> {
+        let (bytes, rest) =
+            self.0.split_first_chunk().ok_or(TurnCodeError::Truncated)?;
+        self.0 = rest;
+        Ok(*bytes)
+    }
+    fn text(&mut self) -> Result<String, TurnCodeError> {
+        let length = usize::from(self.byte()?);
+        if self.0.len() < length {
+            return Err(TurnCodeError::Truncated);
+        }
+        let (text, rest) = self.0.split_at(length);
+        self.0 = rest;
+        String::from_utf8(text.to_vec()).map_err(|_| TurnCodeError::Truncated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn() -> TurnFile {
+        TurnFile {
+            match_id: 1976,
+            board: Board {
+                turn_number: 3,
+                turn: PlayerSelect::Player2,
+                names: ["Ada".to_string(), "Émile".to_string()],
+                soldiers: [
+                    vec![(0, Vec2::new(-3.25, 1.1)), (2, Vec2::new(-9., 0.))],
+                    vec![(1, Vec2::new(0.1, -7.3))],
+                ],
+                active_soldier: 1,
+            },
+            equation: "sin((1.5)x)+0.3".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_turn_code() {
+        let turn = turn();
+        let code = turn.code();
+        assert_eq!(TurnFile::from_code(&code), Ok(turn.clone()));
+        assert_eq!(
+            TurnFile::from_code("not a code!"),
+            Err(TurnCodeError::InvalidCode(
+                base64::DecodeError::InvalidByte(3, b' ')
+            ))
+        );
+        // Accidental changes to the code are caught by the checksum
+        let mut bytes = URL_SAFE_NO_PAD.decode(&code).unwrap();
+        bytes[12] ^= 1;
+        let changed = URL_SAFE_NO_PAD.encode(&bytes);
+        assert_eq!(TurnFile::from_code(&changed), Err(TurnCodeError::Checksum));
+        let cut = URL_SAFE_NO_PAD.encode(&bytes[..5]);
+        assert_eq!(TurnFile::from_code(&cut), Err(TurnCodeError::Truncated));
+
+        let mut shooter_gone = turn.clone();
+        shooter_gone.board.active_soldier = 0;
+        assert_eq!(
+            TurnFile::from_code(&shooter_gone.code()),
+            Err(TurnCodeError::InvalidBoard)
+        );
+        let mut off_board = turn.clone();
+        off_board.board.soldiers[0][0].1.x = f32::NAN;
+        assert_eq!(
+            TurnFile::from_code(&off_board.code()),
+            Err(TurnCodeError::InvalidBoard)
+        );
+        let mut bad_equation = turn.clone();
+        bad_equation.equation = "sin(".to_string();
+        assert_eq!(
+            TurnFile::from_code(&bad_equation.code()),
+            Err(TurnCodeError::InvalidEquation)
+        );
+        // Long equations are cheap to send but slow to simulate
+        let mut heavy = turn;
+        heavy.equation = vec!["sin(x)"; 30].join("+");
+        assert_eq!(
+            TurnFile::from_code(&heavy.code()),
+            Err(TurnCodeError::TooComplex {
+                cost: 149,
+                max: REMOTE_MAX_EQUATION_COST
+            })
+        );
+    }
+
+    #[test]
+    fn test_substituted_turn_code() {
+        let mut params = crate::editor::EquationParams::default();
+        params.0[0].value = 2.;
+        params.0[1].value = -0.5;
+        let equation = "abs(a*x) + max(x,b) - atan2(c,x)";
+        let mut local = equation.parse::<ParsedFunction>().unwrap();
+        params.bind(&mut local);
+
+        let mut turn = turn();
+        turn.equation = params.substitute(equation);
+        assert_eq!(turn.equation, "abs((2)*x) + max(x,(-0.5)) - atan2((1),x)");
+        let received = TurnFile::from_code(&turn.code()).unwrap();
+        assert_eq!(received, turn);
+        // The other side shoots the same function, with no variables left
+        // but x
+        let remote = received.equation.parse::<ParsedFunction>().unwrap();
+        assert_eq!(remote.variables(), vec!['x']);
+        let (local, remote) = (local.bind("x"), remote.bind("x"));
+        for x in [-3., 0.5, 4.] {
+            assert_eq!(local(x).unwrap(), remote(x).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_turn_code_versions() {
+        let turn = turn();
+        let mut bytes = URL_SAFE_NO_PAD.decode(turn.code()).unwrap();
+        // The same code in format 1, which had no game version
+        let mut old = bytes[..bytes.len() - 8].to_vec();
+        old.drain(1..2 + usize::from(old[1]));
+        old[0] = 1;
+        old.extend(fnv1a(&old).to_le_bytes());
+        let old = URL_SAFE_NO_PAD.encode(old);
+        assert_eq!(TurnFile::from_code(&old), Ok(turn));
+
+        bytes[0] = TURN_CODE_VERSION + 1;
+        let error =
+            TurnFile::from_code(&URL_SAFE_NO_PAD.encode(bytes)).unwrap_err();
+        assert_eq!(error, TurnCodeError::UnsupportedVersion {
+            format: TURN_CODE_VERSION + 1,
+            made_by: Some(GAME_VERSION.to_string()),
+        });
+        assert!(error.to_string().contains(GAME_VERSION));
+    }
+}
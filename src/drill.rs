@@ -0,0 +1,233 @@
+//! Accuracy drills: a shooter on the left of a plain board and a single
+//! target placed at random to its right, for `DRILL_ROUNDS` rounds. Each
+//! round is timed until the target is hit, with the shots traced by the
+//! headless simulation, and the best time for each distance band is kept
+//! with the player's records.
+
+use crate::consts::*;
+use crate::models::{PlayerSelect, SoldierId};
+use crate::parse::ParsedFunction;
+use crate::sim::{ShotStepper, Target, aim};
+use bevy::math::Vec2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// How far the target of a round is from the shooter
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum DistanceBand {
+    Close,
+    Medium,
+    Far,
+}
+
+impl DistanceBand {
+    pub const ALL: [DistanceBand; 3] =
+        [DistanceBand::Close, DistanceBand::Medium, DistanceBand::Far];
+    pub fn name(self) -> &'static str {
+        match self {
+            DistanceBand::Close => "Close",
+            DistanceBand::Medium => "Medium",
+            DistanceBand::Far => "Far",
+        }
+    }
+    /// The band of targets `distance` graph units from the shooter
+    pub fn of(distance: f32) -> Self {
+        if distance < DRILL_MEDIUM_DISTANCE {
+            DistanceBand::Close
+        } else if distance < DRILL_FAR_DISTANCE {
+            DistanceBand::Medium
+        } else {
+            DistanceBand::Far
+        }
+    }
+}
+
+/// A round of a drill, once its target was hit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrillRound {
+    pub band: DistanceBand,
+    /// Time from the round starting to the target being hit
+    pub seconds: f32,
+    pub shots: u32,
+}
+
+/// The latest shot of a drill
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrillShot {
+    /// Path of the shot, in graph units
+    pub points: Vec<Vec2>,
+    pub hit: bool,
+}
+
+pub struct Drill {
+    /// Locations in graph units
+    pub shooter: Vec2,
+    pub target: Vec2,
+    /// When the current round started, in seconds of the UI's clock
+    started: f64,
+    /// Shots taken in the current round so far
+    shots: u32,
+    /// Rounds finished so far
+    pub rounds: Vec<DrillRound>,
+    pub last_shot: Option<DrillShot>,
+}
+
+impl Drill {
+    /// Place the shooter and the first target, starting the first round at
+    /// `now`
+    pub fn new(rng: &mut impl Rng, now: f64) -> Self {
+        let shooter =
+            Vec2::new(rng.gen_range(-9.0..-6.0), rng.gen_range(-5.0..5.0));
+        let mut drill = Self {
+            shooter,
+            target: shooter,
+            started: now,
+            shots: 0,
+            rounds: Vec::new(),
+            last_shot: None,
+        };
+        drill.place_target(rng);
+        drill
+    }
+
+    /// Put the target somewhere to the right of the shooter, at least
+    /// `DRILL_MIN_DISTANCE` away from it
+    fn place_target(&mut self, rng: &mut impl Rng) {
+        loop {
+            let target = Vec2::new(
+                rng.gen_range(self.shooter.x + DRILL_MIN_DISTANCE..9.),
+                rng.gen_range(-9.0..9.0),
+            );
+            if target.distance(self.shooter) >= DRILL_MIN_DISTANCE {
+                self.target = target;
+                return;
+            }
+        }
+    }
+
+    /// Number of the round being played, counting from 1
+    pub fn round(&self) -> usize {
+        self.rounds.len() + 1
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.rounds.len() >= DRILL_ROUNDS
+    }
+
+    /// Band of the current target
+    pub fn band(&self) -> DistanceBand {
+        DistanceBand::of(self.shooter.distance(self.target))
+    }
+
+    /// Seconds spent on the current round by `now`
+    pub fn elapsed(&self, now: f64) -> f32 {
+        (now - self.started) as f32
+    }
+
+    /// Seconds all the finished rounds took together
+    pub fn total_seconds(&self) -> f32 {
+        self.rounds.iter().map(|i| i.seconds).sum()
+    }
+
+    /// Shoot `equation` at the target at `now`. Hitting it finishes the
+    /// round, which is returned, and places the target of the next one.
+    pub fn shoot(
+        &mut self,
+        equation: &str,
+        rng: &mut impl Rng,
+        now: f64,
+    ) -> Result<Option<DrillRound>, String> {
+        if self.is_over() {
+            return Ok(None);
+        }
+        let parsed = equation
+            .parse::<ParsedFunction>()
+            .map_err(|e| e.to_string())?;
+        let function = aim(parsed, self.shooter).map_err(|x| {
+            format!("The function can't be evaluated at x = {x:.2}")
+        })?;
+        let target = Target {
+            id: SoldierId {
+                player: PlayerSelect::Player2,
+                number: 0,
+            },
+            location: self.target,
+        };
+        let trace =
+            ShotStepper::new(function, self.shooter.x, vec![target]).trace();
+        let hit = !trace.hits.is_empty();
+        self.shots += 1;
+        self.last_shot = Some(DrillShot {
+            points: trace.points,
+            hit,
+        });
+        if !hit {
+            return Ok(None);
+        }
+        let round = DrillRound {
+            band: self.band(),
+            seconds: self.elapsed(now),
+            shots: self.shots,
+        };
+        self.rounds.push(round);
+        if !self.is_over() {
+            self.place_target(rng);
+            self.started = now;
+            self.shots = 0;
+        }
+        Ok(Some(round))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_drill() {
+        let mut rng = StdRng::seed_from_u64(1936);
+        let mut drill = Drill::new(&mut rng, 0.);
+        assert!(drill.shoot("x^", &mut rng, 1.).is_err());
+        assert_eq!(drill.round(), 1);
+
+        let mut now = 0.;
+        while !drill.is_over() {
+            let target = drill.target;
+            assert!(target.x <= 9. && target.y.abs() <= 9.);
+            assert!(drill.shooter.distance(target) >= DRILL_MIN_DISTANCE);
+            now += 2.;
+            // Straight at the target, after missing it above once
+            let slope =
+                (target.y - drill.shooter.y) / (target.x - drill.shooter.x);
+            let miss = drill.shoot("100x", &mut rng, now).unwrap();
+            assert_eq!(miss, None);
+            assert_eq!(drill.last_shot.as_ref().map(|i| i.hit), Some(false));
+            now += 3.;
+            let round = drill.shoot(&format!("{slope}x"), &mut rng, now);
+            assert_eq!(
+                round.unwrap(),
+                Some(DrillRound {
+                    band: DistanceBand::of(drill.shooter.distance(target)),
+                    seconds: 5.,
+                    shots: 2,
+                })
+            );
+        }
+        assert_eq!(drill.rounds.len(), DRILL_ROUNDS);
+        assert_eq!(drill.total_seconds(), 5. * DRILL_ROUNDS as f32);
+        assert_eq!(drill.shoot("x", &mut rng, now).unwrap(), None);
+    }
+}
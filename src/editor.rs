@@ -0,0 +1,367 @@
+//! Helpers for editing equations that don't depend on the UI
+
+use crate::consts::*;
+use crate::parse::{ParsedFunction, replace_variables};
+use bevy::prelude::*;
+
+/// Names of the variables that can be set with sliders instead of typed in
+pub const PARAM_NAMES: [char; 3] = ['a', 'b', 'c'];
+
+/// A named parameter that is set with a slider
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Param {
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for Param {
+    fn default() -> Self {
+        Self {
+            value: 1.,
+            min: -10.,
+            max: 10.,
+        }
+    }
+}
+
+/// Current values of the slider parameters, in the order of `PARAM_NAMES`
+#[derive(Resource, Default)]
+pub struct EquationParams(pub [Param; 3]);
+
+impl EquationParams {
+    /// Substitute the parameter values into `function`
+    pub fn bind(&self, function: &mut ParsedFunction) {
+        for (name, param) in PARAM_NAMES.iter().zip(&self.0) {
+            function.add_var(name, param.value);
+        }
+    }
+    /// `equation` with the parameters replaced by their values, for display
+    /// and for sending the shot elsewhere. Only the variables are replaced,
+    /// not the letters of function names like `abs` or `max`.
+    pub fn substitute(&self, equation: &str) -> String {
+        replace_variables(equation, |var| {
+            let i = PARAM_NAMES.iter().position(|&name| name == var)?;
+            Some(format!("({})", self.0[i].value))
+        })
+    }
+}
+
+/// Undo and redo stacks for an equation. egui's own text edit history is
+/// lost whenever the text is changed from outside the text edit, so every
+/// version of the text is recorded here instead.
+#[derive(Clone, Debug, Default)]
+pub struct EditHistory {
+    undo: Vec<String>,
+    redo: Vec<String>,
+    /// The text as last recorded
+    current: Option<String>,
+}
+
+impl EditHistory {
+    /// Record the current text, making the previous one undoable if it
+    /// changed
+    pub fn record(&mut self, text: &str) {
+        match &self.current {
+            Some(current) if current == text => (),
+            Some(_) => {
+                let previous = self.current.replace(text.to_string());
+                self.undo.extend(previous);
+                if self.undo.len() > EDIT_HISTORY_LENGTH {
+                    self.undo.remove(0);
+                }
+                self.redo.clear();
+            }
+            None => self.current = Some(text.to_string()),
+        }
+    }
+    /// The text before the last change, if there is one
+    pub fn undo(&mut self) -> Option<String> {
+        let previous = self.undo.pop()?;
+        self.redo.extend(self.current.replace(previous.clone()));
+        Some(previous)
+    }
+    /// The text before the last undo, if there is one
+    pub fn redo(&mut self) -> Option<String> {
+        let next = self.redo.pop()?;
+        self.undo.extend(self.current.replace(next.clone()));
+        Some(next)
+    }
+}
+
+/// How big a step to nudge a number by, relative to its last decimal place
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NudgeScale {
+    Normal,
+    /// One more decimal place
+    Fine,
+    /// Ten times the normal step
+    Coarse,
+}
+
+/// Find the numeric literal in `text` that contains or touches the char index
+/// `cursor`, and move it by `steps` steps. A `-` directly in front of the
+/// number counts as its sign unless it is used as a binary minus, and a binary
+/// plus or minus is flipped when the number would go negative. Returns the
+/// new text and the char index just after the edited number.
+pub fn nudge_number(
+    text: &str,
+    cursor: usize,
+    steps: f32,
+    scale: NudgeScale,
+) -> Option<(String, usize)> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let is_number_char = |c: char| c.is_ascii_digit() || c == '.';
+    let cursor = cursor.min(chars.len());
+    let mut start = cursor;
+    while start > 0 && is_number_char(chars[start - 1]) {
+        start -= 1;
+    }
+    let mut end = cursor;
+    while end < chars.len() && is_number_char(chars[end]) {
+        end += 1;
+    }
+    let literal = chars[start..end].iter().collect::<String>();
+    if !literal.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let magnitude = literal.parse::<f64>().ok()?;
+
+    // The minus is a sign if nothing that could be a left operand is in
+    // front of it
+    let before_minus = chars[..start.saturating_sub(1)]
+        .iter()
+        .rev()
+        .find(|c| !c.is_whitespace());
+    let signed = start > 0
+        && chars[start - 1] == '-'
+        && before_minus.is_none_or(|c| "(+-*/^".contains(*c));
+    if signed {
+        start -= 1;
+    }
+    // A binary plus or minus in front, which is flipped rather than followed
+    // by a sign when the number goes negative
+    let operator = (!signed && start > 0 && "+-".contains(chars[start - 1]))
+        .then(|| start - 1);
+    let value = if signed { -magnitude } else { magnitude };
+
+    let mut decimals = literal
+        .split_once('.')
+        .map(|(_, fraction)| fraction.len() as i32)
+        .unwrap_or(0);
+    let mut step = 10f64.powi(-decimals);
+    match scale {
+        NudgeScale::Normal => (),
+        NudgeScale::Fine => {
+            decimals += 1;
+            step /= 10.;
+        }
+        NudgeScale::Coarse => step *= 10.,
+    }
+    let mut new_value = value + step * steps as f64;
+    let mut chars = chars;
+    if let Some(operator) = operator
+        && new_value < 0.
+    {
+        chars[operator] = if chars[operator] == '-' { '+' } else { '-' };
+        new_value = -new_value;
+    }
+    let mut formatted = format!("{:.*}", decimals.max(0) as usize, new_value);
+    // Don't leave a "-0" behind when nudging through zero
+    if formatted.starts_with('-') && formatted[1..].parse::<f64>() == Ok(0.) {
+        formatted.remove(0);
+    }
+
+    let mut new_text = chars[..start].iter().collect::<String>();
+    new_text.push_str(&formatted);
+    let new_cursor = new_text.chars().count();
+    new_text.extend(&chars[end..]);
+    Some((new_text, new_cursor))
+}
+
+/// A key of the equation palette, for building equations a piece at a time
+/// on touch screens, or by players who can't type them yet. Keys edit the
+/// equation's text, so it parses into the same tree as if it was typed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaletteKey {
+    /// Adds its text: a digit, the point, a variable, an operator, a bracket,
+    /// or a function name along with its opening bracket
+    Insert(&'static str),
+    /// Takes off the last piece, such as a whole function name
+    Backspace,
+    Clear,
+}
+
+/// Functions on the palette, in the form their keys insert them
+const PALETTE_FUNCTIONS: [&str; 5] = ["sin(", "exp(", "ln(", "sqrt(", "log10("];
+
+/// Rows of keys of the equation palette
+pub const PALETTE: [&[PaletteKey]; 6] = {
+    use PaletteKey::*;
+    [
+        &[
+            Insert(PALETTE_FUNCTIONS[0]),
+            Insert(PALETTE_FUNCTIONS[1]),
+            Insert(PALETTE_FUNCTIONS[2]),
+            Insert(PALETTE_FUNCTIONS[3]),
+            Insert(PALETTE_FUNCTIONS[4]),
+        ],
+        &[
+            Insert("7"),
+            Insert("8"),
+            Insert("9"),
+            Insert("/"),
+            Insert("("),
+        ],
+        &[
+            Insert("4"),
+            Insert("5"),
+            Insert("6"),
+            Insert("*"),
+            Insert(")"),
+        ],
+        &[
+            Insert("1"),
+            Insert("2"),
+            Insert("3"),
+            Insert("-"),
+            Insert("^"),
+        ],
+        &[
+            Insert("0"),
+            Insert("."),
+            Insert("x"),
+            Insert("+"),
+            Backspace,
+        ],
+        &[Insert("a"), Insert("b"), Insert("c"), Clear],
+    ]
+};
+
+impl PaletteKey {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PaletteKey::Insert("*") => "×",
+            PaletteKey::Insert("/") => "÷",
+            PaletteKey::Insert("-") => "−",
+            PaletteKey::Insert("sqrt(") => "√(",
+            PaletteKey::Insert(text) => text,
+            PaletteKey::Backspace => "⌫",
+            PaletteKey::Clear => "Clear",
+        }
+    }
+
+    pub fn press(&self, equation: &mut String) {
+        match self {
+            PaletteKey::Insert(text) => equation.push_str(text),
+            PaletteKey::Backspace => {
+                let function = (PALETTE_FUNCTIONS.iter())
+                    .find(|function| equation.ends_with(*function));
+                let len = match function {
+                    Some(function) => function.len(),
+                    None => equation.chars().last().map_or(0, char::len_utf8),
+                };
+                equation.truncate(equation.len() - len);
+            }
+            PaletteKey::Clear => equation.clear(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette() {
+        let mut equation = String::new();
+        let keys = [
+            PaletteKey::Insert("2"),
+            PaletteKey::Insert("sin("),
+            PaletteKey::Insert("x"),
+            PaletteKey::Insert(")"),
+            PaletteKey::Insert("^"),
+            PaletteKey::Insert("2"),
+        ];
+        for key in keys {
+            key.press(&mut equation);
+        }
+        assert_eq!(equation, "2sin(x)^2");
+        assert!(equation.parse::<crate::parse::ParsedFunction>().is_ok());
+        for _ in 0..3 {
+            PaletteKey::Backspace.press(&mut equation);
+        }
+        assert_eq!(equation, "2sin(x");
+        PaletteKey::Backspace.press(&mut equation);
+        // The function goes in one press
+        PaletteKey::Backspace.press(&mut equation);
+        assert_eq!(equation, "2");
+        PaletteKey::Clear.press(&mut equation);
+        PaletteKey::Backspace.press(&mut equation);
+        assert_eq!(equation, "");
+    }
+
+    #[test]
+    fn test_substitute() {
+        let mut params = EquationParams::default();
+        params.0[2].value = 3.;
+        assert_eq!(params.substitute("c x + a"), "(3) x + (1)");
+        // Function names with the letters of parameters are left alone
+        assert_eq!(params.substitute("abs(c*x)"), "abs((3)*x)");
+        assert_eq!(
+            params.substitute("ceil(x)+max(b,1)*atan2(x,a)"),
+            "ceil(x)+max((1),1)*atan2(x,(1))"
+        );
+        assert_eq!(params.substitute("a+("), "(1)+(");
+        assert_eq!(params.substitute("a$"), "a$");
+    }
+
+    #[test]
+    fn test_edit_history() {
+        let mut history = EditHistory::default();
+        history.record("x");
+        assert_eq!(history.undo(), None);
+        history.record("x");
+        history.record("x+");
+        history.record("x+1");
+        assert_eq!(history.undo().as_deref(), Some("x+"));
+        assert_eq!(history.undo().as_deref(), Some("x"));
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.redo().as_deref(), Some("x+"));
+        // Recording the undone text changes nothing
+        history.record("x+");
+        assert_eq!(history.redo().as_deref(), Some("x+1"));
+        history.undo();
+        // A new edit throws away what was undone
+        history.record("x+2");
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo().as_deref(), Some("x+"));
+    }
+
+    #[test]
+    fn test_nudge_number() {
+        let test_sets = [
+            ("2x", 0, 1., NudgeScale::Normal, Some(("3x", 1))),
+            ("2x", 1, -1., NudgeScale::Normal, Some(("1x", 1))),
+            ("x+2.5", 5, 1., NudgeScale::Normal, Some(("x+2.6", 5))),
+            ("x+2.5", 3, 1., NudgeScale::Fine, Some(("x+2.51", 6))),
+            ("x+2.5", 3, -1., NudgeScale::Coarse, Some(("x+1.5", 5))),
+            ("sin(x)", 2, 1., NudgeScale::Normal, None),
+            // A leading minus is a sign...
+            ("-1x", 1, 2., NudgeScale::Normal, Some(("1x", 1))),
+            ("x*(-0.1)", 5, 1., NudgeScale::Normal, Some(("x*(0.0)", 6))),
+            // ...but a binary minus is flipped instead of followed by one
+            ("x-1", 2, -2., NudgeScale::Normal, Some(("x+1", 3))),
+            ("x+1", 3, -3., NudgeScale::Normal, Some(("x-2", 3))),
+            ("x-1", 2, 1., NudgeScale::Normal, Some(("x-2", 3))),
+        ];
+        for (text, cursor, steps, scale, expected) in test_sets {
+            let nudged = nudge_number(text, cursor, steps, scale);
+            assert_eq!(
+                nudged,
+                expected.map(|(text, cursor)| (text.to_string(), cursor)),
+                "nudging {text:?} at {cursor}"
+            );
+        }
+    }
+}
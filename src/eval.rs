@@ -0,0 +1,176 @@
+//! Rough evaluation of who is ahead on the board, for the evaluation bar and
+//! as a scoring function for computer opponents
+
+use crate::consts::*;
+use crate::models::{Obstacle, PlayPhase, PlayerSelect, Soldier};
+use crate::sim::{FieldModifier, ShotStepper, Target};
+use bevy::prelude::*;
+
+/// How many soldiers' worth of advantage a single exposed soldier counts as
+const EXPOSURE_WEIGHT: f32 = 0.5;
+
+/// Advantage of player 1 over player 2, in soldiers: positive if player 1 is
+/// ahead. Counts the soldiers left, minus how exposed each team is to
+/// straight shots.
+pub fn evaluate(state: &PlayPhase) -> f32 {
+    let player_1 = state.player(PlayerSelect::Player1).soldiers();
+    let player_2 = state.player(PlayerSelect::Player2).soldiers();
+    let material = player_1.len() as f32 - player_2.len() as f32;
+    let exposure = exposure(player_2, player_1) - exposure(player_1, player_2);
+    material - EXPOSURE_WEIGHT * exposure
+}
+
+/// Number of extra soldiers of `team` (beyond the first) that the best
+/// straight shot of any of the `enemies` would destroy
+pub fn exposure(team: &[Soldier], enemies: &[Soldier]) -> f32 {
+    let targets = team.iter().map(Target::from).collect::<Vec<_>>();
+    let mut worst = 0;
+    for shooter in enemies {
+        for target in &targets {
+            let from = shooter.graph_location();
+            worst = worst.max(straight_shot(from, target.location, &targets));
+        }
+    }
+    worst.saturating_sub(1) as f32
+}
+
+/// Number of `targets` hit by a straight shot from `from` through `through`
+fn straight_shot(from: Vec2, through: Vec2, targets: &[Target]) -> usize {
+    // Shots only travel to the right, so mirror the board if the target is on
+    // the left, like it is after the sides swap
+    let mirror = if through.x < from.x {
+        Vec2::new(-1., 1.)
+    } else {
+        Vec2::ONE
+    };
+    let (from, through) = (from * mirror, through * mirror);
+    if through.x - from.x < GRAPH_RES {
+        return 0;
+    }
+    let slope = (through.y - from.y) / (through.x - from.x);
+    let targets = targets
+        .iter()
+        .cloned()
+        .map(|target| Target {
+            location: target.location * mirror,
+            ..target
+        })
+        .collect();
+    ShotStepper::line(from, slope, targets).trace().hits.len()
+}
+
+fn mirrored(obstacles: &[Obstacle], mirror: Vec2) -> Vec<Obstacle> {
+    obstacles
+        .iter()
+        .cloned()
+        .map(|obstacle| Obstacle {
+            location: obstacle.location * mirror,
+            ..obstacle
+        })
+        .collect()
+}
+
+/// Mark which cells of a `THREAT_GRID_SIZE` by `THREAT_GRID_SIZE` grid over
+/// the board (row by row, from the bottom left) can be reached by straight
+/// shots from any of the `shooters`, who shoot towards the other side of the
+/// board, get in the way of `obstacles` and are bent by the `field`
+pub fn threat_map(
+    shooters: &[Soldier],
+    obstacles: &[Obstacle],
+    field: &[FieldModifier],
+) -> Vec<bool> {
+    let mut cells = vec![false; THREAT_GRID_SIZE * THREAT_GRID_SIZE];
+    let cell_size = 20. / THREAT_GRID_SIZE as f32;
+    for shooter in shooters {
+        let from = shooter.graph_location();
+        let mirror = if from.x > 0. {
+            Vec2::new(-1., 1.)
+        } else {
+            Vec2::ONE
+        };
+        let field = field
+            .iter()
+            .map(|i| if from.x > 0. { i.mirrored() } else { i.clone() })
+            .collect::<Vec<_>>();
+        for i in 0..THREAT_FAN_LINES {
+            // Fan out between (almost) straight down and straight up
+            let t = (i as f32 + 0.5) / THREAT_FAN_LINES as f32;
+            let angle = (t - 0.5) * std::f32::consts::PI;
+            let trace =
+                ShotStepper::line(from * mirror, angle.tan(), Vec::new())
+                    .with_obstacles(mirrored(obstacles, mirror))
+                    .with_field(field.clone())
+                    .trace();
+            for point in trace.points {
+                let cell = ((point * mirror + 10.) / cell_size).floor();
+                let (x, y) = (cell.x as usize, cell.y as usize);
+                if x < THREAT_GRID_SIZE && y < THREAT_GRID_SIZE {
+                    cells[y * THREAT_GRID_SIZE + x] = true;
+                }
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ObstacleKind, PlayerSelect};
+
+    fn soldier(player: PlayerSelect, id: u8, x: f32, y: f32) -> Soldier {
+        Soldier::new(player, id, Vec2::new(x, y))
+    }
+
+    #[test]
+    fn test_exposure() {
+        let shooter = [soldier(PlayerSelect::Player1, 0, -5., 0.)];
+        let lined_up = [
+            soldier(PlayerSelect::Player2, 0, 2., 0.),
+            soldier(PlayerSelect::Player2, 1, 6., 0.),
+        ];
+        let spread = [
+            soldier(PlayerSelect::Player2, 0, 2., 5.),
+            soldier(PlayerSelect::Player2, 1, 6., -3.),
+        ];
+        assert_eq!(exposure(&lined_up, &shooter), 1.);
+        assert_eq!(exposure(&spread, &shooter), 0.);
+
+        // The same holds with the sides swapped
+        let shooter = [soldier(PlayerSelect::Player1, 0, 5., 0.)];
+        let lined_up = [
+            soldier(PlayerSelect::Player2, 0, -2., 0.),
+            soldier(PlayerSelect::Player2, 1, -6., 0.),
+        ];
+        assert_eq!(exposure(&lined_up, &shooter), 1.);
+    }
+
+    #[test]
+    fn test_threat_map() {
+        let cell = |x: f32, y: f32| {
+            let size = 20. / THREAT_GRID_SIZE as f32;
+            let (x, y) =
+                (((x + 10.) / size) as usize, ((y + 10.) / size) as usize);
+            y * THREAT_GRID_SIZE + x
+        };
+        let shooter = [soldier(PlayerSelect::Player2, 0, 5., 0.)];
+        let map = threat_map(&shooter, &[], &[]);
+        // Shooting from the right, everything to the left is in reach...
+        assert!(map[cell(-9., 0.)]);
+        assert!(map[cell(-5., 8.)]);
+        assert!(map[cell(0., -9.)]);
+        // ...but nothing behind the shooter
+        assert!(!map[cell(8., 0.)]);
+
+        // Obstacles cast a shadow
+        let obstacle = Obstacle {
+            id: 0,
+            location: Vec2::new(0., 0.),
+            kind: ObstacleKind::Block { radius: 1. },
+            hp: None,
+        };
+        let map = threat_map(&shooter, &[obstacle], &[]);
+        assert!(map[cell(-9., 5.)]);
+        assert!(!map[cell(-9., 0.)]);
+    }
+}
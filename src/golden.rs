@@ -0,0 +1,179 @@
+//! Golden tests for the shot simulation: scripted equations are shot on boards
+//! generated from fixed seeds, and the soldiers they hit and the paths they
+//! take are compared with the results recorded in `tests/golden/shots.ron`.
+//!
+//! After an intended change to the simulation, record the results again with
+//! `GRAPHWARS_RECORD_GOLDEN=1 cargo test golden` and commit the new file.
+
+use crate::models::*;
+use crate::parse::ParsedFunction;
+use crate::sim::{ShotStepper, Step, Target, aim};
+use crate::util::fnv1a;
+use serde::{Deserialize, Serialize};
+
+const GOLDEN_FILE: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/shots.ron");
+
+/// Set to record the golden file instead of checking against it
+const RECORD_VAR: &str = "GRAPHWARS_RECORD_GOLDEN";
+
+/// A board generated from `seed`, and the equations the first soldier of
+/// player 1 shoots on it, each on the untouched board
+struct Scenario {
+    seed: u64,
+    soldiers: u8,
+    obstacles: u8,
+    gravity_wells: u8,
+    equations: &'static [&'static str],
+}
+
+const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        seed: 1,
+        soldiers: 3,
+        obstacles: 0,
+        gravity_wells: 0,
+        equations: &["0", "x", "-x", "x^2/10", "sin(x)"],
+    },
+    Scenario {
+        seed: 7,
+        soldiers: 5,
+        obstacles: 4,
+        gravity_wells: 0,
+        equations: &["0", "x/4", "3*sin(x/2)", "-x^2/20", "1/x"],
+    },
+    Scenario {
+        seed: 42,
+        soldiers: 4,
+        obstacles: 2,
+        gravity_wells: 2,
+        equations: &["0", "x/2", "2*sin(x+1)", "sqrt(x+12)", "exp(x/5)"],
+    },
+    Scenario {
+        seed: 1234,
+        soldiers: 6,
+        obstacles: 6,
+        gravity_wells: 3,
+        equations: &["0", "-x/3", "x^3/100", "ln(x+12)", "log10(x^2+1)"],
+    },
+];
+
+/// What came of one scripted shot
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Outcome {
+    seed: u64,
+    equation: String,
+    /// Soldiers hit, in order, like `p2#0`
+    hits: Vec<String>,
+    /// How the shot ended
+    end: String,
+    points: usize,
+    /// Hash of the points of the path, rounded to thousandths of a graph unit
+    path_hash: u64,
+}
+
+fn shoot(scenario: &Scenario, equation: &str) -> Outcome {
+    let SeededBoard {
+        soldiers: [player_1, player_2],
+        obstacles,
+        field,
+    } = SeededBoard::generate(
+        scenario.seed,
+        scenario.soldiers,
+        scenario.obstacles,
+        scenario.gravity_wells,
+    );
+    let from = player_1[0].graph_location();
+    let targets = player_2.iter().map(Target::from).collect();
+    let parsed = equation
+        .parse::<ParsedFunction>()
+        .unwrap_or_else(|e| panic!("{equation} doesn't parse: {e}"));
+    let mut outcome = Outcome {
+        seed: scenario.seed,
+        equation: equation.to_string(),
+        hits: Vec::new(),
+        end: String::new(),
+        points: 0,
+        path_hash: fnv1a(&[]),
+    };
+    let function = match aim(parsed, from) {
+        Ok(function) => function,
+        Err(x) => {
+            outcome.end = format!("failed at {x:.3}");
+            return outcome;
+        }
+    };
+    let trace = ShotStepper::new(function, from.x, targets)
+        .with_obstacles(obstacles)
+        .with_field(field)
+        .trace();
+    outcome.hits = (trace.hits.iter())
+        .map(|hit| {
+            let player = hit.id.player.index() + 1;
+            format!("p{player}#{}", hit.id.number)
+        })
+        .collect();
+    outcome.end = match trace.end {
+        Step::Blocked { obstacle, .. } => format!("blocked by {obstacle}"),
+        Step::Failed(x) => format!("failed at {x:.3}"),
+        Step::Fizzled(_) => "fizzled".to_string(),
+        _ => "done".to_string(),
+    };
+    outcome.points = trace.points.len();
+    let bytes = (trace.points.iter())
+        .flat_map(|point| [point.x, point.y])
+        .flat_map(|i| ((i * 1000.).round() as i32).to_le_bytes())
+        .collect::<Vec<_>>();
+    outcome.path_hash = fnv1a(&bytes);
+    outcome
+}
+
+fn outcomes() -> Vec<Outcome> {
+    SCENARIOS
+        .iter()
+        .flat_map(|scenario| {
+            scenario
+                .equations
+                .iter()
+                .map(move |equation| shoot(scenario, equation))
+        })
+        .collect()
+}
+
+/// Shoot every scenario and compare the outcomes with the golden file. To
+/// record the file again instead, after an intended change to the
+/// simulation, run `GRAPHWARS_RECORD_GOLDEN=1 cargo test golden`.
+#[test]
+fn test_golden_shots() {
+    let outcomes = outcomes();
+    if std::env::var_os(RECORD_VAR).is_some() {
+        let contents = ron::ser::to_string_pretty(
+            &outcomes,
+            ron::ser::PrettyConfig::new(),
+        )
+        .unwrap();
+        std::fs::create_dir_all(
+            std::path::Path::new(GOLDEN_FILE).parent().unwrap(),
+        )
+        .unwrap();
+        std::fs::write(GOLDEN_FILE, contents + "\n").unwrap();
+        return;
+    }
+    let contents = std::fs::read_to_string(GOLDEN_FILE).unwrap_or_else(|e| {
+        panic!(
+            "Couldn't read {GOLDEN_FILE} ({e}), record it with {RECORD_VAR}=1"
+        )
+    });
+    let golden: Vec<Outcome> = ron::from_str(&contents).unwrap();
+    assert_eq!(
+        golden.len(),
+        outcomes.len(),
+        "Scenarios changed, record again with {RECORD_VAR}=1"
+    );
+    for (expected, outcome) in golden.iter().zip(&outcomes) {
+        assert_eq!(
+            expected, outcome,
+            "Shot changed, record again with {RECORD_VAR}=1 if intended"
+        );
+    }
+}
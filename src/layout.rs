@@ -0,0 +1,244 @@
+//! Hand-made boards, such as fan levels or known test layouts, loaded from
+//! CSV files in the `layouts` folder of the data directory. Each line places
+//! a soldier or an obstacle, in graph units, and lines starting with `#` are
+//! comments:
+//!
+//! ```text
+//! # soldier,team,x,y
+//! soldier,1,-7.5,2
+//! soldier,2,6,-3.25
+//! # block,x,y,radius
+//! block,0,1.5,1.2
+//! # wall,x,y,half length
+//! wall,-1,-4,2
+//! # polygon,x,y,radius,sides,rotation in degrees
+//! polygon,2,5,1,5,90
+//! ```
+//!
+//! Team 1 starts on the left half of the board and team 2 on the right.
+//! Obstacles can't be destroyed.
+
+use crate::consts::*;
+use crate::models::{Obstacle, ObstacleKind, PlayerSelect, Soldier};
+use crate::puzzle::{Puzzle, verify};
+use bevy::math::Vec2;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// What a line of a layout file places
+enum Piece {
+    /// A soldier of the team with this index
+    Soldier(usize),
+    Obstacle(ObstacleKind),
+}
+
+/// A board read from a layout file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    /// Locations of each team's soldiers, in graph units
+    pub soldiers: [Vec<Vec2>; 2],
+    pub obstacles: Vec<Obstacle>,
+}
+
+impl Layout {
+    /// Read a layout file. Relative paths are looked up in the `layouts`
+    /// folder of the data directory.
+    pub fn load(file: &str) -> Result<Layout, LayoutError> {
+        let path = crate::util::data_dir().join("layouts").join(file.trim());
+        let contents =
+            std::fs::read_to_string(&path).map_err(|e| LayoutError::Read {
+                path,
+                error: e.to_string(),
+            })?;
+        Layout::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Layout, LayoutError> {
+        let mut soldiers = [Vec::new(), Vec::new()];
+        let mut obstacles = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+            let numbers = (fields[1..].iter())
+                .map(|field| {
+                    field.parse::<f32>().ok().filter(|i| i.is_finite())
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or(LayoutError::Syntax(line_number))?;
+            let (piece, location) = match (fields[0], &numbers[..]) {
+                ("soldier", &[team, x, y]) if team == 1. || team == 2. => {
+                    (Piece::Soldier(team as usize - 1), Vec2::new(x, y))
+                }
+                ("block", &[x, y, radius]) if radius > 0. => (
+                    Piece::Obstacle(ObstacleKind::Block { radius }),
+                    Vec2::new(x, y),
+                ),
+                ("wall", &[x, y, half_length]) if half_length > 0. => (
+                    Piece::Obstacle(ObstacleKind::BouncyWall { half_length }),
+                    Vec2::new(x, y),
+                ),
+                ("polygon", &[x, y, radius, sides, rotation])
+                    if radius > 0.
+                        && sides.fract() == 0.
+                        && (3. ..=f32::from(LAYOUT_MAX_SIDES))
+                            .contains(&sides) =>
+                {
+                    (
+                        Piece::Obstacle(ObstacleKind::Polygon {
+                            radius,
+                            sides: sides as u8,
+                            rotation: rotation.to_radians(),
+                        }),
+                        Vec2::new(x, y),
+                    )
+                }
+                _ => return Err(LayoutError::Syntax(line_number)),
+            };
+            if location.abs().max_element() > 10. {
+                return Err(LayoutError::OffBoard(line_number));
+            }
+            let kind = match piece {
+                Piece::Soldier(team) => {
+                    let on_own_side = match team {
+                        0 => location.x <= 0.,
+                        _ => location.x >= 0.,
+                    };
+                    if !on_own_side {
+                        return Err(LayoutError::WrongSide(line_number));
+                    }
+                    soldiers[team].push(location);
+                    continue;
+                }
+                Piece::Obstacle(kind) => kind,
+            };
+            obstacles.push(Obstacle {
+                id: obstacles.len() as u8,
+                location,
+                kind,
+                hp: None,
+            });
+        }
+        if soldiers
+            .iter()
+            .any(|team| !(1..=LAYOUT_MAX_SOLDIERS).contains(&team.len()))
+            || obstacles.len() > LAYOUT_MAX_OBSTACLES
+        {
+            return Err(LayoutError::TooMany);
+        }
+        Ok(Layout {
+            soldiers,
+            obstacles,
+        })
+    }
+
+    /// Soldiers of each player, to start a match on the layout
+    pub fn player_soldiers(&self) -> (Vec<Soldier>, Vec<Soldier>) {
+        let team = |player, locations: &[Vec2]| {
+            (locations.iter().enumerate())
+                .map(|(id, &location)| Soldier::new(player, id as u8, location))
+                .collect()
+        };
+        (
+            team(PlayerSelect::Player1, &self.soldiers[0]),
+            team(PlayerSelect::Player2, &self.soldiers[1]),
+        )
+    }
+
+    /// The layout as a puzzle, with team 1's only soldier as the shooter and
+    /// team 2's as the targets. It has to be solvable with a single shot.
+    pub fn puzzle(&self) -> Result<Puzzle, LayoutError> {
+        let [shooters, targets] = &self.soldiers;
+        let &[shooter] = &shooters[..] else {
+            return Err(LayoutError::NotAPuzzle);
+        };
+        if targets.len() > PUZZLE_MAX_TARGETS {
+            return Err(LayoutError::NotAPuzzle);
+        }
+        verify(shooter, targets.clone(), self.obstacles.clone())
+            .ok_or(LayoutError::Unsolvable)
+    }
+}
+
+/// Why a layout couldn't be loaded
+#[derive(Debug, Error, PartialEq)]
+pub enum LayoutError {
+    #[error("Couldn't read {}: {error}", .path.display())]
+    Read { path: PathBuf, error: String },
+    #[error(
+        "Line {0} isn't one of soldier,team,x,y or block,x,y,radius or \
+         wall,x,y,half length or polygon,x,y,radius,sides,rotation"
+    )]
+    Syntax(usize),
+    #[error("Line {0} places something off the board")]
+    OffBoard(usize),
+    #[error("Line {0} places a soldier on the other team's half")]
+    WrongSide(usize),
+    #[error(
+        "Layouts need 1 to {LAYOUT_MAX_SOLDIERS} soldiers on each team and at \
+         most {LAYOUT_MAX_OBSTACLES} obstacles"
+    )]
+    TooMany,
+    #[error(
+        "Puzzles need a single soldier on team 1 and at most \
+         {PUZZLE_MAX_TARGETS} on team 2"
+    )]
+    NotAPuzzle,
+    #[error("Layout can't be solved with a single shot")]
+    Unsolvable,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_layout() {
+        let layout = Layout::parse(
+            "# A known layout\n\
+             soldier,1,-7.5,2\n\
+             \n\
+             soldier, 2, 6, -3.25\n\
+             soldier,2,9,0\n\
+             block,0,1.5,1.2\n\
+             wall,-1,-4,2\n\
+             polygon,2,5,1,5,90\n",
+        )
+        .unwrap();
+        assert_eq!(layout.soldiers, [vec![Vec2::new(-7.5, 2.)], vec![
+            Vec2::new(6., -3.25),
+            Vec2::new(9., 0.)
+        ],]);
+        assert_eq!(layout.obstacles.len(), 3);
+        assert_eq!(layout.obstacles[1].kind, ObstacleKind::BouncyWall {
+            half_length: 2.
+        });
+        assert_eq!(layout.player_soldiers().1[1].number(), 1);
+
+        let error = |contents| Layout::parse(contents).unwrap_err();
+        assert_eq!(error("soldier,1,-1"), LayoutError::Syntax(1));
+        assert_eq!(error("#\nsoldier,3,1,1"), LayoutError::Syntax(2));
+        assert_eq!(error("tree,1,1,1"), LayoutError::Syntax(1));
+        assert_eq!(error("block,1,1,inf"), LayoutError::Syntax(1));
+        assert_eq!(error("polygon,1,1,1,2.5,0"), LayoutError::Syntax(1));
+        assert_eq!(error("soldier,1,-11,0"), LayoutError::OffBoard(1));
+        assert_eq!(error("soldier,1,4,0"), LayoutError::WrongSide(1));
+        assert_eq!(error("soldier,1,-4,0"), LayoutError::TooMany);
+    }
+
+    #[test]
+    fn test_layout_puzzle() {
+        let layout =
+            Layout::parse("soldier,1,-8,0\nsoldier,2,0,0\nsoldier,2,4,0")
+                .unwrap();
+        let puzzle = layout.puzzle().unwrap();
+        assert_eq!(puzzle.targets, layout.soldiers[1]);
+        let layout =
+            Layout::parse("soldier,1,-8,0\nsoldier,1,-4,0\nsoldier,2,4,0")
+                .unwrap();
+        assert_eq!(layout.puzzle().unwrap_err(), LayoutError::NotAPuzzle);
+    }
+}
@@ -0,0 +1,618 @@
+#![feature(let_chains)]
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::marker::PhantomData;
+
+mod models;
+use models::*;
+
+mod ui;
+use ui::ui_system;
+
+mod util;
+
+mod polyline;
+
+mod parse;
+
+mod editor;
+
+mod systems;
+use systems::ai::*;
+use systems::animation::*;
+use systems::announce::*;
+use systems::camera::*;
+use systems::demo::*;
+use systems::display::*;
+use systems::emotes::*;
+use systems::evaluation::*;
+use systems::feedback::*;
+use systems::graph_display::*;
+use systems::history::*;
+use systems::input::*;
+use systems::input_window::*;
+use systems::kiosk::*;
+use systems::mutators::*;
+use systems::observer::*;
+use systems::obstacles::*;
+use systems::overlay::*;
+use systems::parse_tree::*;
+use systems::preview::*;
+use systems::reminder::*;
+use systems::threat::*;
+use systems::util::*;
+
+mod consts;
+use consts::*;
+
+mod settings;
+use settings::*;
+
+mod packs;
+use packs::*;
+
+mod sim;
+
+mod eval;
+
+mod ai;
+
+mod net;
+use net::*;
+
+mod puzzle;
+
+mod rng;
+
+mod layout;
+
+mod correspondence;
+use correspondence::replay_turns;
+
+mod challenges;
+use challenges::*;
+
+mod tournament;
+use tournament::*;
+
+mod records;
+use records::*;
+
+mod summary;
+use summary::*;
+
+mod replay;
+use replay::*;
+
+mod save;
+
+mod drill;
+
+#[cfg(test)]
+mod golden;
+
+#[cfg(feature = "bench")]
+pub mod bench;
+
+#[cfg(feature = "platform")]
+mod platform;
+
+/// Run the game until its main window is closed
+pub fn run() {
+    let kiosk = KioskMode::from_args();
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        #[cfg(feature = "platform")]
+        primary_window: Some(platform::primary_window()),
+        // The separate input window shouldn't keep the game running
+        exit_condition: bevy::window::ExitCondition::OnPrimaryClosed,
+        // Shared machines in kiosk mode aren't to be quit
+        close_when_requested: !kiosk.0,
+        ..Default::default()
+    }))
+    .add_plugins(bevy_egui::EguiPlugin)
+    .insert_resource(Time::new(std::time::Instant::now()))
+    .insert_resource(InputCaptureState {
+        keyboard_captured: false,
+        pointer_captured: false,
+    })
+    .insert_resource(GameState::default())
+    .insert_resource(Settings::load())
+    .insert_resource(Records::load())
+    .insert_resource(Challenges::load())
+    .insert_resource(OverlayMode::from_args())
+    .insert_resource(kiosk)
+    .init_resource::<AssetPack>()
+    .init_resource::<ui::UiState>()
+    .init_resource::<CinematicFocus>()
+    .init_resource::<FreeCamera>()
+    .init_resource::<PowerSaving>()
+    .init_resource::<editor::EquationParams>()
+    .init_resource::<PracticePreview>()
+    .init_resource::<sim::SampleCache>()
+    .init_resource::<TurnHistory>()
+    .init_resource::<MatchSummary>()
+    .init_resource::<Chat>()
+    .init_resource::<AnnouncementBanner>()
+    .init_resource::<Evaluation>()
+    .init_resource::<ActiveTournament>()
+    .init_resource::<AiThinking>()
+    .init_resource::<NetLobby>()
+    .add_event::<Announce>()
+    .add_event::<MultiKill>()
+    .add_event::<ShotLanded>()
+    .add_event::<GameAction>()
+    .add_event::<StartPlaying>()
+    .add_event::<Resynced>()
+    .add_event::<NewWave>()
+    .add_event::<StartGraphingEvent>()
+    .add_event::<DoneGraphingEvent>()
+    .add_event::<SkipGraphingEvent>()
+    .add_event::<Damaged>()
+    .add_systems(
+        Startup,
+        (setup, spawn_pip_camera, spawn_announcement_region),
+    )
+    .add_systems(
+        Update,
+        (
+            capture_info,
+            (
+                (dispatch_input, dispatch_gamepad_input),
+                handle_game_actions,
+            )
+                .chain()
+                .after(capture_info)
+                .before(start_graphing),
+            (
+                record_turn.before(next_turn),
+                record_summary.before(next_turn),
+                record_replay.before(next_turn),
+                reset_graph,
+                next_turn,
+            )
+                .run_if(is_turn_over)
+                .after(update_turn_timer),
+            update_turn_timer,
+            finish_drawing_graph.run_if(currently_graphing),
+            update_turn.after(reset_graph).after(finish_drawing_graph),
+            start_graphing.after(update_turn),
+            ui_system.after(update_turn),
+            (run_tournament, attract_mode).before(ui_system),
+            (
+                start_playing.after(ui_system),
+                host_network_match.after(ui_system).before(start_playing),
+            ),
+            spawn_wave.after(next_turn),
+            (
+                ai_turns,
+                reconnect_network.after(ui_system).before(sync_network),
+                desync_ui.after(ui_system).before(sync_network),
+                sync_network.before(replay_turns),
+                respawn_match.after(sync_network),
+                replay_turns,
+                play_replay,
+            )
+                .after(update_turn)
+                .before(start_graphing),
+            (cleanup_match, restart_after_match.run_if(not(kiosk_off))),
+            manage_input_window,
+            load_asset_pack,
+            (
+                apply_display_settings.run_if(resource_changed::<Settings>),
+                remember_window_placement,
+            )
+                .chain(),
+            save_settings.run_if(
+                resource_changed::<Settings>
+                    .and(not(resource_added::<Settings>))
+                    .and(kiosk_off),
+            ),
+            update_records.after(update_turn),
+            save_records.run_if(
+                resource_changed::<Records>.and(not(resource_added::<Records>)),
+            ),
+            (
+                record_challenges,
+                save_challenges.run_if(
+                    resource_changed::<Challenges>
+                        .and(not(resource_added::<Challenges>)),
+                ),
+            )
+                .chain(),
+        ),
+    )
+    // Purely presentational systems
+    .add_systems(
+        Update,
+        (
+            (draw_graph, draw_ai_thinking.after(ai_turns)),
+            (lobby_ui, replay_ui).after(ui_system),
+            draw_team_patterns,
+            draw_field,
+            (show_mutators, show_roulette)
+                .run_if(overlay_off)
+                .after(ui_system),
+            (update_practice_preview, draw_practice_preview)
+                .chain()
+                .after(ui_system),
+            (
+                track_kills,
+                track_summary_hits,
+                history_ui.run_if(overlay_off),
+                draw_history,
+            )
+                .chain()
+                .after(ui_system),
+            (emotes_ui, parse_tree_ui)
+                .run_if(overlay_off)
+                .after(ui_system),
+            (
+                apply_overlay,
+                demo_banner,
+                update_active_outline.after(next_turn),
+            ),
+            stream_events.after(start_graphing),
+            face_soldiers,
+            (flinch_soldiers, animate_soldiers, draw_soldier_names).chain(),
+            (free_camera, cinematic_camera).chain().after(update_turn),
+            update_pip_camera.after(update_turn),
+            (
+                (detect_announcements, show_announcements, beep_low_time)
+                    .chain(),
+                remind_turn,
+            )
+                .after(update_turn),
+            (spawn_explosions, fade_explosions)
+                .chain()
+                .after(update_turn),
+            update_threat_overlay.after(update_turn),
+            (sync_obstacles, crumble_obstacles).chain().after(next_turn),
+            (update_evaluation, show_evaluation_bar.run_if(overlay_off))
+                .chain()
+                .after(update_turn),
+            (show_multi_kills, animate_floating_text)
+                .chain()
+                .after(update_turn)
+                .before(detect_announcements),
+        ),
+    )
+    .add_systems(Last, (update_power_saving, limit_frame_rate).chain());
+    #[cfg(feature = "platform")]
+    app.add_plugins(platform::PlatformPlugin);
+    app.run();
+}
+
+/// Tick the timer for the current turn (if one is active)
+fn update_turn_timer(mut state: ResMut<GameState>, time: Res<Time>) {
+    let Some(playing_state) = state.playing_state_mut() else {
+        return;
+    };
+    if let TurnPhase::ShowPhase(TurnShowPhase::Waiting { timer }) =
+        &mut playing_state.turn_phase_mut()
+    {
+        timer.tick(time.delta());
+    }
+}
+
+/// Send a `SkipGraphingEvent` if a player's turn has expired
+fn is_turn_over(
+    mut events: EventReader<SkipGraphingEvent>,
+    state: Res<GameState>,
+) -> bool {
+    let Some(playing_state) = state.playing_state() else {
+        return false;
+    };
+    events.read().next().is_some()
+        || match playing_state.turn_phase() {
+            TurnPhase::ShowPhase(TurnShowPhase::Waiting { timer }) => {
+                timer.finished()
+            }
+            _ => false,
+        }
+}
+
+/// Do the processes needed to switch the turns of the players, including:
+/// - Checking for a winner
+/// - Going to the next soldier for the current player
+/// - Switch the turn data
+/// - Swap the x coordinates of all soldiers
+/// - Spawn name of new player
+fn next_turn(
+    mut commands: Commands,
+    mut state: ResMut<GameState>,
+    mut soldiers: Query<(Entity, &mut Soldier, &mut Transform), With<Soldier>>,
+    mut animations: Query<&mut SoldierAnimation>,
+    mut scorches: Query<&mut Transform, (With<Scorch>, Without<Soldier>)>,
+    mut new_waves: EventWriter<NewWave>,
+) {
+    let Some(playing_state) = state.playing_state_mut() else {
+        return;
+    };
+
+    // See if somebody won and display that they did if so. The board stays
+    // up (with the winner cheering) until `cleanup_match` clears it.
+    let mut winner = playing_state
+        .get_winner()
+        .or_else(|| playing_state.check_turn_limit())
+        .or_else(|| playing_state.check_idle_forfeit())
+        .or_else(|| playing_state.check_puzzle());
+    // In horde mode, a destroyed horde is replaced by the next wave
+    if winner == Some(PlayerSelect::Player1) && playing_state.next_wave() {
+        new_waves.send(NewWave);
+        winner = None;
+    }
+    if let Some(winner) = winner {
+        state.set_finished(winner);
+        for (entity, soldier, _) in soldiers.iter() {
+            if soldier.player() == winner
+                && let Ok(mut animation) = animations.get_mut(entity)
+            {
+                animation.cheer();
+            }
+        }
+
+        return;
+    }
+
+    let graphed_player = playing_state.current_player_mut();
+
+    // Select the next soldier
+    graphed_player.next_soldier();
+
+    // Switch to the next player's turn
+    let graphed = playing_state.turn();
+    playing_state.next_turn();
+
+    // Move all soldiers, unless the next player starts on the same half, as
+    // happens in free-for-alls
+    if graphed.starts_left() != playing_state.turn().starts_left() {
+        for mut soldier in soldiers.iter_mut() {
+            soldier.2.translation.x *= -1.;
+            soldier.1.graph_location().x *= -1.;
+        }
+        for mut scorch in scorches.iter_mut() {
+            scorch.translation.x *= -1.;
+        }
+        playing_state.swap_sides();
+    }
+
+    // Update the turn phase
+    playing_state.begin_input_phase();
+
+    let next_player = playing_state.current_player_mut();
+
+    // Spawn the next player's name
+    commands.spawn((
+        Text2d::new(&next_player.name),
+        CurrentPlayerText,
+        MatchEntity,
+        Transform {
+            translation: Vec3::new(0., 300., PLAYER_NAME_Z),
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        },
+    ));
+}
+
+/// Despawn displays from currently graphed player
+fn reset_graph(
+    mut commands: Commands,
+    graph: Single<Entity, With<InProgressGraph>>,
+    player_name: Single<Entity, With<CurrentPlayerText>>,
+) {
+    commands.entity(*graph).despawn();
+    commands.entity(*player_name).despawn();
+}
+
+/// Despawn everything that belongs to a match once the game is back in the
+/// setup phase
+fn cleanup_match(
+    mut commands: Commands,
+    state: Res<GameState>,
+    entities: Query<Entity, With<MatchEntity>>,
+) {
+    if !matches!(state.game_phase(), GamePhaseNoData::Setup) {
+        return;
+    }
+    for entity in entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Event that triggers the game to start from the setup phase
+#[derive(Event)]
+struct StartPlaying;
+
+/// Resources needed to spawn the entities showing soldiers
+#[derive(SystemParam)]
+struct SoldierSpawnResources<'w, 's> {
+    asset_server: Res<'w, AssetServer>,
+    pack: Res<'w, AssetPack>,
+    atlas_layouts: ResMut<'w, Assets<TextureAtlasLayout>>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<ColorMaterial>>,
+    _phantom_data: PhantomData<&'s ()>,
+}
+
+/// Transition from a setup phase to a playing phase by changing the game state
+/// and spawning relevant entities
+fn start_playing(
+    mut events: EventReader<StartPlaying>,
+    mut state: ResMut<GameState>,
+    mut commands: Commands,
+    mut resources: SoldierSpawnResources,
+    mut ui_state: ResMut<ui::UiState>,
+) {
+    // Starting more than once in a frame would find the match started
+    if events.read().count() == 0 {
+        return;
+    }
+    // Say what is wrong with the setup next to the Start button
+    ui_state.setup_error = match ui_state.resume.take() {
+        Some(game) => state.resume(game).err(),
+        None => state.start_playing().err(),
+    };
+    if ui_state.setup_error.is_some() {
+        return;
+    }
+    let Some(playing_state) = state.playing_state() else {
+        unreachable!();
+    };
+    spawn_match(&mut commands, playing_state, &mut resources);
+}
+
+/// Sent when a network match carries on from the other side's copy of it
+#[derive(Event)]
+struct Resynced;
+
+/// Show the match afresh once it was replaced by the other side's copy.
+/// Obstacles are left to follow it by themselves.
+fn respawn_match(
+    mut events: EventReader<Resynced>,
+    state: Res<GameState>,
+    mut commands: Commands,
+    mut resources: SoldierSpawnResources,
+    entities: Query<Entity, (With<MatchEntity>, Without<ObstacleDisplay>)>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+    let Some(playing_state) = state.playing_state() else {
+        return;
+    };
+    for entity in entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_match(&mut commands, playing_state, &mut resources);
+}
+
+/// Spawn the entities a match starts out with: the board, the soldiers and
+/// the name of the player whose turn it is
+fn spawn_match(
+    commands: &mut Commands,
+    playing_state: &PlayPhase,
+    resources: &mut SoldierSpawnResources,
+) {
+    commands.spawn((
+        Mesh2d(resources.meshes.add(Rectangle::new(440., 440.))),
+        MeshMaterial2d(resources.materials.add(Color::WHITE)),
+        Transform {
+            translation: Vec3::new(0., 0., GRID_BACKGROUND_Z),
+            ..Default::default()
+        },
+        GridBackground,
+        MatchEntity,
+    ));
+    spawn_soldiers(
+        commands,
+        playing_state.soldiers(),
+        playing_state,
+        resources,
+    );
+
+    commands.spawn((
+        Text2d::new(&playing_state.current_player().name),
+        CurrentPlayerText,
+        MatchEntity,
+        Transform {
+            translation: Vec3::new(0., 300., PLAYER_NAME_Z),
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        },
+    ));
+}
+
+/// Sent when a new horde wave has replaced player 2's soldiers
+#[derive(Event)]
+struct NewWave;
+
+/// Spawn the soldiers of each new horde wave
+fn spawn_wave(
+    mut events: EventReader<NewWave>,
+    state: Res<GameState>,
+    mut commands: Commands,
+    mut resources: SoldierSpawnResources,
+    mut announcements: EventWriter<Announce>,
+) {
+    if events.read().next().is_none() {
+        return;
+    }
+    let Some(playing_state) = state.playing_state() else {
+        return;
+    };
+    let horde = playing_state.player(PlayerSelect::Player2).soldiers();
+    spawn_soldiers(&mut commands, horde.iter(), playing_state, &mut resources);
+    if let Some(score) = playing_state.horde_score() {
+        announcements.send(Announce(format!("Wave {}!", score.wave)));
+    }
+}
+
+/// Spawn the entities showing `soldiers`
+fn spawn_soldiers<'a>(
+    commands: &mut Commands,
+    soldiers: impl Iterator<Item = &'a Soldier>,
+    playing_state: &PlayPhase,
+    resources: &mut SoldierSpawnResources,
+) {
+    let pack = &resources.pack;
+    let materials = PLAYER_COLORS.map(|color| resources.materials.add(color));
+    let mesh = resources.meshes.add(Circle::new(SOLDIER_RADIUS));
+
+    for soldier in soldiers {
+        let pos = soldier.graph_location() * 20.;
+        let translation = Vec3::new(pos.x, pos.y, SOLDIER_Z);
+        let transform = Transform {
+            translation,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        };
+        let player = soldier.player().index();
+        let skin = playing_state.players()[player].skin;
+        let phase = soldier.number() as f32 * 1.7;
+        let Some(sprite_key) = skin.sprite_key() else {
+            commands.spawn((
+                SoldierBundle {
+                    soldier: soldier.clone(),
+                    transform,
+                    mesh: Mesh2d(mesh.clone()),
+                    material: MeshMaterial2d(materials[player].clone()),
+                },
+                SoldierAnimation::new(phase, None),
+                MatchEntity,
+            ));
+            continue;
+        };
+        let mut sprite = Sprite {
+            image: resources.asset_server.load(pack.soldier_icon(sprite_key)),
+            color: PLAYER_COLORS[player],
+            custom_size: Some(Vec2::splat(SOLDIER_SPRITE_SIZE)),
+            flip_x: pos.x > 0.,
+            ..Default::default()
+        };
+        let mut frames = None;
+        if let Some((path, sheet)) = pack.soldier_sheet(sprite_key) {
+            let layout = TextureAtlasLayout::from_grid(
+                UVec2::new(sheet.tile_size.0, sheet.tile_size.1),
+                sheet.columns,
+                sheet.rows,
+                None,
+                None,
+            );
+            sprite.image = resources.asset_server.load(path);
+            sprite.texture_atlas = Some(TextureAtlas {
+                layout: resources.atlas_layouts.add(layout),
+                index: sheet.idle.0,
+            });
+            frames = Some(sheet.into());
+        }
+        commands.spawn((
+            soldier.clone(),
+            transform,
+            sprite,
+            SoldierAnimation::new(phase, frames),
+            MatchEntity,
+        ));
+    }
+}
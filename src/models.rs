@@ -1,10 +1,136 @@
+use crate::consts::*;
+use crate::correspondence::{Board, Correspondence, TurnFile};
+use crate::layout::Layout;
+use crate::net::NetworkSide;
+use crate::parse::{ParsedFunction, Restriction, RestrictionError};
+use crate::puzzle::{Difficulty, Puzzle};
+use crate::replay::{Replay, ReplaySetup};
+use crate::rng::{MatchRng, RngStream};
+use crate::sim::FieldModifier;
+use crate::util::fnv1a;
 use bevy::prelude::*;
-use std::{num::NonZeroU8, sync::Arc, time::Duration};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    num::{NonZeroU8, NonZeroU32},
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PlayerSelect {
     Player1,
     Player2,
+    Player3,
+    Player4,
+}
+
+impl PlayerSelect {
+    /// Every player, in turn order. Only free-for-all matches have more
+    /// than the first two.
+    pub const ALL: [PlayerSelect; MAX_PLAYERS] = [
+        PlayerSelect::Player1,
+        PlayerSelect::Player2,
+        PlayerSelect::Player3,
+        PlayerSelect::Player4,
+    ];
+    /// 0 for player 1, 1 for player 2 and so on, for per-player arrays
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+    pub fn from_index(index: usize) -> Option<PlayerSelect> {
+        PlayerSelect::ALL.get(index).copied()
+    }
+    /// Whether the player's soldiers start on the left half of the board.
+    /// Players in turn order alternate between the halves.
+    pub fn starts_left(&self) -> bool {
+        self.index() % 2 == 0
+    }
+}
+
+/// How a player's soldiers are drawn. This is purely cosmetic; every skin
+/// uses the same circular hitbox of `SOLDIER_RADIUS`.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum SoldierSkin {
+    #[default]
+    Circle,
+    Tank,
+    Wizard,
+    StickFigure,
+}
+
+impl SoldierSkin {
+    pub const ALL: [SoldierSkin; 4] = [
+        SoldierSkin::Circle,
+        SoldierSkin::Tank,
+        SoldierSkin::Wizard,
+        SoldierSkin::StickFigure,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            SoldierSkin::Circle => "Circle",
+            SoldierSkin::Tank => "Tank",
+            SoldierSkin::Wizard => "Wizard",
+            SoldierSkin::StickFigure => "Stick figure",
+        }
+    }
+    /// Key used to look up the sprite in an asset pack, or `None` for the
+    /// plain circle which isn't drawn from an image
+    pub fn sprite_key(&self) -> Option<&'static str> {
+        match self {
+            SoldierSkin::Circle => None,
+            SoldierSkin::Tank => Some("tank"),
+            SoldierSkin::Wizard => Some("wizard"),
+            SoldierSkin::StickFigure => Some("stick_figure"),
+        }
+    }
+}
+
+/// Which controls a player takes their turns with, so that players sharing a
+/// machine can each keep to their own. Equations are typed and clicks made
+/// with the one keyboard and mouse either way.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum InputDevice {
+    /// Every keyboard key and gamepad
+    #[default]
+    Any,
+    /// Number keys 1-4 select soldiers and space shoots
+    KeyboardLeft,
+    /// Number pad keys 1-4 select soldiers and enter shoots
+    KeyboardRight,
+    /// The gamepad with this number, counting from 0 in the order the game
+    /// found them. The d-pad selects soldiers and the south button shoots.
+    Gamepad(u8),
+}
+
+impl InputDevice {
+    /// Every device a player can be given
+    pub fn all() -> impl Iterator<Item = InputDevice> {
+        [
+            InputDevice::Any,
+            InputDevice::KeyboardLeft,
+            InputDevice::KeyboardRight,
+        ]
+        .into_iter()
+        .chain((0..MAX_PLAYERS as u8).map(InputDevice::Gamepad))
+    }
+    pub fn name(&self) -> String {
+        match self {
+            InputDevice::Any => "Any".to_string(),
+            InputDevice::KeyboardLeft => "Left of keyboard".to_string(),
+            InputDevice::KeyboardRight => "Right of keyboard".to_string(),
+            InputDevice::Gamepad(number) => format!("Gamepad {}", number + 1),
+        }
+    }
+    /// Whether a player with this device takes input from `source`. Input
+    /// that could come from any device, like mouse clicks, is from `Any`.
+    pub fn accepts(&self, source: InputDevice) -> bool {
+        *self == InputDevice::Any
+            || source == InputDevice::Any
+            || *self == source
+    }
 }
 
 #[derive(Resource, Default)]
@@ -12,7 +138,7 @@ pub struct GameState(GamePhase);
 
 enum GamePhase {
     Setup(SetupPhase),
-    Playing(PlayPhase),
+    Playing(Box<PlayPhase>),
     GameFinished(FinishedPhase),
 }
 
@@ -36,7 +162,40 @@ impl GameState {
         }
     }
     pub fn set_finished(&mut self, winner: PlayerSelect) {
-        self.0 = GamePhase::GameFinished(FinishedPhase { winner });
+        let horde = self
+            .playing_state()
+            .and_then(|state| state.horde.as_ref())
+            .map(|horde| horde.score);
+        let puzzle =
+            self.playing_state().and_then(|state| state.puzzle.clone());
+        // The opponent still needs to see the winning shot if it was taken
+        // on this side
+        let turn_code = self.playing_state().and_then(|state| {
+            let correspondence = state.correspondence.as_ref()?;
+            (state.turn == correspondence.local)
+                .then(|| correspondence.outgoing.clone())
+                .flatten()
+        });
+        let winner_name = self
+            .playing_state()
+            .map(|state| state.player(winner).name.clone())
+            .unwrap_or_default();
+        let rng = self.playing_state().map(|state| state.rng.clone());
+        let replay = (self.playing_state_mut())
+            .and_then(|state| state.replay.take())
+            .map(|mut replay| {
+                replay.finish(&winner_name);
+                replay
+            });
+        self.0 = GamePhase::GameFinished(FinishedPhase {
+            winner,
+            winner_name,
+            horde,
+            puzzle,
+            turn_code,
+            rng,
+            replay,
+        });
     }
     pub fn setup_state(&self) -> Option<&SetupPhase> {
         match self.0 {
@@ -44,38 +203,187 @@ impl GameState {
             _ => None,
         }
     }
+    pub fn set_setup(&mut self, setup: SetupPhase) {
+        self.0 = GamePhase::Setup(setup);
+    }
     pub fn setup_state_mut(&mut self) -> Option<&mut SetupPhase> {
         match self.0 {
             GamePhase::Setup(ref mut state) => Some(state),
             _ => None,
         }
     }
-    pub fn start_playing(&mut self) -> Result<(), ()> {
+    pub fn start_playing(&mut self) -> Result<(), SetupError> {
         let Some(setup_state) = self.setup_state() else {
-            return Err(());
+            return Err(SetupError::NotInSetup);
         };
-        let soldiers = (
-            gen_soldiers(
-                PlayerSelect::Player1,
-                setup_state.player_1.soldier_num.into(),
-            ),
-            gen_soldiers(
-                PlayerSelect::Player2,
-                setup_state.player_2.soldier_num.into(),
+        // A replay is watched by setting its match up again
+        let watched = match setup_state.mode {
+            MatchMode::Replay => {
+                Some(setup_state.replay.clone().ok_or(SetupError::NoReplay)?)
+            }
+            _ => None,
+        };
+        let replayed = (watched.as_ref())
+            .map(|replay| replay.header.setup.apply(setup_state));
+        let setup_state = replayed.as_ref().unwrap_or(setup_state);
+        setup_state.check()?;
+        let horde = setup_state.mode == MatchMode::Horde;
+        let [config_1, config_2, more_configs @ ..] = &setup_state.players[..]
+        else {
+            return Err(SetupError::PlayerCount);
+        };
+        // Players beyond the first two only join free-for-all matches
+        let more_configs = match setup_state.mode {
+            MatchMode::Versus => more_configs,
+            _ => &[],
+        };
+        let soldier_nums = if horde {
+            // Both players' soldiers form one team against the horde
+            let team = config_1.soldier_num.get() + config_2.soldier_num.get();
+            (team, HORDE_FIRST_WAVE)
+        } else {
+            (config_1.soldier_num.get(), config_2.soldier_num.get())
+        };
+        // Both sides of a network match generate the board from the seed the
+        // host picked
+        let network = match (setup_state.mode, setup_state.network) {
+            (MatchMode::Network, None) => return Err(SetupError::NotConnected),
+            (MatchMode::Network, side) => side,
+            _ => None,
+        };
+        // Everything random about the match comes from its seed
+        let seed = (network.map(|i| i.seed))
+            .or(watched.as_ref().map(|replay| replay.header.setup.seed))
+            .unwrap_or_else(|| rand::thread_rng().r#gen());
+        let recorded = (watched.is_none())
+            .then(|| ReplaySetup::of(setup_state, seed))
+            .flatten();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let puzzle = (setup_state.mode == MatchMode::Puzzle).then(|| {
+            setup_state.custom_puzzle.clone().unwrap_or_else(|| {
+                crate::puzzle::generate(&mut rng, setup_state.puzzle_difficulty)
+            })
+        });
+        let correspondence = setup_state.mode == MatchMode::Correspondence;
+        // Joining a correspondence match starts from the board of the
+        // opponent's first turn, which is then replayed
+        let join = setup_state.join_turn.clone().filter(|_| correspondence);
+        // Layouts have two teams, so they aren't used for free-for-alls
+        let layout = (setup_state.layout.as_ref()).filter(|_| {
+            setup_state.mode == MatchMode::Versus && more_configs.is_empty()
+        });
+        let soldiers = match (&puzzle, &join) {
+            (Some(puzzle), _) => puzzle.soldiers(),
+            (None, Some(turn)) => (
+                turn.board.player_soldiers(PlayerSelect::Player1),
+                turn.board.player_soldiers(PlayerSelect::Player2),
             ),
-        );
-        let player_1 = PlayerState::new(
-            setup_state.player_1.name.clone(),
+            (None, None) => match layout {
+                Some(layout) => layout.player_soldiers(),
+                None => (
+                    gen_soldiers(
+                        &mut rng,
+                        PlayerSelect::Player1,
+                        soldier_nums.0,
+                    ),
+                    gen_soldiers(
+                        &mut rng,
+                        PlayerSelect::Player2,
+                        soldier_nums.1,
+                    ),
+                ),
+            },
+        };
+        // Puzzles bring their own obstacles, and are otherwise solved on a
+        // plain board, as that's what the search checks them on.
+        // Correspondence matches are plain too, as all that is sent between
+        // the players is the soldiers.
+        let plain = puzzle.is_some() || correspondence;
+        for (number, team) in [(1, &soldiers.0), (2, &soldiers.1)] {
+            if team.is_empty() {
+                return Err(SetupError::NoSoldiers(number));
+            }
+        }
+        let mut more_players = Vec::with_capacity(more_configs.len());
+        let mut placed = [soldiers.0.clone(), soldiers.1.clone()].concat();
+        for (&player, config) in PlayerSelect::ALL[2..].iter().zip(more_configs)
+        {
+            let team = gen_soldiers_avoiding(
+                &mut rng,
+                player,
+                config.soldier_num.get(),
+                &placed,
+            );
+            placed.extend(team.iter().cloned());
+            let mut player =
+                PlayerState::new(config.name.clone(), config.skin, team);
+            player.input = config.input;
+            more_players.push(player);
+        }
+        let mut player_1 = PlayerState::new(
+            config_1.name.clone(),
+            config_1.skin,
             soldiers.0.clone(),
         );
-        let player_2 = PlayerState::new(
-            setup_state.player_2.name.clone(),
+        let mut player_2 = PlayerState::new(
+            config_2.name.clone(),
+            config_2.skin,
             soldiers.1.clone(),
         );
+        player_1.input = config_1.input;
+        player_2.input = config_2.input;
+        if horde {
+            player_2.name = "Horde".to_string();
+            player_2.ai = true;
+        } else if puzzle.is_some() {
+            player_2.name = "Targets".to_string();
+        } else if let Some(turn) = &join {
+            [player_1.name, player_2.name] = turn.board.names.clone();
+        } else if let Some(level) = setup_state.computer
+            && setup_state.mode == MatchMode::Versus
+        {
+            player_2.ai = true;
+            player_2.ai_level = Some(level.clamp(1, MAX_AI_LEVEL));
+        }
+        if setup_state.demo {
+            player_1.ai = true;
+            player_2.ai = true;
+        }
+        let obstacles = match (&puzzle, layout) {
+            (Some(puzzle), _) => puzzle.obstacles.clone(),
+            (None, _) if plain => Vec::new(),
+            (None, Some(layout)) => layout.obstacles.clone(),
+            (None, None) => {
+                gen_obstacles(&mut rng, setup_state.obstacles, placed.iter())
+            }
+        };
+        let next_obstacle_id = next_obstacle_id(&obstacles);
+        let gravity_wells = if plain { 0 } else { setup_state.gravity_wells };
+        let turn = join
+            .as_ref()
+            .map_or(PlayerSelect::Player1, |i| i.board.turn);
+        let correspondence = correspondence.then(|| Correspondence {
+            match_id: join.as_ref().map_or_else(|| rng.r#gen(), |i| i.match_id),
+            local: match (&join, turn) {
+                (Some(_), PlayerSelect::Player1) => PlayerSelect::Player2,
+                _ => PlayerSelect::Player1,
+            },
+            outgoing: None,
+            incoming: join.clone(),
+            rejected: None,
+            networked: false,
+            skipped: false,
+        });
+        // Network matches send the same turn codes, just without the players
+        // copying them
+        let correspondence =
+            correspondence.or(network.map(Correspondence::networked));
         let playing_state = PlayPhase {
-            player_1,
-            player_2,
-            turn: PlayerSelect::Player1,
+            players: [player_1, player_2]
+                .into_iter()
+                .chain(more_players)
+                .collect(),
+            turn,
             turn_phase: TurnPhase::InputPhase {
                 timer: Timer::new(
                     Duration::from_secs(setup_state.turn_seconds.into()),
@@ -83,10 +391,65 @@ impl GameState {
                 ),
             },
             turn_length: Duration::from_secs(setup_state.turn_seconds.into()),
+            practice: setup_state.practice,
+            aim_preview: setup_state.aim_preview,
+            blitz: setup_state.blitz,
+            obstacles,
+            field: gen_gravity_wells(&mut rng, gravity_wells),
+            roulette: (setup_state.roulette && !plain).then(|| Roulette {
+                picks: Vec::new(),
+                walls: Vec::new(),
+                base_field: usize::from(gravity_wells),
+            }),
+            restriction: setup_state.restriction,
+            budget: setup_state.budget,
+            // Puzzles and correspondence matches are played without one, as
+            // the shots of both are checked and replayed on a plain board
+            max_shot_length: setup_state.max_shot_length.filter(|_| !plain),
+            energy: setup_state.energy,
+            cooldown: setup_state.cooldown,
+            turn_number: join.as_ref().map_or(1, |i| i.board.turn_number),
+            max_turns: setup_state.max_turns.filter(|_| !plain),
+            idle_forfeit: setup_state
+                .idle_forfeit
+                .filter(|_| network.is_some()),
+            sudden_death: None,
+            horde: horde.then(|| Horde {
+                teammates: [config_1.name.clone(), config_2.name.clone()],
+                human_turns: 0,
+                score: HordeScore { wave: 1, kills: 0 },
+            }),
+            puzzle,
+            correspondence,
+            demo: setup_state.demo,
+            replay: None,
+            watching: watched,
+            rng: MatchRng::new(seed),
+            registry: SoldierRegistry::new(&placed),
+            next_obstacle_id,
         };
+        let mut playing_state = Box::new(playing_state);
+        playing_state.spin_roulette();
+        let mutators = playing_state.mutators();
+        playing_state.replay =
+            recorded.map(|setup| Replay::new(setup, mutators));
         self.0 = GamePhase::Playing(playing_state);
         Ok(())
     }
+    /// Resume a saved match in place of the one being set up
+    pub fn resume(&mut self, game: Box<PlayPhase>) -> Result<(), SetupError> {
+        if self.setup_state().is_none() {
+            return Err(SetupError::NotInSetup);
+        }
+        self.0 = GamePhase::Playing(game);
+        Ok(())
+    }
+    pub fn finished_state(&self) -> Option<&FinishedPhase> {
+        match self.0 {
+            GamePhase::GameFinished(ref state) => Some(state),
+            _ => None,
+        }
+    }
     pub fn finished_state_mut(&mut self) -> Option<&mut FinishedPhase> {
         match self.0 {
             GamePhase::GameFinished(ref mut state) => Some(state),
@@ -105,291 +468,1572 @@ impl GameState {
 impl Default for GamePhase {
     fn default() -> Self {
         Self::Setup(SetupPhase {
-            player_1: PlayerConfig {
-                soldier_num: NonZeroU8::new(1).unwrap(),
-                name: "Player 1".to_string(),
-            },
-            player_2: PlayerConfig {
-                soldier_num: NonZeroU8::new(1).unwrap(),
-                name: "Player 2".to_string(),
-            },
+            players: vec![PlayerConfig::new(1), PlayerConfig::new(2)],
             turn_seconds: 60,
+            practice: false,
+            aim_preview: true,
+            max_turns: None,
+            blitz: None,
+            obstacles: 0,
+            gravity_wells: 0,
+            roulette: false,
+            restriction: Restriction::default(),
+            budget: None,
+            max_shot_length: None,
+            energy: false,
+            cooldown: None,
+            mode: MatchMode::default(),
+            puzzle_difficulty: Difficulty::default(),
+            custom_puzzle: None,
+            join_turn: None,
+            layout: None,
+            network: None,
+            idle_forfeit: NonZeroU8::new(DEFAULT_IDLE_FORFEIT_TURNS),
+            computer: None,
+            demo: false,
+            replay: None,
         })
     }
 }
 
+#[derive(Clone)]
 pub struct SetupPhase {
-    pub player_1: PlayerConfig,
-    pub player_2: PlayerConfig,
+    /// Two players, or up to `MAX_PLAYERS` for a free-for-all. Only versus
+    /// matches use more than the first two.
+    pub players: Vec<PlayerConfig>,
     pub turn_seconds: u32,
+    /// Casual match with learning aids such as a live preview of the shot
+    pub practice: bool,
+    /// Preview the first bit of each shot before it is fired
+    pub aim_preview: bool,
+    /// Number of turns (of either player) after which the match is decided
+    /// by the soldiers left, if limited
+    pub max_turns: Option<NonZeroU32>,
+    pub blitz: Option<Blitz>,
+    /// Number of obstacles placed between the players
+    pub obstacles: u8,
+    /// Number of gravity wells bending shots between the players
+    pub gravity_wells: u8,
+    /// Pick new random mutators at the start of every round
+    pub roulette: bool,
+    /// Limit on the functions players may shoot
+    pub restriction: Restriction,
+    /// Most points an equation may cost, if limited
+    pub budget: Option<u32>,
+    /// Longest path a shot may travel before it fizzles, in graph units, if
+    /// limited
+    pub max_shot_length: Option<u32>,
+    /// Bank the range each shot leaves unused as energy, which lengthens the
+    /// player's later shots
+    pub energy: bool,
+    /// Number of their own turns before a player may shoot the same equation
+    /// again, if repeats are limited
+    pub cooldown: Option<NonZeroU8>,
+    pub mode: MatchMode,
+    pub puzzle_difficulty: Difficulty,
+    /// Puzzle loaded from a sharing code, played instead of a generated one
+    pub custom_puzzle: Option<Puzzle>,
+    /// Opponent's first turn of a correspondence match being joined
+    pub join_turn: Option<TurnFile>,
+    /// Board loaded from a layout file, played instead of a random one in
+    /// versus matches
+    pub layout: Option<Box<Layout>>,
+    /// Side and board seed of a network match, set once the host starts it
+    pub network: Option<NetworkSide>,
+    /// Number of turns in a row a player of a network match can let run out
+    /// before they forfeit, if limited. Turns that run out are skipped.
+    pub idle_forfeit: Option<NonZeroU8>,
+    /// Difficulty of the computer playing as player 2 in versus matches, from
+    /// 1 to `MAX_AI_LEVEL`, if a computer plays for them
+    pub computer: Option<u8>,
+    /// Computer-controlled match on both sides, played while the menu is
+    /// left idle
+    pub demo: bool,
+    /// Replay loaded to be watched, in replay mode
+    pub replay: Option<Arc<Replay>>,
 }
 
+impl SetupPhase {
+    /// Check the parts of the setup that can be set to something that can't
+    /// be played
+    pub fn check(&self) -> Result<(), SetupError> {
+        let joining =
+            self.mode == MatchMode::Correspondence && self.join_turn.is_some();
+        if !(2..=MAX_PLAYERS).contains(&self.players.len()) {
+            return Err(SetupError::PlayerCount);
+        }
+        // Puzzle targets and joined matches don't use player 2's name
+        let named = match self.mode {
+            MatchMode::Versus => self.players.len(),
+            MatchMode::Puzzle => 1,
+            _ if joining => 0,
+            _ => 2,
+        };
+        for (number, player) in (1..).zip(&self.players).take(named) {
+            if player.name.trim().is_empty() {
+                return Err(SetupError::EmptyName(number));
+            }
+        }
+        if !(MIN_TURN_SECONDS..=MAX_TURN_SECONDS).contains(&self.turn_seconds) {
+            return Err(SetupError::TurnLength);
+        }
+        if self.obstacles > MAX_OBSTACLES
+            || self.gravity_wells > MAX_GRAVITY_WELLS
+        {
+            return Err(SetupError::Board);
+        }
+        Ok(())
+    }
+}
+
+/// Why a match couldn't be started from the setup
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum SetupError {
+    #[error("The match has already started")]
+    NotInSetup,
+    #[error("Matches are played by 2 to {MAX_PLAYERS} players")]
+    PlayerCount,
+    #[error(
+        "Host a network match and wait for an opponent to connect, or join \
+         one and wait for the host to start"
+    )]
+    NotConnected,
+    #[error("Load a replay to watch")]
+    NoReplay,
+    #[error("Player {0} needs a name")]
+    EmptyName(u8),
+    #[error("Player {0} needs at least one soldier")]
+    NoSoldiers(u8),
+    #[error(
+        "Turns have to be {MIN_TURN_SECONDS} to {MAX_TURN_SECONDS} seconds long"
+    )]
+    TurnLength,
+    #[error(
+        "Boards can have at most {MAX_OBSTACLES} obstacles and \
+         {MAX_GRAVITY_WELLS} gravity wells"
+    )]
+    Board,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    /// Player 1 against player 2, or a free-for-all of up to
+    /// `MAX_PLAYERS`
+    #[default]
+    Versus,
+    /// Both players on one team, taking turns against waves of computer
+    /// controlled soldiers
+    Horde,
+    /// Hit every target with a single shot
+    Puzzle,
+    /// Player 1 against player 2 on separate machines, sending each other
+    /// their turns as codes
+    Correspondence,
+    /// Player 1 against player 2 on separate machines connected over the
+    /// network, which sends the turns as they are taken
+    Network,
+    /// Watch a saved match play out again
+    Replay,
+}
+
+impl MatchMode {
+    pub const ALL: [MatchMode; 6] = [
+        MatchMode::Versus,
+        MatchMode::Horde,
+        MatchMode::Puzzle,
+        MatchMode::Correspondence,
+        MatchMode::Network,
+        MatchMode::Replay,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            MatchMode::Versus => "Versus",
+            MatchMode::Horde => "Co-op against the horde",
+            MatchMode::Puzzle => "Single-shot puzzle",
+            MatchMode::Correspondence => "Correspondence (turns as codes)",
+            MatchMode::Network => "Network (host or join)",
+            MatchMode::Replay => "Watch a replay",
+        }
+    }
+}
+
+/// State of a co-op match against waves of computer controlled soldiers,
+/// who play as player 2
+#[derive(Serialize, Deserialize)]
+pub struct Horde {
+    /// Names of the two human players, who take turns shooting for player 1
+    teammates: [String; 2],
+    /// Turns player 1 has had so far
+    human_turns: u32,
+    score: HordeScore,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HordeScore {
+    /// Number of the current wave, counting from 1
+    pub wave: u32,
+    /// Horde soldiers destroyed
+    pub kills: u32,
+}
+
+/// Pacing option that shortens the turns after every round (a turn of each
+/// player), to push long matches toward a conclusion
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Blitz {
+    /// Seconds taken off the turn length each round
+    pub step_seconds: u32,
+    /// Turns never get shorter than this many seconds
+    pub floor_seconds: u32,
+}
+
+impl Default for Blitz {
+    fn default() -> Self {
+        Self {
+            step_seconds: 5,
+            floor_seconds: 15,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct PlayerConfig {
     pub soldier_num: NonZeroU8,
     pub name: String,
+    pub skin: SoldierSkin,
+    pub input: InputDevice,
 }
 
+impl PlayerConfig {
+    /// Default setup of the player with this number, counting from 1
+    pub fn new(number: usize) -> Self {
+        Self {
+            soldier_num: NonZeroU8::new(1).unwrap(),
+            name: format!("Player {number}"),
+            skin: SoldierSkin::default(),
+            input: InputDevice::default(),
+        }
+    }
+}
+
+/// A match being played. Matches can be saved between shots and resumed,
+/// except correspondence and network matches, which are kept by the turn
+/// codes the players send each other, and replays and demo matches, which
+/// play themselves.
+#[derive(Serialize, Deserialize)]
 pub struct PlayPhase {
-    player_1: PlayerState,
-    player_2: PlayerState,
+    /// Every player of the match, in turn order, indexed by
+    /// `PlayerSelect::index`
+    players: Vec<PlayerState>,
     turn: PlayerSelect,
     turn_phase: TurnPhase,
+    /// Length of the first turns, before blitz shortens them
     turn_length: Duration,
+    blitz: Option<Blitz>,
+    obstacles: Vec<Obstacle>,
+    /// Modifiers bending the shots, such as gravity wells
+    field: Vec<FieldModifier>,
+    roulette: Option<Roulette>,
+    restriction: Restriction,
+    budget: Option<u32>,
+    max_shot_length: Option<u32>,
+    energy: bool,
+    cooldown: Option<NonZeroU8>,
+    practice: bool,
+    aim_preview: bool,
+    /// Number of the current turn, counting every player's turns from 1
+    turn_number: u32,
+    max_turns: Option<NonZeroU32>,
+    /// Turns in a row a player of a network match can let run out before
+    /// they forfeit
+    idle_forfeit: Option<NonZeroU8>,
+    /// Soldiers each player had when the turn limit ended in a tie. From
+    /// then on, the player who loses the fewest soldiers wins the match.
+    sudden_death: Option<Vec<usize>>,
+    horde: Option<Horde>,
+    puzzle: Option<Puzzle>,
+    #[serde(skip)]
+    correspondence: Option<Correspondence>,
+    #[serde(skip)]
+    demo: bool,
+    /// Turns taken so far, if the match can be replayed
+    replay: Option<Replay>,
+    /// Replay whose turns are taken, if one is being watched
+    #[serde(skip)]
+    watching: Option<Arc<Replay>>,
+    rng: MatchRng,
+    registry: SoldierRegistry,
+    /// Id of the next obstacle added during the match. Ids of obstacles that
+    /// were removed aren't given out again, as their displays are matched
+    /// to them by id, so no more are added once this is past the last id.
+    #[serde(default)]
+    next_obstacle_id: u16,
 }
 
 impl PlayPhase {
+    pub fn is_practice(&self) -> bool {
+        self.practice
+    }
+    pub fn is_demo(&self) -> bool {
+        self.demo
+    }
+    pub fn has_aim_preview(&self) -> bool {
+        self.aim_preview
+    }
+    pub fn restriction(&self) -> Restriction {
+        self.restriction
+    }
+    pub fn budget(&self) -> Option<u32> {
+        self.budget
+    }
+    /// Longest path the current player's shot may travel, in graph units,
+    /// including their energy, if limited
+    pub fn max_shot_length(&self) -> Option<f32> {
+        let length = self.max_shot_length? as f32;
+        Some(length + self.banked_energy().unwrap_or(0.))
+    }
+    /// Energy the current player has banked, if energy is being banked
+    pub fn banked_energy(&self) -> Option<f32> {
+        self.energy.then_some(self.current_player().energy)
+    }
+    /// Bank what the current player's shot, of length `used`, left of their
+    /// range, up to the range of `MAX_BANKED_SHOTS` extra shots
+    pub fn bank_energy(&mut self, used: f32) {
+        let (Some(length), Some(range)) =
+            (self.max_shot_length, self.max_shot_length())
+        else {
+            return;
+        };
+        if self.energy {
+            let most = length as f32 * MAX_BANKED_SHOTS;
+            self.current_player_mut().energy = (range - used).clamp(0., most);
+        }
+    }
+    pub fn cooldown(&self) -> Option<NonZeroU8> {
+        self.cooldown
+    }
+    /// Check that a function may be shot under the match's rules
+    pub fn check_equation(
+        &self,
+        function: &ParsedFunction,
+    ) -> Result<(), RestrictionError> {
+        function.check(self.restriction)?;
+        function.check_budget(self.budget)?;
+        function.check_repeat(
+            self.cooldown,
+            self.current_player().recent_equations(),
+        )
+    }
+    /// Remember a function the current player shot, so they can't repeat it
+    /// until the cooldown is over
+    pub fn remember_equation(&mut self, function: &ParsedFunction) {
+        if let Some(cooldown) = self.cooldown {
+            self.current_player_mut()
+                .remember_equation(function.canonical(), cooldown);
+        }
+    }
     pub fn turn_phase(&self) -> &TurnPhase {
         &self.turn_phase
     }
     pub fn turn_phase_mut(&mut self) -> &mut TurnPhase {
         &mut self.turn_phase
     }
+    /// The last player standing, or the one who came out ahead in sudden
+    /// death
     pub fn get_winner(&self) -> Option<PlayerSelect> {
-        if self.player_2.living_soldiers.is_empty() {
-            Some(PlayerSelect::Player1)
-        } else if self.player_1.living_soldiers.is_empty() {
-            Some(PlayerSelect::Player2)
-        } else {
-            None
+        let mut standing = (PlayerSelect::ALL.iter().zip(&self.players))
+            .filter(|(_, player)| !player.living_soldiers.is_empty());
+        if let (Some((&last, _)), None) = (standing.next(), standing.next()) {
+            return Some(last);
         }
+        let before = self.sudden_death.as_ref()?;
+        let changes = (self.players.iter().zip(before))
+            .map(|(player, &before)| {
+                player.living_soldiers.len() as isize - before as isize
+            })
+            .collect::<Vec<_>>();
+        Self::more_soldiers(&changes)
     }
-    pub fn current_player(&self) -> &PlayerState {
-        if self.turn == PlayerSelect::Player1 {
-            &self.player_1
-        } else {
-            &self.player_2
+    /// In network matches, the player who let too many turns in a row run out
+    /// forfeits to the other
+    pub fn check_idle_forfeit(&self) -> Option<PlayerSelect> {
+        let limit = self.idle_forfeit?.get();
+        let idle = self.players.iter().position(|i| i.idle_turns >= limit)?;
+        // Network matches are always between two players
+        PlayerSelect::from_index(1 - idle)
+    }
+    pub fn idle_forfeit(&self) -> Option<NonZeroU8> {
+        self.idle_forfeit
+    }
+    /// Count a turn that ran out without a shot against the current player,
+    /// and have the opponent of a network match told about it if it was
+    /// taken here
+    pub fn skip_turn(&mut self) {
+        let local = !self.is_remote_turn();
+        self.current_player_mut().idle_turns += 1;
+        if let Some(correspondence) = &mut self.correspondence
+            && local
+        {
+            correspondence.skipped = true;
         }
     }
+    /// Decide the match once the last turn allowed by the turn limit is over:
+    /// the player with more soldiers left wins, and a tie starts sudden death
+    pub fn check_turn_limit(&mut self) -> Option<PlayerSelect> {
+        let max_turns = self.max_turns?;
+        if self.sudden_death.is_some() || self.turn_number < max_turns.get() {
+            return None;
+        }
+        let left = (self.players.iter())
+            .map(|i| i.living_soldiers.len())
+            .collect::<Vec<_>>();
+        let counts = left.iter().map(|&i| i as isize).collect::<Vec<_>>();
+        let winner = Self::more_soldiers(&counts);
+        if winner.is_none() {
+            self.sudden_death = Some(left);
+        }
+        winner
+    }
+    /// The player that scored highest on some count of soldiers, indexed
+    /// like the players, unless several tied for it
+    fn more_soldiers(counts: &[isize]) -> Option<PlayerSelect> {
+        let best = counts.iter().max()?;
+        let mut leaders = (PlayerSelect::ALL.iter().zip(counts))
+            .filter(|&(_, count)| count == best);
+        match (leaders.next(), leaders.next()) {
+            (Some((&leader, _)), None) => Some(leader),
+            _ => None,
+        }
+    }
+    /// Length of the current turn, which shrinks every round with blitz
+    pub fn turn_duration(&self) -> Duration {
+        let Some(blitz) = self.blitz else {
+            return self.turn_length;
+        };
+        let round = self.round() - 1;
+        let shortened = self.turn_length.saturating_sub(Duration::from_secs(
+            (round * blitz.step_seconds).into(),
+        ));
+        let floor = Duration::from_secs(blitz.floor_seconds.into());
+        shortened.max(floor.min(self.turn_length))
+    }
+    /// Random number generator for `stream` in the current turn
+    pub fn turn_rng(&mut self, stream: RngStream) -> StdRng {
+        self.rng.stream(self.turn_number, stream)
+    }
+    pub fn turn_number(&self) -> u32 {
+        self.turn_number
+    }
+    pub fn max_turns(&self) -> Option<NonZeroU32> {
+        self.max_turns
+    }
+    pub fn is_sudden_death(&self) -> bool {
+        self.sudden_death.is_some()
+    }
+    pub fn turn(&self) -> PlayerSelect {
+        self.turn
+    }
+    pub fn current_player(&self) -> &PlayerState {
+        self.player(self.turn)
+    }
     pub fn current_player_mut(&mut self) -> &mut PlayerState {
-        if self.turn == PlayerSelect::Player1 {
-            &mut self.player_1
-        } else {
-            &mut self.player_2
+        self.player_mut(self.turn)
+    }
+    pub fn player(&self, player: PlayerSelect) -> &PlayerState {
+        &self.players[player.index()]
+    }
+    pub fn player_mut(&mut self, player: PlayerSelect) -> &mut PlayerState {
+        &mut self.players[player.index()]
+    }
+    /// Every player of the match, in turn order
+    pub fn players(&self) -> &[PlayerState] {
+        &self.players
+    }
+    /// The players whose soldiers the current player shoots at
+    pub fn opponents(&self) -> impl Iterator<Item = &PlayerState> {
+        let turn = self.turn.index();
+        (self.players.iter().enumerate())
+            .filter(move |&(index, _)| index != turn)
+            .map(|(_, player)| player)
+    }
+    /// Soldiers of every player
+    pub fn soldiers(&self) -> impl Iterator<Item = &Soldier> + Clone {
+        self.players.iter().flat_map(|i| &i.living_soldiers)
+    }
+    /// Destroy the soldier `id`, whoever's turn it is. Returns whether that
+    /// soldier was still alive.
+    pub fn damage_soldier(&mut self, id: SoldierId) -> bool {
+        let owner = self.player_mut(id.player);
+        let destroyed = owner.destroy_soldier(id.number);
+        if destroyed {
+            owner.verify_active_soldier();
+            if let Some(horde) = &mut self.horde
+                && id.player == PlayerSelect::Player2
+            {
+                horde.score.kills += 1;
+            }
         }
+        destroyed
     }
-    pub fn other_player(&self) -> &PlayerState {
-        if self.turn == PlayerSelect::Player1 {
-            &self.player_2
-        } else {
-            &self.player_1
+    /// Add `new_kills` to the kills of the shot being graphed, returning the
+    /// shot's total
+    pub fn record_kills(&mut self, new_kills: u8) -> u8 {
+        match &mut self.turn_phase {
+            TurnPhase::ShowPhase(TurnShowPhase::Graphing { kills, .. }) => {
+                *kills += new_kills;
+                *kills
+            }
+            _ => new_kills,
         }
     }
+    /// The player whose turn is next: the next one in turn order with
+    /// soldiers left
+    pub fn next_player(&self) -> PlayerSelect {
+        let count = self.players.len();
+        (1..=count)
+            .map(|offset| (self.turn.index() + offset) % count)
+            .find(|&index| !self.players[index].living_soldiers.is_empty())
+            .and_then(PlayerSelect::from_index)
+            .unwrap_or(self.turn)
+    }
     pub fn next_turn(&mut self) {
-        self.turn = if self.turn == PlayerSelect::Player1 {
-            PlayerSelect::Player2
-        } else {
-            PlayerSelect::Player1
+        self.turn_number += 1;
+        self.turn = self.next_player();
+        if (self.turn_number - 1) % self.players.len() as u32 == 0 {
+            self.spin_roulette();
+        }
+        // The teammates take turns shooting for player 1
+        if let Some(horde) = &mut self.horde
+            && self.turn == PlayerSelect::Player1
+        {
+            horde.human_turns += 1;
+            let teammate = (horde.human_turns % 2) as usize;
+            self.players[0].name = horde.teammates[teammate].clone();
+        }
+    }
+    pub fn puzzle(&self) -> Option<&Puzzle> {
+        self.puzzle.as_ref()
+    }
+    pub fn correspondence(&self) -> Option<&Correspondence> {
+        self.correspondence.as_ref()
+    }
+    pub fn correspondence_mut(&mut self) -> Option<&mut Correspondence> {
+        self.correspondence.as_mut()
+    }
+    /// Hash of everything that decides how shots play out from here: the
+    /// soldiers, obstacles and field, each player's banked energy and recent
+    /// equations, the roulette's picks and the random streams drawn so far.
+    /// Two copies of a network match with different hashes have drifted
+    /// apart.
+    pub fn state_hash(&self) -> u64 {
+        #[derive(Serialize)]
+        struct Player<'a> {
+            soldiers: Vec<(u8, Vec2)>,
+            energy: f32,
+            recent_equations: &'a [String],
+        }
+        #[derive(Serialize)]
+        struct State<'a> {
+            turn_number: u32,
+            turn: PlayerSelect,
+            players: Vec<Player<'a>>,
+            obstacles: &'a [Obstacle],
+            field: &'a [FieldModifier],
+            roulette: Option<&'a Roulette>,
+            sudden_death: Option<&'a Vec<usize>>,
+            rng: &'a MatchRng,
+        }
+        let players = (self.players.iter())
+            .map(|player| Player {
+                soldiers: (player.living_soldiers.iter())
+                    .map(|i| (i.number(), i.graph_location))
+                    .collect(),
+                energy: player.energy,
+                recent_equations: &player.recent_equations,
+            })
+            .collect();
+        let state = State {
+            turn_number: self.turn_number,
+            turn: self.turn,
+            players,
+            obstacles: &self.obstacles,
+            field: &self.field,
+            roulette: self.roulette.as_ref(),
+            sudden_death: self.sudden_death.as_ref(),
+            rng: &self.rng,
+        };
+        fnv1a(&serde_json::to_vec(&state).unwrap_or_default())
+    }
+    /// Carry on from `synced`, the other side's copy of this network match,
+    /// forgetting the turns on their way between the two sides
+    pub fn resync(&mut self, mut synced: Box<PlayPhase>) {
+        synced.correspondence =
+            self.correspondence
+                .take()
+                .map(|correspondence| Correspondence {
+                    outgoing: None,
+                    incoming: None,
+                    rejected: None,
+                    skipped: false,
+                    ..correspondence
+                });
+        synced.demo = self.demo;
+        *self = *synced;
+    }
+    /// Play `side` of a network match from here on, as when picking it up
+    /// again from the other side's copy
+    pub fn play_networked(&mut self, side: NetworkSide) {
+        self.correspondence = Some(Correspondence::networked(side));
+    }
+    /// Carry on with a network match here alone, the computer taking the
+    /// turns of the player who left
+    pub fn play_offline(&mut self) {
+        let Some(correspondence) = self.correspondence.take() else {
+            return;
+        };
+        for (player, state) in PlayerSelect::ALL.iter().zip(&mut self.players) {
+            state.ai |= *player != correspondence.local;
+        }
+    }
+    /// Whether the current turn is taken on the opponent's machine, in a
+    /// correspondence or network match, or by a replay being watched
+    pub fn is_remote_turn(&self) -> bool {
+        self.watching.is_some()
+            || (self.correspondence.as_ref())
+                .is_some_and(|i| i.local != self.turn)
+    }
+    /// The replay being watched, if any
+    pub fn watching(&self) -> Option<&Replay> {
+        self.watching.as_deref()
+    }
+    /// Record the shot of the current turn in the match's replay
+    pub fn record_shot(&mut self, equation: &str) {
+        if let Some(mut replay) = self.replay.take() {
+            replay.record_shot(self, equation);
+            self.replay = Some(replay);
+        }
+    }
+    /// Finish recording the turn that is over in the match's replay
+    pub fn record_turn_over(&mut self) {
+        if let Some(mut replay) = self.replay.take() {
+            replay.record_turn_over(self);
+            self.replay = Some(replay);
+        }
+    }
+    /// In a correspondence match, keep the code of a shot taken on this side
+    /// to send to the opponent
+    pub fn export_turn(&mut self, equation: String) {
+        if self.is_remote_turn() {
+            return;
+        }
+        let board = Board::of(self);
+        if let Some(correspondence) = &mut self.correspondence {
+            correspondence.export(board, equation);
+        }
+    }
+    /// A puzzle is lost as soon as its one shot leaves a target standing
+    pub fn check_puzzle(&self) -> Option<PlayerSelect> {
+        self.puzzle.as_ref()?;
+        (!self.players[1].living_soldiers.is_empty())
+            .then_some(PlayerSelect::Player2)
+    }
+    pub fn horde_score(&self) -> Option<HordeScore> {
+        self.horde.as_ref().map(|horde| horde.score)
+    }
+    /// In horde mode, replace the destroyed horde with a bigger wave. Returns
+    /// whether a new wave was sent.
+    pub fn next_wave(&mut self) -> bool {
+        let Some(horde) = &mut self.horde else {
+            return false;
+        };
+        horde.score.wave += 1;
+        let size = (u32::from(HORDE_FIRST_WAVE) + horde.score.wave - 1)
+            .min(HORDE_MAX_WAVE.into()) as u8;
+        let mut rng = self.rng.stream(self.turn_number, RngStream::Wave);
+        let mut soldiers = gen_soldiers(&mut rng, PlayerSelect::Player2, size);
+        for soldier in &mut soldiers {
+            // New soldiers start on the side the horde is currently on
+            if self.turn == PlayerSelect::Player2 {
+                soldier.graph_location.x *= -1.;
+            }
+            soldier.id = self.registry.issue(PlayerSelect::Player2);
+        }
+        self.players[1].living_soldiers = soldiers;
+        self.players[1].verify_active_soldier();
+        true
+    }
+    /// Number of the current round (a turn of each player), counting from 1
+    pub fn round(&self) -> u32 {
+        self.turn_number.div_ceil(self.players.len() as u32)
+    }
+    /// In roulette mode, replace the previous round's mutators with new ones
+    /// picked at random
+    fn spin_roulette(&mut self) {
+        use rand::seq::SliceRandom;
+        let Some(roulette) = &mut self.roulette else {
+            return;
+        };
+        let mut rng = self.rng.stream(self.turn_number, RngStream::Roulette);
+        self.obstacles.retain(|i| !roulette.walls.contains(&i.id));
+        self.field.truncate(roulette.base_field);
+        let count = rng.gen_range(1..=2);
+        roulette.picks = Roulette::POOL
+            .choose_multiple(&mut rng, count)
+            .copied()
+            .collect();
+        roulette.walls.clear();
+        for pick in &roulette.picks {
+            match pick {
+                Mutator::GravityWells => {
+                    let num = rng.gen_range(1..=2);
+                    self.field.extend(gen_gravity_wells(&mut rng, num));
+                }
+                Mutator::Wind => {
+                    let strength = rng.gen_range(-WIND_STRENGTH..WIND_STRENGTH);
+                    self.field.push(FieldModifier::Wind { strength });
+                }
+                Mutator::BouncyWalls => {
+                    let before = self.obstacles.len();
+                    let soldiers =
+                        self.players.iter().flat_map(|i| &i.living_soldiers);
+                    place_obstacles(
+                        &mut rng,
+                        &mut self.obstacles,
+                        &mut self.next_obstacle_id,
+                        2,
+                        soldiers,
+                        |rng| (random_wall(rng), None),
+                    );
+                    let added = self.obstacles[before..].iter().map(|i| i.id);
+                    roulette.walls.extend(added);
+                }
+                Mutator::Blitz => (),
+            }
         }
     }
-    pub fn swap_soldiers(&mut self) {
-        for soldier in &mut self.player_1.living_soldiers {
-            soldier.graph_location.x *= -1.;
+    /// The current round and the mutators the roulette picked for it, in
+    /// roulette mode
+    pub fn roulette_picks(&self) -> Option<(u32, &[Mutator])> {
+        let roulette = self.roulette.as_ref()?;
+        Some((self.round(), &roulette.picks))
+    }
+    /// Mirror the board, so the player whose turn it is shoots from the left
+    pub fn swap_sides(&mut self) {
+        for player in &mut self.players {
+            for soldier in &mut player.living_soldiers {
+                soldier.graph_location.x *= -1.;
+            }
+        }
+        for obstacle in &mut self.obstacles {
+            obstacle.mirror();
+        }
+        for modifier in &mut self.field {
+            *modifier = modifier.mirrored();
+        }
+    }
+    pub fn field(&self) -> &[FieldModifier] {
+        &self.field
+    }
+    /// Rule changes active in this match that players should know about
+    pub fn mutators(&self) -> Vec<Mutator> {
+        let mut mutators = Vec::new();
+        let any = |f: fn(&FieldModifier) -> bool| self.field.iter().any(f);
+        if any(|i| matches!(i, FieldModifier::GravityWell { .. })) {
+            mutators.push(Mutator::GravityWells);
         }
-        for soldier in &mut self.player_2.living_soldiers {
-            soldier.graph_location.x *= -1.;
+        if any(|i| matches!(i, FieldModifier::Wind { .. })) {
+            mutators.push(Mutator::Wind);
         }
+        let walls = self
+            .obstacles
+            .iter()
+            .any(|i| matches!(i.kind, ObstacleKind::BouncyWall { .. }));
+        if walls {
+            mutators.push(Mutator::BouncyWalls);
+        }
+        if self.blitz.is_some() {
+            mutators.push(Mutator::Blitz);
+        }
+        mutators
     }
+    /// Number obstacles added from now on after the ones in play, for
+    /// matches saved before the count was kept
+    pub fn recount_obstacles(&mut self) {
+        self.next_obstacle_id = next_obstacle_id(&self.obstacles);
+    }
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+    /// Take a hit off the obstacle `id`, removing it once it has none left.
+    /// Returns the hits it has left, or `None` if it can't be destroyed.
+    pub fn damage_obstacle(&mut self, id: u8) -> Option<u32> {
+        let index = self.obstacles.iter().position(|i| i.id == id)?;
+        let hp = self.obstacles[index].hp.as_mut()?;
+        *hp = hp.saturating_sub(1);
+        let left = *hp;
+        if left == 0 {
+            self.obstacles.remove(index);
+        }
+        Some(left)
+    }
+    /// Start the current player's turn, once the machine has been handed to
+    /// them if it is shared
     pub fn begin_input_phase(&mut self) {
-        self.turn_phase = TurnPhase::InputPhase {
-            timer: Timer::new(self.turn_length, TimerMode::Repeating),
+        self.turn_phase = if self.is_hotseat() {
+            TurnPhase::Handoff
+        } else {
+            self.input_phase()
         };
     }
-    pub fn player_soldiers(&self) -> (&[Soldier], &[Soldier]) {
-        (
-            &self.player_1.living_soldiers,
-            &self.player_2.living_soldiers,
-        )
+    /// Start the turn of the player the machine was handed to
+    pub fn end_handoff(&mut self) {
+        if self.turn_phase.is_handoff() {
+            self.turn_phase = self.input_phase();
+        }
+    }
+    fn input_phase(&self) -> TurnPhase {
+        TurnPhase::InputPhase {
+            timer: Timer::new(self.turn_duration(), TimerMode::Repeating),
+        }
+    }
+    /// Whether the match can be saved to be resumed later
+    pub fn can_save(&self) -> bool {
+        self.correspondence.is_none()
+            && self.watching.is_none()
+            && !self.demo
+            && !self.turn_phase.is_showing()
     }
-    pub fn players_mut(&mut self) -> (&mut PlayerState, &mut PlayerState) {
-        (&mut self.player_1, &mut self.player_2)
+    /// Whether more than one of the players takes their turns on this
+    /// machine
+    pub fn is_hotseat(&self) -> bool {
+        let humans = self.players.iter().filter(|i| !i.ai).count();
+        self.correspondence.is_none() && self.watching.is_none() && humans > 1
     }
 }
 
 pub enum TurnPhase {
-    InputPhase { timer: Timer },
+    /// Players sharing the machine are passing it on to the next one, who
+    /// starts their turn once they have it. Nothing they typed is shown
+    /// until then.
+    Handoff,
+    InputPhase {
+        timer: Timer,
+    },
     ShowPhase(TurnShowPhase),
 }
 
+/// How the turn phase of a saved match is kept: the time the turn has left,
+/// or the handoff to its player. Matches aren't saved while a shot is
+/// shown.
+#[derive(Serialize, Deserialize)]
+enum SavedTurnPhase {
+    Handoff,
+    Input { length: Duration, elapsed: Duration },
+}
+
+impl Serialize for TurnPhase {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let saved = match self {
+            TurnPhase::InputPhase { timer } => SavedTurnPhase::Input {
+                length: timer.duration(),
+                elapsed: timer.elapsed(),
+            },
+            _ => SavedTurnPhase::Handoff,
+        };
+        saved.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TurnPhase {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        Ok(match SavedTurnPhase::deserialize(deserializer)? {
+            SavedTurnPhase::Handoff => TurnPhase::Handoff,
+            SavedTurnPhase::Input { length, elapsed } => {
+                let mut timer = Timer::new(length, TimerMode::Repeating);
+                timer.set_elapsed(elapsed);
+                TurnPhase::InputPhase { timer }
+            }
+        })
+    }
+}
+
 impl TurnPhase {
     pub fn is_input(&self) -> bool {
         matches!(self, TurnPhase::InputPhase { .. })
     }
+    pub fn is_handoff(&self) -> bool {
+        matches!(self, TurnPhase::Handoff)
+    }
+    /// Whether a shot is being shown
+    pub fn is_showing(&self) -> bool {
+        matches!(self, TurnPhase::ShowPhase(_))
+    }
 }
 pub enum TurnShowPhase {
     Graphing {
-        function: Function,
-        prev_y: Option<f32>,
-        next_x: f32,
+        stepper: crate::sim::ShotStepper,
         timer: Timer,
+        /// How long the shot has been animated for
+        elapsed: Duration,
+        /// Soldiers destroyed by this shot so far
+        kills: u8,
     },
     Waiting {
         timer: Timer,
     },
 }
+#[derive(Clone)]
 pub struct Function {
     pub original:
         Arc<dyn Fn(f32) -> Result<f32, crate::parse::EvalError> + Send + Sync>,
     pub shift_up: f32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PlayerState {
     pub name: String,
+    pub skin: SoldierSkin,
     // TODO: consider implementing this with
     // an explicitly non-empty array type to
     // convey that information in the type
     // system. For now, just know that it
     // CANNOT be empty.
     living_soldiers: Vec<Soldier>,
+    /// Number of the soldier that will shoot
     active_soldier: u8,
+    /// Controls this player takes their turns with
+    pub input: InputDevice,
+    /// Turns in a row this player let run out without shooting
+    pub idle_turns: u8,
+    /// Whether the computer plays for this player
+    pub ai: bool,
+    /// Difficulty of a computer opponent picked in the setup, which searches
+    /// for curved shots. Other computer players shoot straight lines.
+    pub ai_level: Option<u8>,
+    /// Range banked from earlier shots, in graph units
+    energy: f32,
+    /// Canonical forms of the equations shot in the last few turns, oldest
+    /// first
+    recent_equations: Vec<String>,
 }
 
 impl PlayerState {
     // TODO: Prevent initialization with zero soldiers
-    pub fn new(name: String, soldiers: Vec<Soldier>) -> PlayerState {
+    pub fn new(
+        name: String,
+        skin: SoldierSkin,
+        soldiers: Vec<Soldier>,
+    ) -> PlayerState {
         Self {
             name,
+            skin,
+            active_soldier: soldiers.first().map_or(0, Soldier::number),
             living_soldiers: soldiers,
-            active_soldier: 0,
+            input: InputDevice::default(),
+            idle_turns: 0,
+            ai: false,
+            ai_level: None,
+            energy: 0.,
+            recent_equations: Vec::new(),
         }
     }
+    pub fn recent_equations(&self) -> &[String] {
+        &self.recent_equations
+    }
+    /// Add an equation to the recent ones, forgetting the oldest beyond the
+    /// last `keep`
+    pub fn remember_equation(&mut self, canonical: String, keep: NonZeroU8) {
+        self.recent_equations.push(canonical);
+        let excess = self
+            .recent_equations
+            .len()
+            .saturating_sub(usize::from(keep.get()));
+        self.recent_equations.drain(..excess);
+    }
     pub fn next_soldier(&mut self) {
         self.active_soldier = self.living_soldiers[(self
-            .living_soldiers
-            .iter()
-            .position(|i| i.id == self.active_soldier)
+            .position(self.active_soldier)
             .unwrap_or(0)
             + 1)
             % self.living_soldiers.len()]
-        .id;
+        .id
+        .number;
+    }
+    /// Index of the soldier with the given number, if it is still alive
+    fn position(&self, number: u8) -> Option<usize> {
+        (self.living_soldiers.iter()).position(|i| i.id.number == number)
+    }
+    /// Make the soldier with the given number active, if it is still alive
+    pub fn select_soldier(&mut self, number: u8) -> bool {
+        let alive = self.position(number).is_some();
+        if alive {
+            self.active_soldier = number;
+        }
+        alive
     }
     pub fn current_soldier(&self) -> &Soldier {
-        self.living_soldiers
-            .iter()
-            .find(|i| i.id == self.active_soldier)
-            .unwrap_or_else(|| &self.living_soldiers[0])
+        &self.living_soldiers[self.position(self.active_soldier).unwrap_or(0)]
     }
     pub fn current_soldier_mut(&mut self) -> &mut Soldier {
-        let current_is_valid = self
-            .living_soldiers
-            .iter()
-            .any(|i| i.id == self.active_soldier);
-        if current_is_valid {
-            self.living_soldiers
-                .iter_mut()
-                .find(|i| i.id == self.active_soldier)
-                .unwrap()
-        } else {
-            &mut self.living_soldiers[0]
-        }
+        let index = self.position(self.active_soldier).unwrap_or(0);
+        &mut self.living_soldiers[index]
     }
     pub fn soldiers(&self) -> &[Soldier] {
         &self.living_soldiers
     }
+    pub fn drafts_mut(&mut self) -> Vec<Draft<'_>> {
+        drafts(&mut self.living_soldiers)
+    }
     pub fn verify_active_soldier(&mut self) -> bool {
-        if !self
-            .living_soldiers
-            .iter()
-            .any(|i| i.id == self.active_soldier)
+        if self.position(self.active_soldier).is_none()
+            && let Some(first) = self.living_soldiers.first()
         {
-            self.active_soldier = self.living_soldiers[0].id;
+            self.active_soldier = first.id.number;
             true
         } else {
             false
         }
     }
-    pub fn destroy_soldier(&mut self, id: u8) -> bool {
-        self.living_soldiers.pop_if(|i| i.id == id).is_some()
+    pub fn destroy_soldier(&mut self, number: u8) -> bool {
+        let Some(index) = self.position(number) else {
+            return false;
+        };
+        self.living_soldiers.remove(index);
+        true
     }
 }
 
 pub struct PlayUiData<'a> {
     pub input_ui: Option<InputUiData<'a>>,
-    pub soldier_loc: Vec2,
+    /// Drafts of the player whose turn is next, who can prepare them while
+    /// the current player's shot is shown
+    pub prepare_ui: Option<PrepareUiData<'a>>,
 }
 pub struct InputUiData<'a> {
-    pub current_input: &'a mut String,
+    pub player: PlayerSelect,
+    /// Equations of all of the current player's soldiers
+    pub drafts: Vec<Draft<'a>>,
+    /// Id of the soldier that will shoot
+    pub active_soldier: SoldierId,
     pub timer: &'a mut Timer,
 }
+pub struct PrepareUiData<'a> {
+    pub player: PlayerSelect,
+    pub name: &'a str,
+    pub drafts: Vec<Draft<'a>>,
+    /// Id of the soldier that will shoot next turn
+    pub active_soldier: SoldierId,
+}
+/// The equations a soldier could shoot with next, which stay around between
+/// turns
+pub struct Draft<'a> {
+    pub soldier_id: SoldierId,
+    pub slots: &'a mut EquationSlots,
+}
+fn drafts(soldiers: &mut [Soldier]) -> Vec<Draft<'_>> {
+    soldiers
+        .iter_mut()
+        .map(|soldier| Draft {
+            soldier_id: soldier.id,
+            slots: &mut soldier.slots,
+        })
+        .collect()
+}
 impl<'a> PlayUiData<'a> {
     pub fn new(state: &'a mut PlayPhase) -> PlayUiData<'a> {
-        let loc = state.current_player().current_soldier().graph_location;
+        // Remote players of correspondence matches are played from their
+        // turn codes, and the players of a replay from its turns, like
+        // computer players
+        let current_remote = state.is_remote_turn();
+        let other_remote = state.watching.is_some()
+            || (state.correspondence.is_some() && !current_remote);
+        let player = state.next_player();
+        // The player taking over the machine is yet to show up
+        if state.turn_phase.is_handoff() {
+            return Self {
+                input_ui: None,
+                prepare_ui: None,
+            };
+        }
         let TurnPhase::InputPhase { timer, .. } = &mut state.turn_phase else {
+            let other_player = &mut state.players[player.index()];
+            if player == state.turn || other_player.ai || other_remote {
+                return Self {
+                    input_ui: None,
+                    prepare_ui: None,
+                };
+            }
             return Self {
                 input_ui: None,
-                soldier_loc: loc,
+                prepare_ui: Some(PrepareUiData {
+                    player,
+                    active_soldier: other_player.current_soldier().id,
+                    name: &other_player.name,
+                    drafts: drafts(&mut other_player.living_soldiers),
+                }),
             };
         };
-        let current_player = if state.turn == PlayerSelect::Player1 {
-            &mut state.player_1
-        } else {
-            &mut state.player_2
-        };
-        let soldier = current_player.current_soldier_mut();
+        let current_player = &mut state.players[state.turn.index()];
+        if current_player.ai || current_remote {
+            return Self {
+                input_ui: None,
+                prepare_ui: None,
+            };
+        }
         Self {
             input_ui: Some(InputUiData {
-                current_input: &mut soldier.equation,
+                player: state.turn,
+                active_soldier: current_player.current_soldier().id,
+                drafts: current_player.drafts_mut(),
                 timer,
             }),
-            soldier_loc: loc,
+            prepare_ui: None,
         }
     }
 }
 
-#[derive(Component, Clone, Debug)]
+/// Identifies a soldier across both players for the whole match, so damage,
+/// turn codes, replays and the UI can all refer to soldiers the same way
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SoldierId {
+    pub player: PlayerSelect,
+    /// Number of the soldier in its team, which is shown on it
+    pub number: u8,
+}
+
+/// Hands out the ids of soldiers that join a match after it has started,
+/// such as new horde waves, so that they aren't confused with soldiers that
+/// were there before
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SoldierRegistry {
+    /// Next number to give to each player's soldiers
+    next: [u8; MAX_PLAYERS],
+}
+
+impl SoldierRegistry {
+    /// Registry for a match starting with the given soldiers
+    pub fn new<'a>(soldiers: impl IntoIterator<Item = &'a Soldier>) -> Self {
+        let mut registry = SoldierRegistry::default();
+        for soldier in soldiers {
+            let next = &mut registry.next[soldier.id.player.index()];
+            *next = (*next).max(soldier.id.number.wrapping_add(1));
+        }
+        registry
+    }
+
+    /// Id for a new soldier of `player`. Numbers wrap around after 255, long
+    /// after the soldiers that had them are gone.
+    pub fn issue(&mut self, player: PlayerSelect) -> SoldierId {
+        let next = &mut self.next[player.index()];
+        let number = *next;
+        *next = next.wrapping_add(1);
+        SoldierId { player, number }
+    }
+}
+
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
 pub struct Soldier {
-    player: PlayerSelect,
-    id: u8,
+    id: SoldierId,
     graph_location: Vec2,
-    pub equation: String,
+    pub slots: EquationSlots,
 }
 
 impl PartialEq for Soldier {
     fn eq(&self, other: &Self) -> bool {
-        self.player == other.player && self.id == other.id
+        self.id == other.id
     }
 }
 
 impl Soldier {
+    pub fn new(player: PlayerSelect, number: u8, graph_location: Vec2) -> Self {
+        Self {
+            id: SoldierId { player, number },
+            graph_location,
+            slots: EquationSlots::default(),
+        }
+    }
+    /// The equation the soldier will shoot with
+    pub fn equation(&self) -> &str {
+        self.slots.current()
+    }
     pub fn graph_location(&self) -> Vec2 {
         self.graph_location
     }
     pub fn player(&self) -> PlayerSelect {
-        self.player
+        self.id.player
     }
-    pub fn id(&self) -> u8 {
+    pub fn id(&self) -> SoldierId {
         self.id
     }
+    /// Number of the soldier in its team
+    pub fn number(&self) -> u8 {
+        self.id.number
+    }
+}
+
+/// Up to `EQUATION_SLOTS` candidate equations of a soldier, one of which is
+/// selected to be shot. Never empty.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EquationSlots {
+    equations: Vec<String>,
+    selected: usize,
 }
 
-fn gen_soldiers(player: PlayerSelect, num: u8) -> Vec<Soldier> {
-    use rand::{Rng, thread_rng};
-    let mut rng = thread_rng();
+impl Default for EquationSlots {
+    fn default() -> Self {
+        Self {
+            equations: vec![DEFAULT_FUNCTION.to_string()],
+            selected: 0,
+        }
+    }
+}
+
+impl EquationSlots {
+    pub fn equations(&self) -> &[String] {
+        &self.equations
+    }
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+    pub fn current(&self) -> &str {
+        &self.equations[self.selected]
+    }
+    pub fn current_mut(&mut self) -> &mut String {
+        &mut self.equations[self.selected]
+    }
+    pub fn select(&mut self, slot: usize) {
+        if slot < self.equations.len() {
+            self.selected = slot;
+        }
+    }
+    /// Add a slot starting as a copy of the current equation, and select it.
+    /// Returns whether there was room for it.
+    pub fn add(&mut self) -> bool {
+        if self.equations.len() >= EQUATION_SLOTS {
+            return false;
+        }
+        self.equations.push(self.current().to_string());
+        self.selected = self.equations.len() - 1;
+        true
+    }
+    /// Remove the selected slot, unless it is the only one
+    pub fn remove_current(&mut self) {
+        if self.equations.len() > 1 {
+            self.equations.remove(self.selected);
+            self.selected = self.selected.saturating_sub(1);
+        }
+    }
+}
+
+/// Place `num` soldiers on `player`'s side of the board, at least two units
+/// apart
+pub fn gen_soldiers(
+    rng: &mut impl Rng,
+    player: PlayerSelect,
+    num: u8,
+) -> Vec<Soldier> {
+    gen_soldiers_avoiding(rng, player, num, &[])
+}
+
+/// Place `num` soldiers of `player` on their half of the board, away from
+/// each other and from `others`, such as the soldiers of players sharing
+/// their half in a free-for-all
+pub fn gen_soldiers_avoiding(
+    rng: &mut impl Rng,
+    player: PlayerSelect,
+    num: u8,
+    others: &[Soldier],
+) -> Vec<Soldier> {
+    let side = if player.starts_left() { -1. } else { 1. };
     let mut soldiers = Vec::with_capacity(num.into());
     while soldiers.len() < num.into() {
         let new_soldier = {
-            let x = rng.gen_range(0.0..10.0);
+            let x = rng.gen_range(0.0..10.0) * side;
             let y = rng.gen_range(-10.0..10.0);
             let pos = Vec2 { x, y };
-            Soldier {
-                player,
-                id: soldiers.len() as u8,
-                graph_location: pos,
-                equation: crate::consts::DEFAULT_FUNCTION.to_string(),
-            }
+            Soldier::new(player, soldiers.len() as u8, pos)
         };
-        if !soldiers.iter().any(|i: &Soldier| {
+        if !soldiers.iter().chain(others).any(|i: &Soldier| {
             new_soldier.graph_location.distance(i.graph_location) < 2.
         }) {
             soldiers.push(new_soldier);
         }
     }
-    if let PlayerSelect::Player1 = player {
-        for soldier in &mut soldiers {
-            soldier.graph_location.x *= -1.;
+    soldiers
+}
+
+/// A rule change that makes a match play differently from a plain one
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mutator {
+    GravityWells,
+    Wind,
+    BouncyWalls,
+    Blitz,
+}
+
+/// Party mode that rerolls the mutators at the start of every round
+#[derive(Serialize, Deserialize)]
+pub struct Roulette {
+    /// Mutators picked for the current round
+    picks: Vec<Mutator>,
+    /// Walls added for the current round
+    walls: Vec<u8>,
+    /// Number of field modifiers that were there from the start, which stay
+    /// for the whole match
+    base_field: usize,
+}
+
+impl Roulette {
+    /// Mutators the roulette picks from
+    pub const POOL: [Mutator; 3] =
+        [Mutator::GravityWells, Mutator::Wind, Mutator::BouncyWalls];
+}
+
+impl Mutator {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mutator::GravityWells => "Gravity wells",
+            Mutator::Wind => "Wind",
+            Mutator::BouncyWalls => "Bouncy walls",
+            Mutator::Blitz => "Blitz",
+        }
+    }
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Mutator::GravityWells => "🌀",
+            Mutator::Wind => "🌬",
+            Mutator::BouncyWalls => "🏓",
+            Mutator::Blitz => "⏱",
+        }
+    }
+    pub fn description(&self) -> &'static str {
+        match self {
+            Mutator::GravityWells => {
+                "Shots passing through the purple rings are pulled towards \
+                 their center"
+            }
+            Mutator::Wind => {
+                "A steady wind pushes every shot up or down, as shown by the \
+                 arrows"
+            }
+            Mutator::BouncyWalls => {
+                "Shots bounce off the green walls, mirroring the rest of the \
+                 graph"
+            }
+            Mutator::Blitz => "Turns get shorter after every round",
+        }
+    }
+}
+
+/// Something on the board that gets in the way of shots
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub id: u8,
+    /// Location of the center in graph units
+    pub location: Vec2,
+    pub kind: ObstacleKind,
+    /// Hits left before it crumbles, or `None` if it can't be destroyed
+    pub hp: Option<u32>,
+}
+
+/// Sizes are in graph units
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ObstacleKind {
+    /// Round obstacle that stops shots
+    Block { radius: f32 },
+    /// Horizontal wall that shots bounce off
+    BouncyWall { half_length: f32 },
+    /// Regular polygon that stops shots, with its corners `radius` from the
+    /// center and the first one `rotation` radians counterclockwise from the
+    /// x axis
+    Polygon {
+        radius: f32,
+        sides: u8,
+        rotation: f32,
+    },
+}
+
+impl Obstacle {
+    /// Distance from the center to the farthest point of the obstacle
+    pub fn extent(&self) -> f32 {
+        match self.kind {
+            ObstacleKind::Block { radius } => radius,
+            ObstacleKind::BouncyWall { half_length } => half_length,
+            ObstacleKind::Polygon { radius, .. } => radius,
+        }
+    }
+    /// Whether a shot reaching `point` is stopped by this obstacle
+    pub fn blocks(&self, point: Vec2) -> bool {
+        match self.kind {
+            ObstacleKind::Block { radius } => {
+                self.location.distance(point) < radius
+            }
+            ObstacleKind::BouncyWall { .. } => false,
+            ObstacleKind::Polygon {
+                radius,
+                sides,
+                rotation,
+            } => {
+                // Inside if closer to the center than the nearest side,
+                // measured along the perpendicular to that side
+                let offset = point - self.location;
+                let wedge = std::f32::consts::TAU / f32::from(sides);
+                let from_side = (offset.to_angle() - rotation)
+                    .rem_euclid(wedge)
+                    - wedge / 2.;
+                offset.length() * from_side.cos() < radius * (wedge / 2.).cos()
+            }
+        }
+    }
+    /// The obstacle as seen with the sides of the board swapped
+    fn mirror(&mut self) {
+        self.location.x *= -1.;
+        if let ObstacleKind::Polygon { rotation, .. } = &mut self.kind {
+            *rotation = std::f32::consts::PI - *rotation;
+        }
+    }
+}
+
+/// Place `num` obstacles in the middle of the board, away from the soldiers
+/// and each other. About a third of them are bouncy walls, and the rest are
+/// round or polygonal blocks, about half of which can be destroyed.
+pub fn gen_obstacles<'a>(
+    rng: &mut impl Rng,
+    num: u8,
+    soldiers: impl Iterator<Item = &'a Soldier> + Clone,
+) -> Vec<Obstacle> {
+    let mut obstacles = Vec::with_capacity(num.into());
+    place_obstacles(rng, &mut obstacles, &mut 0, num, soldiers, |rng| {
+        if rng.gen_bool(1. / 3.) {
+            return (random_wall(rng), None);
+        }
+        let radius = rng.gen_range(OBSTACLE_RADIUS.0..OBSTACLE_RADIUS.1);
+        let hp = rng.gen_bool(0.5).then_some(OBSTACLE_HP);
+        let kind = if rng.gen_bool(0.5) {
+            ObstacleKind::Polygon {
+                radius,
+                sides: rng.gen_range(OBSTACLE_SIDES.0..=OBSTACLE_SIDES.1),
+                rotation: rng.gen_range(0.0..std::f32::consts::TAU),
+            }
+        } else {
+            ObstacleKind::Block { radius }
+        };
+        (kind, hp)
+    });
+    obstacles
+}
+
+/// Id following those of `obstacles`, which is past the last id once the
+/// last one is used
+fn next_obstacle_id(obstacles: &[Obstacle]) -> u16 {
+    (obstacles.iter().map(|i| u16::from(i.id) + 1).max()).unwrap_or(0)
+}
+
+fn random_wall(rng: &mut impl Rng) -> ObstacleKind {
+    ObstacleKind::BouncyWall {
+        half_length: rng.gen_range(WALL_HALF_LENGTH.0..WALL_HALF_LENGTH.1),
+    }
+}
+
+/// Add `num` obstacles of the kinds and hit points given by `make` to
+/// `obstacles`, in the middle of the board and away from the soldiers and the
+/// other obstacles. They are numbered on from `next_id`, which is advanced
+/// past them, and no more are placed once the ids run out.
+fn place_obstacles<'a, R: Rng>(
+    rng: &mut R,
+    obstacles: &mut Vec<Obstacle>,
+    next_id: &mut u16,
+    num: u8,
+    soldiers: impl Iterator<Item = &'a Soldier> + Clone,
+    mut make: impl FnMut(&mut R) -> (ObstacleKind, Option<u32>),
+) {
+    let target = obstacles.len() + usize::from(num);
+    // Give up on crowded boards rather than looping forever
+    for _ in 0..1000 {
+        if obstacles.len() >= target {
+            break;
+        }
+        let Ok(id) = u8::try_from(*next_id) else {
+            break;
+        };
+        let (kind, hp) = make(rng);
+        let obstacle = Obstacle {
+            id,
+            location: Vec2::ZERO,
+            kind,
+            hp,
+        };
+        let location =
+            Vec2::new(rng.gen_range(-6.0..6.0), rng.gen_range(-8.0..8.0));
+        let clearance = obstacle.extent() + OBSTACLE_CLEARANCE;
+        let too_close = soldiers
+            .clone()
+            .any(|i| i.graph_location.distance(location) < clearance)
+            || obstacles.iter().any(|i| {
+                i.location.distance(location) < clearance + i.extent()
+            });
+        if too_close {
+            continue;
+        }
+        obstacles.push(Obstacle {
+            location,
+            ..obstacle
+        });
+        *next_id += 1;
+    }
+}
+
+/// Place `num` gravity wells in the middle of the board
+pub fn gen_gravity_wells(rng: &mut impl Rng, num: u8) -> Vec<FieldModifier> {
+    (0..num)
+        .map(|_| FieldModifier::GravityWell {
+            center: Vec2::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-7.0..7.0),
+            ),
+            radius: rng.gen_range(WELL_RADIUS.0..WELL_RADIUS.1),
+            strength: rng.gen_range(WELL_STRENGTH.0..WELL_STRENGTH.1),
+        })
+        .collect()
+}
+
+/// A board of two players generated from a seed in the same order as for a
+/// real match, without the rest of the match. The golden tests and the
+/// benchmarks both shoot on these, so they can't drift apart.
+#[cfg(any(test, feature = "bench"))]
+pub struct SeededBoard {
+    /// Soldiers of player 1 and of player 2
+    pub soldiers: [Vec<Soldier>; 2],
+    pub obstacles: Vec<Obstacle>,
+    pub field: Vec<FieldModifier>,
+}
+
+#[cfg(any(test, feature = "bench"))]
+impl SeededBoard {
+    pub fn generate(
+        seed: u64,
+        soldiers: u8,
+        obstacles: u8,
+        gravity_wells: u8,
+    ) -> SeededBoard {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let player_1 = gen_soldiers(&mut rng, PlayerSelect::Player1, soldiers);
+        let player_2 = gen_soldiers(&mut rng, PlayerSelect::Player2, soldiers);
+        let obstacles = gen_obstacles(
+            &mut rng,
+            obstacles,
+            player_1.iter().chain(&player_2),
+        );
+        SeededBoard {
+            soldiers: [player_1, player_2],
+            obstacles,
+            field: gen_gravity_wells(&mut rng, gravity_wells),
         }
     }
-    soldiers
 }
 
 #[derive(Bundle)]
@@ -402,6 +2046,18 @@ pub struct SoldierBundle {
 
 pub struct FinishedPhase {
     pub winner: PlayerSelect,
+    pub winner_name: String,
+    /// How far the team got, in horde mode
+    pub horde: Option<HordeScore>,
+    /// The puzzle that was played, in puzzle mode
+    pub puzzle: Option<Puzzle>,
+    /// Code of the winning turn, if it was taken on this side of a
+    /// correspondence match
+    pub turn_code: Option<String>,
+    /// Where the match's randomness came from, to audit it
+    pub rng: Option<MatchRng>,
+    /// Every turn of the match, if it can be replayed
+    pub replay: Option<Replay>,
 }
 
 #[derive(Component)]
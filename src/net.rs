@@ -0,0 +1,1382 @@
+//! Network play: two copies of the game connect over TCP, either directly or
+//! through the relay server (`src/bin/relay.rs`), and play a match together.
+//! Once connected, both sides first say which versions of the protocol they
+//! speak, and play on the newest one they share, if any. The host then sends
+//! the setup and the seed the board is generated from, both sides send the
+//! soldiers they generated to check that their boards match, and from then
+//! on every turn is sent as a turn code (see `correspondence`) as soon as it
+//! is taken, to be replayed on the other side.
+//!
+//! If the connection drops during a match, the match is paused while both
+//! sides connect again the way they did before, for as long as set in the
+//! lobby. Once they are back they say which match they are playing, and the
+//! host's copy of it, or the only copy left if the other side had to start
+//! over, is sent for both to carry on from. If the other player doesn't
+//! come back in time, the match carries on with the computer playing for
+//! them.
+//!
+//! Emotes and chat lines are passed on as they are sent.
+//!
+//! After every shot, both sides send a hash of their copy of the match (see
+//! `PlayPhase::state_hash`). Should the hashes ever differ, or a turn code
+//! not follow on from the board it reaches, the match is paused and both
+//! players are shown the two hashes. Either of them can then have the host
+//! send its copy of the whole match, as a saved game, for the other side to
+//! carry on from, or leave the match.
+//!
+//! Messages are JSON, one per line. The first line each side sends is its
+//! `Hello`, which every version of the protocol reads the same way.
+
+use crate::consts::*;
+use crate::correspondence::{Board, GAME_VERSION, TurnFile};
+use crate::models::*;
+use crate::parse::Restriction;
+use crate::save::{decode, encode};
+use crate::systems::announce::Announce;
+use crate::systems::emotes::{Chat, Said};
+use crate::systems::graph_display::SkipGraphingEvent;
+use crate::util::fnv1a;
+use crate::{Resynced, StartPlaying};
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::num::{NonZeroU8, NonZeroU32};
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError, channel};
+use std::thread;
+
+/// The side of a network match this copy of the game plays, and the seed
+/// both sides generate the board from
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetworkSide {
+    pub seed: u64,
+    pub local: PlayerSelect,
+}
+
+/// Version of the protocol, the messages sides send each other. Bump it
+/// whenever a message is added or changed, and give new messages an arm in
+/// `Message::since`, so they aren't sent to games that can't read them.
+const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version this version of the game can still play on.
+/// Raise it once a change can't be left out when talking to older games.
+const OLDEST_PROTOCOL_VERSION: u32 = 1;
+
+/// Ids and locations (in graph units) of each player's soldiers
+type Spawn = [Vec<(u8, f32, f32)>; 2];
+
+/// Sent by both sides as soon as they are connected, before any `Message`,
+/// as the other side may not be able to read those. Fields may be added but
+/// never removed or changed, so that every version can read it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Hello {
+    /// Newest and oldest protocol versions the sending side speaks
+    protocol: u32,
+    oldest_protocol: u32,
+    /// Version of the game the sending side runs, to name it if the two
+    /// sides can't play together
+    version: String,
+    /// Id of the network match the sending side is playing, if any, to carry
+    /// on with after the connection dropped
+    match_id: Option<u64>,
+}
+
+impl Hello {
+    fn new(match_id: Option<u64>) -> Hello {
+        Hello {
+            protocol: PROTOCOL_VERSION,
+            oldest_protocol: OLDEST_PROTOCOL_VERSION,
+            version: GAME_VERSION.to_string(),
+            match_id,
+        }
+    }
+
+    /// The newest protocol version both this side and the one that sent
+    /// `self` speak, or why they can't play together
+    fn negotiate(&self) -> Result<u32, String> {
+        let protocol = self.protocol.min(PROTOCOL_VERSION);
+        if protocol < self.oldest_protocol.max(OLDEST_PROTOCOL_VERSION) {
+            return Err(format!(
+                "The other player has version {} of the game (protocol {}), \
+                 and this is {GAME_VERSION} (protocol {PROTOCOL_VERSION}). \
+                 Both need to update to the newer one.",
+                self.version, self.protocol
+            ));
+        }
+        Ok(protocol)
+    }
+}
+
+/// What one side of a network match sends the other, once they have agreed
+/// on a protocol version
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Message {
+    /// The host's setup, which starts the match on both sides
+    Setup(NetSetup),
+    /// The soldiers as the match started on the sending side
+    Spawn(Spawn),
+    /// A turn taken on the sending side, as a turn code
+    Turn(String),
+    /// A turn on the sending side ran out without a shot
+    Skip,
+    /// A quick emote, by its index in `EMOTES`
+    Emote(u8),
+    /// A line of chat
+    Chat(String),
+    /// The sending side's copy of the match differs from the host's, and
+    /// its player asked for the host's copy
+    OutOfSync,
+    /// The sending side's copy of the match, as a saved game, for the other
+    /// side to carry on from as `side`
+    State { game: String, side: NetworkSide },
+    /// Hash of the sending side's copy of the match as the turn
+    /// `turn_number` starts, after the shot before it played out
+    StateHash { turn_number: u32, hash: u64 },
+}
+
+impl Message {
+    /// Oldest protocol version that has this message
+    fn since(&self) -> u32 {
+        match self {
+            Message::StateHash { .. } => 2,
+            _ => OLDEST_PROTOCOL_VERSION,
+        }
+    }
+}
+
+/// A turn of the other player, to be played out here
+enum RemoteTurn {
+    Shot(TurnFile),
+    Skip,
+}
+
+/// Everything about the setup of a network match that both sides have to
+/// agree on
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetSetup {
+    seed: u64,
+    players: [NetPlayer; 2],
+    turn_seconds: u32,
+    aim_preview: bool,
+    max_turns: Option<NonZeroU32>,
+    blitz: Option<Blitz>,
+    obstacles: u8,
+    gravity_wells: u8,
+    roulette: bool,
+    restriction: Restriction,
+    budget: Option<u32>,
+    max_shot_length: Option<u32>,
+    energy: bool,
+    cooldown: Option<NonZeroU8>,
+    idle_forfeit: Option<NonZeroU8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct NetPlayer {
+    name: String,
+    skin: SoldierSkin,
+    soldier_num: NonZeroU8,
+}
+
+impl NetSetup {
+    /// The shared parts of the host's setup, for a match on the board
+    /// generated from `seed`
+    pub fn of(setup: &SetupPhase, seed: u64) -> NetSetup {
+        let player = |config: &PlayerConfig| NetPlayer {
+            name: config.name.clone(),
+            skin: config.skin,
+            soldier_num: config.soldier_num,
+        };
+        NetSetup {
+            seed,
+            players: [player(&setup.players[0]), player(&setup.players[1])],
+            turn_seconds: setup.turn_seconds,
+            aim_preview: setup.aim_preview,
+            max_turns: setup.max_turns,
+            blitz: setup.blitz,
+            obstacles: setup.obstacles,
+            gravity_wells: setup.gravity_wells,
+            roulette: setup.roulette,
+            restriction: setup.restriction,
+            budget: setup.budget,
+            max_shot_length: setup.max_shot_length,
+            energy: setup.energy,
+            cooldown: setup.cooldown,
+            idle_forfeit: setup.idle_forfeit,
+        }
+    }
+
+    /// Set up the guest's side of the match the host set up
+    pub fn apply(self, setup: &mut SetupPhase) {
+        setup.mode = MatchMode::Network;
+        setup.network = Some(NetworkSide {
+            seed: self.seed,
+            local: PlayerSelect::Player2,
+        });
+        setup.players = (self.players.into_iter())
+            .map(|player| PlayerConfig {
+                soldier_num: player.soldier_num,
+                name: player.name,
+                skin: player.skin,
+                input: InputDevice::default(),
+            })
+            .collect();
+        setup.turn_seconds = self.turn_seconds;
+        setup.aim_preview = self.aim_preview;
+        setup.max_turns = self.max_turns;
+        setup.blitz = self.blitz;
+        setup.obstacles = self.obstacles;
+        setup.gravity_wells = self.gravity_wells;
+        setup.roulette = self.roulette;
+        setup.restriction = self.restriction;
+        setup.budget = self.budget;
+        setup.max_shot_length = self.max_shot_length;
+        setup.energy = self.energy;
+        setup.cooldown = self.cooldown;
+        setup.idle_forfeit = self.idle_forfeit;
+    }
+}
+
+/// How to reach the other player
+#[derive(Clone, Debug)]
+enum Dial {
+    /// Wait for them to connect to this address, and play as player 1
+    Host(String),
+    /// Connect to them at this address, and play as player 2
+    Join(String),
+    /// Meet them in a room of the relay at this address. Whoever gets there
+    /// first hosts.
+    Relay { address: String, room: String },
+}
+
+impl Dial {
+    /// Make the connection, returning it and the side this copy plays.
+    /// Gives up on hosting once `cancelled` says the lobby stopped waiting.
+    fn connect(
+        &self,
+        cancelled: impl Fn() -> bool,
+    ) -> std::io::Result<(BufReader<TcpStream>, PlayerSelect)> {
+        let other = |message: String| std::io::Error::other(message);
+        match self {
+            Dial::Host(address) => {
+                let listener = TcpListener::bind(address)?;
+                listener.set_nonblocking(true)?;
+                loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            stream.set_nonblocking(false)?;
+                            let reader = BufReader::new(stream);
+                            return Ok((reader, PlayerSelect::Player1));
+                        }
+                        Err(e)
+                            if e.kind() == std::io::ErrorKind::WouldBlock =>
+                        {
+                            if cancelled() {
+                                return Err(other("Stopped hosting".into()));
+                            }
+                            thread::sleep(NET_ACCEPT_INTERVAL);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            Dial::Join(address) => {
+                let stream = TcpStream::connect(address)?;
+                Ok((BufReader::new(stream), PlayerSelect::Player2))
+            }
+            Dial::Relay { address, room } => {
+                let mut stream = TcpStream::connect(address)?;
+                writeln!(stream, "JOIN {room}")?;
+                let mut reader = BufReader::new(stream);
+                let mut local = PlayerSelect::Player2;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line)? == 0 {
+                        return Err(other("The relay hung up".into()));
+                    }
+                    match line.trim_end() {
+                        "WAITING" => local = PlayerSelect::Player1,
+                        "PAIRED" => return Ok((reader, local)),
+                        error => return Err(other(format!("Relay: {error}"))),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What the connection's threads tell the game
+enum NetEvent {
+    Connected(PlayerSelect),
+    /// The other side's hello, and the protocol version agreed on
+    Greeted(Hello, u32),
+    Received(Message),
+    Closed(String),
+}
+
+/// A connection to the other player, being made or made, whose reading and
+/// writing happen on threads of its own. Dropping it hangs up.
+struct Connection {
+    /// Lines to send, as JSON
+    outgoing: Sender<Vec<u8>>,
+    // Behind a lock only because resources have to be `Sync`
+    incoming: Mutex<Receiver<NetEvent>>,
+}
+
+impl Connection {
+    fn open(dial: Dial) -> Connection {
+        let (outgoing, to_send) = channel::<Vec<u8>>();
+        let (events, incoming) = channel();
+        thread::spawn(move || {
+            // The lobby drops its end of the channel once it stops waiting
+            let cancelled = || {
+                matches!(to_send.try_recv(), Err(TryRecvError::Disconnected))
+            };
+            let (reader, local) = match dial.connect(cancelled) {
+                Ok(connected) => connected,
+                Err(e) => {
+                    let _ = events.send(NetEvent::Closed(e.to_string()));
+                    return;
+                }
+            };
+            let Ok(mut writer) = reader.get_ref().try_clone() else {
+                let _ = events.send(NetEvent::Closed("Connection lost".into()));
+                return;
+            };
+            let _ = events.send(NetEvent::Connected(local));
+            thread::spawn(move || read_messages(reader, events));
+            for line in to_send {
+                if writer.write_all(&line).is_err() {
+                    break;
+                }
+            }
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+        Connection {
+            outgoing,
+            incoming: Mutex::new(incoming),
+        }
+    }
+
+    /// What happened since the last call
+    fn events(&self) -> Vec<NetEvent> {
+        let incoming = self.incoming.lock().unwrap();
+        incoming.try_iter().collect()
+    }
+
+    fn send(&self, message: &impl Serialize) {
+        let Ok(mut line) = serde_json::to_vec(message) else {
+            return;
+        };
+        line.push(b'\n');
+        // A closed connection is reported by the reading thread
+        let _ = self.outgoing.send(line);
+    }
+}
+
+/// Read the next line the other player sends, or why there isn't one
+fn read_line(reader: &mut BufReader<TcpStream>) -> Result<Vec<u8>, String> {
+    let mut line = Vec::new();
+    // Lines are limited, so the other side can't run this one out of memory
+    let read = reader
+        .take(NET_MAX_MESSAGE_BYTES)
+        .read_until(b'\n', &mut line);
+    match read {
+        Ok(0) => Err("The other player left".to_string()),
+        Ok(_) => Ok(line),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Check the other player's hello, then pass on each message they send,
+/// until they hang up or send something that isn't a message. Nothing is
+/// read past a hello whose protocol versions don't overlap with this side's.
+fn read_messages(mut reader: BufReader<TcpStream>, events: Sender<NetEvent>) {
+    let greeted = read_line(&mut reader).and_then(|line| {
+        let hello: Hello = serde_json::from_slice(&line).map_err(|_| {
+            "The other player's game is too old to say which protocol it \
+             speaks. Both need the same version of the game."
+                .to_string()
+        })?;
+        let protocol = hello.negotiate()?;
+        Ok(NetEvent::Greeted(hello, protocol))
+    });
+    let reason = match greeted {
+        Ok(greeted) => {
+            if events.send(greeted).is_err() {
+                return;
+            }
+            loop {
+                let line = match read_line(&mut reader) {
+                    Ok(line) => line,
+                    Err(reason) => break reason,
+                };
+                match serde_json::from_slice(&line) {
+                    Ok(message) => {
+                        if events.send(NetEvent::Received(message)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => break format!("Unreadable message: {e}"),
+                }
+            }
+        }
+        Err(reason) => reason,
+    };
+    let _ = reader.get_ref().shutdown(Shutdown::Both);
+    let _ = events.send(NetEvent::Closed(reason));
+}
+
+/// A network match waiting for the other player to come back after the
+/// connection dropped
+struct Reconnect {
+    /// Time they have left to come back, counted in real time as the match
+    /// is paused meanwhile
+    window: Timer,
+    /// Time until the connection is tried again
+    redial: Timer,
+}
+
+/// The network lobby: the connection to the other player, if any, and what
+/// is going on with it
+#[derive(Resource)]
+pub struct NetLobby {
+    /// Address to host on, join or find the relay at, as typed in
+    pub address: String,
+    /// Room on the relay to meet in. Empty to connect directly.
+    pub room: String,
+    /// How long to wait for a player whose connection dropped during a
+    /// match, in seconds
+    pub reconnect_seconds: u32,
+    /// How the connection was made, to make it again if it drops
+    dialed: Option<Dial>,
+    /// Set while the connection of the match being played is down
+    reconnecting: Option<Reconnect>,
+    connection: Option<Connection>,
+    /// The side this copy plays, once connected
+    local: Option<PlayerSelect>,
+    /// The protocol version agreed on with the other side, once they said
+    /// hello
+    protocol: Option<u32>,
+    status: String,
+    /// Soldiers as the current match started on each side, until they have
+    /// been compared
+    spawns: [Option<Spawn>; 2],
+    /// The other player's turns that can't be replayed yet, as the previous
+    /// shot is still being shown here
+    pending: VecDeque<RemoteTurn>,
+    /// Whether the other side is owed the host's copy of the match, to be
+    /// sent between turns
+    resync_due: bool,
+    /// Whether the other side was asked for its copy of the match
+    awaiting_state: bool,
+    /// The other side's copy of the match, to carry on from between turns
+    resync: Option<Box<PlayPhase>>,
+    /// Turn whose start was last hashed here
+    hashed_turn: Option<u32>,
+    /// Hashes of the match as turns started, by turn number, on this side
+    /// and on the other, until they have been compared
+    hashes: [Vec<(u32, u64)>; 2],
+    /// How the two sides' copies of the match were found to differ, while
+    /// the player is asked whether to carry on from the host's
+    desync: Option<String>,
+}
+
+impl Default for NetLobby {
+    fn default() -> Self {
+        Self {
+            address: NET_DEFAULT_ADDRESS.to_string(),
+            room: String::new(),
+            reconnect_seconds: NET_RECONNECT_SECONDS,
+            dialed: None,
+            reconnecting: None,
+            connection: None,
+            local: None,
+            protocol: None,
+            status: "Not connected".to_string(),
+            spawns: [None, None],
+            pending: VecDeque::new(),
+            resync_due: false,
+            awaiting_state: false,
+            resync: None,
+            hashed_turn: None,
+            hashes: [Vec::new(), Vec::new()],
+            desync: None,
+        }
+    }
+}
+
+impl NetLobby {
+    fn dial(&mut self, dial: Dial) {
+        self.status = match &dial {
+            Dial::Host(address) => format!("Waiting for a player on {address}"),
+            Dial::Join(address) => format!("Connecting to {address}"),
+            Dial::Relay { room, .. } => format!("Waiting in room {room}"),
+        };
+        self.dialed = Some(dial.clone());
+        self.connection = Some(Connection::open(dial));
+    }
+
+    fn hang_up(&mut self, status: String) {
+        *self = NetLobby {
+            address: std::mem::take(&mut self.address),
+            room: std::mem::take(&mut self.room),
+            reconnect_seconds: self.reconnect_seconds,
+            status,
+            ..NetLobby::default()
+        };
+    }
+
+    /// Forget about the last match, as a new one starts
+    fn new_match(&mut self) {
+        self.spawns = [None, None];
+        self.pending.clear();
+        self.resync_due = false;
+        self.awaiting_state = false;
+        self.resync = None;
+        self.hashed_turn = None;
+        self.hashes = [Vec::new(), Vec::new()];
+        self.desync = None;
+    }
+
+    /// Compare the hashes both sides sent for the start of the same turn,
+    /// forgetting those of the turns up to it, and return how they differ if
+    /// they do
+    fn compare_hashes(&mut self) -> Option<String> {
+        let [local, theirs] = &self.hashes;
+        let (turn_number, local, theirs) =
+            local.iter().find_map(|&(turn_number, local)| {
+                (theirs.iter())
+                    .find(|i| i.0 == turn_number)
+                    .map(|&(_, theirs)| (turn_number, local, theirs))
+            })?;
+        for hashes in &mut self.hashes {
+            hashes.retain(|i| i.0 > turn_number);
+        }
+        (local != theirs).then(|| {
+            format!(
+                "The boards differ at the start of turn {turn_number} (yours \
+                 is {local:016x}, theirs {theirs:016x})"
+            )
+        })
+    }
+
+    /// Pause the match to ask the player whether to carry on from the
+    /// host's copy, unless that is already under way
+    fn report_desync(
+        &mut self,
+        announcements: &mut EventWriter<Announce>,
+        reason: String,
+    ) {
+        if self.desync.is_some() || self.awaiting_state || self.resync_due {
+            return;
+        }
+        announcements.send(Announce(format!(
+            "Out of sync with the other player: {reason}"
+        )));
+        self.desync = Some(reason);
+    }
+
+    /// Send a message, once a protocol was agreed on that has it. Messages
+    /// can't go out before the hello, which has to be the first line.
+    fn send(&self, message: Message) {
+        if let Some(connection) = &self.connection
+            && (self.protocol)
+                .is_some_and(|protocol| message.since() <= protocol)
+        {
+            connection.send(&message);
+        }
+    }
+
+    /// Whether this copy hosts the match and the other player is there
+    fn hosting(&self) -> bool {
+        self.protocol.is_some() && self.local == Some(PlayerSelect::Player1)
+    }
+}
+
+/// Soldiers of both players in the form sent over the network
+fn spawn_of(state: &PlayPhase) -> Spawn {
+    Board::of(state)
+        .soldiers
+        .map(|team| team.iter().map(|&(id, at)| (id, at.x, at.y)).collect())
+}
+
+/// The side this copy plays in the match being played, if it is a network
+/// match
+fn network_side(state: &GameState) -> Option<NetworkSide> {
+    (state.playing_state())
+        .and_then(PlayPhase::correspondence)
+        .filter(|i| i.networked)
+        .map(|i| NetworkSide {
+            seed: i.match_id,
+            local: i.local,
+        })
+}
+
+/// Whether the match being played is a network match
+fn network_match(state: &GameState) -> bool {
+    network_side(state).is_some()
+}
+
+/// Connect to another player, or hang up, from the setup of a network match
+pub fn lobby_ui(
+    mut contexts: EguiContexts,
+    state: Res<GameState>,
+    mut lobby: ResMut<NetLobby>,
+) {
+    let network_setup = (state.setup_state())
+        .is_some_and(|setup| setup.mode == MatchMode::Network);
+    if !network_setup {
+        return;
+    }
+    egui::Window::new("Network match")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10., 10.))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let idle = lobby.connection.is_none();
+            ui.add_enabled_ui(idle, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Address:");
+                    ui.text_edit_singleline(&mut lobby.address);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Relay room:");
+                    ui.text_edit_singleline(&mut lobby.room);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Wait for a dropped player:");
+                    ui.add(
+                        egui::DragValue::new(&mut lobby.reconnect_seconds)
+                            .range(0..=600)
+                            .suffix(" s"),
+                    );
+                });
+            });
+            let address = lobby.address.trim().to_string();
+            let room = lobby.room.trim().to_string();
+            ui.horizontal(|ui| {
+                if !idle {
+                    if ui.button("Disconnect").clicked() {
+                        lobby.hang_up("Not connected".to_string());
+                    }
+                } else if !room.is_empty() {
+                    if ui.button("Meet on relay").clicked() {
+                        lobby.dial(Dial::Relay { address, room });
+                    }
+                } else {
+                    if ui.button("Host").clicked() {
+                        lobby.dial(Dial::Host(address.clone()));
+                    }
+                    if ui.button("Join").clicked() {
+                        lobby.dial(Dial::Join(address));
+                    }
+                }
+            });
+            ui.label(&lobby.status);
+        });
+}
+
+/// When the host presses Start, pick the seed of the board and send the
+/// setup to the other player, whose match starts when it arrives
+pub fn host_network_match(
+    mut events: EventReader<StartPlaying>,
+    mut state: ResMut<GameState>,
+    mut lobby: ResMut<NetLobby>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+    let Some(setup) = state.setup_state_mut() else {
+        return;
+    };
+    if setup.mode != MatchMode::Network
+        || lobby.local == Some(PlayerSelect::Player2)
+    {
+        return;
+    }
+    if !lobby.hosting() {
+        setup.network = None;
+        return;
+    }
+    // The guest would start a match the host can't
+    if setup.check().is_err() {
+        return;
+    }
+    let seed = rand::thread_rng().r#gen();
+    setup.network = Some(NetworkSide {
+        seed,
+        local: PlayerSelect::Player1,
+    });
+    lobby.new_match();
+    lobby.send(Message::Setup(NetSetup::of(setup, seed)));
+}
+
+/// The opponent of `player` in a network match
+fn other_player(player: PlayerSelect) -> PlayerSelect {
+    match player {
+        PlayerSelect::Player1 => PlayerSelect::Player2,
+        _ => PlayerSelect::Player1,
+    }
+}
+
+/// Pick the match up again with the other player, back after the connection
+/// dropped and playing the match `match_id`, if any. The host sends its
+/// copy of the match, unless the other side started over and only this
+/// side has one.
+fn rejoin(
+    state: &GameState,
+    lobby: &mut NetLobby,
+    announcements: &mut EventWriter<Announce>,
+    match_id: Option<u64>,
+) {
+    let Some(side) = network_side(state) else {
+        return;
+    };
+    if match_id.is_some_and(|id| id != side.seed) {
+        // Tried again later, in case the right player turns up
+        lobby.connection = None;
+        lobby.protocol = None;
+        lobby.status = "The other player is in another match".to_string();
+        return;
+    }
+    lobby.reconnecting = None;
+    lobby.status = "Connected".to_string();
+    if match_id.is_none() || lobby.hosting() {
+        lobby.resync_due = true;
+    } else {
+        lobby.awaiting_state = true;
+    }
+    announcements.send(Announce("The other player is back".to_string()));
+}
+
+/// Give up on the network match, back in the setup with the lobby saying
+/// why
+fn abandon_match(
+    state: &mut GameState,
+    lobby: &mut NetLobby,
+    announcements: &mut EventWriter<Announce>,
+    reason: String,
+) {
+    announcements.send(Announce(reason.clone()));
+    let mut setup = GameState::default().setup_state().unwrap().clone();
+    setup.mode = MatchMode::Network;
+    state.set_setup(setup);
+    lobby.hang_up(reason);
+}
+
+/// Handle what the other player sends, and send them the turns taken here:
+/// their turns are queued up to be replayed by `replay_turns` once their
+/// turn comes up here. The hashes of both sides' copies of the match are
+/// compared as every turn starts, and when they differ, or a turn doesn't
+/// follow on from the board it reaches, the player is asked whether to carry
+/// on from the host's copy (see `desync_ui`).
+pub fn sync_network(
+    mut state: ResMut<GameState>,
+    mut lobby: ResMut<NetLobby>,
+    mut start_playing_events: EventWriter<StartPlaying>,
+    mut announcements: EventWriter<Announce>,
+    mut skip_graphing_events: EventWriter<SkipGraphingEvent>,
+    mut resynced: EventWriter<Resynced>,
+    mut chat: ResMut<Chat>,
+) {
+    let events = (lobby.connection.as_ref())
+        .map(Connection::events)
+        .unwrap_or_default();
+    for event in events {
+        match event {
+            NetEvent::Connected(local) => {
+                // A match carried on after the connection dropped keeps its
+                // sides, whoever got to the relay first
+                let side = network_side(&state);
+                lobby.local = Some(side.map_or(local, |i| i.local));
+                lobby.status = "Connected, checking versions".to_string();
+                if let Some(connection) = &lobby.connection {
+                    connection.send(&Hello::new(side.map(|i| i.seed)));
+                }
+            }
+            NetEvent::Greeted(hello, protocol) => {
+                lobby.protocol = Some(protocol);
+                if lobby.reconnecting.is_some() {
+                    let match_id = hello.match_id;
+                    rejoin(&state, &mut lobby, &mut announcements, match_id);
+                    continue;
+                }
+                lobby.status = if lobby.hosting() {
+                    "Connected. Press Start to play".to_string()
+                } else {
+                    "Connected. Waiting for the host to start".to_string()
+                };
+            }
+            NetEvent::Received(Message::Setup(setup)) => {
+                if lobby.local != Some(PlayerSelect::Player2)
+                    || state.playing_state().is_some()
+                {
+                    continue;
+                }
+                if state.finished_state().is_some() {
+                    state.set_setup(
+                        GameState::default().setup_state().unwrap().clone(),
+                    );
+                }
+                if let Some(setup_state) = state.setup_state_mut() {
+                    lobby.new_match();
+                    setup.apply(setup_state);
+                    start_playing_events.send(StartPlaying);
+                }
+            }
+            NetEvent::Received(Message::Skip) => {
+                lobby.pending.push_back(RemoteTurn::Skip);
+            }
+            NetEvent::Received(Message::Spawn(soldiers)) => {
+                lobby.spawns[1] = Some(soldiers);
+            }
+            NetEvent::Received(Message::Turn(code)) => {
+                match TurnFile::from_code(&code) {
+                    Ok(turn) => lobby.pending.push_back(RemoteTurn::Shot(turn)),
+                    Err(e) => {
+                        log::warn!("Turn from the network turned away: {e}")
+                    }
+                }
+            }
+            NetEvent::Received(Message::Emote(emote)) => {
+                if let Some(side) = network_side(&state) {
+                    chat.emote(other_player(side.local), emote.into());
+                }
+            }
+            NetEvent::Received(Message::Chat(text)) => {
+                if let Some(side) = network_side(&state) {
+                    chat.say(other_player(side.local), &text);
+                }
+            }
+            NetEvent::Received(Message::OutOfSync) => {
+                lobby.resync_due = lobby.hosting();
+            }
+            NetEvent::Received(Message::StateHash { turn_number, hash }) => {
+                lobby.hashes[1].push((turn_number, hash));
+            }
+            NetEvent::Received(Message::State { game, side }) => {
+                let game = match decode(&game) {
+                    Ok(game) => game,
+                    Err(e) if network_match(&state) => {
+                        let reason = format!(
+                            "Couldn't carry on from the other side's board, \
+                             so the match is over: {e}"
+                        );
+                        abandon_match(
+                            &mut state,
+                            &mut lobby,
+                            &mut announcements,
+                            reason,
+                        );
+                        return;
+                    }
+                    Err(e) => {
+                        lobby.status =
+                            format!("Couldn't rejoin the match: {e}");
+                        continue;
+                    }
+                };
+                if network_match(&state) {
+                    lobby.resync = Some(game);
+                    continue;
+                }
+                // Back in a match this side dropped out of, having started
+                // over since
+                let network_setup = (state.setup_state())
+                    .is_some_and(|setup| setup.mode == MatchMode::Network);
+                if !network_setup || state.resume(game).is_err() {
+                    continue;
+                }
+                let Some(playing_state) = state.playing_state_mut() else {
+                    continue;
+                };
+                playing_state.play_networked(side);
+                lobby.new_match();
+                lobby.local = Some(side.local);
+                lobby.spawns[0] = Some(spawn_of(playing_state));
+                resynced.send(Resynced);
+                announcements
+                    .send(Announce("Back in the network match".to_string()));
+            }
+            NetEvent::Closed(reason) => {
+                if network_match(&state) && lobby.dialed.is_some() {
+                    if lobby.reconnecting.is_none() {
+                        let seconds = lobby.reconnect_seconds;
+                        announcements.send(Announce(format!(
+                            "Lost the connection to the other player \
+                             ({reason}). Waiting {seconds} seconds for them to \
+                             come back."
+                        )));
+                        lobby.reconnecting = Some(Reconnect {
+                            window: Timer::from_seconds(
+                                seconds as f32,
+                                TimerMode::Once,
+                            ),
+                            redial: Timer::new(
+                                NET_REDIAL_INTERVAL,
+                                TimerMode::Repeating,
+                            ),
+                        });
+                    }
+                    lobby.connection = None;
+                    lobby.protocol = None;
+                    lobby.status = format!("Reconnecting: {reason}");
+                    return;
+                }
+                if network_match(&state) {
+                    announcements.send(Announce(format!(
+                        "Lost the connection to the other player: {reason}"
+                    )));
+                }
+                lobby.hang_up(reason);
+                return;
+            }
+        }
+    }
+
+    if !network_match(&state) {
+        return;
+    }
+    for said in std::mem::take(&mut chat.outgoing) {
+        lobby.send(match said {
+            Said::Emote(emote) => Message::Emote(emote as u8),
+            Said::Line(text) => Message::Chat(text),
+        });
+    }
+    let Some(playing_state) = state.playing_state_mut() else {
+        return;
+    };
+    if lobby.spawns[0].is_none() {
+        let spawn = spawn_of(playing_state);
+        lobby.send(Message::Spawn(spawn.clone()));
+        lobby.spawns[0] = Some(spawn);
+    }
+    if let [Some(local), Some(theirs)] = &lobby.spawns {
+        if local != theirs {
+            let hash =
+                |spawn: &Spawn| fnv1a(&serde_json::to_vec(spawn).unwrap());
+            let reason = format!(
+                "The boards differ from the start (yours is {:016x}, theirs \
+                 {:016x})",
+                hash(local),
+                hash(theirs)
+            );
+            lobby.report_desync(&mut announcements, reason);
+        }
+        lobby.spawns[1] = None;
+    }
+    // The match is only swapped out between turns, not while a shot is
+    // being shown
+    if playing_state.turn_phase().is_input() {
+        if let Some(game) = lobby.resync.take() {
+            playing_state.resync(game);
+            resynced.send(Resynced);
+            lobby.pending.clear();
+            lobby.awaiting_state = false;
+            lobby.desync = None;
+            // The host's hashes still hold, and are checked against this
+            // copy from now on
+            lobby.hashed_turn = None;
+            lobby.hashes[0].clear();
+            announcements.send(Announce(
+                "Carrying on from the host's board".to_string(),
+            ));
+            return;
+        }
+        if std::mem::take(&mut lobby.resync_due)
+            && let Some(correspondence) = playing_state.correspondence()
+        {
+            let side = NetworkSide {
+                seed: correspondence.match_id,
+                local: other_player(correspondence.local),
+            };
+            match encode(playing_state, 0) {
+                Ok(game) => lobby.send(Message::State { game, side }),
+                Err(e) => {
+                    let reason = format!(
+                        "Couldn't send the board to the other player, so the \
+                         match is over: {e}"
+                    );
+                    abandon_match(
+                        &mut state,
+                        &mut lobby,
+                        &mut announcements,
+                        reason,
+                    );
+                    return;
+                }
+            }
+            lobby.pending.clear();
+            lobby.desync = None;
+            lobby.hashed_turn = None;
+            lobby.hashes = [Vec::new(), Vec::new()];
+            if let Some(correspondence) = playing_state.correspondence_mut() {
+                correspondence.rejected = None;
+            }
+        }
+        // Hash the match once per turn, as it starts
+        let turn_number = playing_state.turn_number();
+        if lobby.hashed_turn != Some(turn_number) {
+            let hash = playing_state.state_hash();
+            lobby.hashed_turn = Some(turn_number);
+            lobby.hashes[0].push((turn_number, hash));
+            lobby.send(Message::StateHash { turn_number, hash });
+        }
+    }
+    if lobby.desync.is_none()
+        && let Some(reason) = lobby.compare_hashes()
+    {
+        lobby.report_desync(&mut announcements, reason);
+    }
+    let remote_input =
+        playing_state.turn_phase().is_input() && playing_state.is_remote_turn();
+    let Some(correspondence) = playing_state.correspondence_mut() else {
+        return;
+    };
+    if let Some(error) = &correspondence.rejected {
+        lobby.report_desync(&mut announcements, error.to_string());
+    }
+    if let Some(code) = correspondence.outgoing.take() {
+        lobby.send(Message::Turn(code));
+    }
+    if std::mem::take(&mut correspondence.skipped) {
+        lobby.send(Message::Skip);
+    }
+    if !remote_input || correspondence.incoming.is_some() {
+        return;
+    }
+    match lobby.pending.pop_front() {
+        Some(RemoteTurn::Shot(turn)) => correspondence.incoming = Some(turn),
+        Some(RemoteTurn::Skip) => {
+            playing_state.skip_turn();
+            skip_graphing_events.send(SkipGraphingEvent);
+        }
+        None => {}
+    }
+}
+
+/// While the connection of a network match is down, keep the match paused
+/// and keep connecting again, until the other player is back or their time
+/// is up and the computer takes over their turns. The match also stays
+/// paused while the two sides are out of sync, until the player decides what
+/// to do about it and the other side's copy arrives.
+pub fn reconnect_network(
+    mut contexts: EguiContexts,
+    mut state: ResMut<GameState>,
+    mut lobby: ResMut<NetLobby>,
+    real_time: Res<Time<Real>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut announcements: EventWriter<Announce>,
+    mut paused: Local<bool>,
+) {
+    if lobby.reconnecting.is_some() && !network_match(&state) {
+        lobby.hang_up("Not connected".to_string());
+    }
+    let pause = lobby.reconnecting.is_some()
+        || lobby.desync.is_some()
+        || (lobby.awaiting_state && lobby.resync.is_none());
+    if pause != *paused {
+        if pause {
+            virtual_time.pause();
+        } else {
+            virtual_time.unpause();
+        }
+        *paused = pause;
+    }
+    let Some(side) = network_side(&state) else {
+        return;
+    };
+    let Some(reconnect) = &mut lobby.reconnecting else {
+        return;
+    };
+    let mut give_up = reconnect.window.tick(real_time.delta()).finished();
+    let redial = reconnect.redial.tick(real_time.delta()).just_finished();
+    let seconds_left = reconnect.window.remaining_secs().ceil();
+    let Some(playing_state) = state.playing_state_mut() else {
+        return;
+    };
+    let opponent = &playing_state.player(other_player(side.local)).name;
+    egui::Window::new("Connection lost")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0., 0.))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Waiting {seconds_left} more seconds for {opponent} to come \
+                 back"
+            ));
+            ui.label(&lobby.status);
+            if ui.button("Play on against the computer").clicked() {
+                give_up = true;
+            }
+        });
+    if give_up {
+        announcements.send(Announce(format!(
+            "{opponent} didn't come back. The computer plays for them from \
+             here."
+        )));
+        playing_state.play_offline();
+        lobby.hang_up("The other player left".to_string());
+        return;
+    }
+    if redial
+        && lobby.connection.is_none()
+        && let Some(dial) = lobby.dialed.clone()
+    {
+        lobby.dial(dial);
+    }
+}
+
+/// Once the two sides' copies of a network match were found to differ, show
+/// how, with both hashes, and let the player carry on from the host's copy
+/// or leave the match
+pub fn desync_ui(
+    mut contexts: EguiContexts,
+    mut state: ResMut<GameState>,
+    mut lobby: ResMut<NetLobby>,
+    mut announcements: EventWriter<Announce>,
+) {
+    let Some(reason) = lobby.desync.clone() else {
+        return;
+    };
+    if !network_match(&state) {
+        lobby.desync = None;
+        return;
+    }
+    let hosting = lobby.hosting();
+    let (mut accept, mut leave) = (false, false);
+    egui::Window::new("Out of sync")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0., 0.))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(reason);
+            ui.label(if hosting {
+                "Send the other player your board to carry on from?"
+            } else {
+                "Carry on from the host's board?"
+            });
+            ui.horizontal(|ui| {
+                let label = if hosting {
+                    "Send my board"
+                } else {
+                    "Take the host's board"
+                };
+                accept = ui.button(label).clicked();
+                leave = ui.button("Leave the match").clicked();
+            });
+        });
+    if accept {
+        lobby.desync = None;
+        if hosting {
+            lobby.resync_due = true;
+        } else {
+            lobby.send(Message::OutOfSync);
+            lobby.awaiting_state = true;
+        }
+    } else if leave {
+        let reason = "Left the match, as the boards differed".to_string();
+        abandon_match(&mut state, &mut lobby, &mut announcements, reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Wait for the next thing to happen on a connection
+    fn next_event(connection: &Connection) -> NetEvent {
+        let incoming = connection.incoming.lock().unwrap();
+        incoming.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn test_setup() {
+        let mut host = GameState::default().setup_state().unwrap().clone();
+        host.players[0].name = "Ada".to_string();
+        host.players[1].soldier_num = NonZeroU8::new(4).unwrap();
+        host.turn_seconds = 45;
+        host.obstacles = 2;
+        host.budget = Some(12);
+        host.cooldown = NonZeroU8::new(2);
+        host.idle_forfeit = None;
+
+        let line =
+            serde_json::to_string(&Message::Setup(NetSetup::of(&host, 1976)))
+                .unwrap();
+        let Ok(Message::Setup(setup)) = serde_json::from_str(&line) else {
+            panic!("{line} should be a setup");
+        };
+        let mut guest = GameState::default().setup_state().unwrap().clone();
+        guest.players.push(PlayerConfig::new(3));
+        guest.turn_seconds = 10;
+        setup.apply(&mut guest);
+
+        assert_eq!(guest.mode, MatchMode::Network);
+        assert_eq!(
+            guest.network,
+            Some(NetworkSide {
+                seed: 1976,
+                local: PlayerSelect::Player2
+            })
+        );
+        assert_eq!(guest.players.len(), 2);
+        assert_eq!(guest.players[0].name, "Ada");
+        assert_eq!(guest.players[1].soldier_num.get(), 4);
+        assert_eq!(guest.turn_seconds, 45);
+        assert_eq!(guest.obstacles, 2);
+        assert_eq!(guest.budget, Some(12));
+        assert_eq!(guest.cooldown, NonZeroU8::new(2));
+        assert_eq!(guest.idle_forfeit, None);
+    }
+
+    #[test]
+    fn test_resync() {
+        let start = |local, obstacles| {
+            let mut state = GameState::default();
+            let setup = state.setup_state_mut().unwrap();
+            setup.mode = MatchMode::Network;
+            setup.obstacles = obstacles;
+            setup.network = Some(NetworkSide { seed: 1972, local });
+            state.start_playing().unwrap();
+            state
+        };
+        // Sides whose boards differ
+        let host = start(PlayerSelect::Player1, 2);
+        let mut guest = start(PlayerSelect::Player2, 0);
+        let host = host.playing_state().unwrap();
+        let guest = guest.playing_state_mut().unwrap();
+        assert_ne!(guest.obstacles(), host.obstacles());
+        assert_ne!(guest.state_hash(), host.state_hash());
+
+        let message = Message::State {
+            game: encode(host, 0).unwrap(),
+            side: NetworkSide {
+                seed: 1972,
+                local: PlayerSelect::Player2,
+            },
+        };
+        let line = serde_json::to_string(&message).unwrap();
+        let Ok(Message::State { game, .. }) = serde_json::from_str(&line)
+        else {
+            panic!("{line} should be a state");
+        };
+        guest.resync(decode(&game).unwrap());
+        assert_eq!(spawn_of(guest), spawn_of(host));
+        assert_eq!(guest.obstacles(), host.obstacles());
+        assert_eq!(guest.state_hash(), host.state_hash());
+        // Each side still plays its own player
+        assert_eq!(
+            guest.correspondence().map(|i| i.local),
+            Some(PlayerSelect::Player2)
+        );
+        assert!(guest.is_remote_turn());
+
+        // A side that started over picks the match up from the copy
+        let mut restarted = GameState::default();
+        restarted.resume(decode(&game).unwrap()).unwrap();
+        let restarted = restarted.playing_state_mut().unwrap();
+        restarted.play_networked(NetworkSide {
+            seed: 1972,
+            local: PlayerSelect::Player2,
+        });
+        assert_eq!(spawn_of(restarted), spawn_of(host));
+        assert!(restarted.is_remote_turn());
+
+        // Once the other player is gone for good, the computer plays for them
+        restarted.play_offline();
+        assert!(restarted.correspondence().is_none());
+        assert!(restarted.player(PlayerSelect::Player1).ai);
+        assert!(!restarted.player(PlayerSelect::Player2).ai);
+    }
+
+    #[test]
+    fn test_protocol() {
+        let hello = |oldest_protocol, protocol| Hello {
+            protocol,
+            oldest_protocol,
+            version: "9.9.9".to_string(),
+            match_id: None,
+        };
+        assert_eq!(Hello::new(None).negotiate(), Ok(PROTOCOL_VERSION));
+        // A newer game that still speaks this one's protocol plays on it
+        let newer = hello(OLDEST_PROTOCOL_VERSION, PROTOCOL_VERSION + 3);
+        assert_eq!(newer.negotiate(), Ok(PROTOCOL_VERSION));
+        // One that doesn't is turned away, naming both versions
+        let error = hello(PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 3)
+            .negotiate()
+            .unwrap_err();
+        assert!(error.contains("9.9.9"));
+        assert!(error.contains(GAME_VERSION));
+        // Hellos from later versions may have more to say
+        let line = r#"{"protocol":7,"oldest_protocol":1,"version":"9.9.9",
+            "match_id":4,"nickname":"Ada"}"#;
+        let hello: Hello = serde_json::from_str(line).unwrap();
+        assert_eq!(hello.negotiate(), Ok(PROTOCOL_VERSION));
+        assert_eq!(hello.match_id, Some(4));
+    }
+
+    #[test]
+    fn test_compare_hashes() {
+        let mut lobby = NetLobby {
+            hashes: [vec![(1, 10), (2, 20)], vec![(1, 10)]],
+            ..NetLobby::default()
+        };
+        assert_eq!(lobby.compare_hashes(), None);
+        assert_eq!(lobby.hashes, [vec![(2, 20)], vec![]]);
+        // The other side may be a turn ahead
+        lobby.hashes[1].extend([(2, 21), (3, 30)]);
+        let desync = lobby.compare_hashes().unwrap();
+        assert!(desync.contains("0000000000000014"), "{desync}");
+        assert!(desync.contains("0000000000000015"), "{desync}");
+        assert_eq!(lobby.hashes, [vec![], vec![(3, 30)]]);
+        assert_eq!(lobby.compare_hashes(), None);
+    }
+
+    #[test]
+    fn test_connection() {
+        let address = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+            .to_string();
+        let host = Connection::open(Dial::Host(address.clone()));
+        // The host may not be listening yet
+        let guest = loop {
+            let guest = Connection::open(Dial::Join(address.clone()));
+            match next_event(&guest) {
+                NetEvent::Connected(local) => {
+                    assert_eq!(local, PlayerSelect::Player2);
+                    break guest;
+                }
+                _ => thread::sleep(NET_ACCEPT_INTERVAL),
+            }
+        };
+        let NetEvent::Connected(local) = next_event(&host) else {
+            panic!("the host should be connected");
+        };
+        assert_eq!(local, PlayerSelect::Player1);
+
+        // Nothing is passed on before the hello
+        host.send(&Hello::new(Some(1971)));
+        let NetEvent::Greeted(hello, protocol) = next_event(&guest) else {
+            panic!("the guest should get the host's hello");
+        };
+        assert_eq!(hello.match_id, Some(1971));
+        assert_eq!(protocol, PROTOCOL_VERSION);
+
+        host.send(&Message::Turn("abc".to_string()));
+        let NetEvent::Received(message) = next_event(&guest) else {
+            panic!("the guest should get the turn");
+        };
+        assert_eq!(message, Message::Turn("abc".to_string()));
+
+        drop(guest);
+        assert!(matches!(next_event(&host), NetEvent::Closed(_)));
+    }
+}
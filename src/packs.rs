@@ -0,0 +1,224 @@
+use crate::settings::Settings;
+use bevy::asset::io::file::FileAssetReader;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// Asset path of the default explosion sprite
+const DEFAULT_EXPLOSION_SPRITE: &str = "explosion.png";
+
+/// Asset path of the default explosion sound
+const DEFAULT_EXPLOSION_SOUND: &str = "explosion.mp3";
+
+/// Directory (inside the assets folder) of the built-in soldier skins
+const SKINS_DIR: &str = "skins";
+
+/// Directory (inside the assets folder) that holds asset packs
+const PACKS_DIR: &str = "packs";
+
+/// Contents of a pack's `pack.ron`. All paths are relative to the pack
+/// directory, and anything left out falls back to the built-in assets.
+///
+/// ```ron
+/// (
+///     name: "Retro",
+///     explosion_sprite: Some("boom.png"),
+///     explosion_sound: Some("boom.ogg"),
+///     soldier_icons: { "tank": "tank.png" },
+///     soldier_sheets: {
+///         "wizard": (
+///             file: "wizard_sheet.png",
+///             tile_size: (64, 64),
+///             columns: 4,
+///             rows: 3,
+///             fps: 8.0,
+///             idle: (0, 3),
+///             flinch: (4, 5),
+///             cheer: (8, 11),
+///         ),
+///     },
+/// )
+/// ```
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PackManifest {
+    pub name: String,
+    pub explosion_sprite: Option<String>,
+    pub explosion_sound: Option<String>,
+    /// Soldier skin sprites keyed by `SoldierSkin::sprite_key`
+    pub soldier_icons: HashMap<String, String>,
+    /// Animated soldier skins keyed by `SoldierSkin::sprite_key`. These take
+    /// priority over `soldier_icons`.
+    pub soldier_sheets: HashMap<String, SpriteSheet>,
+}
+
+/// A grid of animation frames in a single image. Frame ranges are inclusive
+/// and count row by row from the top left.
+#[derive(Deserialize, Clone, Debug)]
+pub struct SpriteSheet {
+    pub file: String,
+    pub tile_size: (u32, u32),
+    pub columns: u32,
+    pub rows: u32,
+    pub fps: f32,
+    pub idle: (usize, usize),
+    pub flinch: (usize, usize),
+    pub cheer: (usize, usize),
+}
+
+/// The currently selected asset pack. Anything that loads a themeable asset
+/// should go through this instead of hard-coding a path.
+#[derive(Resource, Default)]
+pub struct AssetPack {
+    id: Option<String>,
+    manifest: PackManifest,
+}
+
+impl AssetPack {
+    fn resolve(&self, file: &Option<String>, default: &str) -> String {
+        match (&self.id, file) {
+            (Some(id), Some(file)) => format!("{PACKS_DIR}/{id}/{file}"),
+            _ => default.to_string(),
+        }
+    }
+    pub fn explosion_sprite(&self) -> String {
+        self.resolve(&self.manifest.explosion_sprite, DEFAULT_EXPLOSION_SPRITE)
+    }
+    pub fn explosion_sound(&self) -> String {
+        self.resolve(&self.manifest.explosion_sound, DEFAULT_EXPLOSION_SOUND)
+    }
+    pub fn soldier_icon(&self, key: &str) -> String {
+        self.resolve(
+            &self.manifest.soldier_icons.get(key).cloned(),
+            &format!("{SKINS_DIR}/{key}.png"),
+        )
+    }
+    /// The animated sprite sheet for a soldier skin, together with the asset
+    /// path of its image, if the pack provides one
+    pub fn soldier_sheet(&self, key: &str) -> Option<(String, &SpriteSheet)> {
+        let sheet = self.manifest.soldier_sheets.get(key)?;
+        Some((self.resolve(&Some(sheet.file.clone()), ""), sheet))
+    }
+}
+
+fn packs_dir() -> PathBuf {
+    FileAssetReader::get_base_path()
+        .join("assets")
+        .join(PACKS_DIR)
+}
+
+/// Whether `file` is a relative path that stays inside the pack directory
+/// and points at an existing file. Warns about the file if it doesn't.
+fn pack_file_is_valid(id: &str, file: &str) -> bool {
+    let path = Path::new(file);
+    let stays_inside = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if stays_inside && packs_dir().join(id).join(path).is_file() {
+        return true;
+    }
+    log::warn!("Asset pack `{id}` refers to missing or invalid file `{file}`");
+    false
+}
+
+/// Whether the layout and frame ranges of a sprite sheet make sense. Warns
+/// about the sheet if they don't.
+fn sheet_is_valid(id: &str, key: &str, sheet: &SpriteSheet) -> bool {
+    let frame_count = (sheet.columns * sheet.rows) as usize;
+    let problem = if sheet.tile_size.0 == 0 || sheet.tile_size.1 == 0 {
+        Some("tile size is zero".to_string())
+    } else if frame_count == 0 {
+        Some("sheet has no frames".to_string())
+    } else if sheet.fps.is_nan() || sheet.fps <= 0. {
+        Some("fps must be positive".to_string())
+    } else {
+        [
+            ("idle", sheet.idle),
+            ("flinch", sheet.flinch),
+            ("cheer", sheet.cheer),
+        ]
+        .into_iter()
+        .find(|(_, (first, last))| first > last || *last >= frame_count)
+        .map(|(name, (first, last))| {
+            format!(
+                "{name} frames {first}..={last} don't fit in \
+                     {frame_count} frames"
+            )
+        })
+    };
+    if let Some(problem) = problem {
+        log::warn!(
+            "Ignoring sprite sheet `{key}` of asset pack `{id}`: {problem}"
+        );
+        return false;
+    }
+    true
+}
+
+fn read_manifest(id: &str) -> Option<PackManifest> {
+    let contents =
+        std::fs::read_to_string(packs_dir().join(id).join("pack.ron")).ok()?;
+    let mut manifest: PackManifest = match ron::from_str(&contents) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            log::warn!("Invalid manifest for asset pack `{id}`: {e}");
+            return None;
+        }
+    };
+    // Drop anything that can't be loaded so it falls back to the built-in
+    // asset instead of silently not showing up
+    let valid = |file: &Option<String>| {
+        file.as_deref()
+            .is_some_and(|file| pack_file_is_valid(id, file))
+    };
+    if !valid(&manifest.explosion_sprite) {
+        manifest.explosion_sprite = None;
+    }
+    if !valid(&manifest.explosion_sound) {
+        manifest.explosion_sound = None;
+    }
+    manifest
+        .soldier_icons
+        .retain(|_, file| pack_file_is_valid(id, file));
+    manifest.soldier_sheets.retain(|key, sheet| {
+        pack_file_is_valid(id, &sheet.file) && sheet_is_valid(id, key, sheet)
+    });
+    Some(manifest)
+}
+
+/// List the installed packs as `(directory name, display name)` pairs
+pub fn available_packs() -> Vec<(String, String)> {
+    let Ok(entries) = std::fs::read_dir(packs_dir()) else {
+        return Vec::new();
+    };
+    let mut packs = entries
+        .filter_map(|entry| {
+            let id = entry.ok()?.file_name().into_string().ok()?;
+            let manifest = read_manifest(&id)?;
+            let name = if manifest.name.is_empty() {
+                id.clone()
+            } else {
+                manifest.name
+            };
+            Some((id, name))
+        })
+        .collect::<Vec<_>>();
+    packs.sort();
+    packs
+}
+
+/// Reload the asset pack manifest when the selected pack changes
+pub fn load_asset_pack(settings: Res<Settings>, mut pack: ResMut<AssetPack>) {
+    if pack.id == settings.asset_pack && !settings.is_added() {
+        return;
+    }
+    let manifest = settings.asset_pack.as_deref().and_then(read_manifest);
+    *pack = match manifest {
+        Some(manifest) => AssetPack {
+            id: settings.asset_pack.clone(),
+            manifest,
+        },
+        None => AssetPack::default(),
+    };
+}
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU8;
 use std::str::FromStr;
 use thiserror::Error;
 
@@ -16,6 +18,12 @@ impl ParsedFunction {
         }
         self.bound_vars.push(binding);
     }
+    /// Variables used in the function, in order of first appearance
+    pub fn variables(&self) -> Vec<char> {
+        let mut variables = Vec::new();
+        self.tree.collect_variables(&mut variables);
+        variables
+    }
     pub fn bind<T: ToString + Send + Sync>(
         &self,
         var: T,
@@ -32,6 +40,225 @@ impl ParsedFunction {
             )
         }
     }
+    /// The function printed in a canonical form after simplifying it, so
+    /// equations that are only written differently (terms in another order,
+    /// constant arithmetic, parameters instead of their values, ...) print
+    /// the same
+    pub fn canonical(&self) -> String {
+        self.tree.simplify(&self.bound_vars).to_string()
+    }
+    /// The function exactly as parsed, with the exact values of its
+    /// parameters, to tell apart functions that `canonical` rounds to the
+    /// same form
+    pub fn exact(&self) -> String {
+        // Debug prints numbers with as many digits as it takes to read them
+        // back unchanged
+        let mut exact = format!("{:?}", self.tree);
+        for (var, value) in &self.bound_vars {
+            exact.push_str(&format!(";{var}={:08x}", value.to_bits()));
+        }
+        exact
+    }
+    /// The tree the function was parsed into, for showing how it was
+    /// understood
+    pub fn parse_tree(&self) -> ParseTreeNode {
+        self.tree.parse_tree()
+    }
+    /// Points the function costs under the equation budget rule: a point for
+    /// each number, variable and operator, two for powers and three for
+    /// functions
+    pub fn cost(&self) -> u32 {
+        self.tree.cost()
+    }
+    /// Check that the function fits in the turn's budget, if there is one
+    pub fn check_budget(
+        &self,
+        budget: Option<u32>,
+    ) -> Result<(), RestrictionError> {
+        let cost = self.cost();
+        match budget {
+            Some(budget) if cost > budget => {
+                Err(RestrictionError::OverBudget { cost, budget })
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Check that the function isn't one of the `recent` canonical forms, if
+    /// repeats are on a cooldown
+    pub fn check_repeat(
+        &self,
+        cooldown: Option<NonZeroU8>,
+        recent: &[String],
+    ) -> Result<(), RestrictionError> {
+        match cooldown {
+            Some(turns) if recent.contains(&self.canonical()) => {
+                Err(RestrictionError::Repeated { turns: turns.get() })
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Check that the function is allowed by `restriction`. Powers can only
+    /// be told apart from other functions once any parameters in their
+    /// exponents are bound.
+    pub fn check(
+        &self,
+        restriction: Restriction,
+    ) -> Result<(), RestrictionError> {
+        let max_degree = match restriction {
+            Restriction::None => return Ok(()),
+            Restriction::NoTrig => {
+                let trig = self.tree.uses(SupportedFunction::Sine)
+                    || self.tree.uses(SupportedFunction::Atan2);
+                return if trig {
+                    Err(RestrictionError::Trig)
+                } else {
+                    Ok(())
+                };
+            }
+            Restriction::LinesOnly => 1,
+            Restriction::MaxDegree(max) => max,
+        };
+        match self.tree.degree(&self.constants()) {
+            Some(degree) if degree <= max_degree => Ok(()),
+            _ if restriction == Restriction::LinesOnly => {
+                Err(RestrictionError::NotALine)
+            }
+            Some(degree) => Err(RestrictionError::DegreeTooHigh {
+                degree,
+                max: max_degree,
+            }),
+            None => Err(RestrictionError::NotPolynomial),
+        }
+    }
+    /// What kind of shot the function makes: lines and polynomials by their
+    /// degree, and other functions by the families of functions of x in them
+    pub fn shot_type(&self) -> ShotType {
+        match self.tree.degree(&self.constants()) {
+            Some(0 | 1) => return ShotType::Line,
+            Some(_) => return ShotType::Curve,
+            None => (),
+        }
+        let (mut trig, mut sigmoid) = (false, false);
+        self.tree.families(&mut trig, &mut sigmoid);
+        match (trig, sigmoid) {
+            (true, true) => ShotType::Composite,
+            (true, false) => ShotType::Trig,
+            (false, true) => ShotType::Sigmoid,
+            (false, false) => ShotType::Curve,
+        }
+    }
+    /// The bound variables, and the constants players can use by name
+    fn constants(&self) -> Vec<(String, f32)> {
+        let mut vars = self.bound_vars.clone();
+        vars.push(("e".to_string(), std::f32::consts::E));
+        vars.push(("π".to_string(), std::f32::consts::PI));
+        vars
+    }
+}
+
+/// Kind of shot a function makes, for breaking players' stats down by it
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum ShotType {
+    /// Polynomials of degree 0 or 1
+    Line,
+    /// Polynomials of higher degrees, and functions like `sqrt(x)` or `1/x`
+    /// that aren't in another family
+    Curve,
+    /// Steps and switches: exponentials, powers of x in the exponent and
+    /// functions like `abs`, `sign` or `min`
+    Sigmoid,
+    /// Sine waves and other periodic functions
+    Trig,
+    /// Sigmoids and trig functions in the same shot
+    Composite,
+}
+
+impl ShotType {
+    pub const ALL: [ShotType; 5] = [
+        ShotType::Line,
+        ShotType::Curve,
+        ShotType::Sigmoid,
+        ShotType::Trig,
+        ShotType::Composite,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            ShotType::Line => "line",
+            ShotType::Curve => "curve",
+            ShotType::Sigmoid => "sigmoid",
+            ShotType::Trig => "trig",
+            ShotType::Composite => "composite",
+        }
+    }
+}
+
+/// A node of a parsed function's tree: a number, a variable, or an
+/// operation or function applied to its children
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseTreeNode {
+    pub label: String,
+    pub children: Vec<ParseTreeNode>,
+}
+
+/// Rule limiting the functions players may shoot
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize,
+)]
+pub enum Restriction {
+    #[default]
+    None,
+    /// Only straight lines
+    LinesOnly,
+    /// No trigonometric functions
+    NoTrig,
+    /// Only polynomials in x of at most this degree
+    MaxDegree(u32),
+}
+
+impl Restriction {
+    pub const ALL: [Restriction; 4] = [
+        Restriction::None,
+        Restriction::LinesOnly,
+        Restriction::NoTrig,
+        Restriction::MaxDegree(2),
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Restriction::None => "Any",
+            Restriction::LinesOnly => "Lines only",
+            Restriction::NoTrig => "No trig",
+            Restriction::MaxDegree(_) => "Polynomials",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum RestrictionError {
+    #[error("Only straight lines are allowed in this match")]
+    NotALine,
+    #[error("Trig functions aren't allowed in this match")]
+    Trig,
+    #[error("Only polynomials are allowed in this match")]
+    NotPolynomial,
+    #[error(
+        "Powers of x above {max} aren't allowed in this match (found {degree})"
+    )]
+    DegreeTooHigh { degree: u32, max: u32 },
+    #[error("This equation costs {cost} points, over the budget of {budget}")]
+    OverBudget { cost: u32, budget: u32 },
+    #[error("You already shot this equation in your last {turns} turns")]
+    Repeated { turns: u8 },
 }
 
 fn build_expression_tree(
@@ -42,12 +269,19 @@ fn build_expression_tree(
         let new = match token {
             RPNToken::Literal(num) => ExpressionNode::Literal(num),
             RPNToken::Variable(var) => ExpressionNode::Variable(var),
-            RPNToken::Function(func) => ExpressionNode::Function(
-                func,
-                Box::new(
-                    stack.pop().ok_or(TreeBuildError::MissingFunctionArg)?,
-                ),
-            ),
+            RPNToken::Function(func, args) => {
+                if args != func.arity() {
+                    return Err(TreeBuildError::ArgumentCount {
+                        expected: func.arity(),
+                        found: args,
+                    });
+                }
+                let first = stack
+                    .len()
+                    .checked_sub(args)
+                    .ok_or(TreeBuildError::MissingFunctionArg)?;
+                ExpressionNode::Function(func, stack.split_off(first))
+            }
             RPNToken::ExpressionOp(op) => {
                 let right = Box::new(
                     stack.pop().ok_or(TreeBuildError::MissingRightOperand)?,
@@ -102,6 +336,8 @@ pub enum TreeBuildError {
     MissingRightOperand,
     #[error("Missing function argument")]
     MissingFunctionArg,
+    #[error("Function takes {expected} arguments, but was given {found}")]
+    ArgumentCount { expected: usize, found: usize },
     #[error("Invalid expression: multiple nodes remain on stack")]
     RemainingNodes,
     #[error("Empty expression")]
@@ -113,7 +349,8 @@ enum ExpressionNode {
     Literal(f32),
     Variable(char),
     Operation(ExpressionOp, Box<ExpressionNode>, Box<ExpressionNode>),
-    Function(SupportedFunction, Box<ExpressionNode>),
+    /// A function applied to as many arguments as it takes
+    Function(SupportedFunction, Vec<ExpressionNode>),
 }
 
 #[derive(Debug, Error)]
@@ -127,6 +364,232 @@ pub enum EvalError {
 }
 
 impl ExpressionNode {
+    fn parse_tree(&self) -> ParseTreeNode {
+        let (label, children) = match self {
+            ExpressionNode::Literal(value) => (value.to_string(), Vec::new()),
+            ExpressionNode::Variable(var) => (var.to_string(), Vec::new()),
+            ExpressionNode::Function(func, args) => (
+                format!("{func:?}"),
+                args.iter().map(ExpressionNode::parse_tree).collect(),
+            ),
+            ExpressionNode::Operation(op, left, right) => {
+                let label = match op {
+                    ExpressionOp::Add => "+ add",
+                    ExpressionOp::Subtract => "− subtract",
+                    ExpressionOp::Multiply => "× multiply",
+                    ExpressionOp::Divide => "÷ divide",
+                    ExpressionOp::Power => "^ power",
+                };
+                let children = vec![left.parse_tree(), right.parse_tree()];
+                (label.to_string(), children)
+            }
+        };
+        ParseTreeNode { label, children }
+    }
+    fn collect_variables(&self, variables: &mut Vec<char>) {
+        match self {
+            ExpressionNode::Literal(_) => (),
+            ExpressionNode::Variable(var) => {
+                if !variables.contains(var) {
+                    variables.push(*var);
+                }
+            }
+            ExpressionNode::Operation(_, left, right) => {
+                left.collect_variables(variables);
+                right.collect_variables(variables);
+            }
+            ExpressionNode::Function(_, args) => {
+                for arg in args {
+                    arg.collect_variables(variables);
+                }
+            }
+        }
+    }
+    /// Fold constant arithmetic, substitute the bound variables (other than
+    /// x) and drop operations that do nothing, like adding 0
+    fn simplify(&self, vars: &[(String, f32)]) -> ExpressionNode {
+        use ExpressionNode::*;
+        match self {
+            Variable(var) if *var != 'x' => vars
+                .iter()
+                .find(|i| i.0 == var.to_string())
+                .map_or(Variable(*var), |&(_, value)| Literal(value)),
+            Literal(_) | Variable(_) => self.clone(),
+            Function(func, args) => {
+                let args = (args.iter())
+                    .map(|arg| arg.simplify(vars))
+                    .collect::<Vec<_>>();
+                let values = (args.iter())
+                    .map(|arg| match arg {
+                        Literal(value) => Some(*value),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if let Some(values) = values
+                    && let Ok(value) = func.apply(&values)
+                {
+                    return Literal(value);
+                }
+                Function(*func, args)
+            }
+            Operation(op, left, right) => {
+                let (left, right) = (left.simplify(vars), right.simplify(vars));
+                if let (Literal(l), Literal(r)) = (&left, &right)
+                    && let Ok(value) = op.apply(*l, *r)
+                {
+                    return Literal(value);
+                }
+                let identity = match op {
+                    ExpressionOp::Add => 0.,
+                    ExpressionOp::Multiply => 1.,
+                    _ => f32::NAN,
+                };
+                match (op, left, right) {
+                    (
+                        ExpressionOp::Add | ExpressionOp::Multiply,
+                        Literal(l),
+                        other,
+                    ) if l == identity => other,
+                    (
+                        ExpressionOp::Add | ExpressionOp::Subtract,
+                        other,
+                        Literal(0.),
+                    )
+                    | (
+                        ExpressionOp::Multiply
+                        | ExpressionOp::Divide
+                        | ExpressionOp::Power,
+                        other,
+                        Literal(1.),
+                    ) => other,
+                    (_, left, right) => {
+                        Operation(*op, Box::new(left), Box::new(right))
+                    }
+                }
+            }
+        }
+    }
+    /// Operands of a chain of the same commutative operation, such as all
+    /// the terms of a sum
+    fn operands<'a>(&'a self, op: ExpressionOp, into: &mut Vec<&'a Self>) {
+        match self {
+            ExpressionNode::Operation(inner, left, right) if *inner == op => {
+                left.operands(op, into);
+                right.operands(op, into);
+            }
+            _ => into.push(self),
+        }
+    }
+    fn cost(&self) -> u32 {
+        match self {
+            ExpressionNode::Literal(_) | ExpressionNode::Variable(_) => 1,
+            ExpressionNode::Operation(op, left, right) => {
+                let op_cost = if *op == ExpressionOp::Power { 2 } else { 1 };
+                op_cost + left.cost() + right.cost()
+            }
+            ExpressionNode::Function(_, args) => {
+                3 + args.iter().map(ExpressionNode::cost).sum::<u32>()
+            }
+        }
+    }
+    fn uses(&self, function: SupportedFunction) -> bool {
+        match self {
+            ExpressionNode::Literal(_) | ExpressionNode::Variable(_) => false,
+            ExpressionNode::Operation(_, left, right) => {
+                left.uses(function) || right.uses(function)
+            }
+            ExpressionNode::Function(func, args) => {
+                *func == function || args.iter().any(|arg| arg.uses(function))
+            }
+        }
+    }
+    /// Whether x appears in the expression
+    fn uses_x(&self) -> bool {
+        let mut variables = Vec::new();
+        self.collect_variables(&mut variables);
+        variables.contains(&'x')
+    }
+    /// Find which families of functions of x the expression has: periodic
+    /// ones into `trig`, and exponentials and steps into `sigmoid`
+    fn families(&self, trig: &mut bool, sigmoid: &mut bool) {
+        match self {
+            ExpressionNode::Literal(_) | ExpressionNode::Variable(_) => (),
+            ExpressionNode::Operation(op, left, right) => {
+                if *op == ExpressionOp::Power && right.uses_x() {
+                    *sigmoid = true;
+                }
+                left.families(trig, sigmoid);
+                right.families(trig, sigmoid);
+            }
+            ExpressionNode::Function(func, args) => {
+                if args.iter().any(ExpressionNode::uses_x) {
+                    match func {
+                        SupportedFunction::Sine
+                        | SupportedFunction::Atan2
+                        | SupportedFunction::Mod => *trig = true,
+                        SupportedFunction::Exp
+                        | SupportedFunction::Abs
+                        | SupportedFunction::Floor
+                        | SupportedFunction::Ceil
+                        | SupportedFunction::Round
+                        | SupportedFunction::Sign
+                        | SupportedFunction::Min
+                        | SupportedFunction::Max => *sigmoid = true,
+                        SupportedFunction::Ln
+                        | SupportedFunction::Log10
+                        | SupportedFunction::Sqrt => (),
+                    }
+                }
+                for arg in args {
+                    arg.families(trig, sigmoid);
+                }
+            }
+        }
+    }
+    /// Degree of the expression as a polynomial in x, or `None` if it isn't
+    /// one. Other variables are constants, and exponents have to evaluate to
+    /// a whole number with the given variables.
+    fn degree(&self, vars: &[(String, f32)]) -> Option<u32> {
+        match self {
+            ExpressionNode::Literal(_) => Some(0),
+            ExpressionNode::Variable(var) => Some((*var == 'x').into()),
+            ExpressionNode::Operation(op, left, right) => {
+                let (left_degree, right_degree) =
+                    (left.degree(vars), right.degree(vars));
+                match op {
+                    ExpressionOp::Add | ExpressionOp::Subtract => {
+                        Some(left_degree?.max(right_degree?))
+                    }
+                    ExpressionOp::Multiply => {
+                        Some(left_degree? + right_degree?)
+                    }
+                    ExpressionOp::Divide => {
+                        (right_degree? == 0).then_some(left_degree?)
+                    }
+                    ExpressionOp::Power => {
+                        let (left_degree, right_degree) =
+                            (left_degree?, right_degree?);
+                        if right_degree > 0 {
+                            return None;
+                        } else if left_degree == 0 {
+                            return Some(0);
+                        }
+                        let exponent = right.eval(vars).ok()?;
+                        let whole = exponent >= 0. && exponent.fract() == 0.;
+                        whole.then(|| left_degree * exponent as u32)
+                    }
+                }
+            }
+            ExpressionNode::Function(_, args) => {
+                for arg in args {
+                    if arg.degree(vars)? != 0 {
+                        return None;
+                    }
+                }
+                Some(0)
+            }
+        }
+    }
     fn eval(&self, vars: &[(String, f32)]) -> Result<f32, EvalError> {
         match self {
             ExpressionNode::Operation(op, left, right) => {
@@ -142,13 +605,101 @@ impl ExpressionNode {
                     Err(EvalError::UndefinedVariable)
                 }
             }
-            ExpressionNode::Function(func, arg) => {
-                Ok(func.apply(arg.eval(vars)?)?)
+            ExpressionNode::Function(func, args) => {
+                let args = (args.iter())
+                    .map(|arg| arg.eval(vars))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(func.apply(&args)?)
+            }
+        }
+    }
+}
+
+/// Prints the expression fully parenthesized, with the operands of sums and
+/// products sorted so their order doesn't matter
+impl std::fmt::Display for ExpressionNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpressionNode::Literal(value) => {
+                // Round away float noise, and print -0 as 0
+                write!(f, "{}", (value * 1e4).round() / 1e4 + 0.)
+            }
+            ExpressionNode::Variable(var) => write!(f, "{var}"),
+            ExpressionNode::Function(func, args) => {
+                let args =
+                    args.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(f, "{func:?}({})", args.join(","))
+            }
+            ExpressionNode::Operation(op, left, right) => {
+                let symbol = match op {
+                    ExpressionOp::Add => "+",
+                    ExpressionOp::Subtract => "-",
+                    ExpressionOp::Multiply => "*",
+                    ExpressionOp::Divide => "/",
+                    ExpressionOp::Power => "^",
+                };
+                let operands =
+                    if matches!(op, ExpressionOp::Add | ExpressionOp::Multiply)
+                    {
+                        let mut operands = Vec::new();
+                        self.operands(*op, &mut operands);
+                        let mut printed = operands
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>();
+                        printed.sort();
+                        printed
+                    } else {
+                        vec![left.to_string(), right.to_string()]
+                    };
+                write!(f, "({})", operands.join(symbol))
             }
         }
     }
 }
 
+/// The math functions equations are evaluated with.
+///
+/// Shots have to land the same for every player of a match, whatever they
+/// play on, so evaluation must give bit-identical results everywhere. The
+/// basic operations are exact under IEEE 754 and Rust never fuses them into
+/// FMA instructions on its own (and `mul_add` is disallowed in `clippy.toml`),
+/// but the platform's `sin`, `atan2`, `powf`, `ln` and `log10` may round
+/// differently on Windows, Linux and WASM. With the `deterministic` feature
+/// they come from libm, which is the same portable code everywhere.
+mod float {
+    #[cfg(feature = "deterministic")]
+    pub use libm::{
+        atan2f as atan2, log10f as log10, logf as ln, powf, sinf as sin,
+        sqrtf as sqrt,
+    };
+
+    #[cfg(not(feature = "deterministic"))]
+    pub fn sin(x: f32) -> f32 {
+        x.sin()
+    }
+    #[cfg(not(feature = "deterministic"))]
+    pub fn atan2(y: f32, x: f32) -> f32 {
+        y.atan2(x)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    pub fn powf(x: f32, y: f32) -> f32 {
+        x.powf(y)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    pub fn ln(x: f32) -> f32 {
+        x.ln()
+    }
+    #[cfg(not(feature = "deterministic"))]
+    pub fn log10(x: f32) -> f32 {
+        x.log10()
+    }
+    #[cfg(not(feature = "deterministic"))]
+    pub fn sqrt(x: f32) -> f32 {
+        x.sqrt()
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum SupportedFunction {
     Sine,
@@ -156,6 +707,19 @@ enum SupportedFunction {
     Ln,
     Log10,
     Sqrt,
+    Abs,
+    Floor,
+    Ceil,
+    Round,
+    Sign,
+    Min,
+    Max,
+    /// Remainder of dividing the first argument by the second, with the
+    /// sign of the second, so it repeats evenly on both sides of 0
+    Mod,
+    /// Angle of the point (second argument, first argument), like `atan2`
+    /// in most languages
+    Atan2,
 }
 
 #[derive(Debug, Error)]
@@ -164,31 +728,58 @@ pub enum FunctionEvalErr {
     OutOfDomain,
 }
 impl SupportedFunction {
-    fn apply(&self, arg: f32) -> Result<f32, FunctionEvalErr> {
+    /// Number of arguments the function takes
+    fn arity(&self) -> usize {
+        match self {
+            Self::Min | Self::Max | Self::Mod | Self::Atan2 => 2,
+            _ => 1,
+        }
+    }
+    /// Apply the function to its arguments, of which there have to be as
+    /// many as it takes
+    fn apply(&self, args: &[f32]) -> Result<f32, FunctionEvalErr> {
+        let (arg, second) = (args[0], args.get(1).copied().unwrap_or(0.));
         match self {
-            Self::Sine => Ok(arg.sin()),
-            Self::Exp => Ok(1. / (1. + std::f32::consts::E.powf(arg))),
+            Self::Sine => Ok(float::sin(arg)),
+            Self::Exp => Ok(1. / (1. + float::powf(std::f32::consts::E, arg))),
             Self::Ln => {
                 if arg > 0. {
-                    Ok(arg.ln())
+                    Ok(float::ln(arg))
                 } else {
                     Err(FunctionEvalErr::OutOfDomain)
                 }
             }
             Self::Log10 => {
                 if arg > 0. {
-                    Ok(arg.log10())
+                    Ok(float::log10(arg))
                 } else {
                     Err(FunctionEvalErr::OutOfDomain)
                 }
             }
             Self::Sqrt => {
                 if arg >= 0. {
-                    Ok(arg.sqrt())
+                    Ok(float::sqrt(arg))
+                } else {
+                    Err(FunctionEvalErr::OutOfDomain)
+                }
+            }
+            // These are exact, so they are the same on every platform
+            Self::Abs => Ok(arg.abs()),
+            Self::Floor => Ok(arg.floor()),
+            Self::Ceil => Ok(arg.ceil()),
+            // Halves round away from 0
+            Self::Round => Ok(arg.round()),
+            Self::Sign => Ok(if arg == 0. { 0. } else { arg.signum() }),
+            Self::Min => Ok(arg.min(second)),
+            Self::Max => Ok(arg.max(second)),
+            Self::Mod => {
+                if second != 0. {
+                    Ok(arg - second * (arg / second).floor())
                 } else {
                     Err(FunctionEvalErr::OutOfDomain)
                 }
             }
+            Self::Atan2 => Ok(float::atan2(arg, second)),
         }
     }
 }
@@ -196,7 +787,8 @@ impl SupportedFunction {
 #[derive(Debug, PartialEq)]
 enum RPNToken {
     ExpressionOp(ExpressionOp),
-    Function(SupportedFunction),
+    /// A function and the number of arguments it was given
+    Function(SupportedFunction, usize),
     Variable(char),
     Literal(f32),
 }
@@ -229,7 +821,7 @@ impl ExpressionOp {
                     Err(BinaryOpErr::Div0)
                 }
             }
-            Self::Power => Ok(left.powf(right)),
+            Self::Power => Ok(float::powf(left, right)),
         }
     }
 }
@@ -249,6 +841,8 @@ enum InfixToken {
     ParenOpen,
     ParenClose,
     Function(SupportedFunction),
+    /// Separates the arguments of a function
+    Comma,
     Variable(char),
     Operator(InfixTokenOperator),
     Literal(f32),
@@ -261,6 +855,15 @@ fn get_func(input: &str) -> Option<(SupportedFunction, usize)> {
         ("ln", SupportedFunction::Ln),
         ("log10", SupportedFunction::Log10),
         ("sqrt", SupportedFunction::Sqrt),
+        ("abs", SupportedFunction::Abs),
+        ("floor", SupportedFunction::Floor),
+        ("ceil", SupportedFunction::Ceil),
+        ("round", SupportedFunction::Round),
+        ("sign", SupportedFunction::Sign),
+        ("min", SupportedFunction::Min),
+        ("max", SupportedFunction::Max),
+        ("mod", SupportedFunction::Mod),
+        ("atan2", SupportedFunction::Atan2),
     ];
 
     for (name, func) in FUNC_NAMES {
@@ -273,6 +876,8 @@ fn get_func(input: &str) -> Option<(SupportedFunction, usize)> {
 
 #[derive(Debug, Error)]
 pub struct TokenizerError {
+    /// Index of the character that couldn't be read, counting from 0 in the
+    /// expression as typed, spaces included
     pub failure_idx: usize,
 }
 
@@ -286,7 +891,42 @@ impl std::fmt::Display for TokenizerError {
     }
 }
 
-fn tokenize(expression: &str) -> Result<Vec<InfixToken>, TokenizerError> {
+fn tokenize(input: &str) -> Result<Vec<InfixToken>, TokenizerError> {
+    let tokens = tokenize_spans(input)?;
+    Ok(tokens.into_iter().map(|(token, _)| token).collect())
+}
+
+/// `input` with the variables `replace` gives text for replaced by it.
+/// Function names are left alone even where they contain the letter of a
+/// variable, like the `a` of `max`. Input that can't be tokenized is
+/// returned as it is.
+pub fn replace_variables(
+    input: &str,
+    replace: impl Fn(char) -> Option<String>,
+) -> String {
+    let Ok(tokens) = tokenize_spans(input) else {
+        return input.to_string();
+    };
+    let mut replaced = String::new();
+    let mut tokens = tokens.into_iter().peekable();
+    for (i, c) in input.chars().enumerate() {
+        let token = tokens.next_if(|(_, start)| *start == i);
+        match token.and_then(|(token, _)| match token {
+            InfixToken::Variable(var) => replace(var),
+            _ => None,
+        }) {
+            Some(text) => replaced.push_str(&text),
+            None => replaced.push(c),
+        }
+    }
+    replaced
+}
+
+/// Split `input` into tokens, each with the index of the character it
+/// starts at in `input`
+fn tokenize_spans(
+    input: &str,
+) -> Result<Vec<(InfixToken, usize)>, TokenizerError> {
     const TOKEN_OPS: &[(char, InfixTokenOperator)] = &[
         ('+', InfixTokenOperator::Add),
         ('-', InfixTokenOperator::SubtractOrNegate),
@@ -295,69 +935,104 @@ fn tokenize(expression: &str) -> Result<Vec<InfixToken>, TokenizerError> {
         ('^', InfixTokenOperator::Power),
     ];
 
-    let expression = expression
+    let expression = input
         .chars()
         .filter(|c| !c.is_whitespace())
         .collect::<String>();
 
+    // Index in `input` of each character of `expression`, so tokens and
+    // errors point at the characters where the player typed them
+    let positions = (input.chars().enumerate())
+        .filter(|(_, c)| !c.is_whitespace())
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
     let mut tokens = Vec::new();
 
+    // Byte offset into `expression`, always at a character boundary
     let mut at: usize = 0;
-    while at < expression.len() {
-        if let Some((func, len)) = get_func(&expression[at..]) {
-            at += len;
-            tokens.push(InfixToken::Function(func));
-        } else if expression
-            .chars()
-            .nth(at)
-            .is_some_and(|c| c.is_alphabetic())
+    while let Some(c) = expression[at..].chars().next() {
+        let start = positions[expression[..at].chars().count()];
+        let (token, len) = if let Some((func, len)) =
+            get_func(&expression[at..])
         {
-            tokens.push(InfixToken::Variable(
-                expression.chars().nth(at).unwrap(),
-            ));
-            at += 1;
-        } else if let Some((num, len)) = read_literal(&expression[at..]) {
-            tokens.push(InfixToken::Literal(num));
-            at += len;
-        } else if let Some(op) = expression
-            .chars()
-            .nth(at)
-            .and_then(|c| TOKEN_OPS.iter().find(|&i| i.0 == c).map(|v| v.1))
+            (InfixToken::Function(func), len)
+        } else if c.is_alphabetic() {
+            (InfixToken::Variable(c), c.len_utf8())
+        } else if let Some((num, len)) = read_literal(&expression[at..], false)
         {
-            tokens.push(InfixToken::Operator(op));
-            at += 1;
-        } else if let Some('(') = expression.chars().nth(at) {
-            tokens.push(InfixToken::ParenOpen);
-            at += 1;
-        } else if let Some(')') = expression.chars().nth(at) {
-            tokens.push(InfixToken::ParenClose);
-            at += 1;
+            (InfixToken::Literal(num), len)
+        } else if let Some(op) =
+            TOKEN_OPS.iter().find(|&i| i.0 == c).map(|v| v.1)
+        {
+            (InfixToken::Operator(op), 1)
+        } else if c == '(' {
+            (InfixToken::ParenOpen, 1)
+        } else if c == ')' {
+            (InfixToken::ParenClose, 1)
+        } else if c == ',' || c == ';' {
+            // Semicolons are for players who type decimal commas
+            (InfixToken::Comma, 1)
         } else {
-            return Err(TokenizerError { failure_idx: at });
-        }
+            return Err(TokenizerError { failure_idx: start });
+        };
+        tokens.push((token, start));
+        at += len;
     }
 
     Ok(tokens)
 }
 
-fn read_literal(input: &str) -> Option<(f32, usize)> {
+/// Read the number at the start of `input`, returning it and its length.
+/// With `decimal_comma`, a comma followed by a digit is a decimal point too,
+/// so arguments have to be separated with a space after the comma, or with a
+/// semicolon, as in `max(1; 2)`.
+fn read_literal(input: &str, decimal_comma: bool) -> Option<(f32, usize)> {
     if !input.chars().next().is_some_and(|c| c.is_numeric()) {
         return None;
     }
-    let strnum = input
-        .chars()
-        .scan(false, |p, c| {
-            if c.is_numeric() {
-                Some(c)
-            } else if c == '.' && !*p {
-                *p = true;
-                Some(c)
-            } else {
-                None
+    let mut chars = input.chars().peekable();
+    let mut strnum = String::new();
+    let mut len = 0;
+    let mut point = false;
+    while let Some(c) = chars.next() {
+        let comma = decimal_comma
+            && c == ','
+            && chars.peek().is_some_and(|next| next.is_numeric());
+        if c.is_numeric() {
+            strnum.push(c);
+        } else if (c == '.' || comma) && !point {
+            point = true;
+            strnum.push('.');
+        } else {
+            break;
+        }
+        len += c.len_utf8();
+    }
+    strnum.parse::<f32>().ok().map(|v| (v, len))
+}
+
+/// `expression` with the decimal commas of its numbers turned into points,
+/// as equations are always stored and shared with points
+pub fn with_decimal_points(expression: &str) -> String {
+    let mut output = String::with_capacity(expression.len());
+    let mut rest = expression;
+    while let Some(c) = rest.chars().next() {
+        // Whole numbers are skipped at once, so a comma after a number's
+        // decimal point is left alone
+        let len = match read_literal(rest, true) {
+            Some((_, len)) => {
+                output.push_str(&rest[..len].replace(',', "."));
+                len
             }
-        })
-        .collect::<String>();
-    strnum.parse::<f32>().ok().map(|v| (v, strnum.len()))
+            None => {
+                output.push(c);
+                c.len_utf8()
+            }
+        };
+        rest = &rest[len..];
+    }
+    output
 }
 
 fn get_operator_precedence(op: InfixTokenOperator) -> u8 {
@@ -391,7 +1066,12 @@ fn perform_unary_minus(tokens: &[InfixToken]) -> Vec<InfixToken> {
             } else {
                 vec![i]
             };
-            *acc = matches!(i, InfixToken::ParenOpen | InfixToken::Operator(_));
+            *acc = matches!(
+                i,
+                InfixToken::ParenOpen
+                    | InfixToken::Comma
+                    | InfixToken::Operator(_)
+            );
             Some(res)
         })
         .flatten()
@@ -424,6 +1104,40 @@ fn insert_implicit_multiplication(tokens: &[InfixToken]) -> Vec<InfixToken> {
     output
 }
 
+fn expression_op(op: InfixTokenOperator) -> ExpressionOp {
+    match op {
+        InfixTokenOperator::Add => ExpressionOp::Add,
+        InfixTokenOperator::SubtractOrNegate => ExpressionOp::Subtract,
+        InfixTokenOperator::Multiply | InfixTokenOperator::ImplicitMultiply => {
+            ExpressionOp::Multiply
+        }
+        InfixTokenOperator::Divide => ExpressionOp::Divide,
+        InfixTokenOperator::Power => ExpressionOp::Power,
+    }
+}
+
+/// Move the operators on top of `opstack` to `output`, up to the innermost
+/// open parenthesis
+fn pop_to_paren(
+    opstack: &mut Vec<InfixToken>,
+    output: &mut Vec<RPNToken>,
+) -> Result<(), ShuntingYardError> {
+    loop {
+        match opstack.last() {
+            None => return Err(ShuntingYardError::MismatchedParens),
+            Some(InfixToken::ParenOpen) => return Ok(()),
+            Some(InfixToken::Operator(op)) => {
+                output.push(RPNToken::ExpressionOp(expression_op(*op)));
+                opstack.pop();
+            }
+            Some(InfixToken::Function(_)) => {
+                return Err(ShuntingYardError::MissingFunctionParens);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
 fn shunting_yard(
     mut tokens: Vec<InfixToken>,
 ) -> Result<Vec<RPNToken>, ShuntingYardError> {
@@ -431,6 +1145,10 @@ fn shunting_yard(
     tokens = insert_implicit_multiplication(&tokens);
     let mut output: Vec<RPNToken> = Vec::new();
     let mut opstack: Vec<InfixToken> = Vec::new();
+    // For each open parenthesis, the number of arguments so far if it holds
+    // the arguments of a function
+    let mut arg_counts: Vec<Option<usize>> = Vec::new();
+    let mut previous = None;
     for token in tokens {
         match token {
             InfixToken::Literal(num) => output.push(RPNToken::Literal(num)),
@@ -444,78 +1162,44 @@ fn shunting_yard(
                             == get_operator_precedence(*o2)
                             && !is_right_associative(o1)))
                 {
-                    let op = match o2 {
-                        InfixTokenOperator::Add => {
-                            RPNToken::ExpressionOp(ExpressionOp::Add)
-                        }
-                        InfixTokenOperator::Multiply => {
-                            RPNToken::ExpressionOp(ExpressionOp::Multiply)
-                        }
-                        InfixTokenOperator::Divide => {
-                            RPNToken::ExpressionOp(ExpressionOp::Divide)
-                        }
-                        InfixTokenOperator::SubtractOrNegate => {
-                            RPNToken::ExpressionOp(ExpressionOp::Subtract)
-                        }
-                        InfixTokenOperator::Power => {
-                            RPNToken::ExpressionOp(ExpressionOp::Power)
-                        }
-                        InfixTokenOperator::ImplicitMultiply => {
-                            RPNToken::ExpressionOp(ExpressionOp::Multiply)
-                        }
-                    };
-                    output.push(op);
+                    output.push(RPNToken::ExpressionOp(expression_op(*o2)));
                     let _ = opstack.pop();
                 }
                 opstack.push(InfixToken::Operator(o1));
             }
-            InfixToken::ParenOpen => opstack.push(token),
+            InfixToken::ParenOpen => {
+                let of_function =
+                    matches!(opstack.last(), Some(InfixToken::Function(_)));
+                arg_counts.push(of_function.then_some(1));
+                opstack.push(token);
+            }
+            InfixToken::Comma => {
+                if matches!(
+                    previous,
+                    Some(InfixToken::ParenOpen | InfixToken::Comma)
+                ) {
+                    return Err(ShuntingYardError::EmptyArgument);
+                }
+                pop_to_paren(&mut opstack, &mut output)?;
+                match arg_counts.last_mut() {
+                    Some(Some(count)) => *count += 1,
+                    _ => return Err(ShuntingYardError::MisplacedComma),
+                }
+            }
             InfixToken::ParenClose => {
-                loop {
-                    match opstack.last() {
-                        None => {
-                            return Err(ShuntingYardError::MismatchedParens);
-                        }
-                        Some(InfixToken::ParenOpen) => break,
-                        Some(InfixToken::Operator(op)) => {
-                            output.push(match op {
-                                InfixTokenOperator::Add => {
-                                    RPNToken::ExpressionOp(ExpressionOp::Add)
-                                }
-                                InfixTokenOperator::Multiply => {
-                                    RPNToken::ExpressionOp(
-                                        ExpressionOp::Multiply,
-                                    )
-                                }
-                                InfixTokenOperator::Divide => {
-                                    RPNToken::ExpressionOp(ExpressionOp::Divide)
-                                }
-                                InfixTokenOperator::SubtractOrNegate => {
-                                    RPNToken::ExpressionOp(
-                                        ExpressionOp::Subtract,
-                                    )
-                                }
-                                InfixTokenOperator::Power => {
-                                    RPNToken::ExpressionOp(ExpressionOp::Power)
-                                }
-                                InfixTokenOperator::ImplicitMultiply => {
-                                    RPNToken::ExpressionOp(
-                                        ExpressionOp::Multiply,
-                                    )
-                                }
-                            });
-                            opstack.pop();
-                        }
-                        _ => unreachable!(),
-                    }
+                if previous == Some(InfixToken::Comma) {
+                    return Err(ShuntingYardError::EmptyArgument);
                 }
+                pop_to_paren(&mut opstack, &mut output)?;
                 assert!(matches!(opstack.pop(), Some(InfixToken::ParenOpen)));
+                let args = arg_counts.pop().flatten();
                 if let Some(InfixToken::Function(func)) = opstack.last() {
-                    output.push(RPNToken::Function(*func));
+                    output.push(RPNToken::Function(*func, args.unwrap_or(1)));
                     let _ = opstack.pop();
                 }
             }
         }
+        previous = Some(token);
     }
     while let Some(op) = opstack.pop() {
         match op {
@@ -523,18 +1207,10 @@ fn shunting_yard(
                 return Err(ShuntingYardError::MismatchedParens);
             }
             InfixToken::Operator(op) => {
-                output.push(RPNToken::ExpressionOp(match op {
-                    InfixTokenOperator::Add => ExpressionOp::Add,
-                    InfixTokenOperator::Multiply => ExpressionOp::Multiply,
-                    InfixTokenOperator::Divide => ExpressionOp::Divide,
-                    InfixTokenOperator::SubtractOrNegate => {
-                        ExpressionOp::Subtract
-                    }
-                    InfixTokenOperator::Power => ExpressionOp::Power,
-                    InfixTokenOperator::ImplicitMultiply => {
-                        ExpressionOp::Multiply
-                    }
-                }))
+                output.push(RPNToken::ExpressionOp(expression_op(op)))
+            }
+            InfixToken::Function(_) => {
+                return Err(ShuntingYardError::MissingFunctionParens);
             }
             _ => unreachable!(),
         }
@@ -546,12 +1222,304 @@ fn shunting_yard(
 pub enum ShuntingYardError {
     #[error("Mismatched parentheses")]
     MismatchedParens,
+    #[error("Function without parentheses around its argument")]
+    MissingFunctionParens,
+    #[error("Comma outside of a function's arguments")]
+    MisplacedComma,
+    #[error("Function argument left empty")]
+    EmptyArgument,
+}
+
+/// Entry point for fuzzing, as typed equations are untrusted input parsed
+/// every turn: parse `input` and use the result every way the game does.
+/// Panics if any of that panics, or if a tokenizer error doesn't point at a
+/// character of `input` other than a space.
+#[cfg(any(test, feature = "fuzz"))]
+#[allow(dead_code)] // Only called by the fuzz target and the tests
+pub fn parse_and_eval_arbitrary(input: &str) {
+    if let Err(e) = tokenize(input) {
+        assert!(
+            input
+                .chars()
+                .nth(e.failure_idx)
+                .is_some_and(|c| !c.is_whitespace()),
+            "Error index {} of {input:?} isn't a character of it",
+            e.failure_idx
+        );
+    }
+    with_decimal_points(input).parse::<ParsedFunction>().ok();
+    let Ok(mut parsed) = input.parse::<ParsedFunction>() else {
+        return;
+    };
+    parsed.canonical();
+    parsed.parse_tree();
+    parsed.cost();
+    for var in parsed.variables() {
+        parsed.add_var(var, 1.5);
+    }
+    let func = parsed.bind("x");
+    for x in [0., -1., 2.5, -1e30, f32::NAN] {
+        func(x).ok();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_arbitrary_input() {
+        for input in [
+            "",
+            "π",
+            "2π x",
+            "x²",
+            "é+1",
+            "sin(π)",
+            "√x",
+            "ẋ^2",
+            "1٣",
+            "ln(",
+            ")(",
+            "x^^2",
+            "--x",
+            "1e5",
+            "sin",
+            "x/0",
+            "0,5",
+            "🙂x",
+            "x 🙂",
+            ",",
+            "max(,)",
+            "max(x,)",
+            "min(1,2,3)",
+            "mod(x,0)",
+            "sin(1;x)",
+        ] {
+            parse_and_eval_arbitrary(input);
+        }
+    }
+
+    /// Results that every platform must reproduce bit for bit with the
+    /// `deterministic` feature
+    #[cfg(feature = "deterministic")]
+    #[test]
+    fn test_deterministic_eval() {
+        let function = "sin(x)*x^1.5+ln(x)-log10(x)/sqrt(x)+exp(x)"
+            .parse::<ParsedFunction>()
+            .unwrap();
+        let f = function.bind("x");
+        let bits = [0.5, 2.7, 9.25].map(|x| f(x).unwrap().to_bits());
+        assert_eq!(bits, [1049569772, 1076635050, 1088000461]);
+    }
+
+    #[test]
+    fn test_step_functions() {
+        let eval = |input: &str, x: f32| {
+            let function = input.parse::<ParsedFunction>().unwrap();
+            function.bind("x")(x).unwrap()
+        };
+        assert_eq!(eval("abs(x)", -2.5), 2.5);
+        assert_eq!(eval("floor(x)", -2.5), -3.);
+        assert_eq!(eval("ceil(x)", -2.5), -2.);
+        assert_eq!(eval("round(x)", 2.5), 3.);
+        assert_eq!(eval("round(x)", -2.5), -3.);
+        assert_eq!(eval("sign(x)", -0.1), -1.);
+        assert_eq!(eval("sign(x)", 0.), 0.);
+        assert_eq!(eval("2abs(x-1)+floor(x/2)", 5.), 10.);
+        // `sign` isn't mistaken for `sin`
+        assert_eq!(eval("sign(x)", 3.), 1.);
+        assert_eq!(eval("sin(x)", 0.), 0.);
+        let tokens = tokenize("abs(x)").unwrap();
+        assert_eq!(tokens[0], InfixToken::Function(SupportedFunction::Abs));
+    }
+
+    #[test]
+    fn test_multi_argument_functions() {
+        let eval = |input: &str, x: f32| {
+            let function = input.parse::<ParsedFunction>().unwrap();
+            function.bind("x")(x).unwrap()
+        };
+        assert_eq!(eval("max(x, sin(x))", -1.), (-1f32).sin());
+        assert_eq!(eval("min(x,2)", 5.), 2.);
+        assert_eq!(eval("mod(x,2)", 5.5), 1.5);
+        assert_eq!(eval("mod(x,2)", -0.5), 1.5);
+        assert_eq!(eval("mod(x,-2)", 0.5), -1.5);
+        assert_eq!(eval("atan2(1,0)", 0.), std::f32::consts::FRAC_PI_2);
+        assert_eq!(eval("max(-x,2x-1)", 3.), 5.);
+        assert_eq!(eval("2max(1;x)", 3.), 6.);
+        assert_eq!(eval("max(min(x,1),0)", 0.5), 0.5);
+
+        let error = |input: &str| {
+            (input.parse::<ParsedFunction>().err())
+                .unwrap_or_else(|| panic!("{input} shouldn't parse"))
+        };
+        assert!(matches!(
+            error("max(x)"),
+            ParseError::TreeBuild(TreeBuildError::ArgumentCount {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            error("sin(x,1)"),
+            ParseError::TreeBuild(TreeBuildError::ArgumentCount { .. })
+        ));
+        assert!(matches!(
+            error("(1,2)"),
+            ParseError::ShuntingYard(ShuntingYardError::MisplacedComma)
+        ));
+        assert!(matches!(
+            error("max(1,,2)"),
+            ParseError::ShuntingYard(ShuntingYardError::EmptyArgument)
+        ));
+        assert!(matches!(
+            error("max(1,)"),
+            ParseError::ShuntingYard(ShuntingYardError::EmptyArgument)
+        ));
+        assert!(
+            "mod(x,0)".parse::<ParsedFunction>().unwrap().bind("x")(1.)
+                .is_err()
+        );
+
+        let function = "max(a,x)+atan2(x,1)".parse::<ParsedFunction>().unwrap();
+        assert_eq!(function.variables(), vec!['a', 'x']);
+        assert_eq!(function.cost(), 11);
+        assert_eq!(
+            function.check(Restriction::NoTrig),
+            Err(RestrictionError::Trig)
+        );
+        // With decimal commas, arguments are separated by a space or a
+        // semicolon
+        assert_eq!(with_decimal_points("max(1, 2,5)"), "max(1, 2.5)");
+    }
+
+    #[test]
+    fn test_canonical() {
+        let canonical = |input: &str| {
+            let mut function = input.parse::<ParsedFunction>().unwrap();
+            function.add_var("a", 2.);
+            function.canonical()
+        };
+        assert_eq!(canonical("2x+1"), "((2*x)+1)");
+        for same in ["1+x*2", "x2+1+0", "(1+1)x+1", "ax+1", "1(x*2)+1^1"] {
+            assert_eq!(canonical(same), canonical("2x+1"), "{same}");
+        }
+        assert_eq!(canonical("x+sin(x)"), canonical("sin(x)+x"));
+        assert_ne!(canonical("x-1"), canonical("1-x"));
+        assert_ne!(canonical("x^2"), canonical("2^x"));
+        assert_ne!(canonical("bx"), canonical("2x"));
+        let function = "x*2+1".parse::<ParsedFunction>().unwrap();
+        let recent = [canonical("2x+1")];
+        let cooldown = NonZeroU8::new(3);
+        assert_eq!(function.check_repeat(None, &recent), Ok(()));
+        assert_eq!(
+            function.check_repeat(cooldown, &recent),
+            Err(RestrictionError::Repeated { turns: 3 })
+        );
+        assert_eq!(function.check_repeat(cooldown, &[]), Ok(()));
+    }
+
+    #[test]
+    fn test_cost() {
+        let cost =
+            |input: &str| input.parse::<ParsedFunction>().unwrap().cost();
+        assert_eq!(cost("x"), 1);
+        assert_eq!(cost("2x-3"), 5);
+        assert_eq!(cost("x^2"), 4);
+        assert_eq!(cost("sin(x)+1"), 6);
+        let function = "x^2".parse::<ParsedFunction>().unwrap();
+        assert_eq!(function.check_budget(None), Ok(()));
+        assert_eq!(function.check_budget(Some(4)), Ok(()));
+        assert_eq!(
+            function.check_budget(Some(3)),
+            Err(RestrictionError::OverBudget { cost: 4, budget: 3 })
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let check = |input: &str, restriction| {
+            input.parse::<ParsedFunction>().unwrap().check(restriction)
+        };
+        assert_eq!(check("sin(x)", Restriction::None), Ok(()));
+        assert_eq!(check("2x-3", Restriction::LinesOnly), Ok(()));
+        assert_eq!(check("x/2+a", Restriction::LinesOnly), Ok(()));
+        assert_eq!(
+            check("x^2", Restriction::LinesOnly),
+            Err(RestrictionError::NotALine)
+        );
+        assert_eq!(
+            check("1/x", Restriction::LinesOnly),
+            Err(RestrictionError::NotALine)
+        );
+        assert_eq!(check("2sqrt(x)", Restriction::NoTrig), Ok(()));
+        assert_eq!(
+            check("x+sin(2)", Restriction::NoTrig),
+            Err(RestrictionError::Trig)
+        );
+        assert_eq!(check("(x+1)^2-x^3/4", Restriction::MaxDegree(3)), Ok(()));
+        assert_eq!(
+            check("(x^2+1)^2", Restriction::MaxDegree(3)),
+            Err(RestrictionError::DegreeTooHigh { degree: 4, max: 3 })
+        );
+        assert_eq!(check("sqrt(2)x^e^0", Restriction::MaxDegree(1)), Ok(()));
+        for not_polynomial in ["2^x", "x^0.5", "sin(x)", "x^a"] {
+            assert_eq!(
+                check(not_polynomial, Restriction::MaxDegree(3)),
+                Err(RestrictionError::NotPolynomial)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_tree() {
+        let leaf = |label: &str| ParseTreeNode {
+            label: label.to_string(),
+            children: Vec::new(),
+        };
+        let node = |label: &str, children| ParseTreeNode {
+            label: label.to_string(),
+            children,
+        };
+        // Implicit multiplication binds tighter than division, but not
+        // tighter than powers
+        let parsed = "1/2x^3".parse::<ParsedFunction>().unwrap();
+        assert_eq!(
+            parsed.parse_tree(),
+            node("÷ divide", vec![
+                leaf("1"),
+                node("× multiply", vec![
+                    leaf("2"),
+                    node("^ power", vec![leaf("x"), leaf("3")])
+                ]),
+            ])
+        );
+        let parsed = "-sin(x)".parse::<ParsedFunction>().unwrap();
+        assert_eq!(
+            parsed.parse_tree(),
+            node("× multiply", vec![
+                leaf("-1"),
+                node("Sine", vec![leaf("x")])
+            ])
+        );
+    }
+
+    #[test]
+    fn test_decimal_comma() {
+        assert_eq!(read_literal("0,5x", true), Some((0.5, 3)));
+        assert_eq!(read_literal("0,5x", false), Some((0., 1)));
+        // A comma has to be followed by a digit to be a decimal point
+        assert_eq!(read_literal("3,x", true), Some((3., 1)));
+        assert_eq!(read_literal("1.5,5", true), Some((1.5, 3)));
+        assert_eq!(
+            with_decimal_points("0,5x+sin(1,25x)-2"),
+            "0.5x+sin(1.25x)-2"
+        );
+        assert_eq!(with_decimal_points("3,x+1.5,5"), "3,x+1.5,5");
+        assert!("0,5x".parse::<ParsedFunction>().is_err());
+    }
+
     #[test]
     fn test_tokenizer_func() {
         let test_sets = [
@@ -579,6 +1547,32 @@ mod tests {
                 .unwrap_or_else(|_| panic!("Failed to tokenize \"{input}\""));
             assert_eq!(tokens, correct_tokens);
         }
+        for (input, failure_idx) in [("x$", 1), ("2 x + ?", 6), ("é 🙂", 2)]
+        {
+            let error = tokenize(input).expect_err(input);
+            assert_eq!(error.failure_idx, failure_idx, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_shot_type() {
+        let shot_type =
+            |input: &str| input.parse::<ParsedFunction>().unwrap().shot_type();
+        assert_eq!(shot_type("3"), ShotType::Line);
+        assert_eq!(shot_type("2x - sin(π)"), ShotType::Line);
+        assert_eq!(shot_type("a x^2"), ShotType::Curve);
+        assert_eq!(shot_type("1/x"), ShotType::Curve);
+        assert_eq!(shot_type("5/(1+e^(-10x))"), ShotType::Sigmoid);
+        assert_eq!(shot_type("x/abs(x)"), ShotType::Sigmoid);
+        assert_eq!(shot_type("2sin(x) + x"), ShotType::Trig);
+        assert_eq!(shot_type("sin(x)/(1+e^(-x))"), ShotType::Composite);
+    }
+
+    #[test]
+    fn test_variables() {
+        let function =
+            "a x + b sin(c x) - a".parse::<ParsedFunction>().unwrap();
+        assert_eq!(function.variables(), vec!['a', 'x', 'b', 'c']);
     }
 
     #[test]
@@ -650,7 +1644,7 @@ mod tests {
                     RPNToken::Literal(10.),
                     RPNToken::Variable('x'),
                     RPNToken::ExpressionOp(ExpressionOp::Multiply),
-                    RPNToken::Function(SupportedFunction::Sine),
+                    RPNToken::Function(SupportedFunction::Sine, 1),
                     RPNToken::ExpressionOp(ExpressionOp::Add),
                 ],
             ),
@@ -665,7 +1659,7 @@ mod tests {
                 vec![
                     RPNToken::Literal(0.3),
                     RPNToken::Variable('x'),
-                    RPNToken::Function(SupportedFunction::Sine),
+                    RPNToken::Function(SupportedFunction::Sine, 1),
                     RPNToken::ExpressionOp(ExpressionOp::Multiply),
                 ],
             ),
@@ -0,0 +1,46 @@
+//! Integration for builds distributed through stores such as Steam and
+//! itch.io. Only compiled with the `platform` feature.
+//!
+//! Store overlays draw on top of the game's surface, which doesn't work with
+//! exclusive fullscreen, so fullscreen here is always borderless.
+
+use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowMode};
+
+/// Application ID, used as the Wayland app ID / X11 `WM_CLASS` so desktop
+/// entries and store clients can match the window to the game
+pub const APP_ID: &str = "io.github.pati08.graphwars";
+
+/// Title of the main window
+pub const TITLE: &str = "Graph Wars";
+
+/// The primary window as it should be created in platform builds
+pub fn primary_window() -> Window {
+    Window {
+        title: TITLE.to_string(),
+        name: Some(APP_ID.to_string()),
+        ..Default::default()
+    }
+}
+
+pub struct PlatformPlugin;
+
+impl Plugin for PlatformPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, avoid_exclusive_fullscreen);
+    }
+}
+
+/// Replace exclusive fullscreen, however it was requested, with borderless
+/// fullscreen on the same monitor so overlays keep working
+fn avoid_exclusive_fullscreen(
+    mut window: Single<&mut Window, (With<PrimaryWindow>, Changed<Window>)>,
+) {
+    match window.mode {
+        WindowMode::Fullscreen(monitor)
+        | WindowMode::SizedFullscreen(monitor) => {
+            window.mode = WindowMode::BorderlessFullscreen(monitor);
+        }
+        _ => (),
+    }
+}
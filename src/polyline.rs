@@ -0,0 +1,96 @@
+use crate::consts::*;
+use bevy::prelude::*;
+
+/// Draw a polyline whose alpha fades with the distance (along the line) from
+/// its last point, so older parts of the line form a fading tail
+pub fn draw_fading_polyline(
+    gizmos: &mut Gizmos,
+    points: &[Vec2],
+    color: Color,
+) {
+    let Some(&tip) = points.last() else {
+        return;
+    };
+    let mut distance = 0.;
+    let mut previous = tip;
+    let mut vertices = points
+        .iter()
+        .rev()
+        .map(|&point| {
+            distance += point.distance(previous);
+            previous = point;
+            let fade = (1. - distance / TRAIL_FADE_LENGTH).max(0.);
+            let alpha = TRAIL_MIN_ALPHA + (1. - TRAIL_MIN_ALPHA) * fade;
+            (point, color.with_alpha(alpha))
+        })
+        .collect::<Vec<_>>();
+    vertices.reverse();
+    gizmos.linestrip_gradient_2d(vertices);
+}
+
+/// Draw a glowing, pulsing "comet head" at `at`
+pub fn draw_comet_head(
+    gizmos: &mut Gizmos,
+    at: Vec2,
+    color: Color,
+    elapsed: f32,
+) {
+    let pulse = 1. + 0.2 * (elapsed * COMET_PULSE_SPEED).sin();
+    // Concentric rings that get fainter towards the outside give a cheap glow
+    for ring in 0..COMET_GLOW_RINGS {
+        let t = ring as f32 / COMET_GLOW_RINGS as f32;
+        gizmos.circle_2d(
+            Isometry2d::from_translation(at),
+            COMET_HEAD_RADIUS * pulse * (0.3 + t),
+            color.mix(&Color::WHITE, 1. - t).with_alpha(1. - t),
+        );
+    }
+}
+
+/// Draw a polyline as dashes of `DASH_LENGTH` separated by `DASH_GAP`,
+/// measured along the line
+pub fn draw_dashed_polyline(
+    gizmos: &mut Gizmos,
+    points: &[Vec2],
+    color: Color,
+) {
+    let mut distance = 0.;
+    let mut dash = Vec::new();
+    for pair in points.windows(2) {
+        let in_dash = distance % (DASH_LENGTH + DASH_GAP) < DASH_LENGTH;
+        distance += pair[0].distance(pair[1]);
+        if in_dash {
+            if dash.is_empty() {
+                dash.push(pair[0]);
+            }
+            dash.push(pair[1]);
+        } else if !dash.is_empty() {
+            gizmos.linestrip_2d(std::mem::take(&mut dash), color);
+        }
+    }
+    if !dash.is_empty() {
+        gizmos.linestrip_2d(dash, color);
+    }
+}
+
+/// Draw parallel diagonal stripes across a circle
+pub fn draw_striped_circle(
+    gizmos: &mut Gizmos,
+    center: Vec2,
+    radius: f32,
+    color: Color,
+) {
+    let along = Vec2::new(1., 1.).normalize();
+    let across = along.perp();
+    let stripes = (radius / STRIPE_SPACING) as i32;
+    for i in -stripes..=stripes {
+        let offset = i as f32 * STRIPE_SPACING;
+        let half_length = (radius * radius - offset * offset).max(0.).sqrt();
+        let middle = center + across * offset;
+        gizmos.line_2d(
+            middle - along * half_length,
+            middle + along * half_length,
+            color,
+        );
+    }
+}
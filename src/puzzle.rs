@@ -0,0 +1,393 @@
+//! Single-shot puzzles: hit every target with one function
+
+use crate::ai::search_single_shot;
+use crate::challenges::ChallengeId;
+use crate::consts::*;
+use crate::models::{Obstacle, ObstacleKind, PlayerSelect, Soldier, SoldierId};
+use crate::sim::Target;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use bevy::math::Vec2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Version of the sharing code format. Bump it whenever the encoding
+/// changes, so older codes can still be told apart.
+const CODE_VERSION: u8 = 1;
+
+/// Sharing codes store locations and sizes in hundredths of a graph unit
+const CODE_SCALE: f32 = 100.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Difficulty {
+    #[default]
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] =
+        [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    }
+    /// Grade a puzzle by the number of candidate shots the search needed to
+    /// solve it
+    pub fn from_effort(effort: usize) -> Self {
+        if effort <= PUZZLE_EASY_EFFORT {
+            Difficulty::Easy
+        } else if effort <= PUZZLE_MEDIUM_EFFORT {
+            Difficulty::Medium
+        } else {
+            Difficulty::Hard
+        }
+    }
+    /// Degree of the polynomial the targets are placed along
+    fn degree(&self) -> usize {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Medium => 2,
+            Difficulty::Hard => 3,
+        }
+    }
+}
+
+/// A shooter and targets that can all be hit with a single shot
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Puzzle {
+    /// Locations in graph units
+    pub shooter: Vec2,
+    pub targets: Vec<Vec2>,
+    pub obstacles: Vec<Obstacle>,
+    /// A shot that solves the puzzle, as found by the search
+    pub solution: String,
+    /// Candidate shots the search traced before finding the solution
+    pub effort: usize,
+    /// The weekly challenge the puzzle is part of, if any
+    pub challenge: Option<ChallengeId>,
+}
+
+impl Puzzle {
+    pub fn difficulty(&self) -> Difficulty {
+        Difficulty::from_effort(self.effort)
+    }
+    /// The shooter as player 1's only soldier, and the targets as player 2's
+    pub fn soldiers(&self) -> (Vec<Soldier>, Vec<Soldier>) {
+        let shooter = Soldier::new(PlayerSelect::Player1, 0, self.shooter);
+        let targets = (self.targets.iter().enumerate())
+            .map(|(id, &location)| {
+                Soldier::new(PlayerSelect::Player2, id as u8, location)
+            })
+            .collect();
+        (vec![shooter], targets)
+    }
+
+    /// Encode the puzzle as a short code that can be pasted into the puzzle
+    /// menu to play it. The solution isn't included, as it's found again
+    /// when loading.
+    pub fn code(&self) -> String {
+        let mut bytes = vec![CODE_VERSION];
+        write_point(&mut bytes, self.shooter);
+        bytes.push(self.targets.len() as u8);
+        for &target in &self.targets {
+            write_point(&mut bytes, target);
+        }
+        bytes.push(self.obstacles.len() as u8);
+        for obstacle in &self.obstacles {
+            let (kind, size) = match obstacle.kind {
+                ObstacleKind::Block { radius } => (0, radius),
+                ObstacleKind::BouncyWall { half_length } => (1, half_length),
+                ObstacleKind::Polygon { radius, .. } => (2, radius),
+            };
+            bytes.push(kind);
+            write_point(&mut bytes, obstacle.location);
+            write_number(&mut bytes, size);
+            if let ObstacleKind::Polygon {
+                sides, rotation, ..
+            } = obstacle.kind
+            {
+                bytes.push(sides);
+                write_number(&mut bytes, rotation);
+            }
+            bytes.push(obstacle.hp.map_or(0, |hp| hp.min(255) as u8));
+        }
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    /// Load a puzzle from a code made by [`Puzzle::code`], checking that it
+    /// is well formed and can still be solved
+    pub fn from_code(code: &str) -> Result<Puzzle, PuzzleCodeError> {
+        let bytes = URL_SAFE_NO_PAD.decode(code.trim())?;
+        let mut reader = CodeReader(&bytes);
+        let version = reader.byte()?;
+        if version != CODE_VERSION {
+            return Err(PuzzleCodeError::UnsupportedVersion(version));
+        }
+        let shooter = reader.point()?;
+        let targets = (0..reader.byte()?)
+            .map(|_| reader.point())
+            .collect::<Result<Vec<_>, _>>()?;
+        if !(1..=PUZZLE_MAX_TARGETS).contains(&targets.len()) {
+            return Err(PuzzleCodeError::TargetCount(targets.len()));
+        }
+        let obstacles = (0..reader.byte()?)
+            .map(|id| {
+                let kind = reader.byte()?;
+                let location = reader.point()?;
+                let size = reader.number()?;
+                let kind = match kind {
+                    0 => ObstacleKind::Block { radius: size },
+                    1 => ObstacleKind::BouncyWall { half_length: size },
+                    2 => ObstacleKind::Polygon {
+                        radius: size,
+                        sides: match reader.byte()? {
+                            sides @ 3.. => sides,
+                            _ => {
+                                return Err(PuzzleCodeError::ObstacleKind(
+                                    kind,
+                                ));
+                            }
+                        },
+                        rotation: reader.number()?,
+                    },
+                    kind => return Err(PuzzleCodeError::ObstacleKind(kind)),
+                };
+                let hp = Some(reader.byte()?.into()).filter(|&hp| hp > 0);
+                Ok(Obstacle {
+                    id,
+                    location,
+                    kind,
+                    hp,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !reader.0.is_empty() {
+            return Err(PuzzleCodeError::TrailingData);
+        }
+        let on_board = |point: Vec2| point.abs().max_element() <= 10.;
+        if !on_board(shooter)
+            || !targets.iter().copied().all(on_board)
+            || !obstacles.iter().all(|i| on_board(i.location))
+        {
+            return Err(PuzzleCodeError::OffBoard);
+        }
+        verify(shooter, targets, obstacles).ok_or(PuzzleCodeError::Unsolvable)
+    }
+}
+
+/// Why a puzzle code couldn't be loaded
+#[derive(Debug, Error, PartialEq)]
+pub enum PuzzleCodeError {
+    #[error("Not a puzzle code")]
+    InvalidCode(#[from] base64::DecodeError),
+    #[error("Puzzle code uses an unsupported format (version {0})")]
+    UnsupportedVersion(u8),
+    #[error("Puzzle code is incomplete")]
+    Truncated,
+    #[error("Puzzle code has unexpected data at the end")]
+    TrailingData,
+    #[error("Puzzle has an unsupported number of targets ({0})")]
+    TargetCount(usize),
+    #[error("Unknown obstacle kind {0}")]
+    ObstacleKind(u8),
+    #[error("Puzzle has something off the board")]
+    OffBoard,
+    #[error("Puzzle can't be solved with a single shot")]
+    Unsolvable,
+}
+
+fn write_number(bytes: &mut Vec<u8>, number: f32) {
+    let scaled = (number * CODE_SCALE).round() as i16;
+    bytes.extend(scaled.to_le_bytes());
+}
+
+fn write_point(bytes: &mut Vec<u8>, point: Vec2) {
+    write_number(bytes, point.x);
+    write_number(bytes, point.y);
+}
+
+/// Reads the parts of a decoded puzzle code in order
+struct CodeReader<'a>(&'a [u8]);
+
+impl CodeReader<'_> {
+    fn byte(&mut self) -> Result<u8, PuzzleCodeError> {
+        let (&byte, rest) =
+            self.0.split_first().ok_or(PuzzleCodeError::Truncated)?;
+        self.0 = rest;
+        Ok(byte)
+    }
+    fn number(&mut self) -> Result<f32, PuzzleCodeError> {
+        let scaled = i16::from_le_bytes([self.byte()?, self.byte()?]);
+        Ok(f32::from(scaled) / CODE_SCALE)
+    }
+    fn point(&mut self) -> Result<Vec2, PuzzleCodeError> {
+        Ok(Vec2::new(self.number()?, self.number()?))
+    }
+}
+
+/// Round a location to the precision of sharing codes, so puzzles play the
+/// same after being shared
+fn quantize(point: Vec2) -> Vec2 {
+    (point * CODE_SCALE).round() / CODE_SCALE
+}
+
+/// Generate a puzzle of the given difficulty. Each candidate is only kept if
+/// the search can solve it, and is graded by how long that took. If none of
+/// the right difficulty turns up, the last solvable one is used instead.
+pub fn generate(rng: &mut impl Rng, difficulty: Difficulty) -> Puzzle {
+    let mut fallback = None;
+    for _ in 0..PUZZLE_ATTEMPTS {
+        let (shooter, targets) = place_targets(rng, difficulty.degree());
+        let Some(puzzle) = verify(shooter, targets, Vec::new()) else {
+            continue;
+        };
+        if puzzle.difficulty() == difficulty {
+            return puzzle;
+        }
+        fallback = Some(puzzle);
+    }
+    fallback.unwrap_or_else(|| {
+        // A flat row of targets can always be hit
+        let targets = (0..PUZZLE_TARGETS)
+            .map(|i| Vec2::new(i as f32 * 4., 0.))
+            .collect();
+        verify(Vec2::new(-8., 0.), targets, Vec::new())
+            .expect("flat puzzle is solvable")
+    })
+}
+
+/// Solve the puzzle headlessly, so only solvable ones are presented
+pub fn verify(
+    shooter: Vec2,
+    targets: Vec<Vec2>,
+    obstacles: Vec<Obstacle>,
+) -> Option<Puzzle> {
+    let as_targets = (targets.iter().enumerate())
+        .map(|(number, &location)| Target {
+            id: SoldierId {
+                player: PlayerSelect::Player2,
+                number: number as u8,
+            },
+            location,
+        })
+        .collect::<Vec<_>>();
+    let search = search_single_shot(shooter, &as_targets, &obstacles);
+    Some(Puzzle {
+        shooter,
+        targets,
+        obstacles,
+        solution: search.equation?,
+        effort: search.effort,
+        challenge: None,
+    })
+}
+
+/// Place a shooter on the left, and targets to its right along a random
+/// polynomial of the given degree through it
+fn place_targets(rng: &mut impl Rng, degree: usize) -> (Vec2, Vec<Vec2>) {
+    let shooter =
+        Vec2::new(rng.gen_range(-9.0..-6.0), rng.gen_range(-5.0..5.0));
+    let mut xs = Vec::with_capacity(PUZZLE_TARGETS);
+    while xs.len() < PUZZLE_TARGETS {
+        let x = rng.gen_range(-3.0..9.0);
+        if xs.iter().all(|other: &f32| (x - other).abs() > 2.) {
+            xs.push(x);
+        }
+    }
+    // The first `degree` targets pin down the polynomial, and the rest are
+    // placed along it
+    let mut targets = Vec::<Vec2>::with_capacity(PUZZLE_TARGETS);
+    for (i, x) in xs.into_iter().enumerate() {
+        let y = if i < degree {
+            rng.gen_range(-8.0..8.0)
+        } else {
+            lagrange(shooter, &targets, x)
+        };
+        targets.push(quantize(Vec2::new(x, y)));
+    }
+    (quantize(shooter), targets)
+}
+
+/// Value at `x` of the polynomial through `first` and each of the `rest`
+fn lagrange(first: Vec2, rest: &[Vec2], x: f32) -> f32 {
+    let points = std::iter::once(first).chain(rest.iter().copied());
+    points
+        .clone()
+        .enumerate()
+        .map(|(i, point)| {
+            let basis = (points.clone().enumerate())
+                .filter(|&(j, _)| j != i)
+                .map(|(_, other)| (x - other.x) / (point.x - other.x))
+                .product::<f32>();
+            point.y * basis
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_generate() {
+        let mut rng = StdRng::seed_from_u64(1958);
+        for difficulty in Difficulty::ALL {
+            let puzzle = generate(&mut rng, difficulty);
+            assert_eq!(puzzle.targets.len(), PUZZLE_TARGETS);
+            assert_eq!(puzzle.difficulty(), difficulty);
+            assert!(
+                verify(puzzle.shooter, puzzle.targets, Vec::new()).is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn test_code() {
+        let mut rng = StdRng::seed_from_u64(1959);
+        let mut puzzle = generate(&mut rng, Difficulty::Easy);
+        puzzle.obstacles.push(Obstacle {
+            id: 0,
+            location: Vec2::new(0., 9.5),
+            kind: ObstacleKind::BouncyWall { half_length: 1.5 },
+            hp: Some(2),
+        });
+        puzzle.obstacles.push(Obstacle {
+            id: 1,
+            location: Vec2::new(9., -9.),
+            kind: ObstacleKind::Polygon {
+                radius: 0.5,
+                sides: 5,
+                rotation: 1.25,
+            },
+            hp: None,
+        });
+        let loaded = Puzzle::from_code(&puzzle.code()).unwrap();
+        assert_eq!(loaded.shooter, puzzle.shooter);
+        assert_eq!(loaded.targets, puzzle.targets);
+        assert_eq!(loaded.obstacles, puzzle.obstacles);
+
+        let mut bytes = URL_SAFE_NO_PAD.decode(puzzle.code()).unwrap();
+        let truncated = URL_SAFE_NO_PAD.encode(&bytes[..bytes.len() - 1]);
+        assert_eq!(
+            Puzzle::from_code(&truncated).unwrap_err(),
+            PuzzleCodeError::Truncated
+        );
+        bytes[0] = CODE_VERSION + 1;
+        assert_eq!(
+            Puzzle::from_code(&URL_SAFE_NO_PAD.encode(&bytes)).unwrap_err(),
+            PuzzleCodeError::UnsupportedVersion(CODE_VERSION + 1)
+        );
+        assert!(matches!(
+            Puzzle::from_code("not a code!"),
+            Err(PuzzleCodeError::InvalidCode(_))
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::consts::*;
+use crate::drill::{DistanceBand, DrillRound};
+use crate::models::GameState;
+use crate::systems::announce::Announce;
+use crate::systems::feedback::spawn_floating_text;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Best shots of one player, in graph units
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct PlayerRecords {
+    /// Longest path a shot travelled before destroying a soldier
+    pub longest_shot: f32,
+    /// Longest straight distance between the shooter and a destroyed soldier
+    pub longest_hit: f32,
+    /// Quickest accuracy drill round with a target in each band, in seconds
+    pub drill_bests: BTreeMap<DistanceBand, f32>,
+}
+
+/// Shot records of every player by name, which persist between launches
+#[derive(
+    Resource, Serialize, Deserialize, Clone, Debug, PartialEq, Default,
+)]
+#[serde(default)]
+pub struct Records {
+    pub players: BTreeMap<String, PlayerRecords>,
+}
+
+/// Sent for every soldier a shot destroys
+#[derive(Event, Clone, Debug)]
+pub struct ShotLanded {
+    /// Name of the player who took the shot
+    pub shooter: String,
+    /// Length of the shot's path up to the hit, in graph units
+    pub path_length: f32,
+    /// Distance from where the shot was launched to the hit soldier, in graph
+    /// units
+    pub hit_distance: f32,
+    /// Where the soldier was hit, in world coordinates
+    pub at: Vec2,
+}
+
+impl Records {
+    fn path() -> PathBuf {
+        crate::util::data_dir().join("records.ron")
+    }
+
+    /// Read the records file, starting without records if it is missing or
+    /// can't be parsed
+    pub fn load() -> Records {
+        let Ok(contents) = std::fs::read_to_string(Self::path()) else {
+            return Records::default();
+        };
+        ron::from_str(&contents).unwrap_or_else(|e| {
+            log::warn!("Failed to parse records, starting over: {e}");
+            Records::default()
+        })
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents =
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+                .map_err(std::io::Error::other)?;
+        std::fs::write(path, contents)
+    }
+
+    /// Update the shooter's records with a shot, returning the names of the
+    /// records it broke
+    pub fn record(&mut self, shot: &ShotLanded) -> Vec<&'static str> {
+        let records = self.players.entry(shot.shooter.clone()).or_default();
+        let mut broken = Vec::new();
+        if shot.path_length > records.longest_shot {
+            records.longest_shot = shot.path_length;
+            broken.push("longest shot");
+        }
+        if shot.hit_distance > records.longest_hit {
+            records.longest_hit = shot.hit_distance;
+            broken.push("longest hit");
+        }
+        broken
+    }
+
+    /// Update the player's drill bests with a finished round, returning
+    /// whether it was their quickest in its band
+    pub fn record_drill(&mut self, player: &str, round: &DrillRound) -> bool {
+        let records = self.players.entry(player.to_string()).or_default();
+        let best = records.drill_bests.entry(round.band).or_insert(f32::MAX);
+        let broken = round.seconds < *best;
+        *best = best.min(round.seconds);
+        broken
+    }
+}
+
+/// Keep the records up to date with every landed shot and celebrate the
+/// ones that break a record
+pub fn update_records(
+    mut commands: Commands,
+    mut events: EventReader<ShotLanded>,
+    mut records: ResMut<Records>,
+    mut announcements: EventWriter<Announce>,
+    state: Res<GameState>,
+) {
+    // Demo matches and replays play themselves, so their shots aren't
+    // anyone's records
+    let played = state.playing_state();
+    if played.is_some_and(|i| i.is_demo() || i.watching().is_some()) {
+        events.clear();
+        return;
+    }
+    for shot in events.read() {
+        // Only mark the records as changed (and save them) if one was broken
+        let broken = records.bypass_change_detection().record(shot);
+        if broken.is_empty() {
+            continue;
+        }
+        records.set_changed();
+        let text = format!("New record: {}!", broken.join(" and "));
+        spawn_floating_text(
+            &mut commands,
+            &text,
+            RECORD_TEXT_SIZE,
+            shot.at + Vec2::Y * RECORD_TEXT_OFFSET,
+        );
+        announcements.send(Announce(format!("{} set a {text}", shot.shooter)));
+    }
+}
+
+/// Write the records to disk whenever they change
+pub fn save_records(records: Res<Records>) {
+    if let Err(e) = records.save() {
+        log::warn!("Failed to save records: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shot(shooter: &str, path_length: f32, hit_distance: f32) -> ShotLanded {
+        ShotLanded {
+            shooter: shooter.to_string(),
+            path_length,
+            hit_distance,
+            at: Vec2::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let mut records = Records::default();
+        assert_eq!(records.record(&shot("a", 5., 3.)), vec![
+            "longest shot",
+            "longest hit"
+        ]);
+        assert_eq!(records.record(&shot("a", 6., 2.)), vec!["longest shot"]);
+        assert!(records.record(&shot("a", 1., 1.)).is_empty());
+        // Records are kept per player
+        assert_eq!(records.record(&shot("b", 1., 1.)).len(), 2);
+        assert_eq!(records.players["a"], PlayerRecords {
+            longest_shot: 6.,
+            longest_hit: 3.,
+            drill_bests: BTreeMap::new(),
+        });
+    }
+
+    #[test]
+    fn test_record_drill() {
+        let round = |band, seconds| DrillRound {
+            band,
+            seconds,
+            shots: 1,
+        };
+        let mut records = Records::default();
+        assert!(records.record_drill("a", &round(DistanceBand::Far, 9.)));
+        assert!(!records.record_drill("a", &round(DistanceBand::Far, 12.)));
+        assert!(records.record_drill("a", &round(DistanceBand::Close, 12.)));
+        assert!(records.record_drill("a", &round(DistanceBand::Far, 4.)));
+        let bests = &records.players["a"].drill_bests;
+        assert_eq!(bests[&DistanceBand::Far], 4.);
+        assert_eq!(bests[&DistanceBand::Close], 12.);
+        assert!(!records.players.contains_key("b"));
+    }
+}
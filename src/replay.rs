@@ -0,0 +1,885 @@
+//! Replays: the setup and seed of a match and every turn taken in it, saved
+//! as RON files in the `replays` folder of the data directory. Since
+//! everything random about a match comes from its seed, a replay is watched
+//! by setting the same match up again and taking each turn as it was taken,
+//! through the same graphing as the match itself. The soldiers left after
+//! each turn are recorded as well, to notice a replay that plays out
+//! differently, as it may when recorded by another version of the game.
+//!
+//! Matches on random boards are recorded: versus matches (free-for-alls and
+//! matches against the computer included), horde matches and network
+//! matches. Layouts, puzzles and correspondence matches aren't.
+//!
+//! Each replay starts with a header saying what the match was: who played
+//! it by which rules, when, and on which version of the game. The replay
+//! browser lists saved replays by their headers, with a thumbnail of the
+//! board they end on. From there they can be watched, looked through turn
+//! by turn, exported as animated GIFs or deleted.
+
+use crate::StartGraphingEvent;
+use crate::consts::*;
+use crate::correspondence::{GAME_VERSION, game_version_text};
+use crate::models::*;
+use crate::parse::{ParsedFunction, Restriction, ShotType};
+use crate::summary::{draw_text, fill_circle, fill_rect, font, rgba};
+use crate::systems::announce::Announce;
+use crate::systems::graph_display::SkipGraphingEvent;
+use ab_glyph::FontRef;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::num::{NonZeroU8, NonZeroU32};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Format of replay files. Bump it whenever replays change in a way that
+/// older ones can't be read as, and teach `migrate` to bring replays of the
+/// previous format up to date.
+const REPLAY_FORMAT: u32 = 1;
+
+/// A recorded match
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    /// Format of the replay, 0 for replays from before it was written
+    #[serde(default)]
+    format: u32,
+    pub header: ReplayHeader,
+    pub turns: Vec<ReplayTurn>,
+}
+
+/// What a recorded match was
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    /// Version of the game that recorded it
+    pub version: String,
+    /// When the match started and ended, in seconds since 1970-01-01 UTC
+    pub started: u64,
+    pub ended: Option<u64>,
+    /// Players, rules and seed of the match
+    pub setup: ReplaySetup,
+    /// Board the match was played on
+    pub map: String,
+    /// Mutators in play at the start of the match
+    pub mutators: Vec<Mutator>,
+    /// Name of the winner, once the match is over
+    pub winner: Option<String>,
+}
+
+/// Everything about the setup of a recorded match that decides how it plays
+/// out
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplaySetup {
+    pub seed: u64,
+    pub horde: bool,
+    pub players: Vec<ReplayPlayer>,
+    turn_seconds: u32,
+    max_turns: Option<NonZeroU32>,
+    blitz: Option<Blitz>,
+    obstacles: u8,
+    gravity_wells: u8,
+    roulette: bool,
+    restriction: Restriction,
+    budget: Option<u32>,
+    max_shot_length: Option<u32>,
+    energy: bool,
+    cooldown: Option<NonZeroU8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayPlayer {
+    pub name: String,
+    skin: SoldierSkin,
+    soldier_num: NonZeroU8,
+    /// Whether the computer played for them
+    ai: bool,
+}
+
+/// A turn of a recorded match
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayTurn {
+    pub turn_number: u32,
+    /// Index of the player who took it
+    pub player: usize,
+    /// Number of the soldier who shot
+    pub soldier: u8,
+    /// The equation shot, or `None` if the turn ran out without a shot
+    pub equation: Option<String>,
+    /// Soldiers the shot destroyed
+    pub hits: Vec<ReplaySoldier>,
+    /// Soldiers left standing after the turn
+    pub soldiers: Vec<ReplaySoldier>,
+}
+
+/// A soldier and where it stood, in graph units as seen from player 1's
+/// half of the board
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplaySoldier {
+    pub player: usize,
+    pub number: u8,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl ReplaySoldier {
+    fn of(soldier: &Soldier, from_left: bool) -> ReplaySoldier {
+        let at = soldier.graph_location();
+        ReplaySoldier {
+            player: soldier.player().index(),
+            number: soldier.number(),
+            x: if from_left { at.x } else { -at.x },
+            y: at.y,
+        }
+    }
+
+    /// Whether both are the same soldier at the same place, give or take
+    /// rounding
+    fn matches(&self, other: &ReplaySoldier) -> bool {
+        self.player == other.player
+            && self.number == other.number
+            && Vec2::new(self.x, self.y).distance(Vec2::new(other.x, other.y))
+                < REPLAY_TOLERANCE
+    }
+}
+
+impl ReplaySetup {
+    /// The setup of a match about to start on the board generated from
+    /// `seed`, if it can be replayed
+    pub fn of(setup: &SetupPhase, seed: u64) -> Option<ReplaySetup> {
+        let random_board = match setup.mode {
+            MatchMode::Versus => {
+                setup.layout.is_none() || setup.players.len() > 2
+            }
+            MatchMode::Horde | MatchMode::Network => true,
+            _ => false,
+        };
+        if !random_board || setup.demo {
+            return None;
+        }
+        // Only versus matches have more than two players
+        let players = match setup.mode {
+            MatchMode::Versus => &setup.players[..],
+            _ => &setup.players[..2],
+        };
+        let computer =
+            setup.computer.is_some() && setup.mode == MatchMode::Versus;
+        Some(ReplaySetup {
+            seed,
+            horde: setup.mode == MatchMode::Horde,
+            players: (players.iter().enumerate())
+                .map(|(index, player)| ReplayPlayer {
+                    name: player.name.clone(),
+                    skin: player.skin,
+                    soldier_num: player.soldier_num,
+                    ai: computer && index == 1,
+                })
+                .collect(),
+            turn_seconds: setup.turn_seconds,
+            max_turns: setup.max_turns,
+            blitz: setup.blitz,
+            obstacles: setup.obstacles,
+            gravity_wells: setup.gravity_wells,
+            roulette: setup.roulette,
+            restriction: setup.restriction,
+            budget: setup.budget,
+            max_shot_length: setup.max_shot_length,
+            energy: setup.energy,
+            cooldown: setup.cooldown,
+        })
+    }
+
+    /// Description of the board the match is played on
+    fn map(&self) -> String {
+        format!(
+            "Random board with {} obstacles and {} gravity wells",
+            self.obstacles, self.gravity_wells
+        )
+    }
+
+    /// `setup` changed to set the recorded match up again
+    pub fn apply(&self, setup: &SetupPhase) -> SetupPhase {
+        let mut setup = setup.clone();
+        setup.mode = if self.horde {
+            MatchMode::Horde
+        } else {
+            MatchMode::Versus
+        };
+        setup.players = (self.players.iter().enumerate())
+            .map(|(index, player)| PlayerConfig {
+                name: player.name.clone(),
+                skin: player.skin,
+                soldier_num: player.soldier_num,
+                ..PlayerConfig::new(index + 1)
+            })
+            .collect();
+        setup.turn_seconds = self.turn_seconds;
+        setup.max_turns = self.max_turns;
+        setup.blitz = self.blitz;
+        setup.obstacles = self.obstacles;
+        setup.gravity_wells = self.gravity_wells;
+        setup.roulette = self.roulette;
+        setup.restriction = self.restriction;
+        setup.budget = self.budget;
+        setup.max_shot_length = self.max_shot_length;
+        setup.energy = self.energy;
+        setup.cooldown = self.cooldown;
+        setup.practice = false;
+        setup.aim_preview = false;
+        setup.layout = None;
+        setup.network = None;
+        setup.computer =
+            (self.players.iter().any(|i| i.ai)).then_some(DEFAULT_AI_LEVEL);
+        setup.demo = false;
+        setup
+    }
+}
+
+impl Replay {
+    /// Replay of a match starting now, with the given mutators in play
+    pub fn new(setup: ReplaySetup, mutators: Vec<Mutator>) -> Replay {
+        Replay {
+            format: REPLAY_FORMAT,
+            header: ReplayHeader {
+                version: GAME_VERSION.to_string(),
+                started: now(),
+                ended: None,
+                map: setup.map(),
+                setup,
+                mutators,
+                winner: None,
+            },
+            turns: Vec::new(),
+        }
+    }
+
+    /// Record the end of the match, won by `winner`
+    pub fn finish(&mut self, winner: &str) {
+        self.header.ended = Some(now());
+        self.header.winner = Some(winner.to_string());
+    }
+
+    /// The recorded turn with this number, if the match got that far
+    pub fn turn(&self, turn_number: u32) -> Option<&ReplayTurn> {
+        self.turns.iter().find(|i| i.turn_number == turn_number)
+    }
+
+    /// Record the shot the current player of `state` is taking. Every
+    /// soldier standing is noted down as hit for now, and those still
+    /// standing once the turn is over are crossed off.
+    pub fn record_shot(&mut self, state: &PlayPhase, equation: &str) {
+        let from_left = state.turn().starts_left();
+        self.turns.push(ReplayTurn {
+            turn_number: state.turn_number(),
+            player: state.turn().index(),
+            soldier: state.current_player().current_soldier().number(),
+            equation: Some(equation.to_string()),
+            hits: (state.soldiers())
+                .map(|soldier| ReplaySoldier::of(soldier, from_left))
+                .collect(),
+            soldiers: Vec::new(),
+        });
+    }
+
+    /// Finish recording the turn that is over in `state`
+    pub fn record_turn_over(&mut self, state: &PlayPhase) {
+        let turn_number = state.turn_number();
+        let from_left = state.turn().starts_left();
+        if self
+            .turns
+            .last()
+            .is_none_or(|i| i.turn_number != turn_number)
+        {
+            // No shot was taken, as the turn ran out
+            self.turns.push(ReplayTurn {
+                turn_number,
+                player: state.turn().index(),
+                soldier: state.current_player().current_soldier().number(),
+                equation: None,
+                hits: Vec::new(),
+                soldiers: Vec::new(),
+            });
+        }
+        let Some(turn) = self.turns.last_mut() else {
+            return;
+        };
+        turn.soldiers = (state.soldiers())
+            .map(|soldier| ReplaySoldier::of(soldier, from_left))
+            .collect();
+        let soldiers = &turn.soldiers;
+        turn.hits.retain(|hit| {
+            !soldiers
+                .iter()
+                .any(|i| i.player == hit.player && i.number == hit.number)
+        });
+    }
+
+    fn dir() -> PathBuf {
+        crate::util::data_dir().join("replays")
+    }
+
+    /// Save the replay among the others, returning where it was saved
+    pub fn save(&self) -> std::io::Result<PathBuf> {
+        let dir = Self::dir();
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("match-{}.ron", self.header.started));
+        let contents =
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+                .map_err(std::io::Error::other)?;
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    /// Read a saved replay. Relative paths are looked up in the `replays`
+    /// folder of the data directory.
+    pub fn load(file: &str) -> Result<Replay, String> {
+        let path = Self::dir().join(file.trim());
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Couldn't read {}: {e}", path.display()))?;
+        Self::decode(&contents).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Read the text of a replay, in the current format or an older one
+    pub fn decode(contents: &str) -> Result<Replay, String> {
+        let info = ron::from_str::<ReplayInfo>(contents)
+            .map_err(|e| format!("Not a replay: {e}"))?;
+        let made_by = game_version_text(&Some(info.header.version));
+        if info.format > REPLAY_FORMAT {
+            return Err(format!(
+                "Recorded by {made_by} (format {}), which this version can't \
+                 read",
+                info.format
+            ));
+        }
+        migrate(info.format, contents).map_err(|e| {
+            format!(
+                "Recorded by {made_by} (format {}) but damaged: {e}",
+                info.format
+            )
+        })
+    }
+
+    /// Every saved replay that can be read, by file name
+    pub fn saved() -> Vec<(String, Replay)> {
+        let Ok(entries) = std::fs::read_dir(Self::dir()) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .filter(|i| i.path().extension().is_some_and(|i| i == "ron"))
+            .filter_map(|entry| {
+                let file = entry.file_name().to_string_lossy().into_owned();
+                let replay = Self::load(&file).ok()?;
+                Some((file, replay))
+            })
+            .collect()
+    }
+
+    /// Whether the replay has `filter` in the name of a player, a mutator
+    /// or the version that recorded it, ignoring case
+    pub fn matches(&self, filter: &str) -> bool {
+        let filter = filter.trim().to_lowercase();
+        let header = &self.header;
+        let names = header.setup.players.iter().map(|i| i.name.as_str());
+        let mutators = header.mutators.iter().map(|i| i.name());
+        (names.chain(mutators).chain([header.version.as_str()]))
+            .any(|i| i.to_lowercase().contains(&filter))
+    }
+}
+
+/// What a player did over a recorded match
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PlayerStats {
+    /// Turns they shot in
+    pub shots: u32,
+    /// Turns they let run out
+    pub skipped: u32,
+    /// Shots that destroyed soldiers of other players
+    pub hits: u32,
+    /// Soldiers of other players destroyed
+    pub kills: u32,
+    /// Shots of each type, in the order of [`ShotType::ALL`]
+    pub by_type: [ShotTypeStats; ShotType::ALL.len()],
+}
+
+/// Shots of one type a player took, and how many of them hit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShotTypeStats {
+    pub shots: u32,
+    pub hits: u32,
+}
+
+impl PlayerStats {
+    /// The type of shot the player misses the most of, with the share of
+    /// those shots missed, once they took enough of them to tell
+    pub fn worst_shot_type(&self) -> Option<(ShotType, f32)> {
+        (ShotType::ALL.into_iter().zip(self.by_type))
+            .filter(|(_, i)| {
+                i.shots >= SHOT_HABIT_MIN_SHOTS && i.hits < i.shots
+            })
+            .map(|(shot_type, i)| {
+                (shot_type, 1. - i.hits as f32 / i.shots as f32)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+impl Replay {
+    /// What each player did over the match, in turn order
+    pub fn stats(&self) -> Vec<PlayerStats> {
+        let players = self.header.setup.players.len();
+        let mut stats = vec![PlayerStats::default(); players];
+        for turn in &self.turns {
+            let Some(player) = stats.get_mut(turn.player) else {
+                continue;
+            };
+            let Some(equation) = &turn.equation else {
+                player.skipped += 1;
+                continue;
+            };
+            let kills = (turn.hits.iter())
+                .filter(|i| i.player != turn.player)
+                .count() as u32;
+            player.shots += 1;
+            player.hits += u32::from(kills > 0);
+            player.kills += kills;
+            if let Ok(function) = equation.parse::<ParsedFunction>() {
+                let by_type =
+                    &mut player.by_type[function.shot_type() as usize];
+                by_type.shots += 1;
+                by_type.hits += u32::from(kills > 0);
+            }
+        }
+        stats
+    }
+
+    /// Soldiers left standing when the recording ends
+    pub fn final_soldiers(&self) -> &[ReplaySoldier] {
+        self.turns.last().map_or(&[], |i| &i.soldiers)
+    }
+
+    /// Export the board after each turn as the frames of an animated GIF,
+    /// saved next to the replay `file`. Returns where it was saved.
+    pub fn export_gif(&self, file: &str) -> image::ImageResult<PathBuf> {
+        let path = Self::dir().join(file.trim()).with_extension("gif");
+        let fonts = egui::FontDefinitions::default();
+        let font = font(&fonts);
+        let players = &self.header.setup.players;
+        let frames = self.turns.iter().map(|turn| {
+            let name = players.get(turn.player).map_or("?", |i| &i.name);
+            let caption = match &turn.equation {
+                Some(equation) => {
+                    format!(
+                        "Turn {}: {name} shoots {equation}",
+                        turn.turn_number
+                    )
+                }
+                None => {
+                    format!("Turn {}: {name} ran out of time", turn.turn_number)
+                }
+            };
+            let delay = Delay::from_numer_denom_ms(REPLAY_GIF_FRAME_MS, 1);
+            Frame::from_parts(
+                render_frame(turn, &caption, font.as_ref()),
+                0,
+                0,
+                delay,
+            )
+        });
+        let mut encoder = GifEncoder::new_with_speed(
+            std::fs::File::create(&path)?,
+            REPLAY_GIF_SPEED,
+        );
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)?;
+        Ok(path)
+    }
+
+    /// Delete the saved replay `file`, and the GIF exported from it if there
+    /// is one
+    pub fn delete(file: &str) -> std::io::Result<()> {
+        let path = Self::dir().join(file.trim());
+        std::fs::remove_file(&path)?;
+        match std::fs::remove_file(path.with_extension("gif")) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Draw the board after `turn` with `caption` above it. The soldiers it
+/// destroyed are left faintly where they stood.
+fn render_frame(
+    turn: &ReplayTurn,
+    caption: &str,
+    font: Option<&FontRef>,
+) -> RgbaImage {
+    let board = 20. * REPLAY_GIF_SCALE;
+    let top = 2. * REPLAY_GIF_MARGIN + REPLAY_GIF_CAPTION_SIZE;
+    let mut image = RgbaImage::from_pixel(
+        (board + 2. * REPLAY_GIF_MARGIN) as u32,
+        (board + top + REPLAY_GIF_MARGIN) as u32,
+        rgba(SUMMARY_BACKGROUND),
+    );
+    let corner = Vec2::new(REPLAY_GIF_MARGIN, top);
+    fill_rect(
+        &mut image,
+        corner,
+        Vec2::splat(board),
+        rgba(SUMMARY_BOARD_COLOR),
+    );
+    let radius = SOLDIER_RADIUS / 20. * REPLAY_GIF_SCALE;
+    let soldiers = (turn.soldiers.iter().map(|i| (i, 1.)))
+        .chain(turn.hits.iter().map(|i| (i, REPLAY_GIF_HIT_ALPHA)));
+    for (soldier, alpha) in soldiers {
+        let Some(color) = SUMMARY_SOLDIER_COLORS.get(soldier.player) else {
+            continue;
+        };
+        let center = corner
+            + Vec2::new(soldier.x + 10., 10. - soldier.y) * REPLAY_GIF_SCALE;
+        fill_circle(&mut image, center, radius, rgba(color.with_alpha(alpha)));
+    }
+    if let Some(font) = font {
+        let at = Vec2::splat(REPLAY_GIF_MARGIN);
+        draw_text(&mut image, font, caption, at, REPLAY_GIF_CAPTION_SIZE);
+    }
+    image
+}
+
+/// Order of the replays in the replay browser
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ReplaySort {
+    #[default]
+    Newest,
+    Oldest,
+    Longest,
+    Players,
+}
+
+impl ReplaySort {
+    pub const ALL: [ReplaySort; 4] = [
+        ReplaySort::Newest,
+        ReplaySort::Oldest,
+        ReplaySort::Longest,
+        ReplaySort::Players,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReplaySort::Newest => "Newest first",
+            ReplaySort::Oldest => "Oldest first",
+            ReplaySort::Longest => "Most turns first",
+            ReplaySort::Players => "By players",
+        }
+    }
+    pub fn sort(&self, replays: &mut [(String, Replay)]) {
+        match self {
+            ReplaySort::Newest => {
+                replays.sort_by_key(|(_, i)| Reverse(i.header.started))
+            }
+            ReplaySort::Oldest => {
+                replays.sort_by_key(|(_, i)| i.header.started)
+            }
+            ReplaySort::Longest => {
+                replays.sort_by_key(|(_, i)| Reverse(i.turns.len()))
+            }
+            ReplaySort::Players => replays.sort_by_key(|(_, i)| {
+                (i.header.setup.players.iter())
+                    .map(|i| i.name.to_lowercase())
+                    .collect::<Vec<_>>()
+            }),
+        }
+    }
+}
+
+/// Seconds since 1970-01-01 UTC
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// A time in seconds since 1970-01-01 UTC, as a UTC date and time
+pub fn format_time(secs: u64) -> String {
+    let minutes = secs % 86400 / 60;
+    // Days counted from 0000-03-01, so leap days fall at the end of a year
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+        - day_of_era / 146096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let march_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * march_month + 2) / 5 + 1;
+    let month = (march_month + 2) % 12 + 1;
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+    format!(
+        "{year}-{month:02}-{day:02} {:02}:{:02}",
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Keep the turns of the match being played for its replay, and check
+/// those of the replay being watched against what it recorded. Runs as each
+/// turn is over, before the sides swap.
+pub fn record_replay(
+    mut state: ResMut<GameState>,
+    mut announcements: EventWriter<Announce>,
+    // Turn at which the replay being watched first went differently, if it
+    // did
+    mut diverged: Local<Option<u32>>,
+) {
+    let Some(playing_state) = state.playing_state_mut() else {
+        *diverged = None;
+        return;
+    };
+    playing_state.record_turn_over();
+    let Some(recorded) = (playing_state.watching())
+        .and_then(|replay| replay.turn(playing_state.turn_number()))
+    else {
+        return;
+    };
+    let from_left = playing_state.turn().starts_left();
+    let soldiers = (playing_state.soldiers())
+        .map(|soldier| ReplaySoldier::of(soldier, from_left))
+        .collect::<Vec<_>>();
+    let same = soldiers.len() == recorded.soldiers.len()
+        && (soldiers.iter()).all(|soldier| {
+            recorded.soldiers.iter().any(|i| i.matches(soldier))
+        });
+    if !same && diverged.is_none() {
+        let turn_number = playing_state.turn_number();
+        *diverged = Some(turn_number);
+        announcements.send(Announce(format!(
+            "From turn {turn_number}, the replay plays out differently from \
+             the match it recorded"
+        )));
+    }
+}
+
+/// Take the turns of the replay being watched: once each turn has started,
+/// its soldier shoots the recorded equation, or the turn is skipped if it
+/// ran out
+pub fn play_replay(
+    mut state: ResMut<GameState>,
+    time: Res<Time>,
+    mut start_graphing_events: EventWriter<StartGraphingEvent>,
+    mut skip_graphing_events: EventWriter<SkipGraphingEvent>,
+    // How long the current turn has been shown for
+    mut waited: Local<Duration>,
+) {
+    let playing_state = (state.playing_state_mut())
+        .filter(|state| state.turn_phase().is_input());
+    let Some(playing_state) = playing_state else {
+        *waited = Duration::ZERO;
+        return;
+    };
+    let Some(turn) = (playing_state.watching())
+        .and_then(|replay| replay.turn(playing_state.turn_number()))
+        .cloned()
+    else {
+        return;
+    };
+    *waited += time.delta();
+    if *waited < REPLAY_TURN_PAUSE {
+        return;
+    }
+    *waited = Duration::ZERO;
+    let player = playing_state.current_player_mut();
+    player.select_soldier(turn.soldier);
+    let Some(equation) = turn.equation else {
+        playing_state.skip_turn();
+        skip_graphing_events.send(SkipGraphingEvent);
+        return;
+    };
+    *player.current_soldier_mut().slots.current_mut() = equation.clone();
+    match equation.parse() {
+        Ok(func) => {
+            start_graphing_events.send(StartGraphingEvent(func));
+        }
+        Err(_) => {
+            skip_graphing_events.send(SkipGraphingEvent);
+        }
+    }
+}
+
+/// Show how far the replay being watched has got, with its speed and a way
+/// out. Watching at another speed speeds up or slows down the game's clock,
+/// which goes back to normal once the replay is over.
+pub fn replay_ui(
+    mut contexts: EguiContexts,
+    mut state: ResMut<GameState>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    let Some(playing_state) = state.playing_state() else {
+        if time.relative_speed() != 1. {
+            time.set_relative_speed(1.);
+        }
+        return;
+    };
+    let Some(replay) = playing_state.watching() else {
+        return;
+    };
+    let turn_number = playing_state.turn_number();
+    let last = replay.turns.last().map_or(0, |i| i.turn_number);
+    let mut stop = false;
+    egui::Window::new("Replay")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0., -20.))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if turn_number > last {
+                ui.label("End of the replay");
+            } else {
+                ui.label(format!("Turn {turn_number} of {last}"));
+            }
+            ui.horizontal(|ui| {
+                let mut speed = time.relative_speed();
+                let slider = egui::Slider::new(
+                    &mut speed,
+                    REPLAY_MIN_SPEED..=REPLAY_MAX_SPEED,
+                )
+                .logarithmic(true)
+                .suffix("×")
+                .text("Speed");
+                if ui.add(slider).changed() {
+                    time.set_relative_speed(speed);
+                }
+                stop = ui.button("Stop watching").clicked();
+            });
+        });
+    if stop {
+        *state = GameState::default();
+    }
+}
+
+/// What a replay says about itself, which can be read before the rest of it
+#[derive(Deserialize)]
+struct ReplayInfo {
+    #[serde(default)]
+    format: u32,
+    header: ReplayVersion,
+}
+
+#[derive(Deserialize)]
+struct ReplayVersion {
+    version: String,
+}
+
+/// Read a replay in an older format, bringing it up to the current one
+fn migrate(
+    format: u32,
+    contents: &str,
+) -> Result<Replay, ron::error::SpannedError> {
+    let mut replay = ron::from_str::<Replay>(contents)?;
+    // Format 0 only lacked the format itself
+    replay.format = format.max(REPLAY_FORMAT);
+    Ok(replay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_setup() {
+        let mut setup = GameState::default().setup_state().unwrap().clone();
+        setup.players.push(PlayerConfig::new(3));
+        setup.players[2].name = "Noether".to_string();
+        setup.gravity_wells = 2;
+        setup.cooldown = NonZeroU8::new(3);
+
+        let replay = Replay::new(ReplaySetup::of(&setup, 1936).unwrap(), vec![
+            Mutator::GravityWells,
+        ]);
+        let text = ron::to_string(&replay).unwrap();
+        assert_eq!(Replay::decode(&text).unwrap(), replay);
+        let old = text.replace("format:1,", "");
+        assert_eq!(Replay::decode(&old).unwrap(), replay);
+        let newer = text.replace("format:1,", "format:2,");
+        let error = Replay::decode(&newer).unwrap_err();
+        assert!(error.contains(GAME_VERSION), "{error}");
+        assert!(replay.matches("noeth"));
+        assert!(replay.matches("gravity"));
+        assert!(!replay.matches("Hilbert"));
+
+        let watched = replay
+            .header
+            .setup
+            .apply(&GameState::default().setup_state().unwrap().clone());
+        assert_eq!(watched.mode, MatchMode::Versus);
+        assert_eq!(watched.players.len(), 3);
+        assert_eq!(watched.players[2].name, "Noether");
+        assert_eq!(watched.gravity_wells, 2);
+        assert_eq!(watched.cooldown, NonZeroU8::new(3));
+
+        // Puzzles aren't recorded
+        setup.mode = MatchMode::Puzzle;
+        assert_eq!(ReplaySetup::of(&setup, 1936), None);
+    }
+
+    #[test]
+    fn test_replay_stats() {
+        let setup = GameState::default().setup_state().unwrap().clone();
+        let mut replay =
+            Replay::new(ReplaySetup::of(&setup, 1936).unwrap(), Vec::new());
+        let soldier = |player, number| ReplaySoldier {
+            player,
+            number,
+            x: 0.,
+            y: 0.,
+        };
+        let turn =
+            |turn_number, player, equation: Option<&str>, hits| ReplayTurn {
+                turn_number,
+                player,
+                soldier: 0,
+                equation: equation.map(str::to_string),
+                hits,
+                soldiers: vec![soldier(0, 0)],
+            };
+        replay.turns = vec![
+            turn(1, 0, Some("x"), vec![soldier(1, 0), soldier(1, 1)]),
+            turn(2, 1, None, Vec::new()),
+            turn(3, 0, Some("-x"), vec![soldier(0, 1)]),
+            turn(4, 1, Some("sin(x)"), Vec::new()),
+            turn(5, 1, Some("2sin(x)"), Vec::new()),
+            turn(6, 1, Some("x^2"), Vec::new()),
+            turn(7, 1, Some("sin(2x)"), vec![soldier(0, 1)]),
+            turn(8, 1, Some("sin(x)+1"), Vec::new()),
+        ];
+        let stats = replay.stats();
+        let line = ShotType::Line as usize;
+        assert_eq!(stats[0], PlayerStats {
+            shots: 2,
+            skipped: 0,
+            hits: 1,
+            kills: 2,
+            by_type: std::array::from_fn(|i| ShotTypeStats {
+                shots: if i == line { 2 } else { 0 },
+                hits: u32::from(i == line),
+            }),
+        });
+        assert_eq!(stats[0].worst_shot_type(), None);
+        assert_eq!(stats[1].skipped, 1);
+        assert_eq!(stats[1].by_type[ShotType::Trig as usize], ShotTypeStats {
+            shots: 4,
+            hits: 1
+        });
+        assert_eq!(stats[1].worst_shot_type(), Some((ShotType::Trig, 0.75)));
+        assert_eq!(replay.final_soldiers(), &[soldier(0, 0)]);
+
+        let frame = render_frame(&replay.turns[0], "Turn 1", None);
+        assert!(frame.width() > 0 && frame.height() > frame.width());
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(951_827_696), "2000-02-29 12:34");
+        assert_eq!(format_time(1_790_000_000), "2026-09-21 14:13");
+    }
+}
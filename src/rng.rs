@@ -0,0 +1,114 @@
+//! Randomness during a match. Everything random that happens once a match
+//! has started (mutator rolls, horde waves, computer players' aim) is drawn
+//! from a stream derived from the match's seed, the turn and what it is for.
+//! Given the seed, a replay or the other end of a network match reproduces
+//! the same outcomes, and every stream handed out is logged so that disputes
+//! can be audited afterwards.
+
+use crate::util::fnv1a;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+
+/// What random numbers are drawn for. Each purpose has a stream of its own,
+/// so drawing more for one doesn't change the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RngStream {
+    Roulette,
+    Wave,
+    Aim,
+}
+
+impl RngStream {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RngStream::Roulette => "mutator roulette",
+            RngStream::Wave => "horde wave",
+            RngStream::Aim => "computer aim",
+        }
+    }
+}
+
+/// A stream handed out during a match
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RngDraw {
+    pub turn_number: u32,
+    pub stream: RngStream,
+    /// Seed of the stream, from which its numbers can be drawn again
+    pub seed: u64,
+}
+
+/// Source of a match's randomness, with the log of the streams it handed out
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatchRng {
+    seed: u64,
+    log: Vec<RngDraw>,
+}
+
+impl MatchRng {
+    pub fn new(seed: u64) -> Self {
+        MatchRng {
+            seed,
+            log: Vec::new(),
+        }
+    }
+
+    /// Seed the whole match was played from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Every stream handed out so far, in order
+    pub fn log(&self) -> &[RngDraw] {
+        &self.log
+    }
+
+    /// Generator for `stream` in the given turn. Streams asked for again in
+    /// the same turn continue with new numbers rather than repeating them.
+    pub fn stream(&mut self, turn_number: u32, stream: RngStream) -> StdRng {
+        let index = (self.log.iter())
+            .filter(|i| i.turn_number == turn_number && i.stream == stream)
+            .count();
+        let mut bytes = self.seed.to_le_bytes().to_vec();
+        bytes.extend(turn_number.to_le_bytes());
+        bytes.push(stream as u8);
+        bytes.extend((index as u32).to_le_bytes());
+        let seed = fnv1a(&bytes);
+        log::info!(
+            "Turn {turn_number}: {} stream seeded with {seed:016x}",
+            stream.name()
+        );
+        self.log.push(RngDraw {
+            turn_number,
+            stream,
+            seed,
+        });
+        StdRng::seed_from_u64(seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_match_rng_streams() {
+        let draw = |rng: &mut MatchRng, turn, stream| {
+            rng.stream(turn, stream).r#gen::<u64>()
+        };
+        let mut rng = MatchRng::new(1990);
+        let mut again = MatchRng::new(1990);
+        let first = draw(&mut rng, 3, RngStream::Aim);
+        // Other streams and turns don't disturb a stream
+        draw(&mut again, 1, RngStream::Roulette);
+        draw(&mut again, 3, RngStream::Wave);
+        assert_eq!(draw(&mut again, 3, RngStream::Aim), first);
+        assert_ne!(draw(&mut rng, 3, RngStream::Aim), first);
+        assert_ne!(draw(&mut rng, 4, RngStream::Aim), first);
+        assert_ne!(draw(&mut MatchRng::new(1991), 3, RngStream::Aim), first);
+        assert_eq!(rng.log().len(), 3);
+        assert_eq!(rng.log()[1].turn_number, 3);
+        assert_eq!(again.log()[2].seed, rng.log()[0].seed);
+    }
+}
@@ -0,0 +1,195 @@
+//! Saved matches, kept as RON files in the `saves` folder of the data
+//! directory to be resumed later, with the soldiers, turn order and the time
+//! left in the turn as they were
+
+use crate::correspondence::{GAME_VERSION, game_version_text};
+use crate::models::PlayPhase;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Format of saved games. Bump it whenever `PlayPhase` changes in a way that
+/// older saves can't be read as, and teach `migrate` to bring saves of the
+/// previous format up to date. The saves in `tests/golden` have to keep
+/// loading either way.
+const SAVE_FORMAT: u32 = 1;
+
+/// A match saved at some point, as `SavedGame<&PlayPhase>` when saving and
+/// `SavedGame<Box<PlayPhase>>` when loading
+#[derive(Serialize, Deserialize)]
+pub struct SavedGame<G> {
+    /// Format of the save, 0 for saves from before it was written
+    #[serde(default)]
+    format: u32,
+    /// Version of the game that saved it
+    version: String,
+    /// When it was saved, in seconds since 1970-01-01 UTC
+    saved: u64,
+    game: G,
+}
+
+fn dir() -> PathBuf {
+    crate::util::data_dir().join("saves")
+}
+
+/// Save the match, returning where it was saved
+pub fn save_game(game: &PlayPhase) -> std::io::Result<PathBuf> {
+    let saved = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let dir = dir();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("game-{saved}.ron"));
+    let contents = encode(game, saved).map_err(std::io::Error::other)?;
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
+
+/// The text of the match as saved at `saved`, in seconds since 1970-01-01
+/// UTC, to be read by `decode`
+pub fn encode(game: &PlayPhase, saved: u64) -> Result<String, ron::Error> {
+    let game = SavedGame {
+        format: SAVE_FORMAT,
+        version: GAME_VERSION.to_string(),
+        saved,
+        game,
+    };
+    ron::ser::to_string_pretty(&game, ron::ser::PrettyConfig::new())
+}
+
+/// Read a saved match. Relative paths are looked up in the `saves` folder of
+/// the data directory.
+pub fn load_game(file: &str) -> Result<Box<PlayPhase>, String> {
+    let path = dir().join(file.trim());
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Couldn't read {}: {e}", path.display()))?;
+    decode(&contents).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// What a save says about itself, which can be read before the rest of it
+#[derive(Deserialize)]
+struct SaveInfo {
+    #[serde(default)]
+    format: u32,
+    version: String,
+}
+
+/// Read the text of a saved match, in the current format or an older one
+pub fn decode(contents: &str) -> Result<Box<PlayPhase>, String> {
+    let info = ron::from_str::<SaveInfo>(contents)
+        .map_err(|e| format!("Not a saved game: {e}"))?;
+    let made_by = game_version_text(&Some(info.version));
+    if info.format > SAVE_FORMAT {
+        return Err(format!(
+            "Saved by {made_by} (format {}), which this version can't read",
+            info.format
+        ));
+    }
+    migrate(info.format, contents).map_err(|e| {
+        format!(
+            "Saved by {made_by} (format {}) but damaged: {e}",
+            info.format
+        )
+    })
+}
+
+/// Read a save in an older format, bringing it up to the current one
+fn migrate(
+    format: u32,
+    contents: &str,
+) -> Result<Box<PlayPhase>, ron::error::SpannedError> {
+    let mut game = ron::from_str::<SavedGame<Box<PlayPhase>>>(contents)?.game;
+    if format < 1 {
+        // Format 0 didn't keep count of the obstacles added during the match
+        game.recount_obstacles();
+    }
+    Ok(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{GameState, TurnPhase};
+    use std::time::Duration;
+
+    #[test]
+    fn test_saved_game() {
+        let mut state = GameState::default();
+        if let Some(setup) = state.setup_state_mut() {
+            setup.obstacles = 3;
+            setup.gravity_wells = 1;
+        }
+        state.start_playing().unwrap();
+        let game = state.playing_state_mut().unwrap();
+        game.next_turn();
+        let TurnPhase::InputPhase { timer } = game.turn_phase_mut() else {
+            panic!("The match should start with a turn being played");
+        };
+        timer.tick(Duration::from_secs(20));
+
+        let loaded = decode(&encode(game, 0).unwrap()).unwrap();
+        assert_eq!(loaded.turn(), game.turn());
+        assert_eq!(loaded.turn_number(), game.turn_number());
+        assert_eq!(loaded.obstacles(), game.obstacles());
+        assert_eq!(loaded.field(), game.field());
+        let soldiers = |game: &PlayPhase| {
+            (game.soldiers())
+                .map(|i| (i.id(), i.graph_location()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(soldiers(&loaded), soldiers(game));
+        let TurnPhase::InputPhase { timer } = loaded.turn_phase() else {
+            panic!("The turn should be resumed where it was left");
+        };
+        assert_eq!(timer.remaining(), Duration::from_secs(40));
+    }
+
+    #[test]
+    fn test_save_formats() {
+        let mut state = GameState::default();
+        if let Some(setup) = state.setup_state_mut() {
+            setup.obstacles = 3;
+        }
+        state.start_playing().unwrap();
+        let game = state.playing_state().unwrap();
+        let text = |format, version: &str| {
+            ron::to_string(&SavedGame {
+                format,
+                version: version.to_string(),
+                saved: 0,
+                game,
+            })
+            .unwrap()
+        };
+
+        // Saves from before the format was written lack it, and the count
+        // of obstacles
+        let old = text(0, "0.1.0").replace("format:0,", "");
+        let old = remove_field(&old, ",next_obstacle_id:");
+        assert_eq!(decode(&old).unwrap().obstacles(), game.obstacles());
+
+        let newer = text(SAVE_FORMAT + 1, "9.9.9");
+        let error = decode(&newer).err().unwrap();
+        assert!(error.contains("9.9.9"), "{error}");
+        let damaged = text(SAVE_FORMAT, "9.9.8").replace("soldiers", "x");
+        let error = decode(&damaged).err().unwrap();
+        assert!(error.contains("9.9.8"), "{error}");
+        assert!(decode("()").is_err());
+
+        // A match saved in format 1, which has to keep loading for as long
+        // as `SAVE_FORMAT` is 1
+        let format_1 = include_str!("../tests/golden/save-format-1.ron");
+        let loaded = decode(format_1).unwrap();
+        assert_eq!(loaded.turn_number(), 3);
+        assert_eq!(loaded.obstacles().len(), 5);
+        assert_eq!(loaded.roulette_picks().map(|(round, _)| round), Some(2));
+        let again = decode(&encode(&loaded, 0).unwrap()).unwrap();
+        assert_eq!(again.state_hash(), loaded.state_hash());
+    }
+
+    /// Remove the field starting with `field` from `text`
+    fn remove_field(text: &str, field: &str) -> String {
+        let start = text.find(field).unwrap();
+        let end = start + 1 + text[start + 1..].find([',', ')']).unwrap();
+        format!("{}{}", &text[..start], &text[end..])
+    }
+}
@@ -0,0 +1,252 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// User preferences that persist between launches
+#[derive(
+    Resource, Serialize, Deserialize, Clone, Debug, PartialEq, Default,
+)]
+#[serde(default)]
+pub struct Settings {
+    /// Directory name of the selected pack in `assets/packs`, or `None` for
+    /// the built-in assets
+    pub asset_pack: Option<String>,
+    /// Slow down and zoom in on shots that are about to hit
+    pub cinematic_shots: bool,
+    /// Show a magnified inset around the active soldier while aiming
+    pub pip_enabled: bool,
+    /// Also fit the nearest enemy into the inset
+    pub pip_enemy: bool,
+    /// Enlarge the board, show big nameplates and move the equation input to
+    /// a small overlay, for casting matches
+    pub presentation_mode: bool,
+    /// Show the equation input on a second window instead of the main one
+    pub input_window: bool,
+    /// Show a bar estimating which player is ahead
+    pub evaluation_bar: bool,
+    /// Shade the parts of the board the enemies can reach with straight shots
+    pub threat_overlay: bool,
+    /// Hide the emotes and chat players send each other
+    pub mute_emotes: bool,
+    /// Beep as the turn timer runs out
+    pub timer_beeps: bool,
+    /// Print match events to stdout as JSON lines, for overlays and stats
+    /// tools
+    pub observer_stream: bool,
+    pub decimal_comma: DecimalComma,
+    /// Build equations from a palette of keys instead of typing them
+    pub palette_input: bool,
+    /// Show the tree the current equation parses into, for debugging
+    pub show_parse_tree: bool,
+    pub display: DisplaySettings,
+    pub accessibility: AccessibilitySettings,
+    pub panels: PanelLayout,
+    pub turn_reminder: TurnReminderSettings,
+}
+
+/// How to get the player's attention when their turn of a correspondence or
+/// network match starts while the game is in the background
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TurnReminderSettings {
+    /// Flash the game's taskbar entry
+    pub flash: bool,
+    pub chime: bool,
+    /// Show a desktop notification, where the system has a command for it
+    pub notification: bool,
+}
+
+impl Default for TurnReminderSettings {
+    fn default() -> Self {
+        Self {
+            flash: true,
+            chime: true,
+            notification: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    /// Draw shots instantly and turn off camera moves, explosion flashes and
+    /// soldier animations
+    pub reduced_motion: bool,
+    /// Announce turns, time warnings, hits and the winner in a banner and to
+    /// screen readers
+    pub announcements: bool,
+    /// Also read announcements out loud
+    pub text_to_speech: bool,
+    /// Tell the teams apart by pattern as well as by color: player 2's
+    /// soldiers are striped and their shots dashed
+    pub high_contrast: bool,
+}
+
+/// How the main window is shown. Exclusive fullscreen isn't offered since it
+/// breaks overlays and alt-tabbing.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum WindowModeSetting {
+    #[default]
+    Windowed,
+    Borderless,
+}
+
+impl WindowModeSetting {
+    pub const ALL: [WindowModeSetting; 2] =
+        [WindowModeSetting::Windowed, WindowModeSetting::Borderless];
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowModeSetting::Windowed => "Windowed",
+            WindowModeSetting::Borderless => "Borderless fullscreen",
+        }
+    }
+}
+
+/// Whether numbers in equations can be typed with a decimal comma, as in
+/// `0,5x`. They are turned into decimal points as they are typed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum DecimalComma {
+    /// Follow the system's locale
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl DecimalComma {
+    pub const ALL: [DecimalComma; 3] = [
+        DecimalComma::Auto,
+        DecimalComma::Always,
+        DecimalComma::Never,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            DecimalComma::Auto => "From system language",
+            DecimalComma::Always => "Always",
+            DecimalComma::Never => "Never",
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        match self {
+            DecimalComma::Auto => locale_uses_decimal_comma(),
+            DecimalComma::Always => true,
+            DecimalComma::Never => false,
+        }
+    }
+}
+
+/// Whether the system's language writes numbers with a decimal comma, going
+/// by the usual locale variables. Looked up once, as the locale can't change
+/// while the game runs.
+fn locale_uses_decimal_comma() -> bool {
+    // Languages whose most common locales use a decimal comma
+    const COMMA_LANGUAGES: &[&str] = &[
+        "bg", "ca", "cs", "da", "de", "el", "es", "et", "fi", "fr", "hr", "hu",
+        "id", "it", "lt", "lv", "nb", "nl", "nn", "pl", "pt", "ro", "ru", "sk",
+        "sl", "sr", "sv", "tr", "uk", "vi",
+    ];
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|locale| !locale.is_empty())
+            .unwrap_or_default();
+        let language = locale.split(['_', '-', '.', '@']).next();
+        language.is_some_and(|language| COMMA_LANGUAGES.contains(&language))
+    })
+}
+
+/// How the panels were last arranged, so they stay that way between launches
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PanelLayout {
+    /// Height of the equation panel at the bottom of the window, in logical
+    /// pixels
+    pub input_height: f32,
+    /// Fold the equation panel away while shots are shown. It comes back at
+    /// the start of each turn.
+    pub input_collapsed: bool,
+}
+
+impl Default for PanelLayout {
+    fn default() -> Self {
+        Self {
+            input_height: crate::consts::INPUT_PANEL_HEIGHT,
+            input_collapsed: false,
+        }
+    }
+}
+
+/// Window and frame pacing options, applied to the window as they change
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub mode: WindowModeSetting,
+    /// Size of the window in logical pixels when windowed
+    pub resolution: (u32, u32),
+    /// Name of the monitor the window was last on, or `None` for the primary
+    /// monitor
+    pub monitor: Option<String>,
+    /// Top left corner of the window when windowed, in physical pixels from
+    /// the top left corner of its monitor. `None` lets the system place it.
+    pub position: Option<(i32, i32)>,
+    pub vsync: bool,
+    /// Maximum frames per second, if limited
+    pub frame_cap: Option<u32>,
+    /// Drop the frame rate and skip redrawing what hasn't changed while
+    /// nothing is animating, such as while players think about their
+    /// equations
+    pub battery_saver: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            mode: WindowModeSetting::Windowed,
+            resolution: (1280, 720),
+            monitor: None,
+            position: None,
+            vsync: true,
+            frame_cap: None,
+            battery_saver: false,
+        }
+    }
+}
+
+impl Settings {
+    fn path() -> PathBuf {
+        crate::util::data_dir().join("settings.ron")
+    }
+
+    /// Read the settings file, falling back to the defaults if it is missing
+    /// or can't be parsed
+    pub fn load() -> Settings {
+        let Ok(contents) = std::fs::read_to_string(Self::path()) else {
+            return Settings::default();
+        };
+        ron::from_str(&contents).unwrap_or_else(|e| {
+            log::warn!("Failed to parse settings, using defaults: {e}");
+            Settings::default()
+        })
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents =
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+                .map_err(std::io::Error::other)?;
+        std::fs::write(path, contents)
+    }
+}
+
+/// Write the settings to disk whenever they change
+pub fn save_settings(settings: Res<Settings>) {
+    if let Err(e) = settings.save() {
+        log::warn!("Failed to save settings: {e}");
+    }
+}
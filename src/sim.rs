@@ -0,0 +1,673 @@
+//! Headless simulation of shots. This is independent of rendering so the same
+//! stepping logic can drive the animated shot, look ahead of it, or evaluate
+//! shots that are never shown.
+
+use crate::consts::*;
+use crate::models::{Function, Obstacle, ObstacleKind, Soldier, SoldierId};
+use crate::parse::ParsedFunction;
+use crate::util::fnv1a;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A soldier that a shot can hit
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    pub id: SoldierId,
+    /// Location in graph units
+    pub location: Vec2,
+}
+
+impl From<&Soldier> for Target {
+    fn from(soldier: &Soldier) -> Self {
+        Self {
+            id: soldier.id(),
+            location: soldier.graph_location(),
+        }
+    }
+}
+
+/// Outcome of advancing a shot by one sample
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    /// The shot reached a new point (in graph units), hitting the given
+    /// targets on the way
+    Point { point: Vec2, hits: Vec<Target> },
+    /// The shot left the board
+    Done,
+    /// The shot ran into an obstacle at this point
+    Blocked { point: Vec2, obstacle: u8 },
+    /// The function couldn't be evaluated, or was discontinuous, at this x
+    Failed(f32),
+    /// The shot ran out of range before reaching this point
+    Fizzled(Vec2),
+}
+
+/// Prepare a parsed equation to be shot from `from`: bind the constants and
+/// shift the graph so that it passes through `from`. Fails with the x
+/// coordinate of `from` if the function can't be evaluated there.
+pub fn aim(mut parsed: ParsedFunction, from: Vec2) -> Result<Function, f32> {
+    parsed.add_var("e", std::f32::consts::E);
+    parsed.add_var("π", std::f32::consts::PI);
+    let func = parsed.bind("x");
+    let Ok(y_start) = func(from.x) else {
+        return Err(from.x);
+    };
+    Ok(Function {
+        original: Arc::new(func),
+        shift_up: from.y - y_start,
+    })
+}
+
+/// Values of a function at every step of a shot from `start_x` to the edge
+/// of the board, `GRAPH_RES` apart, before the shot is shifted or bent.
+/// Samples that couldn't be evaluated are `None`.
+#[derive(Debug, PartialEq)]
+pub struct SampleTable {
+    start_x: f32,
+    values: Vec<Option<f32>>,
+}
+
+impl SampleTable {
+    pub fn new(function: &Function, start_x: f32) -> Self {
+        let mut values = Vec::new();
+        let mut x = start_x;
+        // Up to and including the first sample off the board, which is
+        // evaluated before the shot finds out it left
+        loop {
+            values.push((function.original)(x).ok());
+            if x.abs() > 10. {
+                break;
+            }
+            x += GRAPH_RES;
+        }
+        Self { start_x, values }
+    }
+}
+
+/// Sample tables of the last few functions shot or previewed, so the preview
+/// and the shot of the same equation from the same soldier evaluate it only
+/// once. Tables are keyed by a hash of the function exactly as parsed, with
+/// its parameters' exact values, and the x it is shot from, which with the
+/// fixed step is all that decides the samples, so a changed equation never
+/// finds a stale table.
+#[derive(Resource, Default)]
+pub struct SampleCache {
+    /// Most recently used last
+    tables: Vec<((u64, u32), Arc<SampleTable>)>,
+}
+
+impl SampleCache {
+    /// Aim `parsed` from `from` like `aim`, and start a stepper for it that
+    /// reads the function's values from the cache
+    pub fn stepper(
+        &mut self,
+        parsed: ParsedFunction,
+        from: Vec2,
+        targets: Vec<Target>,
+    ) -> Result<ShotStepper, f32> {
+        let key = (fnv1a(parsed.exact().as_bytes()), from.x.to_bits());
+        let function = aim(parsed, from)?;
+        let table = match self.tables.iter().position(|(i, _)| *i == key) {
+            Some(index) => self.tables.remove(index).1,
+            None => Arc::new(SampleTable::new(&function, from.x)),
+        };
+        if self.tables.len() >= SAMPLE_CACHE_TABLES {
+            self.tables.remove(0);
+        }
+        self.tables.push((key, table.clone()));
+        Ok(ShotStepper::new(function, from.x, targets).with_samples(table))
+    }
+}
+
+/// Something that bends shots passing through part of the board. The
+/// modifiers of a board form a chain, each adding its own pull to the shot as
+/// it is stepped.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FieldModifier {
+    /// Pulls shots towards `center` while they are within `radius` of it,
+    /// more strongly the closer they get. Distances are in graph units, and
+    /// `strength` is the vertical pull at the center per unit travelled.
+    GravityWell {
+        center: Vec2,
+        radius: f32,
+        strength: f32,
+    },
+    /// Pushes every shot up (or down, if negative) by `strength` per unit
+    /// travelled
+    Wind { strength: f32 },
+}
+
+impl FieldModifier {
+    /// Vertical pull on a shot at `point`, per unit travelled along x
+    pub fn pull(&self, point: Vec2) -> f32 {
+        match *self {
+            FieldModifier::GravityWell {
+                center,
+                radius,
+                strength,
+            } => {
+                let closeness = (1. - center.distance(point) / radius).max(0.);
+                strength * closeness * closeness * (center.y - point.y).signum()
+            }
+            FieldModifier::Wind { strength } => strength,
+        }
+    }
+
+    /// The modifier as seen with the sides of the board swapped
+    pub fn mirrored(&self) -> Self {
+        match self.clone() {
+            FieldModifier::GravityWell {
+                center,
+                radius,
+                strength,
+            } => FieldModifier::GravityWell {
+                center: center * Vec2::new(-1., 1.),
+                radius,
+                strength,
+            },
+            wind @ FieldModifier::Wind { .. } => wind,
+        }
+    }
+}
+
+/// A whole shot, as traced by `ShotStepper::trace`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    /// Points in graph units
+    pub points: Vec<Vec2>,
+    pub hits: Vec<Target>,
+    /// How the shot ended: `Step::Done`, `Step::Blocked`, `Step::Failed` or
+    /// `Step::Fizzled`
+    pub end: Step,
+}
+
+/// Traces a shot one sample at a time
+#[derive(Clone)]
+pub struct ShotStepper {
+    function: Function,
+    /// Precomputed values of the function, if any
+    samples: Option<Arc<SampleTable>>,
+    next_x: f32,
+    prev_y: Option<f32>,
+    targets: Vec<Target>,
+    obstacles: Vec<Obstacle>,
+    field: Vec<FieldModifier>,
+    /// How far the field has bent the shot so far, before reflections
+    deflection: f32,
+    /// Heights of the walls the shot bounced off, in order. Each bounce
+    /// mirrors the rest of the function about the wall, so these are applied
+    /// to every later sample.
+    reflections: Vec<f32>,
+    /// First and latest point of the shot
+    ends: Option<(Vec2, Vec2)>,
+    path_length: f32,
+    /// Longest the path may get, in graph units, if limited
+    max_length: Option<f32>,
+}
+
+impl ShotStepper {
+    /// Start tracing `function` at `start_x`. Each target can only be hit
+    /// once per shot.
+    pub fn new(function: Function, start_x: f32, targets: Vec<Target>) -> Self {
+        Self {
+            function,
+            samples: None,
+            next_x: start_x,
+            prev_y: None,
+            targets,
+            obstacles: Vec::new(),
+            field: Vec::new(),
+            deflection: 0.,
+            reflections: Vec::new(),
+            ends: None,
+            path_length: 0.,
+            max_length: None,
+        }
+    }
+
+    /// Stepper for a straight shot from `from` with the given slope
+    pub fn line(from: Vec2, slope: f32, targets: Vec<Target>) -> Self {
+        let function = Function {
+            original: Arc::new(move |x| Ok(slope * x)),
+            shift_up: from.y - slope * from.x,
+        };
+        Self::new(function, from.x, targets)
+    }
+
+    /// Stop the shot at any of the solid `obstacles`, and bounce it off the
+    /// bouncy walls
+    pub fn with_obstacles(mut self, obstacles: Vec<Obstacle>) -> Self {
+        self.obstacles = obstacles;
+        self
+    }
+
+    /// Bend the shot with the `field` modifiers
+    pub fn with_field(mut self, field: Vec<FieldModifier>) -> Self {
+        self.field = field;
+        self
+    }
+
+    /// Read the function's values from `samples` instead of evaluating it,
+    /// as far as they go. Samples from another start are ignored.
+    pub fn with_samples(mut self, samples: Arc<SampleTable>) -> Self {
+        if samples.start_x == self.next_x && self.ends.is_none() {
+            self.samples = Some(samples);
+        }
+        self
+    }
+
+    /// Make the shot fizzle once its path is longer than `max_length` graph
+    /// units, if given
+    pub fn with_max_length(mut self, max_length: Option<f32>) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Length of the shot so far, along its path, in graph units
+    pub fn path_length(&self) -> f32 {
+        self.path_length
+    }
+
+    /// Where the shot was launched from, once it has been stepped
+    pub fn origin(&self) -> Option<Vec2> {
+        self.ends.map(|(origin, _)| origin)
+    }
+
+    pub fn step(&mut self) -> Step {
+        let x = self.next_x;
+        // Both step the same way from the same start, so this finds the
+        // sample taken at exactly this x
+        let cached = self.samples.as_ref().and_then(|samples| {
+            let steps = ((x - samples.start_x) / GRAPH_RES).round() as usize;
+            samples.values.get(steps)
+        });
+        let sample = match cached {
+            Some(&sample) => sample,
+            None => (self.function.original)(x).ok(),
+        };
+        let Some(y) = sample else {
+            return Step::Failed(x);
+        };
+        let y = (self.reflections.iter())
+            .fold(y + self.function.shift_up + self.deflection, |y, axis| {
+                2. * axis - y
+            });
+        let mut point = Vec2::new(x, y);
+        if let Some((_, last)) = self.ends
+            && let Some(axis) = self.bounce(last, point)
+        {
+            self.reflections.push(axis);
+            point.y = 2. * axis - point.y;
+        }
+        if point.y.is_nan()
+            || point.y.is_infinite()
+            || self.prev_y.is_some_and(|y| {
+                (y - point.y).abs() > GRAPH_RES * DISCONTINUITY_THRESHOLD
+            })
+        {
+            return Step::Failed(point.x);
+        } else if point.x.abs() > 10. || point.y.abs() > 10. {
+            return Step::Done;
+        } else if let Some(obstacle) =
+            self.obstacles.iter().find(|i| i.blocks(point))
+        {
+            return Step::Blocked {
+                point,
+                obstacle: obstacle.id,
+            };
+        } else if let Some(max_length) = self.max_length
+            && let Some((_, last)) = self.ends
+            && self.path_length + last.distance(point) > max_length
+        {
+            return Step::Fizzled(point);
+        }
+        // `prev_y` is deliberately left unset, as in the original stepping
+        // loop, so the discontinuity check doesn't reject steep or
+        // step-like shots until a threshold has been chosen for it
+        self.next_x += GRAPH_RES;
+        // The pull is applied before the reflections, so it has to be
+        // mirrored by each of them as well
+        let pull = self.field.iter().map(|i| i.pull(point)).sum::<f32>();
+        let mirrored = self.reflections.len() % 2 == 1;
+        self.deflection += GRAPH_RES * if mirrored { -pull } else { pull };
+        match &mut self.ends {
+            Some((_, last)) => {
+                self.path_length += last.distance(point);
+                *last = point;
+            }
+            None => self.ends = Some((point, point)),
+        }
+
+        let mut hits = Vec::new();
+        self.targets.retain(|target| {
+            let hit = target.location.distance(point) < SOLDIER_RADIUS / 20.;
+            if hit {
+                hits.push(target.clone());
+            }
+            !hit
+        });
+        Step::Point { point, hits }
+    }
+
+    /// Height of the bouncy wall crossed going from `from` to `to`, if any
+    fn bounce(&self, from: Vec2, to: Vec2) -> Option<f32> {
+        self.obstacles.iter().find_map(|obstacle| {
+            let ObstacleKind::BouncyWall { half_length } = obstacle.kind else {
+                return None;
+            };
+            let axis = obstacle.location.y;
+            let crossed = (from.y - axis) * (to.y - axis) < 0.;
+            let within = (to.x - obstacle.location.x).abs() <= half_length;
+            (crossed && within).then_some(axis)
+        })
+    }
+
+    /// Evaluate the rest of the shot at once
+    pub fn trace(mut self) -> Trace {
+        let mut points = Vec::new();
+        let mut hits = Vec::new();
+        loop {
+            match self.step() {
+                Step::Point {
+                    point,
+                    hits: new_hits,
+                } => {
+                    points.push(point);
+                    hits.extend(new_hits);
+                }
+                end => return Trace { points, hits, end },
+            }
+        }
+    }
+
+    /// Look up to `samples` steps ahead without advancing this stepper, and
+    /// return the number of steps until the first hit and where it happens
+    pub fn upcoming_hit(&self, samples: usize) -> Option<(usize, Vec2)> {
+        let mut lookahead = self.clone();
+        for i in 0..samples {
+            match lookahead.step() {
+                Step::Point { hits, .. } if !hits.is_empty() => {
+                    return Some((i, hits[0].location));
+                }
+                Step::Point { .. } => (),
+                Step::Done
+                | Step::Blocked { .. }
+                | Step::Failed(_)
+                | Step::Fizzled(_) => {
+                    return None;
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PlayerSelect;
+
+    fn stepper(
+        f: impl Fn(f32) -> f32 + Send + Sync + 'static,
+        start_x: f32,
+        targets: Vec<Target>,
+    ) -> ShotStepper {
+        let function = Function {
+            original: Arc::new(move |x| Ok(f(x))),
+            shift_up: 0.,
+        };
+        ShotStepper::new(function, start_x, targets)
+    }
+
+    fn target(number: u8, x: f32, y: f32) -> Target {
+        Target {
+            id: SoldierId {
+                player: PlayerSelect::Player2,
+                number,
+            },
+            location: Vec2::new(x, y),
+        }
+    }
+
+    /// Step until the shot ends, returning all hits and how it ended
+    fn run(stepper: &mut ShotStepper) -> (Vec<Target>, Step) {
+        let mut all_hits = Vec::new();
+        loop {
+            match stepper.step() {
+                Step::Point { hits, .. } => all_hits.extend(hits),
+                end => return (all_hits, end),
+            }
+        }
+    }
+
+    #[test]
+    fn test_targets_hit_once() {
+        let mut stepper =
+            stepper(|_| 0., 0., vec![target(0, 2., 0.), target(1, 5., 3.)]);
+        let (hits, end) = run(&mut stepper);
+        assert_eq!(hits, vec![target(0, 2., 0.)]);
+        assert_eq!(end, Step::Done);
+    }
+
+    #[test]
+    fn test_done_when_leaving_board() {
+        let mut right = stepper(|_| 0., 9.95, Vec::new());
+        assert_eq!(run(&mut right).1, Step::Done);
+        let mut top = stepper(|x| x * 100., 0., Vec::new());
+        assert_eq!(run(&mut top).1, Step::Done);
+    }
+
+    #[test]
+    fn test_max_length() {
+        let targets = vec![target(0, 0., 0.)];
+        let trace = stepper(|_| 0., -8., targets)
+            .with_max_length(Some(5.))
+            .trace();
+        assert!(trace.hits.is_empty());
+        assert!(matches!(trace.end, Step::Fizzled(point) if point.x > -3.));
+        let last = trace.points.last().unwrap();
+        assert!(last.x - -8. <= 5.);
+    }
+
+    #[test]
+    fn test_failed_on_nan_and_eval_error() {
+        let mut nan = stepper(|x| (x - 1.).sqrt(), 0., Vec::new());
+        assert!(matches!(nan.step(), Step::Failed(x) if x == 0.));
+
+        let function = Function {
+            original: Arc::new(|_| {
+                Err(crate::parse::EvalError::UndefinedVariable)
+            }),
+            shift_up: 0.,
+        };
+        let mut error = ShotStepper::new(function, 3., Vec::new());
+        assert!(matches!(error.step(), Step::Failed(x) if x == 3.));
+    }
+
+    #[test]
+    fn test_aim_and_trace() {
+        let parsed = "x^2".parse::<ParsedFunction>().unwrap();
+        let function = aim(parsed, Vec2::new(1., 3.)).unwrap();
+        let trace =
+            ShotStepper::new(function, 1., vec![target(0, 2., 6.)]).trace();
+        assert_eq!(trace.points[0], Vec2::new(1., 3.));
+        assert_eq!(trace.hits, vec![target(0, 2., 6.)]);
+        assert_eq!(trace.end, Step::Done);
+
+        let parsed = "ln(x)".parse::<ParsedFunction>().unwrap();
+        assert!(aim(parsed, Vec2::new(-1., 0.)).is_err());
+    }
+
+    #[test]
+    fn test_sample_cache() {
+        let from = Vec2::new(-4., 1.);
+        let targets = vec![target(0, 2., 7.)];
+        let parsed = || "x + sin(x)".parse::<ParsedFunction>().unwrap();
+        let plain = ShotStepper::new(
+            aim(parsed(), from).unwrap(),
+            from.x,
+            targets.clone(),
+        )
+        .trace();
+        let mut cache = SampleCache::default();
+        let first = cache.stepper(parsed(), from, targets.clone()).unwrap();
+        let second = cache.stepper(parsed(), from, targets).unwrap();
+        assert_eq!(first.trace(), plain);
+        // The second shot of the same function reuses the first one's table
+        assert_eq!(cache.tables.len(), 1);
+        assert!(Arc::ptr_eq(
+            second.samples.as_ref().unwrap(),
+            &cache.tables[0].1
+        ));
+        assert_eq!(second.trace(), plain);
+        // Another equation or start gets a table of its own, and only the
+        // latest few are kept
+        for i in 0..SAMPLE_CACHE_TABLES {
+            let parsed = format!("x + {i}").parse::<ParsedFunction>().unwrap();
+            cache.stepper(parsed, from, Vec::new()).unwrap();
+        }
+        assert_eq!(cache.tables.len(), SAMPLE_CACHE_TABLES);
+        let first_key = fnv1a(parsed().exact().as_bytes());
+        assert!(cache.tables.iter().all(|(key, _)| key.0 != first_key));
+
+        // Equations that only differ below the rounding of their canonical
+        // forms, or in a parameter, don't share a table either
+        let mut cache = SampleCache::default();
+        let near = ["0.00012x^3", "0.00014x^3"]
+            .map(|i| i.parse::<ParsedFunction>().unwrap());
+        assert_eq!(near[0].canonical(), near[1].canonical());
+        let with_a = |a| {
+            let mut parsed = "a*x^3".parse::<ParsedFunction>().unwrap();
+            parsed.add_var('a', a);
+            parsed
+        };
+        let close = [with_a(0.00012), with_a(0.000_120_01)];
+        for parsed in near.into_iter().chain(close) {
+            cache.stepper(parsed, from, Vec::new()).unwrap();
+        }
+        assert_eq!(cache.tables.len(), 4);
+        assert_ne!(cache.tables[0].1, cache.tables[1].1);
+    }
+
+    #[test]
+    fn test_blocked_by_obstacle() {
+        let obstacle = Obstacle {
+            id: 3,
+            location: Vec2::new(4., 0.),
+            kind: ObstacleKind::Block { radius: 1. },
+            hp: None,
+        };
+        let mut stepper = stepper(|_| 0., 0., vec![target(0, 6., 0.)])
+            .with_obstacles(vec![obstacle]);
+        let (hits, end) = run(&mut stepper);
+        assert!(hits.is_empty());
+        assert!(matches!(
+            end,
+            Step::Blocked { point, obstacle: 3 } if (point.x - 3.).abs() < 0.02
+        ));
+    }
+
+    #[test]
+    fn test_blocked_by_polygon() {
+        // A square standing on a corner, then on a side
+        let blocked_at = |rotation| {
+            let square = Obstacle {
+                id: 4,
+                location: Vec2::new(4., 0.),
+                kind: ObstacleKind::Polygon {
+                    radius: 1.,
+                    sides: 4,
+                    rotation,
+                },
+                hp: None,
+            };
+            let trace = stepper(|_| 0.5, 0., Vec::new())
+                .with_obstacles(vec![square])
+                .trace();
+            match trace.end {
+                Step::Blocked { point, .. } => point.x,
+                end => panic!("{end:?}"),
+            }
+        };
+        assert!((blocked_at(0.) - 3.5).abs() < 0.02);
+        let half_side = std::f32::consts::FRAC_1_SQRT_2;
+        assert!(
+            (blocked_at(std::f32::consts::FRAC_PI_4) - (4. - half_side)).abs()
+                < 0.02
+        );
+    }
+
+    #[test]
+    fn test_bounce() {
+        let wall = Obstacle {
+            id: 0,
+            location: Vec2::new(2.5, 2.),
+            kind: ObstacleKind::BouncyWall { half_length: 2.5 },
+            hp: None,
+        };
+        // y = x turns into y = 4 - x after bouncing off the wall at y = 2
+        let trace = stepper(|x| x, 0., vec![target(0, 6., -2.)])
+            .with_obstacles(vec![wall.clone()])
+            .trace();
+        assert_eq!(trace.hits, vec![target(0, 6., -2.)]);
+        assert!(trace.points.iter().all(|point| point.y <= 2.01));
+
+        // Past the end of the wall the shot carries on
+        let trace = stepper(|x| x - 4., 0., Vec::new())
+            .with_obstacles(vec![wall])
+            .trace();
+        assert!(trace.points.iter().any(|point| point.y > 3.));
+    }
+
+    #[test]
+    fn test_gravity_well() {
+        let well = FieldModifier::GravityWell {
+            center: Vec2::new(5., 2.),
+            radius: 4.,
+            strength: 2.,
+        };
+        let trace = stepper(|_| 0., 0., Vec::new())
+            .with_field(vec![well.clone()])
+            .trace();
+        // Out of reach of the well the shot is untouched...
+        assert_eq!(trace.points[50].y, 0.);
+        // ...and it stays bent after passing it
+        let end = trace.points.last().unwrap();
+        assert!(end.x > 9.9 && end.y > 0.5);
+
+        assert_eq!(well.mirrored().pull(Vec2::new(-5., 0.)), 2. * 0.25);
+    }
+
+    #[test]
+    fn test_path_length() {
+        let mut stepper = stepper(|x| x, 0., Vec::new());
+        assert_eq!(stepper.origin(), None);
+        run(&mut stepper);
+        assert_eq!(stepper.origin(), Some(Vec2::ZERO));
+        let expected = 10. * std::f32::consts::SQRT_2;
+        assert!((stepper.path_length() - expected).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_upcoming_hit() {
+        let original = stepper(|_| 0., 0., vec![target(0, 2., 0.)]);
+        let mut stepped = original.clone();
+        let mut steps_before_hit = 0;
+        while let Step::Point { hits, .. } = stepped.step()
+            && hits.is_empty()
+        {
+            steps_before_hit += 1;
+        }
+        assert_eq!(
+            original.upcoming_hit(1000),
+            Some((steps_before_hit, Vec2::new(2., 0.)))
+        );
+        assert_eq!(original.upcoming_hit(steps_before_hit), None);
+
+        // Looking ahead must not advance the original stepper
+        let mut original = original;
+        let step = original.step();
+        assert!(matches!(step, Step::Point { point, .. } if point.x == 0.));
+    }
+}
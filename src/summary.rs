@@ -0,0 +1,410 @@
+//! Shareable image summing up a finished match: the final board with every
+//! shot's trail, the players' names, the score and a few stats
+
+use crate::consts::*;
+use crate::models::{FinishedPhase, GameState, InProgressGraph, PlayerSelect};
+use crate::records::ShotLanded;
+use crate::util::simplify_polyline;
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use bevy::prelude::*;
+use bevy_egui::egui;
+use image::{Rgba, RgbaImage};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// What the summary of the match in progress shows, collected as it is
+/// played. Locations are in graph units, with player 1 on the left.
+#[derive(Resource, Default, Clone, Debug)]
+pub struct MatchSummary {
+    names: Vec<String>,
+    /// Each shot's trail, oldest first. Only the latest
+    /// `SUMMARY_DETAILED_TRAILS` are kept in full.
+    trails: VecDeque<Vec<Vec2>>,
+    /// Points in all of the trails
+    trail_points: usize,
+    /// Soldiers standing after the latest shot
+    soldiers: Vec<(PlayerSelect, Vec2)>,
+    /// Soldiers destroyed by each player
+    kills: [u32; MAX_PLAYERS],
+    /// Longest shot that destroyed a soldier: its shooter and its length
+    /// along its path
+    longest_hit: Option<(String, f32)>,
+    turns: u32,
+}
+
+/// Count the soldiers each player destroys, and start over once a new match
+/// is being set up
+pub fn track_summary_hits(
+    state: Res<GameState>,
+    mut events: EventReader<ShotLanded>,
+    mut summary: ResMut<MatchSummary>,
+) {
+    if state.setup_state().is_some() && summary.turns > 0 {
+        *summary = MatchSummary::default();
+    }
+    let Some(playing_state) = state.playing_state() else {
+        return;
+    };
+    let shooter = playing_state.turn().index();
+    for shot in events.read() {
+        summary.kills[shooter] += 1;
+        if summary
+            .longest_hit
+            .as_ref()
+            .is_none_or(|(_, length)| shot.path_length > *length)
+        {
+            summary.longest_hit =
+                Some((shot.shooter.clone(), shot.path_length));
+        }
+    }
+}
+
+/// Add each shot and the board it left to the summary, before the sides
+/// swap
+pub fn record_summary(
+    state: Res<GameState>,
+    graph: Option<Single<&InProgressGraph>>,
+    mut summary: ResMut<MatchSummary>,
+) {
+    let Some(playing_state) = state.playing_state() else {
+        return;
+    };
+    // Player 1 is on the left during the turns of players starting there
+    let mirror = if playing_state.turn().starts_left() {
+        Vec2::ONE
+    } else {
+        Vec2::new(-1., 1.)
+    };
+    summary.names = (playing_state.players().iter())
+        .map(|i| i.name.clone())
+        .collect();
+    let trail = graph.map_or_else(Vec::new, |graph| {
+        graph
+            .points
+            .iter()
+            .map(|&point| point / 20. * mirror)
+            .collect()
+    });
+    summary.add_trail(trail);
+    summary.soldiers = (playing_state.soldiers())
+        .map(|soldier| (soldier.player(), soldier.graph_location() * mirror))
+        .collect();
+    summary.turns += 1;
+}
+
+impl MatchSummary {
+    /// Add the latest shot's trail, simplifying the one that stops being
+    /// among the latest few and dropping the oldest once there are too many
+    /// points
+    fn add_trail(&mut self, trail: Vec<Vec2>) {
+        self.trail_points += trail.len();
+        self.trails.push_back(trail);
+        if let Some(index) =
+            self.trails.len().checked_sub(SUMMARY_DETAILED_TRAILS + 1)
+        {
+            let old = &mut self.trails[index];
+            let simplified = simplify_polyline(old, SUMMARY_TRAIL_TOLERANCE);
+            self.trail_points -= old.len() - simplified.len();
+            *old = simplified;
+        }
+        while self.trail_points > SUMMARY_MAX_TRAIL_POINTS {
+            let Some(oldest) = self.trails.pop_front() else {
+                break;
+            };
+            self.trail_points -= oldest.len();
+        }
+    }
+
+    /// Draw the summary of the match that ended in `finished`
+    pub fn render(&self, finished: &FinishedPhase) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(
+            SUMMARY_WIDTH,
+            SUMMARY_HEIGHT,
+            rgba(SUMMARY_BACKGROUND),
+        );
+        let board_size = 20. * SUMMARY_SCALE;
+        let to_pixels = |point: Vec2| {
+            Vec2::new(
+                SUMMARY_MARGIN + (point.x + 10.) * SUMMARY_SCALE,
+                SUMMARY_BOARD_TOP + (10. - point.y) * SUMMARY_SCALE,
+            )
+        };
+        fill_rect(
+            &mut image,
+            Vec2::new(SUMMARY_MARGIN, SUMMARY_BOARD_TOP),
+            Vec2::splat(board_size),
+            rgba(SUMMARY_BOARD_COLOR),
+        );
+        // Faded into the board up front, so crossing trails don't darken
+        let trail_color = rgba(
+            (SUMMARY_BOARD_COLOR.to_srgba())
+                .mix(&TRAIL_COLOR.to_srgba(), SUMMARY_TRAIL_ALPHA)
+                .into(),
+        );
+        for trail in &self.trails {
+            for pair in trail.windows(2) {
+                let (from, to) = (to_pixels(pair[0]), to_pixels(pair[1]));
+                draw_line(&mut image, from, to, trail_color);
+            }
+        }
+        for &(player, location) in &self.soldiers {
+            let color = rgba(SUMMARY_SOLDIER_COLORS[player.index()]);
+            let radius = SOLDIER_RADIUS / 20. * SUMMARY_SCALE;
+            fill_circle(&mut image, to_pixels(location), radius, color);
+        }
+
+        let fonts = egui::FontDefinitions::default();
+        let Some(font) = font(&fonts) else {
+            return image;
+        };
+        let mut text = |line: &str, y: f32, size: f32| {
+            let at = Vec2::new(SUMMARY_MARGIN, y);
+            draw_text(&mut image, &font, line, at, size);
+        };
+        text(&self.names.join(" vs "), SUMMARY_MARGIN, 32.);
+        let result = match (finished.horde, &finished.puzzle) {
+            (Some(score), _) => format!(
+                "Reached wave {} with {} kills",
+                score.wave, score.kills
+            ),
+            (None, Some(_)) if finished.winner == PlayerSelect::Player1 => {
+                "Puzzle solved".to_string()
+            }
+            (None, Some(_)) => "Puzzle missed".to_string(),
+            (None, None) => format!("{} wins!", finished.winner_name),
+        };
+        text(&result, SUMMARY_MARGIN + 40., 24.);
+        let count = |player| {
+            (self.soldiers.iter()).filter(|(i, _)| *i == player).count()
+        };
+        let per_player = |number: &dyn Fn(PlayerSelect) -> usize| {
+            (PlayerSelect::ALL.iter().zip(&self.names))
+                .map(|(&player, name)| format!("{name} {}", number(player)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut lines = vec![
+            format!("Soldiers left: {}", per_player(&count)),
+            format!(
+                "Soldiers destroyed: {}",
+                per_player(&|player| self.kills[player.index()] as usize)
+            ),
+            format!("Turns played: {}", self.turns),
+        ];
+        if let Some((shooter, length)) = &self.longest_hit {
+            lines.push(format!("Longest hit: {shooter}, {length:.1} units"));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            let y = SUMMARY_BOARD_TOP
+                + board_size
+                + SUMMARY_MARGIN
+                + i as f32 * 28.;
+            text(line, y, 20.);
+        }
+        image
+    }
+
+    /// Render the summary and save it among the other summaries, returning
+    /// where it was saved
+    pub fn save(
+        &self,
+        finished: &FinishedPhase,
+    ) -> image::ImageResult<PathBuf> {
+        let dir = crate::util::data_dir().join("summaries");
+        std::fs::create_dir_all(&dir)?;
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let path = dir.join(format!("match-{}.png", time.as_secs()));
+        self.render(finished).save(&path)?;
+        Ok(path)
+    }
+}
+
+/// The font images are written in, out of egui's
+pub fn font(fonts: &egui::FontDefinitions) -> Option<FontRef<'_>> {
+    let data = fonts.font_data.get("Ubuntu-Light")?;
+    FontRef::try_from_slice_and_index(&data.font, data.index).ok()
+}
+
+pub fn rgba(color: Color) -> Rgba<u8> {
+    Rgba(color.to_srgba().to_u8_array())
+}
+
+/// Paint `color` over a pixel, `coverage` of the way
+fn blend(
+    image: &mut RgbaImage,
+    x: i32,
+    y: i32,
+    color: Rgba<u8>,
+    coverage: f32,
+) {
+    let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) else {
+        return;
+    };
+    let Some(pixel) = image.get_pixel_mut_checked(x, y) else {
+        return;
+    };
+    let alpha = coverage.clamp(0., 1.) * f32::from(color[3]) / 255.;
+    for channel in 0..3 {
+        let mixed = f32::from(pixel[channel]) * (1. - alpha)
+            + f32::from(color[channel]) * alpha;
+        pixel[channel] = mixed.round() as u8;
+    }
+}
+
+pub fn fill_rect(
+    image: &mut RgbaImage,
+    min: Vec2,
+    size: Vec2,
+    color: Rgba<u8>,
+) {
+    for y in min.y as i32..(min.y + size.y) as i32 {
+        for x in min.x as i32..(min.x + size.x) as i32 {
+            blend(image, x, y, color, 1.);
+        }
+    }
+}
+
+pub fn fill_circle(
+    image: &mut RgbaImage,
+    center: Vec2,
+    radius: f32,
+    color: Rgba<u8>,
+) {
+    let min = (center - radius).floor();
+    let max = (center + radius).ceil();
+    for y in min.y as i32..=max.y as i32 {
+        for x in min.x as i32..=max.x as i32 {
+            let pixel = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            // Smooth the edge over a pixel
+            let coverage = radius + 0.5 - pixel.distance(center);
+            if coverage > 0. {
+                blend(image, x, y, color, coverage);
+            }
+        }
+    }
+}
+
+/// Stamp a small circle every half pixel along the line
+fn draw_line(image: &mut RgbaImage, from: Vec2, to: Vec2, color: Rgba<u8>) {
+    let steps = (from.distance(to) * 2.).ceil().max(1.) as u32;
+    for step in 0..=steps {
+        let point = from.lerp(to, step as f32 / steps as f32);
+        fill_circle(image, point, SUMMARY_TRAIL_WIDTH / 2., color);
+    }
+}
+
+/// Write a line of text with its top left corner at `at`
+pub fn draw_text(
+    image: &mut RgbaImage,
+    font: &FontRef,
+    text: &str,
+    at: Vec2,
+    size: f32,
+) {
+    let color = rgba(SUMMARY_TEXT_COLOR);
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut x = at.x;
+    let mut last = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(last) = last {
+            x += scaled.kern(last, id);
+        }
+        last = Some(id);
+        let position = ab_glyph::point(x, at.y + scaled.ascent());
+        x += scaled.h_advance(id);
+        let Some(outline) =
+            font.outline_glyph(id.with_scale_and_position(size, position))
+        else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let x = bounds.min.x as i32 + gx as i32;
+            let y = bounds.min.y as i32 + gy as i32;
+            blend(image, x, y, color, coverage);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trail_compaction() {
+        let straight = (0..1000)
+            .map(|i| Vec2::new(i as f32 / 100. - 5., 1.))
+            .collect::<Vec<_>>();
+        let zigzag = (0..1000)
+            .map(|i| Vec2::new(i as f32 / 100. - 5., (i % 2) as f32))
+            .collect::<Vec<_>>();
+        let mut summary = MatchSummary::default();
+        summary.add_trail(straight.clone());
+        for _ in 0..SUMMARY_DETAILED_TRAILS {
+            summary.add_trail(zigzag.clone());
+        }
+        // The oldest straight trail is down to its ends, the rest are whole
+        assert_eq!(summary.trails[0], vec![straight[0], straight[999]]);
+        assert_eq!(summary.trails[1], zigzag);
+        assert_eq!(summary.trail_points, 2 + 1000 * SUMMARY_DETAILED_TRAILS);
+        // Trails that can't be simplified are dropped, oldest first
+        for _ in 0..2 * SUMMARY_MAX_TRAIL_POINTS / 1000 {
+            summary.add_trail(zigzag.clone());
+        }
+        assert!(summary.trail_points <= SUMMARY_MAX_TRAIL_POINTS);
+        assert_eq!(
+            summary.trail_points,
+            summary.trails.iter().map(Vec::len).sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn test_render_summary() {
+        let summary = MatchSummary {
+            names: vec!["Ada".to_string(), "Émile".to_string()],
+            trails: VecDeque::from([vec![
+                Vec2::new(-5., 0.),
+                Vec2::new(5., 0.),
+            ]]),
+            trail_points: 2,
+            soldiers: vec![(PlayerSelect::Player2, Vec2::new(5., 5.))],
+            kills: [1, 0, 0, 0],
+            longest_hit: Some(("Ada".to_string(), 10.)),
+            turns: 1,
+        };
+        let finished = FinishedPhase {
+            winner: PlayerSelect::Player1,
+            winner_name: "Ada".to_string(),
+            horde: None,
+            puzzle: None,
+            turn_code: None,
+            rng: None,
+            replay: None,
+        };
+        let image = summary.render(&finished);
+        assert_eq!(image.dimensions(), (SUMMARY_WIDTH, SUMMARY_HEIGHT));
+        let board = rgba(SUMMARY_BOARD_COLOR);
+        let at = |point: Vec2| {
+            let x = SUMMARY_MARGIN + (point.x + 10.) * SUMMARY_SCALE;
+            let y = SUMMARY_BOARD_TOP + (10. - point.y) * SUMMARY_SCALE;
+            *image.get_pixel(x as u32, y as u32)
+        };
+        // The trail and the soldier are drawn on the board, and the board
+        // is left bare elsewhere
+        assert_ne!(at(Vec2::ZERO), board);
+        assert_eq!(at(Vec2::new(5., 5.)), rgba(SUMMARY_SOLDIER_COLORS[1]));
+        assert_eq!(at(Vec2::new(-5., -5.)), board);
+        // The title is written above the board
+        let title =
+            (0..SUMMARY_WIDTH).flat_map(|x| (0..60).map(move |y| (x, y)));
+        let background = rgba(SUMMARY_BACKGROUND);
+        assert!(
+            title
+                .into_iter()
+                .any(|(x, y)| *image.get_pixel(x, y) != background)
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use crate::ai::{ShotSearch, choose_slope, line_equation};
+use crate::consts::*;
+use crate::models::*;
+use crate::rng::RngStream;
+use crate::systems::graph_display::StartGraphingEvent;
+use bevy::prelude::*;
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::time::Duration;
+
+/// A computer player making up their mind during their turn
+pub struct Thinking {
+    /// How long they have been thinking for
+    elapsed: Duration,
+    /// Search for a computer opponent's shot, a batch of shots each frame.
+    /// Other computer players shoot straight lines, which are picked at once.
+    search: Option<ShotSearch>,
+    rng: StdRng,
+}
+
+/// What the computer player whose turn it is is doing, if one is thinking
+#[derive(Resource, Default)]
+pub struct AiThinking(pub Option<Thinking>);
+
+impl Thinking {
+    /// Least and most time the thinking is shown for. Stronger opponents
+    /// take longer, so the pacing matches how hard they think.
+    fn bounds(level: Option<u8>) -> (Duration, Duration) {
+        let least = match level {
+            Some(level) => {
+                AI_MIN_THINK_SECONDS
+                    + AI_THINK_SECONDS_PER_LEVEL
+                        * level.saturating_sub(1) as f32
+            }
+            None => AI_THINK_SECONDS,
+        };
+        (
+            Duration::from_secs_f32(least),
+            Duration::from_secs_f32(AI_MAX_THINK_SECONDS.max(least)),
+        )
+    }
+}
+
+/// Take the turns of computer-controlled players: think for a moment, then
+/// type in a straight shot at the best target, with a little error, or the
+/// best shot a computer opponent found at its difficulty. Computer opponents
+/// search a batch of shots each frame, and shoot once their search is over
+/// and they have thought for long enough, or once they have thought for too
+/// long.
+pub fn ai_turns(
+    mut state: ResMut<GameState>,
+    time: Res<Time>,
+    mut start_graphing_events: EventWriter<StartGraphingEvent>,
+    mut thinking: ResMut<AiThinking>,
+) {
+    let playing_state = state.playing_state_mut().filter(|state| {
+        state.turn_phase().is_input()
+            && state.current_player().ai
+            && state.watching().is_none()
+    });
+    let Some(playing_state) = playing_state else {
+        if thinking.0.is_some() {
+            thinking.0 = None;
+        }
+        return;
+    };
+    let level = playing_state.current_player().ai_level;
+    let current = thinking.0.get_or_insert_with(|| Thinking {
+        elapsed: Duration::ZERO,
+        search: level.map(|level| ShotSearch::new(playing_state, level)),
+        rng: playing_state.turn_rng(RngStream::Aim),
+    });
+    current.elapsed += time.delta();
+    let searched = match &mut current.search {
+        Some(search) => search.step(playing_state, &mut current.rng),
+        None => true,
+    };
+    let (least, most) = Thinking::bounds(level);
+    if !(searched && current.elapsed >= least) && current.elapsed < most {
+        return;
+    }
+    let Some(Thinking {
+        search, mut rng, ..
+    }) = thinking.0.take()
+    else {
+        return;
+    };
+    let equation = match search {
+        Some(search) => search.finish(&mut rng),
+        None => {
+            let error = rng.gen_range(-AI_AIM_ERROR..AI_AIM_ERROR);
+            line_equation(choose_slope(playing_state) + error)
+        }
+    };
+    if let Ok(func) = equation.parse() {
+        start_graphing_events.send(StartGraphingEvent(func));
+    }
+    *playing_state
+        .current_player_mut()
+        .current_soldier_mut()
+        .slots
+        .current_mut() = equation;
+}
+
+/// Spin a little arc over the active soldier of a computer player while it
+/// thinks
+pub fn draw_ai_thinking(
+    mut gizmos: Gizmos,
+    state: Res<GameState>,
+    thinking: Res<AiThinking>,
+) {
+    let (Some(playing_state), Some(current)) =
+        (state.playing_state(), &thinking.0)
+    else {
+        return;
+    };
+    let soldier = playing_state.current_player().current_soldier();
+    let center = soldier.graph_location() * 20.
+        + Vec2::Y * (SOLDIER_RADIUS + AI_THINKING_RADIUS * 2.);
+    let angle = -current.elapsed.as_secs_f32()
+        * AI_THINKING_SPEED
+        * std::f32::consts::TAU;
+    gizmos.arc_2d(
+        Isometry2d::new(center, Rot2::radians(angle)),
+        std::f32::consts::PI * 1.5,
+        AI_THINKING_RADIUS,
+        AI_THINKING_COLOR,
+    );
+}
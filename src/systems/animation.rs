@@ -0,0 +1,170 @@
+use crate::consts::*;
+use crate::models::*;
+use crate::packs::SpriteSheet;
+use crate::settings::Settings;
+use crate::systems::display::PowerSaving;
+use bevy::prelude::*;
+
+/// What a soldier is currently doing, visually
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SoldierMotion {
+    Idle,
+    /// Flinching away from a passing graph, with the seconds since it began
+    Flinch(f32),
+    Cheer,
+}
+
+/// Frame ranges of an animated sprite sheet, taken from an asset pack
+#[derive(Clone, Debug)]
+pub struct SheetFrames {
+    fps: f32,
+    idle: (usize, usize),
+    flinch: (usize, usize),
+    cheer: (usize, usize),
+}
+
+impl From<&SpriteSheet> for SheetFrames {
+    fn from(sheet: &SpriteSheet) -> Self {
+        Self {
+            fps: sheet.fps,
+            idle: sheet.idle,
+            flinch: sheet.flinch,
+            cheer: sheet.cheer,
+        }
+    }
+}
+
+/// Lightweight procedural (and optionally sprite sheet) animation of a
+/// soldier. The offset is applied on top of the soldier's real position, so
+/// it never affects gameplay.
+#[derive(Component)]
+pub struct SoldierAnimation {
+    motion: SoldierMotion,
+    elapsed: f32,
+    offset: Vec2,
+    frames: Option<SheetFrames>,
+}
+
+impl SoldierAnimation {
+    /// Create an idle animation. `phase` offsets the idle bob so that
+    /// soldiers don't all move in unison.
+    pub fn new(phase: f32, frames: Option<SheetFrames>) -> Self {
+        Self {
+            motion: SoldierMotion::Idle,
+            elapsed: phase,
+            offset: Vec2::ZERO,
+            frames,
+        }
+    }
+    pub fn flinch(&mut self) {
+        if self.motion == SoldierMotion::Idle {
+            self.motion = SoldierMotion::Flinch(0.);
+        }
+    }
+    pub fn cheer(&mut self) {
+        self.motion = SoldierMotion::Cheer;
+    }
+}
+
+/// Advance soldier animations and apply their offsets and frames. With
+/// reduced motion, or while the battery saver is saving power, soldiers stand
+/// still on the first frame of their animation.
+pub fn animate_soldiers(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    saving: Res<PowerSaving>,
+    mut soldiers: Query<(
+        &mut Transform,
+        &mut SoldierAnimation,
+        Option<&mut Sprite>,
+    )>,
+) {
+    let delta = time.delta_secs();
+    for (mut transform, mut animation, sprite) in soldiers.iter_mut() {
+        animation.elapsed += delta;
+        let t = animation.elapsed;
+        let (offset, range) = match animation.motion {
+            SoldierMotion::Idle => (
+                Vec2::new(0., (t * IDLE_BOB_SPEED).sin() * IDLE_BOB_HEIGHT),
+                animation.frames.as_ref().map(|f| f.idle),
+            ),
+            SoldierMotion::Flinch(since) => {
+                let since = since + delta;
+                animation.motion = if since > FLINCH_DURATION {
+                    SoldierMotion::Idle
+                } else {
+                    SoldierMotion::Flinch(since)
+                };
+                let strength = 1. - since / FLINCH_DURATION;
+                (
+                    Vec2::new(
+                        (since * 60.).sin() * FLINCH_SHAKE * strength,
+                        0.,
+                    ),
+                    animation.frames.as_ref().map(|f| f.flinch),
+                )
+            }
+            SoldierMotion::Cheer => (
+                Vec2::new(0., (t * CHEER_SPEED).sin().abs() * CHEER_HEIGHT),
+                animation.frames.as_ref().map(|f| f.cheer),
+            ),
+        };
+        let reduced_motion =
+            settings.accessibility.reduced_motion || saving.active();
+        let offset = if reduced_motion { Vec2::ZERO } else { offset };
+        let previous = animation.offset;
+        // Standing soldiers are left unchanged, so their names aren't redrawn
+        if offset != previous {
+            transform.translation += (offset - previous).extend(0.);
+            animation.offset = offset;
+        }
+
+        let (Some((first, last)), Some(frames), Some(mut sprite)) =
+            (range, &animation.frames, sprite)
+        else {
+            continue;
+        };
+        if let Some(atlas) = &mut sprite.texture_atlas {
+            let len = last.saturating_sub(first) + 1;
+            let index = if reduced_motion {
+                first
+            } else {
+                first + (t * frames.fps) as usize % len
+            };
+            if atlas.index != index {
+                atlas.index = index;
+            }
+        }
+    }
+}
+
+/// Make the shooter's enemies flinch when the graph being drawn passes close
+/// to them
+pub fn flinch_soldiers(
+    state: Res<GameState>,
+    graph: Option<Single<&InProgressGraph>>,
+    mut soldiers: Query<(&Soldier, &Transform, &mut SoldierAnimation)>,
+) {
+    let Some(playing_state) = state.playing_state() else {
+        return;
+    };
+    if !matches!(
+        playing_state.turn_phase(),
+        TurnPhase::ShowPhase(TurnShowPhase::Graphing { .. })
+    ) {
+        return;
+    }
+    let Some(tip) = graph.and_then(|graph| graph.points.last().copied()) else {
+        return;
+    };
+    let shooter = playing_state.current_player().current_soldier().player();
+    for (soldier, transform, mut animation) in soldiers.iter_mut() {
+        if soldier.player() == shooter {
+            continue;
+        }
+        let position = transform.translation.truncate() - animation.offset;
+        if position.distance(tip) < FLINCH_DISTANCE * 20. {
+            animation.flinch();
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use super::overlay::OverlayMode;
+use crate::consts::*;
+use crate::models::*;
+use crate::settings::Settings;
+use accesskit::{Live, Node, Role};
+use bevy::a11y::AccessibilityNode;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::process::Command;
+
+/// Something worth telling players who can't (or aren't) watching the board
+#[derive(Event, Clone, Debug)]
+pub struct Announce(pub String);
+
+/// Live region that screen readers read out whenever its label changes
+#[derive(Component)]
+pub struct AnnouncementRegion;
+
+/// The announcement currently shown in the banner
+#[derive(Resource, Default)]
+pub struct AnnouncementBanner {
+    text: String,
+    timer: Timer,
+}
+
+pub fn spawn_announcement_region(mut commands: Commands) {
+    let mut node = Node::new(Role::Label);
+    node.set_live(Live::Assertive);
+    commands.spawn((AccessibilityNode(node), AnnouncementRegion));
+}
+
+/// What the game looked like last frame, to notice what changed
+#[derive(Default)]
+pub struct SeenState {
+    turn: Option<PlayerSelect>,
+    input: bool,
+    warned: bool,
+    soldiers_left: Vec<usize>,
+    names: Vec<String>,
+    finished: bool,
+    sudden_death: bool,
+}
+
+/// Send announcements for whose turn it is, time running out, hits, sudden
+/// death and the winner
+pub fn detect_announcements(
+    state: Res<GameState>,
+    mut announcements: EventWriter<Announce>,
+    mut seen: Local<SeenState>,
+) {
+    if let Some(finished) = state.finished_state() {
+        if !seen.finished {
+            let winner = (seen.names.get(finished.winner.index()))
+                .map_or("Nobody", String::as_str);
+            announcements.send(Announce(format!("{winner} wins!")));
+            seen.finished = true;
+        }
+        return;
+    }
+    let Some(playing_state) = state.playing_state() else {
+        *seen = SeenState::default();
+        return;
+    };
+    let players = playing_state.players();
+    let soldiers_left = (players.iter())
+        .map(|i| i.soldiers().len())
+        .collect::<Vec<_>>();
+    if seen.turn.is_some() {
+        let counts = seen.soldiers_left.iter().zip(&soldiers_left);
+        for (player, (&before, &after)) in players.iter().zip(counts) {
+            if after < before {
+                announcements.send(Announce(format!(
+                    "Hit! {} has {after} soldiers left",
+                    player.name
+                )));
+            }
+        }
+    }
+
+    if playing_state.is_sudden_death() && !seen.sudden_death {
+        announcements.send(Announce(
+            "Sudden death! The next player to lose a soldier loses".into(),
+        ));
+    }
+    seen.sudden_death = playing_state.is_sudden_death();
+
+    let name = &playing_state.current_player().name;
+    let input = playing_state.turn_phase().is_input();
+    if input && (!seen.input || seen.turn != Some(playing_state.turn())) {
+        announcements.send(Announce(format!("{name}'s turn")));
+        seen.warned = false;
+    }
+    if let TurnPhase::InputPhase { timer } = playing_state.turn_phase()
+        && !seen.warned
+        && timer.duration().as_secs() > TURN_WARNING_SECONDS
+        && timer.remaining().as_secs() < TURN_WARNING_SECONDS
+    {
+        announcements
+            .send(Announce(format!("{TURN_WARNING_SECONDS} seconds left")));
+        seen.warned = true;
+    }
+
+    seen.turn = Some(playing_state.turn());
+    seen.input = input;
+    seen.soldiers_left = soldiers_left;
+    seen.names = players.iter().map(|i| i.name.clone()).collect();
+}
+
+/// Beep at a few seconds before the turn runs out, if turned on
+pub fn beep_low_time(
+    mut commands: Commands,
+    state: Res<GameState>,
+    settings: Res<Settings>,
+    mut pitches: ResMut<Assets<Pitch>>,
+    // Time that was left last frame
+    mut last_remaining: Local<Option<f32>>,
+) {
+    let timer = state.playing_state().and_then(|state| {
+        let TurnPhase::InputPhase { timer } = state.turn_phase() else {
+            return None;
+        };
+        Some(timer)
+    });
+    let Some(timer) = timer.filter(|_| settings.timer_beeps) else {
+        *last_remaining = None;
+        return;
+    };
+    let remaining = timer.remaining().as_secs_f32();
+    let before = last_remaining.replace(remaining);
+    // A new turn starts with more time than the last one had left
+    let Some(before) = before.filter(|&before| before >= remaining) else {
+        return;
+    };
+    if TURN_BEEP_SECONDS
+        .iter()
+        .any(|&beep| before >= beep && remaining < beep)
+    {
+        commands.spawn((
+            AudioPlayer(
+                pitches.add(Pitch::new(TURN_BEEP_PITCH, TURN_BEEP_LENGTH)),
+            ),
+            PlaybackSettings::DESPAWN,
+        ));
+    }
+}
+
+/// Show announcements in a high-contrast banner, pass them on to screen
+/// readers and optionally speak them
+pub fn show_announcements(
+    mut events: EventReader<Announce>,
+    mut contexts: EguiContexts,
+    mut banner: ResMut<AnnouncementBanner>,
+    mut region: Single<&mut AccessibilityNode, With<AnnouncementRegion>>,
+    settings: Res<Settings>,
+    overlay: Res<OverlayMode>,
+    time: Res<Time>,
+) {
+    let accessibility = &settings.accessibility;
+    for Announce(text) in events.read() {
+        if !accessibility.announcements {
+            continue;
+        }
+        log::info!("Announcement: {text}");
+        region.set_label(text.clone());
+        if accessibility.text_to_speech {
+            speak(text);
+        }
+        banner.text = text.clone();
+        banner.timer =
+            Timer::from_seconds(ANNOUNCEMENT_DURATION, TimerMode::Once);
+    }
+
+    if banner.timer.tick(time.delta()).finished()
+        || banner.text.is_empty()
+        || overlay.0
+    {
+        return;
+    }
+    egui::Area::new(egui::Id::new("announcement_banner"))
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0., 60.))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::none()
+                .fill(egui::Color32::BLACK)
+                .stroke(egui::Stroke::new(2., egui::Color32::WHITE))
+                .inner_margin(12.)
+                .show(ui, |ui| {
+                    ui.label(
+                        egui::RichText::new(&banner.text)
+                            .size(32.)
+                            .strong()
+                            .color(egui::Color32::WHITE),
+                    );
+                });
+        });
+}
+
+/// Read `text` out loud with the platform's speech synthesizer, without
+/// waiting for it to finish
+fn speak(text: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("say");
+        command.arg(text);
+        command
+    } else if cfg!(windows) {
+        let mut command = Command::new("powershell");
+        command.args([
+            "-NoProfile",
+            "-Command",
+            &format!(
+                "Add-Type -AssemblyName System.Speech; \
+                 (New-Object System.Speech.Synthesis.SpeechSynthesizer)\
+                 .Speak('{}')",
+                text.replace('\'', "''")
+            ),
+        ]);
+        command
+    } else {
+        let mut command = Command::new("spd-say");
+        command.arg(text);
+        command
+    };
+    if let Err(e) = command.spawn() {
+        log::warn!("Failed to speak announcement: {e}");
+    }
+}
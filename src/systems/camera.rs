@@ -0,0 +1,226 @@
+use super::overlay::OverlayMode;
+use super::util::InputCaptureState;
+use crate::consts::*;
+use crate::models::GameState;
+use crate::settings::Settings;
+use bevy::input::mouse::{
+    AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit,
+};
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::window::PrimaryWindow;
+
+/// The camera that shows the board
+#[derive(Component)]
+pub struct MainCamera;
+
+/// Point (in pixels) the main camera should zoom towards, if any. Once set,
+/// the focus is held for `SLOWMO_HOLD` so the effect is actually noticeable.
+#[derive(Resource, Default)]
+pub struct CinematicFocus {
+    target: Option<Vec2>,
+    remaining: f32,
+}
+
+impl CinematicFocus {
+    pub fn focus(&mut self, at: Vec2) {
+        self.target = Some(at);
+        self.remaining = SLOWMO_HOLD;
+    }
+    /// Count down the time the focus is held for (in real seconds)
+    pub fn tick(&mut self, delta: f32) {
+        self.remaining -= delta;
+        if self.remaining <= 0. {
+            self.target = None;
+        }
+    }
+    pub fn is_active(&self) -> bool {
+        self.target.is_some()
+    }
+}
+
+/// View the viewer picked by panning and zooming while a shot is shown: its
+/// center in pixels and how far it is zoomed in. While set, it takes over
+/// from the cinematic camera until the next turn starts.
+#[derive(Resource, Default)]
+pub struct FreeCamera {
+    view: Option<(Vec2, f32)>,
+}
+
+/// Let the viewer pan (by dragging) and zoom (by scrolling) the main camera
+/// while a shot is shown, and go back to the normal view afterwards
+pub fn free_camera(
+    state: Res<GameState>,
+    capture: Res<InputCaptureState>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    motion: Res<AccumulatedMouseMotion>,
+    scroll: Res<AccumulatedMouseScroll>,
+    camera: Single<(&Transform, &OrthographicProjection), With<MainCamera>>,
+    mut free: ResMut<FreeCamera>,
+) {
+    let showing = state
+        .playing_state()
+        .is_some_and(|state| state.turn_phase().is_showing());
+    if !showing {
+        if free.view.is_some() {
+            free.view = None;
+        }
+        return;
+    }
+    if capture.pointer_captured {
+        return;
+    }
+    let lines = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        MouseScrollUnit::Pixel => scroll.delta.y / FREE_CAMERA_PIXELS_PER_LINE,
+    };
+    let drag = if mouse.pressed(MouseButton::Left) {
+        motion.delta
+    } else {
+        Vec2::ZERO
+    };
+    if lines == 0. && drag == Vec2::ZERO {
+        return;
+    }
+    let (transform, projection) = *camera;
+    let (center, zoom) = free
+        .view
+        .get_or_insert((transform.translation.truncate(), 1.));
+    *zoom = (*zoom * FREE_CAMERA_ZOOM_STEP.powf(lines))
+        .clamp(1., FREE_CAMERA_MAX_ZOOM);
+    // Screen coordinates grow downwards, unlike the world's
+    *center -= drag * Vec2::new(1., -1.) * projection.scale;
+    *center = center.clamp(Vec2::splat(-220.), Vec2::splat(220.));
+}
+
+/// Ease the main camera towards the cinematic focus, or back to the default
+/// view when there is none. In presentation and overlay mode the default view
+/// is zoomed so the board fills the window. The viewer's free camera, if they
+/// moved it, takes precedence over both.
+pub fn cinematic_camera(
+    focus: Res<CinematicFocus>,
+    free: Res<FreeCamera>,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    overlay: Res<OverlayMode>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<
+        (&mut Transform, &mut OrthographicProjection),
+        With<MainCamera>,
+    >,
+) {
+    let (mut transform, mut projection) = camera.into_inner();
+    let base_scale = if settings.presentation_mode || overlay.0 {
+        let shortest = window.width().min(window.height()).max(1.);
+        PRESENTATION_VIEW_SIZE / shortest
+    } else {
+        1.
+    };
+    let reduced_motion = settings.accessibility.reduced_motion;
+    let focus = focus.target.filter(|_| !reduced_motion);
+    let (target, scale) = match (free.view, focus) {
+        (Some((center, zoom)), _) => (center, base_scale / zoom),
+        (None, Some(at)) => (at * SLOWMO_ZOOM_PULL, base_scale / SLOWMO_ZOOM),
+        (None, None) => (Vec2::ZERO, base_scale),
+    };
+    let t = if reduced_motion {
+        1.
+    } else {
+        (time.delta_secs() * CAMERA_EASE_SPEED).min(1.)
+    };
+    let z = transform.translation.z;
+    transform.translation =
+        transform.translation.truncate().lerp(target, t).extend(z);
+    projection.scale += (scale - projection.scale) * t;
+}
+
+/// Second camera that shows a magnified inset around the active soldier
+#[derive(Component)]
+pub struct PipCamera;
+
+pub fn spawn_pip_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2d,
+        Camera {
+            order: 1,
+            is_active: false,
+            clear_color: ClearColorConfig::Custom(Color::srgb(0.9, 0.9, 0.9)),
+            ..Default::default()
+        },
+        PipCamera,
+    ));
+}
+
+/// Show the inset during the input phase and keep it pointed at the active
+/// soldier (and, if enabled, its nearest enemy)
+pub fn update_pip_camera(
+    state: Res<GameState>,
+    settings: Res<Settings>,
+    overlay: Res<OverlayMode>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<
+        (&mut Camera, &mut Transform, &mut OrthographicProjection),
+        With<PipCamera>,
+    >,
+) {
+    let (mut camera, mut transform, mut projection) = camera.into_inner();
+    let playing_state = state
+        .playing_state()
+        .filter(|state| state.turn_phase().is_input());
+    let Some(playing_state) =
+        playing_state.filter(|_| settings.pip_enabled && !overlay.0)
+    else {
+        if camera.is_active {
+            camera.is_active = false;
+        }
+        return;
+    };
+
+    // Shrink the inset to fit small windows, and drop it entirely when there
+    // is no room left for it
+    let scale_factor = window.scale_factor();
+    let margin = (PIP_MARGIN * scale_factor) as u32;
+    let available = window
+        .physical_width()
+        .min(window.physical_height())
+        .saturating_sub(2 * margin);
+    let size = ((PIP_SIZE * scale_factor) as u32).min(available);
+    if size < (PIP_MIN_SIZE * scale_factor) as u32 {
+        camera.is_active = false;
+        return;
+    }
+    let logical_size = size as f32 / scale_factor;
+
+    let soldier = playing_state.current_player().current_soldier();
+    let soldier_pos = soldier.graph_location() * 20.;
+    let nearest_enemy = (playing_state.opponents())
+        .flat_map(|i| i.soldiers())
+        .map(|i| i.graph_location() * 20.)
+        .min_by(|a, b| {
+            a.distance(soldier_pos).total_cmp(&b.distance(soldier_pos))
+        });
+    let (center, scale) = match nearest_enemy.filter(|_| settings.pip_enemy) {
+        Some(enemy) => {
+            let extent =
+                (enemy - soldier_pos).abs().max_element() + SOLDIER_RADIUS * 4.;
+            (
+                (soldier_pos + enemy) / 2.,
+                (extent / logical_size).max(1. / PIP_ZOOM),
+            )
+        }
+        None => (soldier_pos, 1. / PIP_ZOOM),
+    };
+    transform.translation = center.extend(transform.translation.z);
+    projection.scale = scale;
+
+    let position = UVec2::new(
+        window.physical_width().saturating_sub(size + margin),
+        margin,
+    );
+    camera.is_active = true;
+    camera.viewport = Some(Viewport {
+        physical_position: position,
+        physical_size: UVec2::splat(size),
+        ..Default::default()
+    });
+}
@@ -0,0 +1,94 @@
+use crate::StartPlaying;
+use crate::consts::*;
+use crate::models::*;
+use bevy::audio::GlobalVolume;
+use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::num::NonZeroU8;
+use std::time::Duration;
+
+/// Names of the computer players of demo matches
+const DEMO_NAMES: [&str; 2] = ["Euler", "Gauss"];
+
+/// What the attract mode keeps track of between frames
+#[derive(Default)]
+pub struct Attract {
+    /// How long the menu has been left alone
+    idle: Duration,
+    /// The menu as it was left, to go back to once the demo is over. Only
+    /// set while a demo is playing.
+    menu: Option<SetupPhase>,
+}
+
+/// Play a muted, sped up match between two computer players when the menu is
+/// left alone for a while, for kiosks. Any input goes back to the menu, and
+/// a new demo starts when one is over.
+#[allow(clippy::too_many_arguments)]
+pub fn attract_mode(
+    mut state: ResMut<GameState>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    time: Res<Time<Real>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut volume: ResMut<GlobalVolume>,
+    mut start_playing_events: EventWriter<StartPlaying>,
+    mut attract: Local<Attract>,
+) {
+    let input = keys.get_just_pressed().count() > 0
+        || mouse.get_just_pressed().count() > 0
+        || motion.read().count() > 0
+        || wheel.read().count() > 0;
+    if input {
+        attract.idle = Duration::ZERO;
+    }
+    if let Some(menu) = &attract.menu {
+        if input || matches!(state.game_phase(), GamePhaseNoData::GameFinished)
+        {
+            state.set_setup(menu.clone());
+            attract.menu = None;
+            virtual_time.set_relative_speed(1.);
+            *volume = GlobalVolume::default();
+        }
+        return;
+    }
+    let Some(menu) = state.setup_state() else {
+        attract.idle = Duration::ZERO;
+        return;
+    };
+    attract.idle += time.delta();
+    if attract.idle.as_secs_f32() < DEMO_IDLE_SECONDS {
+        return;
+    }
+    attract.menu = Some(menu.clone());
+    let mut demo = GameState::default().setup_state().unwrap().clone();
+    for (player, name) in demo.players.iter_mut().zip(DEMO_NAMES) {
+        player.name = name.to_string();
+        player.soldier_num = NonZeroU8::new(DEMO_SOLDIERS).unwrap();
+    }
+    demo.obstacles = DEMO_OBSTACLES;
+    demo.demo = true;
+    state.set_setup(demo);
+    start_playing_events.send(StartPlaying);
+    virtual_time.set_relative_speed(DEMO_SPEED);
+    *volume = GlobalVolume::new(0.);
+}
+
+/// Tell kiosk visitors how to leave a demo match
+pub fn demo_banner(mut contexts: EguiContexts, state: Res<GameState>) {
+    if !state.playing_state().is_some_and(PlayPhase::is_demo) {
+        return;
+    }
+    egui::Area::new(egui::Id::new("demo_banner"))
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0., -60.))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new("Demo · press any key to play")
+                    .size(DEMO_BANNER_TEXT_SIZE)
+                    .strong(),
+            );
+        });
+}
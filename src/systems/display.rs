@@ -0,0 +1,219 @@
+use crate::consts::*;
+use crate::models::*;
+use crate::settings::{DisplaySettings, Settings, WindowModeSetting};
+use crate::systems::feedback::FloatingText;
+use crate::systems::graph_display::ExplosionFadeTimer;
+use crate::systems::obstacles::Crumbling;
+use bevy::prelude::*;
+use bevy::window::{
+    Monitor, MonitorSelection, PresentMode, PrimaryMonitor, PrimaryWindow,
+    WindowMode, WindowMoved, WindowPosition, WindowResized,
+};
+use std::time::{Duration, Instant};
+
+/// Apply the display settings to the main window. Only the options that
+/// changed since they were last applied are touched, so resizing the window
+/// by hand isn't undone by editing an unrelated setting.
+pub fn apply_display_settings(
+    settings: Res<Settings>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut applied: Local<Option<DisplaySettings>>,
+) {
+    let display = &settings.display;
+    let previous = applied.as_ref();
+    if previous.is_some_and(|previous| previous == display) {
+        return;
+    }
+    if previous.is_none_or(|previous| previous.mode != display.mode) {
+        window.mode = match display.mode {
+            WindowModeSetting::Windowed => WindowMode::Windowed,
+            WindowModeSetting::Borderless => {
+                WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+            }
+        };
+    }
+    if previous.is_none_or(|previous| previous.resolution != display.resolution)
+    {
+        let (width, height) = display.resolution;
+        window.resolution.set(width as f32, height as f32);
+    }
+    if previous.is_none_or(|previous| previous.vsync != display.vsync) {
+        window.present_mode = if display.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+    }
+    *applied = Some(display.clone());
+}
+
+/// What [`remember_window_placement`] keeps track of between frames
+#[derive(Default)]
+pub struct PlacementTracker {
+    /// Whether the saved placement has been restored yet
+    restored: bool,
+    /// How long the window has stayed put since it was last moved or resized
+    still: Duration,
+}
+
+/// Put the main window back on the monitor and at the position it was last
+/// at, once the monitors are known, and from then on save where it is moved
+/// to and how it is resized. The size is only saved while windowed, so
+/// leaving fullscreen goes back to it.
+pub fn remember_window_placement(
+    mut settings: ResMut<Settings>,
+    mut window: Single<(Entity, &mut Window), With<PrimaryWindow>>,
+    monitors: Query<(Entity, &Monitor, Has<PrimaryMonitor>)>,
+    mut moved: EventReader<WindowMoved>,
+    mut resized: EventReader<WindowResized>,
+    time: Res<Time<Real>>,
+    mut tracker: Local<PlacementTracker>,
+) {
+    let (entity, window) = &mut *window;
+    // Not every platform says which monitor is the primary one
+    let primary = (monitors.iter().find(|(_, _, primary)| *primary))
+        .or_else(|| monitors.iter().next());
+    let Some((primary, primary_monitor, _)) = primary else {
+        // The monitors aren't known until the window is up
+        return;
+    };
+    let display = &settings.display;
+    if !tracker.restored {
+        tracker.restored = true;
+        let saved = match &display.monitor {
+            None => Some((primary, primary_monitor)),
+            Some(name) => (monitors.iter())
+                .find(|(_, monitor, _)| monitor.name.as_ref() == Some(name))
+                .map(|(monitor, info, _)| (monitor, info)),
+        };
+        match (saved, display.position) {
+            (Some((_, monitor)), Some((x, y))) => {
+                window.position = WindowPosition::At(
+                    monitor.physical_position + IVec2::new(x, y),
+                );
+            }
+            // The monitor it was on is gone, so the old position could be
+            // off screen
+            (None, _) => {
+                window.position =
+                    WindowPosition::Centered(MonitorSelection::Primary);
+            }
+            (Some(_), None) => (),
+        }
+        if display.mode == WindowModeSetting::Borderless {
+            let monitor = saved.map_or(primary, |(monitor, _)| monitor);
+            window.mode = WindowMode::BorderlessFullscreen(
+                MonitorSelection::Entity(monitor),
+            );
+        }
+        return;
+    }
+    let ours = |event_window: Entity| event_window == *entity;
+    let moving = moved.read().filter(|event| ours(event.window)).count()
+        + resized.read().filter(|event| ours(event.window)).count()
+        > 0;
+    if moving {
+        tracker.still = Duration::ZERO;
+        return;
+    }
+    tracker.still += time.delta();
+    if tracker.still < WINDOW_PLACEMENT_SAVE_DELAY {
+        return;
+    }
+    let windowed = display.mode == WindowModeSetting::Windowed;
+    let mut placement = display.clone();
+    if windowed {
+        placement.resolution = (
+            window.resolution.width().round() as u32,
+            window.resolution.height().round() as u32,
+        );
+    }
+    // Some platforms don't tell where windows are
+    if let WindowPosition::At(position) = window.position {
+        // The window is on the monitor its center is on
+        let center = position + window.physical_size().as_ivec2() / 2;
+        let on = monitors.iter().find(|(_, monitor, _)| {
+            let min = monitor.physical_position;
+            let max = min + monitor.physical_size().as_ivec2();
+            center.cmpge(min).all() && center.cmplt(max).all()
+        });
+        if let Some((monitor, info, _)) = on {
+            placement.monitor =
+                (monitor != primary).then(|| info.name.clone()).flatten();
+            if windowed {
+                let relative = position - info.physical_position;
+                placement.position = Some((relative.x, relative.y));
+            }
+        }
+    }
+    // Only written when it changes, as that saves the settings
+    if placement != *display {
+        settings.display = placement;
+    }
+}
+
+/// Whether the battery saver is saving power, which it does once nothing
+/// has animated for a moment
+#[derive(Resource, Default)]
+pub struct PowerSaving {
+    /// How long nothing has animated for
+    idle: Duration,
+    active: bool,
+}
+
+impl PowerSaving {
+    pub fn active(&self) -> bool {
+        self.active
+    }
+}
+
+/// Effects that animate for a while after a shot
+type AnimatedEffect = Or<(
+    With<ExplosionFadeTimer>,
+    With<FloatingText>,
+    With<Crumbling>,
+)>;
+
+/// Check whether anything is animating: a shot being shown, or the effects
+/// it leaves behind
+pub fn update_power_saving(
+    settings: Res<Settings>,
+    state: Res<GameState>,
+    effects: Query<(), AnimatedEffect>,
+    time: Res<Time<Real>>,
+    mut saving: ResMut<PowerSaving>,
+) {
+    let showing = state
+        .playing_state()
+        .is_some_and(|playing| playing.turn_phase().is_showing());
+    if showing || !effects.is_empty() {
+        saving.idle = Duration::ZERO;
+    } else {
+        saving.idle += time.delta();
+    }
+    saving.active =
+        settings.display.battery_saver && saving.idle >= BATTERY_SAVER_DELAY;
+}
+
+/// Sleep at the end of the frame if needed to stay under the frame cap, or
+/// the battery saver's cap while it is saving power
+pub fn limit_frame_rate(
+    settings: Res<Settings>,
+    saving: Res<PowerSaving>,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    let cap = match settings.display.frame_cap {
+        Some(cap) if saving.active => Some(cap.min(BATTERY_SAVER_FRAME_CAP)),
+        None if saving.active => Some(BATTERY_SAVER_FRAME_CAP),
+        cap => cap,
+    };
+    if let (Some(cap), Some(last_frame)) = (cap, *last_frame) {
+        let frame_time =
+            Duration::from_secs_f32(1. / cap.max(MIN_FRAME_CAP) as f32);
+        let elapsed = last_frame.elapsed();
+        if elapsed < frame_time {
+            std::thread::sleep(frame_time - elapsed);
+        }
+    }
+    *last_frame = Some(Instant::now());
+}
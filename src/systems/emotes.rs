@@ -0,0 +1,226 @@
+use crate::consts::*;
+use crate::models::*;
+use crate::settings::Settings;
+use crate::util::smoothstep;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+/// Quick messages players can send each other during a match
+pub const EMOTES: [&str; 4] = ["gg", "Nice shot!", "Oops!", "Good luck!"];
+
+/// An emote shown as a bubble by a player's nameplate until its timer runs
+/// out
+pub struct Bubble {
+    player: PlayerSelect,
+    emote: &'static str,
+    timer: Timer,
+}
+
+/// A line of the chat panel
+pub struct ChatLine {
+    player: PlayerSelect,
+    text: String,
+}
+
+/// Something said on this side of a network match, for the other side
+#[derive(Clone, Debug, PartialEq)]
+pub enum Said {
+    /// An emote, by its index in `EMOTES`
+    Emote(usize),
+    Line(String),
+}
+
+/// The emotes and chat of the match being played
+#[derive(Resource, Default)]
+pub struct Chat {
+    bubbles: Vec<Bubble>,
+    /// Lines of the chat panel, oldest first
+    lines: Vec<ChatLine>,
+    /// Line being typed into the chat panel
+    draft: String,
+    /// What was said here since the network last passed it on
+    pub outgoing: Vec<Said>,
+}
+
+impl Chat {
+    /// Show the emote with index `emote` in `EMOTES` by the player's
+    /// nameplate
+    pub fn emote(&mut self, player: PlayerSelect, emote: usize) {
+        let Some(emote) = EMOTES.get(emote) else {
+            return;
+        };
+        self.bubbles.push(Bubble {
+            player,
+            emote,
+            timer: Timer::from_seconds(EMOTE_DURATION, TimerMode::Once),
+        });
+    }
+
+    /// Add a line by the player to the chat panel, cut short if it is too
+    /// long
+    pub fn say(&mut self, player: PlayerSelect, text: &str) {
+        let text = text.trim().chars().take(CHAT_MAX_LENGTH).collect();
+        self.lines.push(ChatLine { player, text });
+        let excess = self.lines.len().saturating_sub(CHAT_MAX_LINES);
+        self.lines.drain(..excess);
+    }
+}
+
+/// Buttons for each player to send quick emotes, and the bubbles showing the
+/// emotes sent. In network matches only the local player has buttons, and
+/// there is a chat panel as well. Emotes and chat can be muted in the
+/// settings.
+pub fn emotes_ui(
+    mut contexts: EguiContexts,
+    state: Res<GameState>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    mut chat: ResMut<Chat>,
+) {
+    let Some(playing_state) = state.playing_state() else {
+        *chat = Chat::default();
+        return;
+    };
+    let local = (playing_state.correspondence())
+        .filter(|i| i.networked)
+        .map(|i| i.local);
+    let context = contexts.ctx_mut();
+    egui::Area::new(egui::Id::new("emote_buttons"))
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(10., -10.))
+        .show(context, |ui| {
+            let players = (PlayerSelect::ALL.into_iter())
+                .zip(playing_state.players())
+                .filter(|(player, i)| local.map_or(!i.ai, |i| i == *player));
+            for (player, state) in players {
+                ui.horizontal(|ui| {
+                    ui.label(&state.name);
+                    for (i, emote) in EMOTES.into_iter().enumerate() {
+                        if ui.small_button(emote).clicked() {
+                            chat.emote(player, i);
+                            if local.is_some() {
+                                chat.outgoing.push(Said::Emote(i));
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    if let Some(local) = local {
+        chat_panel(context, playing_state, &settings, &mut chat, local);
+    }
+
+    let chat = &mut *chat;
+    chat.bubbles
+        .retain_mut(|bubble| !bubble.timer.tick(time.delta()).finished());
+    // Keep only the latest few of each player's emotes, so they can't be
+    // spammed
+    for player in PlayerSelect::ALL {
+        let sent = chat.bubbles.iter().filter(|i| i.player == player).count();
+        let mut excess = sent.saturating_sub(EMOTE_MAX_BUBBLES);
+        chat.bubbles.retain(|bubble| {
+            let drop = excess > 0 && bubble.player == player;
+            excess -= usize::from(drop);
+            !drop
+        });
+    }
+    if settings.mute_emotes {
+        return;
+    }
+    // Below the nameplates and mutators in presentation mode
+    let offset = if settings.presentation_mode {
+        110.
+    } else {
+        50.
+    };
+    // Under each player's nameplate, which take the corners in turn
+    for player in PlayerSelect::ALL {
+        let row = (player.index() / 2) as f32 * EMOTE_ROW_HEIGHT;
+        let (align, x) = if player.index() % 2 == 0 {
+            (egui::Align2::LEFT_TOP, 20.)
+        } else {
+            (egui::Align2::RIGHT_TOP, -20.)
+        };
+        egui::Area::new(egui::Id::new("emote_bubbles").with(player.index()))
+            .anchor(align, egui::vec2(x, offset + row))
+            .interactable(false)
+            .show(context, |ui| {
+                for bubble in chat.bubbles.iter().filter(|i| i.player == player)
+                {
+                    ui.scope(|ui| {
+                        ui.set_opacity(smoothstep(
+                            1. - bubble.timer.fraction(),
+                        ));
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.label(
+                                egui::RichText::new(bubble.emote)
+                                    .size(EMOTE_TEXT_SIZE),
+                            );
+                        });
+                    });
+                }
+            });
+    }
+}
+
+/// The chat of a network match, where `local` is the player on this side.
+/// Lines from the other player are hidden while chat is muted.
+fn chat_panel(
+    context: &egui::Context,
+    playing_state: &PlayPhase,
+    settings: &Settings,
+    chat: &mut Chat,
+    local: PlayerSelect,
+) {
+    egui::Window::new("Chat")
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10., -10.))
+        .default_width(240.)
+        .resizable(false)
+        .show(context, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(160.)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    let lines = (chat.lines.iter())
+                        .filter(|i| !settings.mute_emotes || i.player == local);
+                    for line in lines {
+                        let name = &playing_state.player(line.player).name;
+                        ui.label(format!("{name}: {}", line.text));
+                    }
+                    if settings.mute_emotes {
+                        ui.weak("Chat is muted in the settings");
+                    }
+                });
+            let edit = ui.text_edit_singleline(&mut chat.draft);
+            let entered = edit.lost_focus()
+                && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if entered && !chat.draft.trim().is_empty() {
+                let text = std::mem::take(&mut chat.draft);
+                chat.say(local, &text);
+                chat.outgoing.push(Said::Line(text));
+                edit.request_focus();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat() {
+        let mut chat = Chat::default();
+        chat.emote(PlayerSelect::Player1, 0);
+        chat.emote(PlayerSelect::Player2, EMOTES.len());
+        assert_eq!(chat.bubbles.len(), 1);
+        assert_eq!(chat.bubbles[0].emote, EMOTES[0]);
+
+        let long = "a".repeat(CHAT_MAX_LENGTH * 2);
+        chat.say(PlayerSelect::Player2, &format!("  {long}"));
+        assert_eq!(chat.lines[0].text.len(), CHAT_MAX_LENGTH);
+        for i in 0..CHAT_MAX_LINES {
+            chat.say(PlayerSelect::Player1, &i.to_string());
+        }
+        assert_eq!(chat.lines.len(), CHAT_MAX_LINES);
+        assert_eq!(chat.lines[0].text, "0");
+    }
+}
@@ -0,0 +1,71 @@
+use crate::consts::*;
+use crate::eval::evaluate;
+use crate::models::*;
+use crate::settings::Settings;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+/// Latest evaluation of the board, from player 1's point of view
+#[derive(Resource, Default)]
+pub struct Evaluation {
+    pub value: f32,
+    /// Turn number and soldiers left when it was computed
+    key: Option<(u32, usize)>,
+}
+
+/// Re-evaluate the board at the start of each turn and after every hit
+pub fn update_evaluation(
+    state: Res<GameState>,
+    mut evaluation: ResMut<Evaluation>,
+) {
+    let Some(playing_state) = state.playing_state() else {
+        if evaluation.key.is_some() {
+            *evaluation = Evaluation::default();
+        }
+        return;
+    };
+    let key = Some((
+        playing_state.turn_number(),
+        playing_state.soldiers().count(),
+    ));
+    if evaluation.key != key {
+        evaluation.value = evaluate(playing_state);
+        evaluation.key = key;
+    }
+}
+
+/// Draw the evaluation as a bar under the top of the board, filled with
+/// player 1's color in proportion to how far ahead they are
+pub fn show_evaluation_bar(
+    mut contexts: EguiContexts,
+    state: Res<GameState>,
+    evaluation: Res<Evaluation>,
+    settings: Res<Settings>,
+) {
+    // The bar weighs player 1 against player 2, which says little about a
+    // free-for-all
+    let two_players =
+        (state.playing_state()).is_some_and(|i| i.players().len() == 2);
+    if !settings.evaluation_bar || !two_players {
+        return;
+    }
+    // Squash the advantage into (0, 1), with an even position in the middle
+    let share = 0.5 + 0.5 * (evaluation.value / EVALUATION_SCALE).tanh();
+    egui::Area::new(egui::Id::new("evaluation_bar"))
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0., 40.))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let size = egui::vec2(EVALUATION_BAR_SIZE.0, EVALUATION_BAR_SIZE.1);
+            let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+            let painter = ui.painter();
+            painter.rect_filled(rect, 2., egui::Color32::from_rgb(255, 0, 0));
+            let mut left = rect;
+            left.set_width(rect.width() * share);
+            painter.rect_filled(left, 2., egui::Color32::from_rgb(0, 0, 255));
+            painter.rect_stroke(
+                rect,
+                2.,
+                egui::Stroke::new(1., egui::Color32::BLACK),
+            );
+        });
+}
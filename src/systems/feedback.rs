@@ -0,0 +1,102 @@
+use crate::consts::*;
+use crate::settings::Settings;
+use crate::systems::announce::Announce;
+use crate::systems::graph_display::MatchEntity;
+use crate::util::smoothstep;
+use bevy::prelude::*;
+
+/// Sent whenever a single shot has destroyed more than one soldier so far
+#[derive(Event, Clone, Copy, Debug)]
+pub struct MultiKill {
+    /// Soldiers destroyed by the shot, including earlier hits
+    pub kills: u8,
+    /// Where the latest kill happened, in world coordinates
+    pub at: Vec2,
+}
+
+impl MultiKill {
+    /// Escalating name for the multi-kill
+    pub fn title(&self) -> &'static str {
+        match self.kills {
+            0 | 1 => "Kill!",
+            2 => "Double kill!",
+            3 => "Triple kill!",
+            4 => "Quadruple kill!",
+            _ => "Rampage!",
+        }
+    }
+}
+
+/// Text that floats up from where it was spawned and fades out
+#[derive(Component)]
+pub struct FloatingText {
+    timer: Timer,
+    origin: Vec3,
+}
+
+/// Pop up a text celebrating each multi-kill over the board, getting bigger
+/// the more soldiers the shot has destroyed
+pub fn show_multi_kills(
+    mut commands: Commands,
+    mut events: EventReader<MultiKill>,
+    mut announcements: EventWriter<Announce>,
+) {
+    for multi_kill in events.read() {
+        announcements.send(Announce(multi_kill.title().to_string()));
+        spawn_floating_text(
+            &mut commands,
+            multi_kill.title(),
+            20. + 6. * multi_kill.kills as f32,
+            multi_kill.at,
+        );
+    }
+}
+
+/// Spawn a text over the board at `at` (in world coordinates) that floats up
+/// and fades out
+pub fn spawn_floating_text(
+    commands: &mut Commands,
+    text: &str,
+    size: f32,
+    at: Vec2,
+) {
+    let origin = at.extend(FLOATING_TEXT_Z);
+    commands.spawn((
+        Text2d::new(text),
+        TextFont::from_font_size(size),
+        TextColor(FLOATING_TEXT_COLOR),
+        Transform::from_translation(origin),
+        FloatingText {
+            timer: Timer::from_seconds(FLOATING_TEXT_DURATION, TimerMode::Once),
+            origin,
+        },
+        MatchEntity,
+    ));
+}
+
+pub fn animate_floating_text(
+    mut commands: Commands,
+    mut texts: Query<(
+        Entity,
+        &mut FloatingText,
+        &mut Transform,
+        &mut TextColor,
+    )>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    for (entity, mut text, mut transform, mut color) in texts.iter_mut() {
+        if text.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let t = text.timer.fraction();
+        let rise = if settings.accessibility.reduced_motion {
+            0.
+        } else {
+            FLOATING_TEXT_RISE * t
+        };
+        transform.translation = text.origin + Vec3::Y * rise;
+        color.0 = color.0.with_alpha(smoothstep(1. - t));
+    }
+}
@@ -1,11 +1,18 @@
 use crate::consts::*;
 use crate::models::*;
 use crate::parse::ParsedFunction;
+use crate::polyline::{
+    draw_comet_head, draw_dashed_polyline, draw_fading_polyline,
+    draw_striped_circle,
+};
+use crate::records::ShotLanded;
+use crate::sim::{FieldModifier, SampleCache, Step, Target};
+use crate::systems::camera::CinematicFocus;
+use crate::systems::feedback::MultiKill;
 use crate::util::smoothstep;
 use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use std::marker::PhantomData;
-use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Component)]
@@ -14,12 +21,36 @@ pub struct CurrentPlayerText;
 #[derive(Component)]
 pub struct GridBackground;
 
+/// Marks entities that only exist for the duration of a match, so they can
+/// all be cleaned up together when returning to the setup screen
+#[derive(Component)]
+pub struct MatchEntity;
+
 #[derive(Component)]
 pub struct ExplosionFadeTimer(Timer);
 
+/// Mark left on the board where a soldier was destroyed, for the rest of the
+/// match. Like the soldiers, it is mirrored when the sides swap.
+#[derive(Component)]
+pub struct Scorch;
+
+/// A soldier or obstacle took damage from a shot
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Damaged {
+    /// Where it was hit, in world coordinates
+    pub at: Vec2,
+    pub damage: u32,
+    /// Hits it can still take, 0 if it was destroyed
+    pub hp_left: u32,
+}
+
 #[derive(Component)]
 pub struct SoldierNameText;
 
+/// Ring around the soldier whose turn it is
+#[derive(Component)]
+pub struct ActiveSoldierOutline;
+
 #[derive(Event, Clone)]
 pub struct StartGraphingEvent(pub ParsedFunction);
 
@@ -36,8 +67,10 @@ pub fn start_graphing(
     mut state: ResMut<GameState>,
     mut events: EventReader<StartGraphingEvent>,
     mut finish_graphing_events: EventWriter<DoneGraphingEvent>,
+    params: Res<crate::editor::EquationParams>,
+    mut samples: ResMut<SampleCache>,
 ) {
-    let Some(StartGraphingEvent(mut parsed_function)) =
+    let Some(StartGraphingEvent(parsed_function)) =
         events.read().next().cloned()
     else {
         return;
@@ -49,34 +82,45 @@ pub fn start_graphing(
     if !playing_state.turn_phase().is_input() {
         return;
     };
+    if let Err(e) = playing_state.check_equation(&parsed_function) {
+        log::info!("Equation not allowed: {e}");
+        return;
+    }
+    playing_state.remember_equation(&parsed_function);
+    playing_state.current_player_mut().idle_turns = 0;
+    let equation = params.substitute(
+        playing_state.current_player().current_soldier().equation(),
+    );
+    playing_state.record_shot(&equation);
+    playing_state.export_turn(equation);
 
     let current_player = playing_state.current_player();
-
-    parsed_function.add_var("e", std::f32::consts::E);
-    parsed_function.add_var("π", std::f32::consts::PI);
-    let func = parsed_function.bind("x");
+    let targets = (playing_state.opponents())
+        .flat_map(|i| i.soldiers())
+        .map(Target::from)
+        .collect();
 
     let active_soldier_pos = current_player.current_soldier().graph_location();
-    let Ok(y_start) = func(active_soldier_pos.x) else {
-        finish_graphing_events
-            .send(DoneGraphingEvent::Failed(active_soldier_pos.x));
-        return;
-    };
-    let offset = active_soldier_pos.y - y_start;
-    // - expression.clone().bind("x").unwrap()(active_soldier_pos.x as f64)
-    // as f32;
+    let stepper =
+        match samples.stepper(parsed_function, active_soldier_pos, targets) {
+            Ok(stepper) => stepper,
+            Err(fail_x) => {
+                finish_graphing_events.send(DoneGraphingEvent::Failed(fail_x));
+                return;
+            }
+        };
     *playing_state.turn_phase_mut() =
         TurnPhase::ShowPhase(TurnShowPhase::Graphing {
-            function: Function {
-                original: Arc::new(func),
-                shift_up: offset,
-            },
-            prev_y: None,
-            next_x: active_soldier_pos.x,
+            stepper: stepper
+                .with_obstacles(playing_state.obstacles().to_vec())
+                .with_field(playing_state.field().to_vec())
+                .with_max_length(playing_state.max_shot_length()),
             timer: Timer::new(
                 Duration::from_secs_f32(GRAPH_RES / GRAPHING_SPEED),
                 TimerMode::Repeating,
             ),
+            elapsed: Duration::ZERO,
+            kills: 0,
         });
 }
 
@@ -102,11 +146,20 @@ pub fn finish_drawing_graph(
         });
 }
 
+/// Respawn the number over each soldier. While the battery saver is saving
+/// power, that only happens when soldiers come, go or move.
 pub fn draw_soldier_names(
     mut commands: Commands,
     soldiers: Query<(&Soldier, &Transform)>,
     soldier_names: Query<Entity, With<SoldierNameText>>,
+    moved: Query<(), (With<Soldier>, Changed<Transform>)>,
+    mut removed: RemovedComponents<Soldier>,
+    saving: Res<crate::systems::display::PowerSaving>,
 ) {
+    let changed = !moved.is_empty() || removed.read().count() > 0;
+    if saving.active() && !changed {
+        return;
+    }
     // Despawn previous ones
     for i in soldier_names.iter() {
         commands.entity(i).despawn();
@@ -114,7 +167,7 @@ pub fn draw_soldier_names(
 
     for (soldier, loc) in soldiers.iter() {
         commands.spawn((
-            Text2d::new((soldier.id() + 1).to_string()),
+            Text2d::new((soldier.number() + 1).to_string()),
             TextColor(Color::BLACK),
             SoldierNameText,
             Transform {
@@ -127,6 +180,108 @@ pub fn draw_soldier_names(
     }
 }
 
+/// Keep the ring around the soldier whose turn it is, only touching it when
+/// the active soldier changes or the match starts or ends
+pub fn update_active_outline(
+    mut commands: Commands,
+    state: Res<GameState>,
+    outline: Option<
+        Single<(&mut Transform, &mut Visibility), With<ActiveSoldierOutline>>,
+    >,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let at = (state.playing_state())
+        .map(|playing| playing.current_player().current_soldier())
+        .map(|soldier| (soldier.graph_location() * 20.).extend(0.));
+    let Some(outline) = outline else {
+        if let Some(at) = at {
+            let half_width = ACTIVE_SOLDIER_OUTLINE_WIDTH / 2.;
+            commands.spawn((
+                ActiveSoldierOutline,
+                MatchEntity,
+                Mesh2d(meshes.add(Annulus::new(
+                    SOLDIER_RADIUS - half_width,
+                    SOLDIER_RADIUS + half_width,
+                ))),
+                MeshMaterial2d(materials.add(ACTIVE_SOLDIER_OUTLINE_COLOR)),
+                Transform::from_translation(
+                    at.with_z(ACTIVE_SOLDIER_OUTLINE_Z),
+                ),
+            ));
+        }
+        return;
+    };
+    let (mut transform, mut visibility) = outline.into_inner();
+    // Hidden once the match is over, until `cleanup_match` clears it
+    let Some(at) = at else {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
+    visibility.set_if_neq(Visibility::Inherited);
+    let translation = at.with_z(ACTIVE_SOLDIER_OUTLINE_Z);
+    if transform.translation != translation {
+        transform.translation = translation;
+    }
+}
+
+/// Make soldier sprites face the middle of the board, which flips them when
+/// the sides are swapped between turns
+pub fn face_soldiers(
+    mut soldiers: Query<(&Transform, &mut Sprite), With<Soldier>>,
+) {
+    for (transform, mut sprite) in soldiers.iter_mut() {
+        let flip = transform.translation.x > 0.;
+        if sprite.flip_x != flip {
+            sprite.flip_x = flip;
+        }
+    }
+}
+
+/// Blow up what shots damage: a big blast for whatever is destroyed, and a
+/// small tinted flash for hits it survives, both growing with the damage
+pub fn spawn_explosions(
+    mut commands: Commands,
+    mut events: EventReader<Damaged>,
+    asset_server: Res<AssetServer>,
+    pack: Res<crate::packs::AssetPack>,
+    settings: Res<crate::settings::Settings>,
+) {
+    for damaged in events.read() {
+        let destroyed = damaged.hp_left == 0;
+        if destroyed {
+            commands.spawn(AudioPlayer::new(
+                asset_server.load(pack.explosion_sound()),
+            ));
+        }
+        if settings.accessibility.reduced_motion {
+            continue;
+        }
+        let (scale, color) = if destroyed {
+            (1., Color::WHITE)
+        } else {
+            (CHIP_EXPLOSION_SCALE, CHIP_EXPLOSION_COLOR)
+        };
+        let size =
+            EXPLOSION_SPRITE_SIZE * scale * (damaged.damage as f32).sqrt();
+        commands.spawn((
+            Sprite {
+                color,
+                ..Sprite::from_image(asset_server.load(pack.explosion_sprite()))
+            },
+            ExplosionFadeTimer(Timer::new(
+                EXPLOSION_FADE_TIME,
+                TimerMode::Once,
+            )),
+            Transform {
+                translation: damaged.at.extend(EXPLOSION_Z),
+                rotation: Quat::IDENTITY,
+                scale: Vec3::ONE * (size / EXPLOSION_IMAGE_SIZE),
+            },
+        ));
+    }
+}
+
 pub fn fade_explosions(
     mut commands: Commands,
     mut explosions: Query<(Entity, &mut ExplosionFadeTimer, &mut Sprite)>,
@@ -137,8 +292,8 @@ pub fn fade_explosions(
             commands.entity(entity).despawn();
             continue;
         }
-        sprite.color =
-            Color::hsva(0., 0., 1., smoothstep(1. - timer.0.fraction()));
+        // Only the alpha fades, keeping any tint
+        sprite.color.set_alpha(smoothstep(1. - timer.0.fraction()));
     }
 }
 
@@ -151,124 +306,173 @@ pub fn update_turn(
     soldiers: Query<(Entity, &Soldier)>,
     mut resources: UpdateTurnResources,
 ) {
+    let real_delta = resources.time.delta_secs();
+    resources.focus.tick(real_delta);
     let Some(playing_state) = resources.state.playing_state_mut() else {
         return;
     };
+    // The opponent's turns in correspondence matches have no time limit, as
+    // they are waiting on the opponent's code
+    let remote = playing_state.is_remote_turn();
     match playing_state.turn_phase_mut() {
         TurnPhase::ShowPhase(TurnShowPhase::Graphing {
-            function,
-            prev_y,
-            next_x,
+            stepper,
             timer,
+            elapsed,
+            ..
         }) => {
-            let func = Arc::clone(&function.original);
-            let func_shift = function.shift_up;
-            let mut points = Vec::new();
-            let prev_y = *prev_y;
-            let mut current_x = *next_x;
-            for _ in 0..timer
-                .tick(resources.time.delta())
-                .times_finished_this_tick()
+            let reduced_motion =
+                resources.settings.accessibility.reduced_motion;
+            // Slow down (and let the camera zoom in) right before a hit
+            let lookahead = (SLOWMO_LOOKAHEAD / GRAPH_RES) as usize;
+            if resources.settings.cinematic_shots
+                && !reduced_motion
+                && let Some((_, at)) = stepper.upcoming_hit(lookahead)
             {
-                // if timer.tick(time.delta()).finished() {
-                let Ok(next_y) = func(current_x) else {
-                    finish_graphing_events
-                        .send(DoneGraphingEvent::Failed(current_x));
-                    break;
-                };
-                let point = Vec2::new(current_x, next_y + func_shift);
-                if point.y.is_nan()
-                    || point.y.is_infinite()
-                    || prev_y.is_some_and(|y| {
-                        (y - point.y).abs()
-                            > GRAPH_RES * DISCONTINUITY_THRESHOLD
-                    })
-                {
-                    finish_graphing_events
-                        .send(DoneGraphingEvent::Failed(point.x));
-                    break;
-                } else if point.x.abs() > 10. || point.y.abs() > 10. {
-                    finish_graphing_events.send(DoneGraphingEvent::Done);
-                    break;
+                resources.focus.focus(at * 20.);
+            }
+            let mut delta = resources.time.delta();
+            if resources.focus.is_active() {
+                delta = delta.mul_f32(SLOWMO_FACTOR);
+            }
+            *elapsed += resources.time.delta();
+            // With reduced motion, or once the shot has taken too long, the
+            // rest of it is drawn at once. The loop still ends, as every shot
+            // eventually leaves the board.
+            let steps = if reduced_motion || *elapsed >= MAX_GRAPHING_TIME {
+                u32::MAX
+            } else {
+                timer.tick(delta).times_finished_this_tick()
+            };
+
+            let mut points = Vec::new();
+            let mut hits = Vec::new();
+            // Length of the shot, once it is over
+            let mut finished = None;
+            for _ in 0..steps {
+                match stepper.step() {
+                    Step::Point {
+                        point,
+                        hits: new_hits,
+                    } => {
+                        points.push(point * 20.);
+                        let origin = stepper.origin().unwrap_or(point);
+                        hits.extend(new_hits.into_iter().map(|hit| {
+                            let distance = origin.distance(hit.location);
+                            (hit, stepper.path_length(), distance)
+                        }));
+                    }
+                    Step::Done | Step::Fizzled(_) => {
+                        finished = Some(stepper.path_length());
+                        finish_graphing_events.send(DoneGraphingEvent::Done);
+                        break;
+                    }
+                    Step::Blocked { point, obstacle } => {
+                        points.push(point * 20.);
+                        finished = Some(stepper.path_length());
+                        if let Some(hp_left) =
+                            playing_state.damage_obstacle(obstacle)
+                        {
+                            resources.damaged_events.send(Damaged {
+                                at: point * 20.,
+                                damage: 1,
+                                hp_left,
+                            });
+                        }
+                        finish_graphing_events.send(DoneGraphingEvent::Done);
+                        break;
+                    }
+                    Step::Failed(x) => {
+                        finished = Some(stepper.path_length());
+                        finish_graphing_events
+                            .send(DoneGraphingEvent::Failed(x));
+                        break;
+                    }
                 }
-                current_x += GRAPH_RES;
-                points.push(point * 20.);
-
-                #[allow(clippy::unnecessary_to_owned)]
-                for i in playing_state
-                    .other_player()
-                    .soldiers()
-                    .to_vec()
-                    .into_iter()
-                    .filter(|i| {
-                        i.graph_location().distance(point)
-                            < SOLDIER_RADIUS / 20.
-                    })
-                {
+            }
+            if let Some(used) = finished {
+                playing_state.bank_energy(used);
+            }
+            let mut new_kills = 0;
+            let mut last_kill = Vec2::ZERO;
+            let shooter = playing_state.current_player().name.clone();
+            for (i, path_length, hit_distance) in hits {
+                // Soldiers go down in one hit
+                resources.damaged_events.send(Damaged {
+                    at: i.location * 20.,
+                    damage: 1,
+                    hp_left: 0,
+                });
+                for soldier in soldiers.iter() {
+                    if soldier.1.id() == i.id {
+                        commands.entity(soldier.0).despawn();
+                    }
+                }
+                if playing_state.damage_soldier(i.id) {
+                    new_kills += 1;
+                    last_kill = i.location * 20.;
                     commands.spawn((
-                        Sprite::from_image(
-                            resources.asset_server.load("explosion.png"),
+                        Scorch,
+                        MatchEntity,
+                        Mesh2d(
+                            resources.meshes.add(Circle::new(SCORCH_RADIUS)),
                         ),
-                        ExplosionFadeTimer(Timer::new(
-                            Duration::from_secs(1),
-                            TimerMode::Once,
-                        )),
-                        Transform {
-                            translation: Vec3::new(
-                                i.graph_location().x * 20.,
-                                i.graph_location().y * 20.,
-                                EXPLOSION_Z,
-                            ),
-                            rotation: Quat::IDENTITY,
-                            scale: Vec3::ONE
-                                * (EXPLOSION_SPRITE_SIZE
-                                    / EXPLOSION_IMAGE_SIZE),
-                        },
+                        MeshMaterial2d(resources.materials.add(SCORCH_COLOR)),
+                        Transform::from_translation(last_kill.extend(SCORCH_Z)),
                     ));
-                    commands.spawn(AudioPlayer::new(
-                        resources.asset_server.load("explosion.mp3"),
-                    ));
-                    for soldier in soldiers.iter() {
-                        if soldier.1.player() == i.player()
-                            && soldier.1.id() == i.id()
-                        {
-                            commands.entity(soldier.0).despawn();
-                        }
-                    }
-                    playing_state.current_player_mut().destroy_soldier(i.id());
+                    resources.shot_landed_events.send(ShotLanded {
+                        shooter: shooter.clone(),
+                        path_length,
+                        hit_distance,
+                        at: last_kill,
+                    });
+                }
+            }
+            if new_kills > 0 {
+                let kills = playing_state.record_kills(new_kills);
+                if kills >= 2 {
+                    resources.multi_kill_events.send(MultiKill {
+                        kills,
+                        at: last_kill,
+                    });
                 }
-                playing_state.players_mut().0.verify_active_soldier();
-                playing_state.players_mut().1.verify_active_soldier();
             }
             if let Some(graph) = &mut graph {
                 graph.points.extend(points)
             } else {
                 commands.spawn(InProgressGraph { points });
             }
-            if let TurnPhase::ShowPhase(TurnShowPhase::Graphing {
-                next_x,
-                ..
-            }) = playing_state.turn_phase_mut()
-            {
-                *next_x = current_x;
-            }
         }
         TurnPhase::InputPhase { timer } => {
-            if timer.tick(resources.time.delta()).finished() {
+            if !remote && timer.tick(resources.time.delta()).finished() {
+                // Network matches with an idle limit skip turns that run
+                // out, so that a player who left can't keep shooting
+                if playing_state.idle_forfeit().is_some() {
+                    playing_state.skip_turn();
+                    skip_graphing_events.send(SkipGraphingEvent);
+                    return;
+                }
                 let current_player = playing_state.current_player();
-                let func_input = &current_player.current_soldier().equation;
-                let func = match func_input
+                let func_input = current_player.current_soldier().equation();
+                let mut func = match func_input
                     .parse::<crate::parse::ParsedFunction>()
                 {
                     Ok(f) => f,
                     Err(e) => {
-                        skip_graphing_events.send(SkipGraphingEvent);
                         log::info!(
                             "User typed bad function. Input:\n`{func_input}`\nError:\n{e}"
                         );
+                        playing_state.skip_turn();
+                        skip_graphing_events.send(SkipGraphingEvent);
                         return;
                     }
                 };
+                resources.params.bind(&mut func);
+                if playing_state.check_equation(&func).is_err() {
+                    playing_state.skip_turn();
+                    skip_graphing_events.send(SkipGraphingEvent);
+                    return;
+                }
                 start_graphing_events.send(StartGraphingEvent(func));
             }
         }
@@ -280,7 +484,14 @@ pub fn update_turn(
 pub struct UpdateTurnResources<'w, 's> {
     state: ResMut<'w, GameState>,
     time: Res<'w, Time>,
-    asset_server: Res<'w, AssetServer>,
+    settings: Res<'w, crate::settings::Settings>,
+    focus: ResMut<'w, CinematicFocus>,
+    params: Res<'w, crate::editor::EquationParams>,
+    multi_kill_events: EventWriter<'w, MultiKill>,
+    shot_landed_events: EventWriter<'w, ShotLanded>,
+    damaged_events: EventWriter<'w, Damaged>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<ColorMaterial>>,
     _phantom_data: PhantomData<&'s ()>,
 }
 
@@ -288,13 +499,12 @@ pub fn draw_graph(
     mut gizmos: Gizmos,
     state: Res<GameState>,
     graph: Option<Single<&InProgressGraph>>,
+    time: Res<Time>,
+    settings: Res<crate::settings::Settings>,
 ) {
-    if state.playing_state().is_none() {
+    if let GamePhaseNoData::Setup = state.game_phase() {
         return;
     }
-    // let GamePhase::Playing(_) = *state else {
-    //     return;
-    // };
 
     gizmos
         .grid_2d(
@@ -305,7 +515,91 @@ pub fn draw_graph(
         )
         .outer_edges();
 
-    if let Some(graph) = graph {
-        gizmos.linestrip_2d(graph.points.clone(), Color::srgb(1., 0., 0.));
+    let Some(graph) = graph else {
+        return;
+    };
+    // In high-contrast mode the shot is black and stays fully opaque, and
+    // player 2's shots are dashed so the shooter doesn't have to be told
+    // apart by color
+    let high_contrast = settings.accessibility.high_contrast;
+    let color = if high_contrast {
+        HIGH_CONTRAST_TRAIL_COLOR
+    } else {
+        TRAIL_COLOR
+    };
+    let dashed = high_contrast
+        && state
+            .playing_state()
+            .is_some_and(|state| state.turn() == PlayerSelect::Player2);
+    if dashed {
+        draw_dashed_polyline(&mut gizmos, &graph.points, color);
+    } else if high_contrast {
+        gizmos.linestrip_2d(graph.points.iter().copied(), color);
+    } else {
+        draw_fading_polyline(&mut gizmos, &graph.points, color);
+    }
+    if let Some(&tip) = graph.points.last() {
+        // Keep the head still rather than pulsing with reduced motion
+        let elapsed = if settings.accessibility.reduced_motion {
+            0.
+        } else {
+            time.elapsed_secs()
+        };
+        draw_comet_head(&mut gizmos, tip, color, elapsed);
+    }
+}
+
+/// Draw each gravity well as rings that get fainter towards its edge, and
+/// wind as arrows along the sides of the board
+pub fn draw_field(mut gizmos: Gizmos, state: Res<GameState>) {
+    let Some(playing_state) = state.playing_state() else {
+        return;
+    };
+    for modifier in playing_state.field() {
+        match *modifier {
+            FieldModifier::GravityWell { center, radius, .. } => {
+                for ring in 0..WELL_RINGS {
+                    let t = (ring + 1) as f32 / WELL_RINGS as f32;
+                    let alpha = WELL_COLOR.alpha() * (1. - t * 0.75);
+                    gizmos.circle_2d(
+                        Isometry2d::from_translation(center * 20.),
+                        radius * 20. * t,
+                        WELL_COLOR.with_alpha(alpha),
+                    );
+                }
+            }
+            FieldModifier::Wind { strength } => {
+                let length = WIND_ARROW_LENGTH * strength / WIND_STRENGTH;
+                for x in [-215., 215.] {
+                    for y in [-120., 0., 120.] {
+                        let start = Vec2::new(x, y - length / 2.);
+                        let end = start + Vec2::Y * length;
+                        gizmos.arrow_2d(start, end, WIND_COLOR);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// In high-contrast mode, stripe player 2's soldiers so the teams can be told
+/// apart without relying on color
+pub fn draw_team_patterns(
+    mut gizmos: Gizmos,
+    settings: Res<crate::settings::Settings>,
+    soldiers: Query<(&Soldier, &GlobalTransform)>,
+) {
+    if !settings.accessibility.high_contrast {
+        return;
+    }
+    for (soldier, transform) in soldiers.iter() {
+        if soldier.player() == PlayerSelect::Player2 {
+            draw_striped_circle(
+                &mut gizmos,
+                transform.translation().truncate(),
+                SOLDIER_RADIUS,
+                STRIPE_COLOR,
+            );
+        }
     }
 }
@@ -0,0 +1,161 @@
+use crate::consts::*;
+use crate::models::*;
+use crate::records::ShotLanded;
+use crate::util::simplify_polyline;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::collections::VecDeque;
+
+/// The board as it was left at the end of a turn
+#[derive(Clone, Debug)]
+pub struct TurnSnapshot {
+    pub turn_number: u32,
+    pub shooter: String,
+    /// Whether the board was seen from player 1's half, as it is during the
+    /// turns of players starting on the left
+    pub from_left: bool,
+    /// Soldiers still standing, in graph units
+    pub soldiers: Vec<Vec2>,
+    /// The shot's trail, in pixels, simplified to within
+    /// `HISTORY_TRAIL_TOLERANCE`
+    pub trail: Vec<Vec2>,
+    /// Where soldiers were destroyed by the shot, in pixels
+    pub kills: Vec<Vec2>,
+}
+
+/// Snapshots of the turns played so far in the match, which can be looked
+/// back through during the input phase. Only the last `HISTORY_TURNS` are
+/// kept.
+#[derive(Resource, Default)]
+pub struct TurnHistory {
+    snapshots: VecDeque<TurnSnapshot>,
+    /// Kills of the shot in progress, until its turn is recorded
+    kills: Vec<Vec2>,
+    /// How many turns back the board is being viewed, if at all
+    viewing: Option<usize>,
+}
+
+impl TurnHistory {
+    /// The snapshot being viewed, if any
+    pub fn viewed(&self) -> Option<&TurnSnapshot> {
+        let back = self.viewing?;
+        self.snapshots.iter().rev().nth(back - 1)
+    }
+}
+
+/// Remember where the shot in progress destroyed soldiers
+pub fn track_kills(
+    mut events: EventReader<ShotLanded>,
+    mut history: ResMut<TurnHistory>,
+) {
+    history.kills.extend(events.read().map(|landed| landed.at));
+}
+
+/// Take a snapshot of the board at the end of each turn, before the sides
+/// swap
+pub fn record_turn(
+    state: Res<GameState>,
+    graph: Option<Single<&InProgressGraph>>,
+    mut history: ResMut<TurnHistory>,
+) {
+    let Some(playing_state) = state.playing_state() else {
+        return;
+    };
+    let soldiers = (playing_state.soldiers())
+        .map(Soldier::graph_location)
+        .collect();
+    let kills = std::mem::take(&mut history.kills);
+    if history.snapshots.len() >= HISTORY_TURNS {
+        history.snapshots.pop_front();
+    }
+    history.snapshots.push_back(TurnSnapshot {
+        turn_number: playing_state.turn_number(),
+        shooter: playing_state.current_player().name.clone(),
+        from_left: playing_state.turn().starts_left(),
+        soldiers,
+        trail: graph.map_or_else(Vec::new, |graph| {
+            simplify_polyline(&graph.points, HISTORY_TRAIL_TOLERANCE)
+        }),
+        kills,
+    });
+}
+
+/// Slider to look back at previous turns during the input phase. The board
+/// goes back to live as soon as the turn is taken.
+pub fn history_ui(
+    mut contexts: EguiContexts,
+    state: Res<GameState>,
+    mut history: ResMut<TurnHistory>,
+) {
+    let Some(playing_state) = state.playing_state() else {
+        // Start over for the next match
+        if !history.snapshots.is_empty() || !history.kills.is_empty() {
+            *history = TurnHistory::default();
+        }
+        return;
+    };
+    if !playing_state.turn_phase().is_input() || history.snapshots.is_empty() {
+        history.viewing = None;
+        return;
+    }
+    let turns = history.snapshots.len();
+    let mut back = history.viewing.unwrap_or(0);
+    egui::Window::new("History")
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::Vec2::new(-10., -10.))
+        .collapsible(true)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Slider::new(&mut back, 0..=turns).text("turns back"));
+            match history.viewed() {
+                Some(snapshot) => ui.label(format!(
+                    "End of turn {}: {}'s shot",
+                    snapshot.turn_number, snapshot.shooter
+                )),
+                None => ui.label("Live board"),
+            };
+        });
+    let viewing = (back > 0).then_some(back);
+    if history.viewing != viewing {
+        history.viewing = viewing;
+    }
+}
+
+/// Draw the turn being looked back at over the board: its soldiers, the
+/// shot's trail and where it destroyed soldiers
+pub fn draw_history(
+    mut gizmos: Gizmos,
+    state: Res<GameState>,
+    history: Res<TurnHistory>,
+) {
+    let (Some(playing_state), Some(snapshot)) =
+        (state.playing_state(), history.viewed())
+    else {
+        return;
+    };
+    // The sides swap whenever the turn passes to the other half, so the
+    // snapshot is mirrored if it was seen from the other one
+    let mirror = if snapshot.from_left != playing_state.turn().starts_left() {
+        Vec2::new(-1., 1.)
+    } else {
+        Vec2::ONE
+    };
+    for &location in &snapshot.soldiers {
+        gizmos.circle_2d(
+            Isometry2d::from_translation(location * 20. * mirror),
+            SOLDIER_RADIUS,
+            HISTORY_SOLDIER_COLOR,
+        );
+    }
+    gizmos.linestrip_2d(
+        snapshot.trail.iter().map(|&point| point * mirror),
+        TRAIL_COLOR.with_alpha(HISTORY_TRAIL_ALPHA),
+    );
+    // Mark each kill with a cross
+    for &kill in &snapshot.kills {
+        let at = kill * mirror;
+        for arm in [Vec2::ONE, Vec2::new(1., -1.)] {
+            let arm = arm * SOLDIER_RADIUS;
+            gizmos.line_2d(at - arm, at + arm, HISTORY_KILL_COLOR);
+        }
+    }
+}
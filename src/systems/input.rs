@@ -0,0 +1,218 @@
+use super::camera::MainCamera;
+use super::graph_display::StartGraphingEvent;
+use super::kiosk::KioskMode;
+use super::overlay::OverlayMode;
+use super::util::InputCaptureState;
+use crate::consts::*;
+use crate::editor::EquationParams;
+use crate::models::*;
+use crate::settings::{Settings, WindowModeSetting};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+/// Something the player asked for with a keyboard shortcut or by clicking on
+/// the board. All gameplay input outside of egui should go through this so
+/// that it respects what egui has captured.
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub enum GameAction {
+    /// Shoot with the current equation
+    Submit {
+        from: InputDevice,
+    },
+    /// Make the current player's soldier with this number the active one
+    SelectSoldier {
+        number: u8,
+        from: InputDevice,
+    },
+    /// A soldier was clicked on, which selects it if it belongs to the
+    /// current player
+    ClickSoldier(SoldierId),
+    ToggleFullscreen,
+    /// Switch overlay mode on or off
+    ToggleOverlay,
+}
+
+/// Each half of the keyboard, with the keys that shoot and the keys that
+/// select soldiers, in order of soldier id
+const KEYBOARD_HALVES: [(InputDevice, &[KeyCode], [KeyCode; 4]); 2] = [
+    (InputDevice::KeyboardLeft, &[KeyCode::Space], [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+    ]),
+    (
+        InputDevice::KeyboardRight,
+        &[KeyCode::Enter, KeyCode::NumpadEnter],
+        [
+            KeyCode::Numpad1,
+            KeyCode::Numpad2,
+            KeyCode::Numpad3,
+            KeyCode::Numpad4,
+        ],
+    ),
+];
+
+/// Gamepad buttons that select soldiers, in order of soldier id
+const SOLDIER_BUTTONS: [GamepadButton; 4] = [
+    GamepadButton::DPadUp,
+    GamepadButton::DPadRight,
+    GamepadButton::DPadDown,
+    GamepadButton::DPadLeft,
+];
+
+/// Turn raw keyboard and mouse input into `GameAction`s. Keyboard shortcuts
+/// are ignored while egui wants the keyboard (e.g. while typing an
+/// equation), and clicks while the pointer is over egui.
+pub fn dispatch_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    capture: Res<InputCaptureState>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    soldiers: Query<(&Soldier, &GlobalTransform)>,
+    mut actions: EventWriter<GameAction>,
+) {
+    // Window shortcuts can't be mistaken for typing, so they always work
+    let alt = keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    if keys.just_pressed(KeyCode::F11)
+        || alt && keys.just_pressed(KeyCode::Enter)
+    {
+        actions.send(GameAction::ToggleFullscreen);
+        return;
+    }
+    if keys.just_pressed(KeyCode::F9) {
+        actions.send(GameAction::ToggleOverlay);
+        return;
+    }
+
+    if !capture.keyboard_captured {
+        for (from, submit_keys, soldier_keys) in KEYBOARD_HALVES {
+            if keys.any_just_pressed(submit_keys.iter().copied()) {
+                actions.send(GameAction::Submit { from });
+            }
+            for (number, key) in soldier_keys.into_iter().enumerate() {
+                if keys.just_pressed(key) {
+                    actions.send(GameAction::SelectSoldier {
+                        number: number as u8,
+                        from,
+                    });
+                }
+            }
+        }
+    }
+
+    if !capture.pointer_captured && mouse.just_pressed(MouseButton::Left) {
+        let (camera, camera_transform) = *camera;
+        let clicked = window.cursor_position().and_then(|cursor| {
+            camera.viewport_to_world_2d(camera_transform, cursor).ok()
+        });
+        let Some(clicked) = clicked else {
+            return;
+        };
+        let soldier = soldiers.iter().find(|(_, transform)| {
+            transform.translation().truncate().distance(clicked)
+                < SOLDIER_RADIUS
+        });
+        if let Some((soldier, _)) = soldier {
+            actions.send(GameAction::ClickSoldier(soldier.id()));
+        }
+    }
+}
+
+/// Turn gamepad buttons into `GameAction`s. Gamepads can't type, so unlike
+/// keys they work while egui wants the keyboard.
+pub fn dispatch_gamepad_input(
+    gamepads: Query<(Entity, &Gamepad)>,
+    mut actions: EventWriter<GameAction>,
+) {
+    let mut gamepads: Vec<_> = gamepads.iter().collect();
+    gamepads.sort_by_key(|(entity, _)| *entity);
+    for (number, (_, gamepad)) in gamepads.into_iter().enumerate() {
+        let from = InputDevice::Gamepad(number as u8);
+        if gamepad.just_pressed(GamepadButton::South) {
+            actions.send(GameAction::Submit { from });
+        }
+        for (soldier, button) in SOLDIER_BUTTONS.into_iter().enumerate() {
+            if gamepad.just_pressed(button) {
+                actions.send(GameAction::SelectSoldier {
+                    number: soldier as u8,
+                    from,
+                });
+            }
+        }
+    }
+}
+
+/// Carry out the gameplay actions
+pub fn handle_game_actions(
+    mut actions: EventReader<GameAction>,
+    mut state: ResMut<GameState>,
+    mut settings: ResMut<Settings>,
+    mut overlay: ResMut<OverlayMode>,
+    kiosk: Res<KioskMode>,
+    mut start_graphing_events: EventWriter<StartGraphingEvent>,
+    params: Res<EquationParams>,
+) {
+    for action in actions.read() {
+        if *action == GameAction::ToggleFullscreen {
+            // Kiosk mode locks the settings, the window mode included
+            if !kiosk.0 {
+                settings.display.mode = match settings.display.mode {
+                    WindowModeSetting::Windowed => {
+                        WindowModeSetting::Borderless
+                    }
+                    WindowModeSetting::Borderless => {
+                        WindowModeSetting::Windowed
+                    }
+                };
+            }
+            continue;
+        }
+        if *action == GameAction::ToggleOverlay {
+            overlay.0 = !overlay.0;
+            continue;
+        }
+        let Some(playing_state) = state.playing_state_mut() else {
+            continue;
+        };
+        // Computer-controlled players type their own equations, and remote
+        // players' equations come from their turn codes
+        if !playing_state.turn_phase().is_input()
+            || playing_state.current_player().ai
+            || playing_state.is_remote_turn()
+        {
+            continue;
+        }
+        // Players sharing a machine only answer to their own controls
+        let from = match *action {
+            GameAction::Submit { from }
+            | GameAction::SelectSoldier { from, .. } => from,
+            _ => InputDevice::Any,
+        };
+        if !playing_state.current_player().input.accepts(from) {
+            continue;
+        }
+        match *action {
+            GameAction::Submit { .. } => {
+                let equation =
+                    playing_state.current_player().current_soldier().equation();
+                if let Ok(mut func) = equation.parse() {
+                    params.bind(&mut func);
+                    start_graphing_events.send(StartGraphingEvent(func));
+                }
+            }
+            GameAction::SelectSoldier { number, .. } => {
+                playing_state.current_player_mut().select_soldier(number);
+            }
+            GameAction::ClickSoldier(id) => {
+                if id.player == playing_state.turn() {
+                    playing_state
+                        .current_player_mut()
+                        .select_soldier(id.number);
+                }
+            }
+            GameAction::ToggleFullscreen | GameAction::ToggleOverlay => (),
+        }
+    }
+}
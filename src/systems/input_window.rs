@@ -0,0 +1,71 @@
+use super::overlay::OverlayMode;
+use crate::consts::*;
+use crate::models::GameState;
+use crate::settings::Settings;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::view::RenderLayers;
+use bevy::window::WindowRef;
+
+/// Secondary OS window that shows the equation input, so that on a two
+/// monitor setup only the active player sees what is being typed
+#[derive(Component)]
+pub struct InputWindow;
+
+/// Open the input window while a match is being played with the setting or
+/// overlay mode enabled, and close it otherwise. Closing the window by hand
+/// turns both off.
+pub fn manage_input_window(
+    mut commands: Commands,
+    state: Res<GameState>,
+    mut settings: ResMut<Settings>,
+    mut overlay: ResMut<OverlayMode>,
+    windows: Query<Entity, With<InputWindow>>,
+    // The window and the camera that renders to it
+    mut spawned: Local<Option<(Entity, Entity)>>,
+) {
+    if let Some((window, camera)) = *spawned {
+        if !windows.contains(window) {
+            commands.entity(camera).despawn();
+            *spawned = None;
+            settings.input_window = false;
+            overlay.0 = false;
+            return;
+        }
+    }
+    let wanted =
+        (settings.input_window || overlay.0) && state.playing_state().is_some();
+    match (*spawned, wanted) {
+        (None, true) => {
+            let window = commands
+                .spawn((
+                    Window {
+                        title: "Equation input".to_string(),
+                        resolution: INPUT_WINDOW_SIZE.into(),
+                        ..Default::default()
+                    },
+                    InputWindow,
+                ))
+                .id();
+            // The window only needs a camera so that it gets drawn to; the
+            // render layer keeps the board out of it
+            let camera = commands
+                .spawn((
+                    Camera2d,
+                    Camera {
+                        target: RenderTarget::Window(WindowRef::Entity(window)),
+                        ..Default::default()
+                    },
+                    RenderLayers::layer(INPUT_WINDOW_LAYER),
+                ))
+                .id();
+            *spawned = Some((window, camera));
+        }
+        (Some((window, camera)), false) => {
+            commands.entity(window).despawn();
+            commands.entity(camera).despawn();
+            *spawned = None;
+        }
+        _ => (),
+    }
+}
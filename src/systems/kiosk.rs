@@ -0,0 +1,39 @@
+use crate::consts::*;
+use crate::models::*;
+use bevy::prelude::*;
+
+/// Whether the game is locked down for shared machines, such as in a school
+/// lab: the windows can't be closed, the settings can't be changed,
+/// correspondence matches can't be set up, and every match goes back to the
+/// setup screen on its own. It is turned on with `--kiosk`.
+#[derive(Resource, Default, Clone, Copy)]
+pub struct KioskMode(pub bool);
+
+impl KioskMode {
+    /// Kiosk mode as asked for on the command line
+    pub fn from_args() -> Self {
+        KioskMode(std::env::args().skip(1).any(|arg| arg == "--kiosk"))
+    }
+}
+
+/// Run condition for what is locked in kiosk mode
+pub fn kiosk_off(kiosk: Res<KioskMode>) -> bool {
+    !kiosk.0
+}
+
+/// Go back to the setup screen a while after each match, so the next person
+/// at the machine doesn't have to
+pub fn restart_after_match(
+    mut state: ResMut<GameState>,
+    time: Res<Time<Real>>,
+    mut finished_for: Local<f32>,
+) {
+    if state.finished_state().is_none() {
+        *finished_for = 0.;
+        return;
+    }
+    *finished_for += time.delta_secs();
+    if *finished_for >= KIOSK_RESTART_SECONDS {
+        *state = GameState::default();
+    }
+}
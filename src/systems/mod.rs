@@ -1,2 +1,23 @@
+pub mod ai;
+pub mod animation;
+pub mod announce;
+pub mod camera;
+pub mod demo;
+pub mod display;
+pub mod emotes;
+pub mod evaluation;
+pub mod feedback;
 pub mod graph_display;
+pub mod history;
+pub mod input;
+pub mod input_window;
+pub mod kiosk;
+pub mod mutators;
+pub mod observer;
+pub mod obstacles;
+pub mod overlay;
+pub mod parse_tree;
+pub mod preview;
+pub mod reminder;
+pub mod threat;
 pub mod util;
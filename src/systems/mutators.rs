@@ -0,0 +1,147 @@
+use crate::consts::*;
+use crate::models::*;
+use crate::settings::Settings;
+use crate::systems::announce::Announce;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+/// Round the roulette was last shown for, and how long it has been shown
+#[derive(Default)]
+pub struct RouletteSpin {
+    round: u32,
+    timer: Timer,
+}
+
+/// Spin a roulette through the mutators at the start of each round in
+/// roulette mode, landing on the ones picked for the round
+pub fn show_roulette(
+    mut contexts: EguiContexts,
+    state: Res<GameState>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    mut announcements: EventWriter<Announce>,
+    mut spin: Local<RouletteSpin>,
+) {
+    let Some((round, picks)) =
+        state.playing_state().and_then(PlayPhase::roulette_picks)
+    else {
+        spin.round = 0;
+        return;
+    };
+    if spin.round != round {
+        spin.round = round;
+        spin.timer = Timer::from_seconds(ROULETTE_DURATION, TimerMode::Once);
+        let names = picks.iter().map(Mutator::name).collect::<Vec<_>>();
+        announcements
+            .send(Announce(format!("Round {round}: {}", names.join(", "))));
+    }
+    if spin.timer.tick(time.delta()).finished() {
+        return;
+    }
+    // Cycle through the pool for most of the time, then settle on the picks.
+    // With reduced motion, go straight to the picks.
+    let spinning = !settings.accessibility.reduced_motion
+        && spin.timer.fraction() < ROULETTE_SPIN_FRACTION;
+    egui::Window::new(format!("Round {round}"))
+        .id(egui::Id::new("roulette"))
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if spinning {
+                let elapsed = spin.timer.elapsed_secs();
+                let index = (elapsed * ROULETTE_SPEED) as usize;
+                let mutator = Roulette::POOL[index % Roulette::POOL.len()];
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{} {}",
+                        mutator.icon(),
+                        mutator.name()
+                    ))
+                    .heading(),
+                );
+                return;
+            }
+            for mutator in picks {
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{} {}",
+                        mutator.icon(),
+                        mutator.name()
+                    ))
+                    .heading()
+                    .strong(),
+                );
+                ui.label(mutator.description());
+            }
+        });
+}
+
+/// Show the active mutators as a strip of icons with tooltips, and list them
+/// in the middle of the screen for a moment when a match starts
+pub fn show_mutators(
+    mut contexts: EguiContexts,
+    state: Res<GameState>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    mut announcements: EventWriter<Announce>,
+    mut intro: Local<Option<Timer>>,
+) {
+    let Some(playing_state) = state.playing_state() else {
+        *intro = None;
+        return;
+    };
+    let mutators = playing_state.mutators();
+    if mutators.is_empty() {
+        return;
+    }
+    let intro = intro.get_or_insert_with(|| {
+        let names = mutators.iter().map(Mutator::name).collect::<Vec<_>>();
+        announcements.send(Announce(format!("Mutators: {}", names.join(", "))));
+        Timer::from_seconds(MUTATOR_INTRO_DURATION, TimerMode::Once)
+    });
+    let context = contexts.ctx_mut();
+
+    // Keep clear of the nameplates in presentation mode
+    let offset = if settings.presentation_mode { 70. } else { 10. };
+    egui::Area::new(egui::Id::new("mutator_strip"))
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10., offset))
+        .show(context, |ui| {
+            ui.horizontal(|ui| {
+                for mutator in &mutators {
+                    ui.label(egui::RichText::new(mutator.icon()).size(24.))
+                        .on_hover_text(format!(
+                            "{}: {}",
+                            mutator.name(),
+                            mutator.description()
+                        ));
+                }
+            });
+        });
+
+    // The roulette shows the mutators of each round itself
+    if intro.tick(time.delta()).finished()
+        || playing_state.roulette_picks().is_some()
+    {
+        return;
+    }
+    egui::Window::new("Mutators")
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .interactable(false)
+        .show(context, |ui| {
+            for mutator in &mutators {
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{} {}",
+                        mutator.icon(),
+                        mutator.name()
+                    ))
+                    .heading(),
+                );
+                ui.label(mutator.description());
+            }
+        });
+}
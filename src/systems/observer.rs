@@ -0,0 +1,128 @@
+use crate::editor::EquationParams;
+use crate::models::*;
+use crate::records::ShotLanded;
+use crate::settings::Settings;
+use bevy::prelude::*;
+use serde::Serialize;
+use std::io::Write;
+
+/// Something that happened in a match, for tools watching the game. Printed
+/// as one JSON object per line, tagged by `"event"`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ObserverEvent {
+    TurnStarted {
+        turn_number: u32,
+        player: String,
+    },
+    /// The equation is shown with any parameters filled in
+    EquationSubmitted {
+        player: String,
+        equation: String,
+    },
+    /// A soldier was destroyed at `x`, `y`, in graph units
+    Hit {
+        shooter: String,
+        x: f32,
+        y: f32,
+    },
+    Winner {
+        player: String,
+    },
+}
+
+/// What the match looked like last frame, to notice what changed
+#[derive(Default)]
+pub struct SeenTurn {
+    turn: Option<PlayerSelect>,
+    input: bool,
+    finished: bool,
+}
+
+/// Print match events to stdout while the observer stream is on, so
+/// streaming overlays and stats tools can follow along without patching the
+/// game
+pub fn stream_events(
+    state: Res<GameState>,
+    settings: Res<Settings>,
+    params: Res<EquationParams>,
+    mut shots: EventReader<ShotLanded>,
+    mut seen: Local<SeenTurn>,
+) {
+    let mut events = Vec::new();
+    if let Some(finished) = state.finished_state() {
+        if !seen.finished {
+            events.push(ObserverEvent::Winner {
+                player: finished.winner_name.clone(),
+            });
+            seen.finished = true;
+        }
+    } else if let Some(playing_state) = state.playing_state() {
+        let player = playing_state.current_player();
+        let input = playing_state.turn_phase().is_input();
+        if input && (!seen.input || seen.turn != Some(playing_state.turn())) {
+            events.push(ObserverEvent::TurnStarted {
+                turn_number: playing_state.turn_number(),
+                player: player.name.clone(),
+            });
+        }
+        if !input
+            && seen.input
+            && matches!(
+                playing_state.turn_phase(),
+                TurnPhase::ShowPhase(TurnShowPhase::Graphing { .. })
+            )
+        {
+            let equation = player.current_soldier().equation();
+            events.push(ObserverEvent::EquationSubmitted {
+                player: player.name.clone(),
+                equation: params.substitute(equation),
+            });
+        }
+        seen.turn = Some(playing_state.turn());
+        seen.input = input;
+        seen.finished = false;
+    } else {
+        *seen = SeenTurn::default();
+    }
+    events.extend(shots.read().map(|shot| ObserverEvent::Hit {
+        shooter: shot.shooter.clone(),
+        x: shot.at.x / 20.,
+        y: shot.at.y / 20.,
+    }));
+    if !settings.observer_stream {
+        return;
+    }
+    let mut stdout = std::io::stdout().lock();
+    for event in events {
+        if let Ok(line) = serde_json::to_string(&event) {
+            let _ = writeln!(stdout, "{line}");
+        }
+    }
+    let _ = stdout.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observer_json() {
+        let event = ObserverEvent::EquationSubmitted {
+            player: "Ada".to_string(),
+            equation: "sin(x)".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"equation_submitted","player":"Ada","equation":"sin(x)"}"#
+        );
+        let event = ObserverEvent::TurnStarted {
+            turn_number: 3,
+            player: "Émile".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"turn_started","turn_number":3,"player":"Émile"}"#
+        );
+    }
+}
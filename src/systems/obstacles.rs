@@ -0,0 +1,128 @@
+use crate::consts::*;
+use crate::models::*;
+use crate::systems::graph_display::MatchEntity;
+use crate::util::smoothstep;
+use bevy::prelude::*;
+
+/// Shows the obstacle with this id
+#[derive(Component)]
+pub struct ObstacleDisplay(u8);
+
+/// An obstacle that was destroyed, shrinking and fading away
+#[derive(Component)]
+pub struct Crumbling(Timer);
+
+/// Keep the obstacle meshes in line with the obstacles of the match: follow
+/// them when the sides swap, lighten them as they take hits and let them
+/// crumble once destroyed
+pub fn sync_obstacles(
+    mut commands: Commands,
+    state: Res<GameState>,
+    mut displays: Query<(
+        Entity,
+        &ObstacleDisplay,
+        &mut Transform,
+        &MeshMaterial2d<ColorMaterial>,
+    )>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let Some(playing_state) = state.playing_state() else {
+        return;
+    };
+    let obstacles = playing_state.obstacles();
+    for (entity, display, mut transform, material) in displays.iter_mut() {
+        let Some(obstacle) = obstacles.iter().find(|i| i.id == display.0)
+        else {
+            commands.entity(entity).remove::<ObstacleDisplay>().insert(
+                Crumbling(Timer::from_seconds(
+                    CRUMBLE_DURATION,
+                    TimerMode::Once,
+                )),
+            );
+            continue;
+        };
+        transform.translation.x = obstacle.location.x * 20.;
+        transform.rotation = obstacle_rotation(obstacle);
+        if let Some(material) = materials.get_mut(&material.0) {
+            material.color = obstacle_color(obstacle);
+        }
+    }
+    for obstacle in obstacles {
+        if displays
+            .iter()
+            .any(|(_, display, ..)| display.0 == obstacle.id)
+        {
+            continue;
+        }
+        let location = obstacle.location * 20.;
+        let mesh = match obstacle.kind {
+            ObstacleKind::Block { radius } => {
+                meshes.add(Circle::new(radius * 20.))
+            }
+            ObstacleKind::BouncyWall { half_length } => {
+                meshes.add(Rectangle::new(half_length * 40., WALL_THICKNESS))
+            }
+            ObstacleKind::Polygon { radius, sides, .. } => {
+                meshes.add(RegularPolygon::new(radius * 20., sides.into()))
+            }
+        };
+        commands.spawn((
+            Mesh2d(mesh),
+            MeshMaterial2d(materials.add(obstacle_color(obstacle))),
+            Transform::from_translation(location.extend(OBSTACLE_Z))
+                .with_rotation(obstacle_rotation(obstacle)),
+            ObstacleDisplay(obstacle.id),
+            MatchEntity,
+        ));
+    }
+}
+
+/// Rotation of the obstacle's mesh. Polygon meshes start with a corner at
+/// the top.
+fn obstacle_rotation(obstacle: &Obstacle) -> Quat {
+    match obstacle.kind {
+        ObstacleKind::Polygon { rotation, .. } => {
+            Quat::from_rotation_z(rotation - std::f32::consts::FRAC_PI_2)
+        }
+        _ => Quat::IDENTITY,
+    }
+}
+
+fn obstacle_color(obstacle: &Obstacle) -> Color {
+    if let ObstacleKind::BouncyWall { .. } = obstacle.kind {
+        return BOUNCY_WALL_COLOR;
+    }
+    match obstacle.hp {
+        None => OBSTACLE_COLOR,
+        Some(hp) => {
+            let damage = 1. - hp as f32 / OBSTACLE_HP as f32;
+            DESTRUCTIBLE_OBSTACLE_COLOR.mix(&Color::WHITE, damage * 0.6)
+        }
+    }
+}
+
+pub fn crumble_obstacles(
+    mut commands: Commands,
+    mut obstacles: Query<(
+        Entity,
+        &mut Crumbling,
+        &mut Transform,
+        &MeshMaterial2d<ColorMaterial>,
+    )>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    time: Res<Time>,
+) {
+    for (entity, mut crumbling, mut transform, material) in obstacles.iter_mut()
+    {
+        if crumbling.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let left = smoothstep(1. - crumbling.0.fraction());
+        transform.scale = Vec3::splat(0.5 + 0.5 * left);
+        if let Some(material) = materials.get_mut(&material.0) {
+            material.color = material.color.with_alpha(left);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use super::graph_display::GridBackground;
+use crate::consts::*;
+use bevy::prelude::*;
+
+/// Whether the board is drawn for capturing as a stream overlay: on a solid
+/// chroma-key background, enlarged, with thick lines and big nameplates and
+/// without any panels. The equation input moves to the input window. This
+/// isn't saved with the settings, as it's only wanted while streaming; it is
+/// turned on with `--overlay` or toggled with F9.
+#[derive(Resource, Default)]
+pub struct OverlayMode(pub bool);
+
+impl OverlayMode {
+    /// Overlay mode as asked for on the command line
+    pub fn from_args() -> Self {
+        OverlayMode(std::env::args().skip(1).any(|arg| arg == "--overlay"))
+    }
+}
+
+/// Run condition for the panels that are hidden in overlay mode
+pub fn overlay_off(overlay: Res<OverlayMode>) -> bool {
+    !overlay.0
+}
+
+/// Switch the background, the board and the line width between the overlay
+/// and the normal look
+pub fn apply_overlay(
+    overlay: Res<OverlayMode>,
+    mut clear_color: ResMut<ClearColor>,
+    mut gizmo_config: ResMut<GizmoConfigStore>,
+    mut backgrounds: Query<&mut Visibility, With<GridBackground>>,
+) {
+    let (color, line_width) = if overlay.0 {
+        (OVERLAY_BACKGROUND_COLOR, OVERLAY_LINE_WIDTH)
+    } else {
+        (ClearColor::default().0, GizmoConfig::default().line_width)
+    };
+    if clear_color.0 != color {
+        clear_color.0 = color;
+    }
+    let (config, _) = gizmo_config.config_mut::<DefaultGizmoConfigGroup>();
+    if config.line_width != line_width {
+        config.line_width = line_width;
+    }
+    let visibility = if overlay.0 {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    for mut background in &mut backgrounds {
+        background.set_if_neq(visibility);
+    }
+}
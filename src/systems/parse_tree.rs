@@ -0,0 +1,57 @@
+use crate::models::*;
+use crate::parse::{ParseTreeNode, ParsedFunction};
+use crate::settings::Settings;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+/// Debug panel showing the tree the current player's equation parses into,
+/// to see how implicit multiplication and precedence were resolved when a
+/// shot doesn't graph as expected
+pub fn parse_tree_ui(
+    mut contexts: EguiContexts,
+    state: Res<GameState>,
+    settings: Res<Settings>,
+) {
+    if !settings.show_parse_tree {
+        return;
+    }
+    // Equations stay hidden while the machine is handed to the next player
+    let Some(playing_state) = state
+        .playing_state()
+        .filter(|i| !i.turn_phase().is_handoff())
+    else {
+        return;
+    };
+    let equation = playing_state.current_player().current_soldier().equation();
+    egui::Window::new("Parse tree")
+        .default_pos(egui::pos2(10., 80.))
+        .collapsible(true)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.monospace(equation);
+            match equation.parse::<ParsedFunction>() {
+                Ok(parsed) => {
+                    node_ui(ui, &parsed.parse_tree(), egui::Id::new("parse"))
+                }
+                Err(e) => {
+                    ui.colored_label(egui::Color32::RED, e.to_string());
+                }
+            }
+        });
+}
+
+/// A node as a collapsible header over its children, or a label if it has
+/// none. `id` tells apart nodes with the same label.
+fn node_ui(ui: &mut egui::Ui, node: &ParseTreeNode, id: egui::Id) {
+    if node.children.is_empty() {
+        ui.monospace(&node.label);
+        return;
+    }
+    egui::CollapsingHeader::new(&node.label)
+        .id_salt(id)
+        .default_open(true)
+        .show(ui, |ui| {
+            for (i, child) in node.children.iter().enumerate() {
+                node_ui(ui, child, id.with(i));
+            }
+        });
+}
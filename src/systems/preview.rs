@@ -0,0 +1,145 @@
+use crate::consts::*;
+use crate::editor::EquationParams;
+use crate::models::*;
+use crate::parse::ParsedFunction;
+use crate::sim::SampleCache;
+use bevy::prelude::*;
+
+/// Live preview of the equation being typed. Practice matches show the whole
+/// shot, and the aim preview only its first `AIM_PREVIEW_LENGTH` units,
+/// dashed, for players to check what they typed before they shoot it.
+#[derive(Resource, Default)]
+pub struct PracticePreview {
+    /// What the preview was last requested for: the equation with its
+    /// parameters filled in, and where it is shot from
+    key: Option<(String, Vec2)>,
+    /// Seconds since `key` last changed
+    unchanged_for: f32,
+    /// Points in pixels, empty if there is nothing to show
+    points: Vec<Vec2>,
+    /// Whether it is the aim preview, which is drawn dashed
+    dashed: bool,
+    up_to_date: bool,
+}
+
+/// Retrace the preview once the equation has stopped changing for a moment,
+/// so it doesn't get recomputed on every keystroke
+pub fn update_practice_preview(
+    state: Res<GameState>,
+    params: Res<EquationParams>,
+    time: Res<Time>,
+    mut preview: ResMut<PracticePreview>,
+    mut samples: ResMut<SampleCache>,
+) {
+    let playing_state = state.playing_state().filter(|state| {
+        (state.is_practice() || state.has_aim_preview())
+            && state.turn_phase().is_input()
+            && !state.current_player().ai
+            && !state.is_remote_turn()
+    });
+    let Some(playing_state) = playing_state else {
+        if preview.key.is_some() {
+            *preview = PracticePreview::default();
+        }
+        return;
+    };
+    let soldier = playing_state.current_player().current_soldier();
+    let key = (
+        params.substitute(soldier.equation()),
+        soldier.graph_location(),
+    );
+    if preview.key.as_ref() != Some(&key) {
+        preview.key = Some(key);
+        preview.unchanged_for = 0.;
+        preview.up_to_date = false;
+        return;
+    }
+    preview.unchanged_for += time.delta_secs();
+    if preview.up_to_date || preview.unchanged_for < PREVIEW_DEBOUNCE {
+        return;
+    }
+    preview.up_to_date = true;
+    let from = soldier.graph_location();
+    let stepper = soldier.equation().parse::<ParsedFunction>().ok().and_then(
+        |mut parsed| {
+            params.bind(&mut parsed);
+            samples.stepper(parsed, from, Vec::new()).ok()
+        },
+    );
+    let mut points = match stepper {
+        Some(stepper) => stepper
+            .with_obstacles(playing_state.obstacles().to_vec())
+            .with_field(playing_state.field().to_vec())
+            .with_max_length(playing_state.max_shot_length())
+            .trace()
+            .points
+            .into_iter()
+            .map(|point| point * 20.)
+            .collect(),
+        None => Vec::new(),
+    };
+    preview.dashed = !playing_state.is_practice();
+    if preview.dashed {
+        points.truncate(within_length(&points, AIM_PREVIEW_LENGTH * 20.));
+    }
+    preview.points = points;
+}
+
+/// Number of points at the start of the path that are within `length` of
+/// its start, measured along it
+fn within_length(points: &[Vec2], length: f32) -> usize {
+    let mut travelled = 0.;
+    let steps = points.windows(2).take_while(|pair| {
+        travelled += pair[0].distance(pair[1]);
+        travelled <= length
+    });
+    (steps.count() + 1).min(points.len())
+}
+
+pub fn draw_practice_preview(
+    mut gizmos: Gizmos,
+    preview: Res<PracticePreview>,
+) {
+    if preview.points.len() < 2 {
+        return;
+    }
+    if !preview.dashed {
+        gizmos.linestrip_2d(preview.points.iter().copied(), PREVIEW_COLOR);
+        return;
+    }
+    for dash in dashes(&preview.points, AIM_PREVIEW_DASH, AIM_PREVIEW_GAP) {
+        gizmos.linestrip_2d(dash, PREVIEW_COLOR);
+    }
+}
+
+/// The path cut into dashes `dash` long, `gap` apart, measured along it
+fn dashes(points: &[Vec2], dash: f32, gap: f32) -> Vec<Vec<Vec2>> {
+    let mut dashes = vec![vec![points[0]]];
+    // Distance along the current dash or gap, and whether it is a gap
+    let mut along = 0.;
+    let mut in_gap = false;
+    for pair in points.windows(2) {
+        let (mut from, to) = (pair[0], pair[1]);
+        let mut left = from.distance(to);
+        while left > 0. {
+            let length = if in_gap { gap } else { dash };
+            let step = (length - along).min(left);
+            let point = from.move_towards(to, step);
+            if !in_gap && let Some(current) = dashes.last_mut() {
+                current.push(point);
+            }
+            along += step;
+            left -= step;
+            from = point;
+            if along >= length {
+                along = 0.;
+                in_gap = !in_gap;
+                if !in_gap {
+                    dashes.push(vec![point]);
+                }
+            }
+        }
+    }
+    dashes.retain(|i| i.len() > 1);
+    dashes
+}
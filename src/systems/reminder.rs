@@ -0,0 +1,80 @@
+use crate::consts::*;
+use crate::models::*;
+use crate::settings::Settings;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy::winit::WinitWindows;
+use std::process::Command;
+use winit::window::UserAttentionType;
+
+/// Get the player's attention when their turn of a correspondence or network
+/// match starts while they are in another window, as they tend to be while
+/// the opponent takes their turn
+pub fn remind_turn(
+    mut commands: Commands,
+    state: Res<GameState>,
+    settings: Res<Settings>,
+    window: Single<(Entity, &Window), With<PrimaryWindow>>,
+    winit_windows: NonSend<WinitWindows>,
+    mut pitches: ResMut<Assets<Pitch>>,
+    // Whether it was the local player's turn last frame
+    mut was_local_turn: Local<bool>,
+) {
+    let playing_state = state.playing_state().filter(|state| {
+        state.correspondence().is_some()
+            && state.turn_phase().is_input()
+            && !state.is_remote_turn()
+    });
+    let started = playing_state.is_some() && !*was_local_turn;
+    *was_local_turn = playing_state.is_some();
+    let (entity, window) = *window;
+    let Some(playing_state) = playing_state.filter(|_| started) else {
+        return;
+    };
+    if window.focused {
+        return;
+    }
+    let reminder = &settings.turn_reminder;
+    if reminder.flash
+        && let Some(window) = winit_windows.get_window(entity)
+    {
+        window.request_user_attention(Some(UserAttentionType::Informational));
+    }
+    if reminder.chime {
+        commands.spawn((
+            AudioPlayer(
+                pitches.add(Pitch::new(TURN_CHIME_PITCH, TURN_CHIME_LENGTH)),
+            ),
+            PlaybackSettings::DESPAWN,
+        ));
+    }
+    if reminder.notification {
+        let name = &playing_state.current_player().name;
+        notify(&format!("{name}, it's your turn"));
+    }
+}
+
+/// Show a desktop notification with the system's own command for it
+fn notify(text: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.args([
+            "-e",
+            &format!(
+                "display notification \"{}\" with title \"Graphwars\"",
+                text.replace(['"', '\\'], "")
+            ),
+        ]);
+        command
+    } else if cfg!(windows) {
+        // The flashing taskbar entry has to do
+        return;
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["Graphwars", text]);
+        command
+    };
+    if let Err(e) = command.spawn() {
+        log::warn!("Failed to show notification: {e}");
+    }
+}
@@ -0,0 +1,87 @@
+use crate::consts::*;
+use crate::eval::threat_map;
+use crate::models::*;
+use crate::settings::Settings;
+use crate::systems::graph_display::MatchEntity;
+use bevy::image::ImageSampler;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{
+    Extent3d, TextureDimension, TextureFormat,
+};
+
+/// Sprite shading the parts of the board the enemies can reach
+#[derive(Component)]
+pub struct ThreatOverlay;
+
+/// Recompute the threat overlay for the current player at the start of each
+/// turn (and after hits), and remove it when it's turned off
+pub fn update_threat_overlay(
+    mut commands: Commands,
+    state: Res<GameState>,
+    settings: Res<Settings>,
+    mut images: ResMut<Assets<Image>>,
+    overlay: Option<Single<(Entity, &mut Sprite), With<ThreatOverlay>>>,
+    mut computed_for: Local<Option<(u32, usize)>>,
+) {
+    let playing_state =
+        state.playing_state().filter(|_| settings.threat_overlay);
+    let Some(playing_state) = playing_state else {
+        if let Some(overlay) = overlay {
+            commands.entity(overlay.0).despawn();
+        }
+        *computed_for = None;
+        return;
+    };
+    let key = Some((
+        playing_state.turn_number(),
+        playing_state.soldiers().count(),
+    ));
+    if *computed_for == key {
+        return;
+    }
+    *computed_for = key;
+
+    let enemies = (playing_state.opponents())
+        .flat_map(|i| i.soldiers())
+        .cloned()
+        .collect::<Vec<_>>();
+    let cells =
+        threat_map(&enemies, playing_state.obstacles(), playing_state.field());
+    let color = THREAT_COLOR.to_srgba().to_u8_array();
+    let mut data = Vec::with_capacity(cells.len() * 4);
+    // Image rows go from the top down, while the cells go from the bottom up
+    for row in cells.chunks(THREAT_GRID_SIZE).rev() {
+        for &threatened in row {
+            data.extend(if threatened { color } else { [0; 4] });
+        }
+    }
+    let mut image = Image::new(
+        Extent3d {
+            width: THREAT_GRID_SIZE as u32,
+            height: THREAT_GRID_SIZE as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::nearest();
+    let image = images.add(image);
+
+    if let Some(mut overlay) = overlay {
+        overlay.1.image = image;
+    } else {
+        commands.spawn((
+            Sprite {
+                image,
+                custom_size: Some(Vec2::splat(400.)),
+                ..Default::default()
+            },
+            Transform::from_xyz(0., 0., THREAT_OVERLAY_Z),
+            ThreatOverlay,
+            MatchEntity,
+        ));
+    }
+}
@@ -16,7 +16,7 @@ pub struct InputCaptureState {
 }
 
 pub fn setup(mut commands: Commands) {
-    commands.spawn(Camera2d);
+    commands.spawn((Camera2d, super::camera::MainCamera));
 }
 
 pub fn currently_graphing(
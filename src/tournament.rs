@@ -0,0 +1,201 @@
+//! Single-elimination tournaments between local players, played one match
+//! after another with the same settings
+
+use crate::models::{GameState, MatchMode, SetupPhase};
+use bevy::prelude::*;
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+/// Most players that can enter a tournament
+pub const MAX_ENTRANTS: usize = 8;
+
+/// A single-elimination bracket. Each round holds the players (by name) in
+/// its slots, and the winner of the match between slots `2i` and `2i + 1`
+/// moves on to slot `i` of the next round. Slots are empty until decided, or
+/// for byes in the first round.
+#[derive(Clone)]
+pub struct Tournament {
+    rounds: Vec<Vec<Option<String>>>,
+    /// Settings every match is played with
+    settings: SetupPhase,
+}
+
+/// A match of a tournament between the players in two slots of a round
+#[derive(Clone, Debug, PartialEq)]
+pub struct BracketMatch {
+    pub round: usize,
+    /// Index of the match within the round
+    pub index: usize,
+    pub players: [String; 2],
+}
+
+impl Tournament {
+    /// Seed the entrants into a bracket at random. Fewer entrants than a
+    /// power of two leaves some of them a bye, straight into the second
+    /// round. Returns `None` unless there are 2 to `MAX_ENTRANTS` entrants.
+    pub fn new(
+        rng: &mut impl Rng,
+        mut entrants: Vec<String>,
+        mut settings: SetupPhase,
+    ) -> Option<Self> {
+        if !(2..=MAX_ENTRANTS).contains(&entrants.len()) {
+            return None;
+        }
+        entrants.shuffle(rng);
+        let size = entrants.len().next_power_of_two();
+        let byes = size - entrants.len();
+        let mut first_round = Vec::with_capacity(size);
+        let mut entrants = entrants.into_iter();
+        // Byes are spread out so no match is between two of them
+        for pair in 0..size / 2 {
+            first_round.push(entrants.next());
+            first_round.push(if pair < byes { None } else { entrants.next() });
+        }
+        let mut rounds = vec![first_round];
+        while rounds.last().is_some_and(|round| round.len() > 1) {
+            let slots = rounds.last().map_or(0, Vec::len) / 2;
+            rounds.push(vec![None; slots]);
+        }
+        for pair in 0..byes {
+            rounds[1][pair] = rounds[0][pair * 2].clone();
+        }
+        // Tournament matches are plain matches between two players
+        settings.mode = MatchMode::Versus;
+        settings.custom_puzzle = None;
+        settings.join_turn = None;
+        Some(Tournament { rounds, settings })
+    }
+
+    pub fn rounds(&self) -> &[Vec<Option<String>>] {
+        &self.rounds
+    }
+
+    /// The next match to be played, if the tournament isn't over
+    pub fn next_match(&self) -> Option<BracketMatch> {
+        self.rounds
+            .windows(2)
+            .enumerate()
+            .find_map(|(round, pair)| {
+                let [slots, next] = pair else {
+                    return None;
+                };
+                (0..next.len()).find_map(|index| {
+                    let players = [
+                        slots[index * 2].clone()?,
+                        slots[index * 2 + 1].clone()?,
+                    ];
+                    next[index].is_none().then_some(BracketMatch {
+                        round,
+                        index,
+                        players,
+                    })
+                })
+            })
+    }
+
+    /// Move the winner of the next match on to the next round. Does nothing
+    /// if `winner` isn't playing in it.
+    pub fn record(&mut self, winner: &str) {
+        let Some(next) = self.next_match() else {
+            return;
+        };
+        if next.players.iter().any(|player| player == winner) {
+            self.rounds[next.round + 1][next.index] = Some(winner.to_string());
+        }
+    }
+
+    pub fn champion(&self) -> Option<&str> {
+        self.rounds.last()?.first()?.as_deref()
+    }
+
+    /// Setup for the next match, with the tournament's settings and the
+    /// names of the players in it
+    pub fn next_setup(&self) -> Option<SetupPhase> {
+        let next = self.next_match()?;
+        let mut setup = self.settings.clone();
+        // Tournament matches are one on one
+        setup.players.truncate(2);
+        for (player, name) in setup.players.iter_mut().zip(next.players) {
+            player.name = name;
+        }
+        // Every entrant is a person at the board
+        setup.computer = None;
+        Some(setup)
+    }
+}
+
+/// The tournament being played, if any
+#[derive(Resource, Default)]
+pub struct ActiveTournament(pub Option<Tournament>);
+
+/// Record the result of each tournament match once it is decided, and set
+/// up the next one when going back to the setup screen
+pub fn run_tournament(
+    mut state: ResMut<GameState>,
+    mut tournament: ResMut<ActiveTournament>,
+    mut recorded: Local<bool>,
+) {
+    let Some(current) = &mut tournament.0 else {
+        return;
+    };
+    if let Some(finished) = state.finished_state() {
+        if !*recorded {
+            current.record(&finished.winner_name);
+            *recorded = true;
+        }
+        return;
+    }
+    *recorded = false;
+    let Some(setup_state) = state.setup_state() else {
+        return;
+    };
+    if let Some(next) = current.next_setup()
+        && (setup_state.players.iter().map(|i| &i.name))
+            .ne(next.players.iter().map(|i| &i.name))
+    {
+        state.set_setup(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn setup() -> SetupPhase {
+        GameState::default().setup_state().unwrap().clone()
+    }
+
+    #[test]
+    fn test_tournament() {
+        let mut rng = StdRng::seed_from_u64(1961);
+        let names = ["a", "b", "c", "d", "e"].map(String::from).to_vec();
+        let alone = names[..1].to_vec();
+        assert!(Tournament::new(&mut rng, alone, setup()).is_none());
+        let mut tournament =
+            Tournament::new(&mut rng, names.clone(), setup()).unwrap();
+        // 5 players fill a bracket of 8, with 3 byes
+        assert_eq!(tournament.rounds().len(), 4);
+        assert_eq!(tournament.rounds()[1].iter().flatten().count(), 3);
+        let mut matches = 0;
+        while let Some(next) = tournament.next_match() {
+            assert_eq!(
+                tournament.next_setup().unwrap().players[0].name,
+                next.players[0]
+            );
+            // Recording somebody who isn't playing changes nothing
+            tournament.record("nobody");
+            assert_eq!(tournament.next_match(), Some(next.clone()));
+            tournament.record(&next.players[0]);
+            matches += 1;
+        }
+        // Every match knocks out one player
+        assert_eq!(matches, names.len() - 1);
+        assert!(
+            names
+                .iter()
+                .any(|i| Some(i.as_str()) == tournament.champion())
+        );
+    }
+}
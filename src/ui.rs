@@ -1,10 +1,143 @@
 use super::StartPlaying;
+use crate::challenges::{Challenges, IsoWeek, badge, calendar, weekly_puzzles};
+use crate::correspondence::{GAME_VERSION, TurnCodeError, TurnFile};
+use crate::drill::{DistanceBand, Drill};
+use crate::editor::{
+    EditHistory, EquationParams, NudgeScale, PALETTE, PARAM_NAMES, nudge_number,
+};
+use crate::layout::Layout;
+use crate::packs::available_packs;
+use crate::parse::{
+    ParseError, ParsedFunction, Restriction, ShotType, with_decimal_points,
+};
+use crate::puzzle::{Difficulty, Puzzle};
+use crate::records::Records;
+use crate::replay::{Replay, ReplaySort, format_time};
+use crate::rng::MatchRng;
+use crate::save::{load_game, save_game};
+use crate::settings::{
+    DecimalComma, DisplaySettings, PanelLayout, Settings, WindowModeSetting,
+};
+use crate::summary::MatchSummary;
+use crate::systems::input_window::InputWindow;
+use crate::systems::kiosk::KioskMode;
+use crate::systems::overlay::OverlayMode;
+use crate::tournament::{ActiveTournament, MAX_ENTRANTS, Tournament};
 use crate::{StartGraphingEvent, models::*};
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy_egui::{
     EguiContexts,
     egui::{self, RichText},
 };
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::num::{NonZeroU8, NonZeroU32};
+use std::time::Duration;
+
+/// State of the UI that isn't part of the game itself
+#[derive(Resource, Default)]
+pub struct UiState {
+    pub settings_open: bool,
+    pub records_open: bool,
+    pub challenges_open: bool,
+    pub tournament_open: bool,
+    pub replays_open: bool,
+    /// Names typed in for the next tournament
+    entrants: Vec<String>,
+    /// This week's challenge puzzles, generated once each time the
+    /// challenges open
+    weekly_puzzles: Option<Vec<Puzzle>>,
+    /// For each player, the soldier whose draft is shown in the equation
+    /// editor if it isn't the active one
+    draft_tabs: [Option<SoldierId>; super::MAX_PLAYERS],
+    /// Undo history of each draft equation, by soldier and slot
+    edit_histories: HashMap<(SoldierId, usize), EditHistory>,
+    /// The equation being edited, parsed once for all that is shown about it
+    parsed_equation: ParsedEquation,
+    /// Installed asset packs, scanned once each time the settings open
+    available_packs: Option<Vec<(String, String)>>,
+    /// Puzzle sharing code typed into the setup screen, and why it couldn't
+    /// be loaded if it couldn't
+    puzzle_code: String,
+    puzzle_code_error: Option<String>,
+    /// Turn code of a correspondence match typed in, and why it couldn't be
+    /// imported if it couldn't
+    turn_code: String,
+    turn_code_error: Option<String>,
+    /// Name of the layout file typed in, and why it couldn't be loaded if it
+    /// couldn't
+    layout_file: String,
+    layout_error: Option<String>,
+    /// Where the summary image of the finished match was saved, or why it
+    /// couldn't be
+    summary_saved: Option<String>,
+    /// Name of the replay file typed in, and why it couldn't be loaded if it
+    /// couldn't
+    replay_file: String,
+    replay_error: Option<String>,
+    /// Where the replay of the finished match was saved, or why it couldn't
+    /// be
+    replay_saved: Option<String>,
+    /// Saved replays listed in the replay browser, read each time it opens
+    saved_replays: Option<Vec<(String, Replay)>>,
+    /// Text the listed replays are filtered by, and the order they are in
+    replay_filter: String,
+    replay_sort: ReplaySort,
+    /// Saved replay the library asks about deleting
+    replay_deleting: Option<String>,
+    /// Where the library last exported a GIF to, or why it couldn't
+    replay_exported: Option<String>,
+    /// Replay whose turns are looked through
+    analyzed_replay: Option<Replay>,
+    /// Accuracy drill being played, the equation typed into it, why the
+    /// last one couldn't be shot and the last best time it set, if any
+    drill: Option<Drill>,
+    drill_equation: String,
+    drill_error: Option<String>,
+    drill_best: Option<String>,
+    /// Name of the saved game file typed in, and why it couldn't be loaded
+    /// if it couldn't
+    save_file: String,
+    save_error: Option<String>,
+    /// Saved game loaded to be resumed in place of starting a new match
+    pub resume: Option<Box<PlayPhase>>,
+    /// Where the match being played was saved, or why it couldn't be
+    game_saved: Option<String>,
+    /// Why the match couldn't be started the last time Start was pressed
+    pub setup_error: Option<SetupError>,
+}
+
+/// An equation along with what it parses into, or why it doesn't, so it is
+/// only parsed again once it changes
+#[derive(Default)]
+struct ParsedEquation {
+    equation: String,
+    function: Option<ParsedFunction>,
+    error: Option<ParseError>,
+}
+
+impl ParsedEquation {
+    fn update(&mut self, equation: &str) {
+        if self.equation != equation {
+            self.equation = equation.to_string();
+            (self.function, self.error) = match equation.parse() {
+                Ok(function) => (Some(function), None),
+                Err(e) => (None, Some(e)),
+            };
+        }
+    }
+
+    fn get(&mut self, equation: &str) -> Option<&ParsedFunction> {
+        self.update(equation);
+        self.function.as_ref()
+    }
+
+    fn error(&mut self, equation: &str) -> Option<&ParseError> {
+        self.update(equation);
+        self.error.as_ref()
+    }
+}
 
 /// Render the UI (run each frame on the Update schedule) and handle user
 /// interactions with the UI. This sends events for major state transitions
@@ -13,34 +146,611 @@ pub fn ui_system(
     mut contexts: EguiContexts,
     mut state: ResMut<GameState>,
     start_playing_events: EventWriter<StartPlaying>,
-    gizmos: Gizmos,
     start_graphing_events: EventWriter<StartGraphingEvent>,
+    mut resources: UiResources,
 ) {
+    settings_ui(
+        contexts.ctx_mut(),
+        &mut resources.ui_state,
+        &mut resources.settings,
+    );
+    records_ui(
+        contexts.ctx_mut(),
+        &mut resources.ui_state,
+        &resources.records,
+    );
+    // The input window's context isn't there until the frame after the
+    // window is opened
+    let input_context = resources
+        .input_window
+        .as_ref()
+        .and_then(|window| contexts.try_ctx_for_entity_mut(**window).cloned());
     match state.game_phase() {
         GamePhaseNoData::Setup => {
-            setup_ui(contexts.ctx_mut(), &mut state, start_playing_events)
+            resources.ui_state.edit_histories.clear();
+            resources.ui_state.game_saved = None;
+            setup_ui(
+                contexts.ctx_mut(),
+                &mut state,
+                &mut resources.ui_state,
+                &resources.challenges,
+                &mut resources.tournament,
+                *resources.kiosk,
+                start_playing_events,
+            );
+            // Drills are played by player 1, whose records they count for
+            if let Some(setup_state) = state.setup_state() {
+                drill_ui(
+                    contexts.ctx_mut(),
+                    &mut resources.ui_state,
+                    &mut resources.records,
+                    &setup_state.players[0].name,
+                );
+            }
         }
         GamePhaseNoData::Playing => play_ui(
             contexts.ctx_mut(),
             &mut state,
-            gizmos,
             start_graphing_events,
+            &mut resources,
+            input_context.as_ref(),
         ),
         GamePhaseNoData::GameFinished => {
-            finished_ui(contexts.ctx_mut(), &mut state)
+            let ui_state = &mut *resources.ui_state;
+            finished_ui(
+                contexts.ctx_mut(),
+                &mut state,
+                &resources.tournament,
+                &resources.summary,
+                &mut ui_state.summary_saved,
+                &mut ui_state.replay_saved,
+            )
+        }
+    };
+}
+
+#[derive(SystemParam)]
+pub struct UiResources<'w, 's> {
+    ui_state: ResMut<'w, UiState>,
+    settings: ResMut<'w, Settings>,
+    input_window: Option<Single<'w, Entity, With<InputWindow>>>,
+    params: ResMut<'w, EquationParams>,
+    records: ResMut<'w, Records>,
+    challenges: Res<'w, Challenges>,
+    tournament: ResMut<'w, ActiveTournament>,
+    summary: Res<'w, MatchSummary>,
+    overlay: Res<'w, OverlayMode>,
+    kiosk: Res<'w, KioskMode>,
+    _phantom_data: PhantomData<&'s ()>,
+}
+
+fn settings_ui(
+    context: &bevy_egui::egui::Context,
+    ui_state: &mut UiState,
+    settings: &mut ResMut<Settings>,
+) {
+    if !ui_state.settings_open {
+        ui_state.available_packs = None;
+        return;
+    }
+    let packs = ui_state
+        .available_packs
+        .get_or_insert_with(available_packs)
+        .clone();
+    let mut edited = Settings::clone(settings);
+    egui::Window::new("Settings")
+        .open(&mut ui_state.settings_open)
+        .collapsible(false)
+        .show(context, |ui| {
+            let selected = match &edited.asset_pack {
+                Some(id) => id.clone(),
+                None => "Default".to_string(),
+            };
+            egui::ComboBox::from_label("Asset pack")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut edited.asset_pack,
+                        None,
+                        "Default",
+                    );
+                    for (id, name) in packs {
+                        ui.selectable_value(
+                            &mut edited.asset_pack,
+                            Some(id),
+                            name,
+                        );
+                    }
+                });
+            ui.checkbox(&mut edited.cinematic_shots, "Slow motion before hits");
+            ui.checkbox(&mut edited.evaluation_bar, "Show evaluation bar");
+            ui.checkbox(
+                &mut edited.threat_overlay,
+                "Shade where enemies can shoot straight",
+            );
+            ui.checkbox(&mut edited.pip_enabled, "Zoomed inset while aiming");
+            ui.add_enabled(
+                edited.pip_enabled,
+                egui::Checkbox::new(
+                    &mut edited.pip_enemy,
+                    "Include nearest enemy in inset",
+                ),
+            );
+            ui.checkbox(&mut edited.mute_emotes, "Mute emotes and chat");
+            ui.checkbox(
+                &mut edited.timer_beeps,
+                "Beep when the turn is running out",
+            );
+            ui.checkbox(
+                &mut edited.palette_input,
+                "Build equations from a palette of keys",
+            );
+            ui.checkbox(
+                &mut edited.show_parse_tree,
+                "Show how equations are parsed",
+            );
+            egui::ComboBox::from_label("Decimal comma in equations")
+                .selected_text(edited.decimal_comma.name())
+                .show_ui(ui, |ui| {
+                    for option in DecimalComma::ALL {
+                        ui.selectable_value(
+                            &mut edited.decimal_comma,
+                            option,
+                            option.name(),
+                        );
+                    }
+                });
+            ui.checkbox(&mut edited.observer_stream, "Stream match events")
+                .on_hover_text(
+                    "Print turns, equations, hits and the winner to standard \
+                     output as JSON lines, for overlays and stats tools",
+                );
+            ui.checkbox(&mut edited.presentation_mode, "Presentation mode");
+            ui.checkbox(
+                &mut edited.input_window,
+                "Equation input in a separate window",
+            );
+            ui.label(
+                "When my turn starts while the game is in the background:",
+            );
+            let reminder = &mut edited.turn_reminder;
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut reminder.flash, "Flash the taskbar");
+                ui.checkbox(&mut reminder.chime, "Chime");
+                ui.checkbox(&mut reminder.notification, "Notify")
+                    .on_hover_text(
+                        "Show a desktop notification. Uses notify-send on \
+                         Linux and isn't available on Windows.",
+                    );
+            });
+            ui.separator();
+            display_settings_ui(ui, &mut edited.display);
+            ui.separator();
+            ui.label(RichText::new("Accessibility").strong());
+            ui.checkbox(
+                &mut edited.accessibility.reduced_motion,
+                "Reduced motion",
+            );
+            ui.checkbox(
+                &mut edited.accessibility.announcements,
+                "Announce turns, hits and the winner",
+            );
+            ui.add_enabled(
+                edited.accessibility.announcements,
+                egui::Checkbox::new(
+                    &mut edited.accessibility.text_to_speech,
+                    "Read announcements aloud",
+                ),
+            );
+            ui.checkbox(
+                &mut edited.accessibility.high_contrast,
+                "High contrast with patterned teams",
+            );
+        });
+    // Only touch the resource on an actual edit so that change detection can
+    // be used to save the settings
+    if edited != **settings {
+        **settings = edited;
+    }
+}
+
+fn display_settings_ui(ui: &mut egui::Ui, display: &mut DisplaySettings) {
+    egui::ComboBox::from_label("Window mode")
+        .selected_text(display.mode.name())
+        .show_ui(ui, |ui| {
+            for option in WindowModeSetting::ALL {
+                ui.selectable_value(&mut display.mode, option, option.name());
+            }
+        });
+    let (width, height) = display.resolution;
+    ui.add_enabled_ui(display.mode == WindowModeSetting::Windowed, |ui| {
+        egui::ComboBox::from_label("Resolution")
+            .selected_text(format!("{width}×{height}"))
+            .show_ui(ui, |ui| {
+                for (width, height) in super::RESOLUTIONS {
+                    ui.selectable_value(
+                        &mut display.resolution,
+                        (width, height),
+                        format!("{width}×{height}"),
+                    );
+                }
+            });
+    });
+    ui.checkbox(&mut display.vsync, "VSync");
+    ui.checkbox(&mut display.battery_saver, "Battery saver")
+        .on_hover_text(format!(
+            "Drop to {} fps while nothing is moving, such as while players \
+             think about their equations",
+            super::BATTERY_SAVER_FRAME_CAP
+        ));
+    ui.horizontal(|ui| {
+        let mut capped = display.frame_cap.is_some();
+        ui.checkbox(&mut capped, "Limit frame rate");
+        let mut cap = display.frame_cap.unwrap_or(60);
+        ui.add_enabled(
+            capped,
+            egui::widgets::DragValue::new(&mut cap)
+                .range(super::MIN_FRAME_CAP..=360)
+                .suffix(" fps"),
+        );
+        display.frame_cap = capped.then_some(cap);
+    });
+}
+
+fn records_ui(
+    context: &bevy_egui::egui::Context,
+    ui_state: &mut UiState,
+    records: &Records,
+) {
+    egui::Window::new("Records")
+        .open(&mut ui_state.records_open)
+        .collapsible(false)
+        .show(context, |ui| {
+            if records.players.is_empty() {
+                ui.label("No shots have landed yet");
+                return;
+            }
+            egui::Grid::new("records_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("Player");
+                    ui.strong("Longest shot");
+                    ui.strong("Longest hit");
+                    ui.end_row();
+                    for (name, player) in &records.players {
+                        ui.label(name);
+                        ui.label(format!("{:.1}", player.longest_shot));
+                        ui.label(format!("{:.1}", player.longest_hit));
+                        ui.end_row();
+                    }
+                });
+        });
+}
+
+/// Pick the difficulty of a generated puzzle, or load a shared one
+fn puzzle_setup_ui(
+    ui: &mut egui::Ui,
+    setup_state: &mut SetupPhase,
+    ui_state: &mut UiState,
+) {
+    if let Some(puzzle) = &setup_state.custom_puzzle {
+        ui.label(format!(
+            "Playing a shared {} puzzle",
+            puzzle.difficulty().name().to_lowercase()
+        ));
+        if ui.button("Generate puzzles instead").clicked() {
+            setup_state.custom_puzzle = None;
+        }
+        return;
+    }
+    egui::ComboBox::from_label("Difficulty")
+        .selected_text(setup_state.puzzle_difficulty.name())
+        .show_ui(ui, |ui| {
+            for difficulty in Difficulty::ALL {
+                ui.selectable_value(
+                    &mut setup_state.puzzle_difficulty,
+                    difficulty,
+                    difficulty.name(),
+                );
+            }
+        });
+    ui.horizontal(|ui| {
+        ui.label("Puzzle code:");
+        ui.text_edit_singleline(&mut ui_state.puzzle_code);
+        if ui.button("Load").clicked() {
+            match Puzzle::from_code(&ui_state.puzzle_code) {
+                Ok(puzzle) => {
+                    setup_state.custom_puzzle = Some(puzzle);
+                    ui_state.puzzle_code_error = None;
+                }
+                Err(e) => ui_state.puzzle_code_error = Some(e.to_string()),
+            }
+        }
+    });
+    if let Some(error) = &ui_state.puzzle_code_error {
+        ui.colored_label(egui::Color32::RED, error);
+    }
+    if let Some(layout) = layout_file_ui(ui, ui_state) {
+        match layout.puzzle() {
+            Ok(puzzle) => setup_state.custom_puzzle = Some(puzzle),
+            Err(e) => ui_state.layout_error = Some(e.to_string()),
+        }
+    }
+    if let Some(error) = &ui_state.layout_error {
+        ui.colored_label(egui::Color32::RED, error);
+    }
+}
+
+/// Play on a board loaded from a layout file instead of a random one
+fn layout_setup_ui(
+    ui: &mut egui::Ui,
+    setup_state: &mut SetupPhase,
+    ui_state: &mut UiState,
+) {
+    if setup_state.layout.is_some() {
+        ui.label(format!("Playing on the layout {}", ui_state.layout_file));
+        if ui.button("Random board instead").clicked() {
+            setup_state.layout = None;
+        }
+        return;
+    }
+    if let Some(layout) = layout_file_ui(ui, ui_state) {
+        setup_state.layout = Some(Box::new(layout));
+    }
+    if let Some(error) = &ui_state.layout_error {
+        ui.colored_label(egui::Color32::RED, error);
+    }
+}
+
+/// Load the replay to watch, and show whose match it is
+fn replay_setup_ui(
+    ui: &mut egui::Ui,
+    setup_state: &mut SetupPhase,
+    ui_state: &mut UiState,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Replay file:");
+        ui.text_edit_singleline(&mut ui_state.replay_file);
+        if ui.button("Load").clicked() {
+            match Replay::load(&ui_state.replay_file) {
+                Ok(replay) => {
+                    setup_state.replay = Some(replay.into());
+                    ui_state.replay_error = None;
+                }
+                Err(e) => ui_state.replay_error = Some(e),
+            }
         }
+    });
+    if let Some(error) = &ui_state.replay_error {
+        ui.colored_label(egui::Color32::RED, error);
+    }
+    let Some(replay) = &setup_state.replay else {
+        return;
     };
+    let names = (replay.header.setup.players.iter())
+        .map(|i| i.name.as_str())
+        .collect::<Vec<_>>();
+    ui.label(format!(
+        "{}, {} turns",
+        names.join(" vs. "),
+        replay.turns.len()
+    ));
+    if replay.header.version != GAME_VERSION {
+        ui.colored_label(
+            egui::Color32::ORANGE,
+            format!(
+                "Recorded by version {} of the game, so it may play out \
+                 differently",
+                replay.header.version
+            ),
+        );
+    }
+}
+
+/// Field for the name of a layout file, returning the layout when it is
+/// loaded
+fn layout_file_ui(ui: &mut egui::Ui, ui_state: &mut UiState) -> Option<Layout> {
+    let mut loaded = None;
+    ui.horizontal(|ui| {
+        ui.label("Layout file:");
+        ui.text_edit_singleline(&mut ui_state.layout_file);
+        if ui.button("Load").clicked() {
+            match Layout::load(&ui_state.layout_file) {
+                Ok(layout) => {
+                    loaded = Some(layout);
+                    ui_state.layout_error = None;
+                }
+                Err(e) => ui_state.layout_error = Some(e.to_string()),
+            }
+        }
+    });
+    loaded
+}
+
+fn correspondence_setup_ui(
+    ui: &mut egui::Ui,
+    setup_state: &mut SetupPhase,
+    ui_state: &mut UiState,
+) {
+    if let Some(turn) = &setup_state.join_turn {
+        let [player_1, player_2] = &turn.board.names;
+        ui.label(format!("Joining {player_1} and {player_2}'s match"));
+        if ui.button("Start a new match instead").clicked() {
+            setup_state.join_turn = None;
+        }
+        return;
+    }
+    ui.label("Start a new match, or paste your opponent's first turn code");
+    ui.horizontal(|ui| {
+        ui.label("Turn code:");
+        ui.text_edit_singleline(&mut ui_state.turn_code);
+        if ui.button("Join").clicked() {
+            match TurnFile::from_code(&ui_state.turn_code) {
+                Ok(turn) => {
+                    setup_state.join_turn = Some(turn);
+                    ui_state.turn_code.clear();
+                    ui_state.turn_code_error = None;
+                }
+                Err(e) => ui_state.turn_code_error = Some(e.to_string()),
+            }
+        }
+    });
+    if let Some(error) = &ui_state.turn_code_error {
+        ui.colored_label(egui::Color32::RED, error);
+    }
+}
+
+/// During the opponent's turn of a correspondence match, show the code of
+/// the last turn taken here to send them, and take the code of their reply
+fn correspondence_ui(
+    context: &egui::Context,
+    playing_state: &mut PlayPhase,
+    turn_code: &mut String,
+    turn_code_error: &mut Option<String>,
+) {
+    let Some(correspondence) = playing_state.correspondence() else {
+        return;
+    };
+    let outgoing = correspondence.outgoing.clone();
+    let rejected = (correspondence.rejected.as_ref()).map(ToString::to_string);
+    let opponent = playing_state.current_player().name.clone();
+    // Network matches send and receive the codes themselves
+    if correspondence.networked {
+        egui::Window::new("Network match")
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0., -20.))
+            .collapsible(false)
+            .resizable(false)
+            .show(context, |ui| {
+                ui.label(format!("Waiting for {opponent}'s turn"));
+                if let Some(error) = rejected {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+        return;
+    }
+    egui::Window::new("Correspondence")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0., -20.))
+        .collapsible(false)
+        .resizable(false)
+        .show(context, |ui| {
+            if let Some(code) = outgoing {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Send this code to {opponent}:"));
+                    ui.monospace(&code);
+                    if ui.button("Copy").clicked() {
+                        ui.ctx().copy_text(code.clone());
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.label(format!("{opponent}'s turn code:"));
+                ui.text_edit_singleline(turn_code);
+                if ui.button("Import").clicked() {
+                    match import_turn(playing_state, turn_code) {
+                        Ok(()) => {
+                            turn_code.clear();
+                            *turn_code_error = None;
+                        }
+                        Err(e) => *turn_code_error = Some(e.to_string()),
+                    }
+                }
+            });
+            if let Some(error) = turn_code_error.as_ref().or(rejected.as_ref())
+            {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+}
+
+/// Check that `code` is the opponent's next turn, with a shot they were
+/// allowed to take, and queue it up to be replayed
+fn import_turn(
+    playing_state: &mut PlayPhase,
+    code: &str,
+) -> Result<(), TurnCodeError> {
+    let turn = TurnFile::from_code(code)?;
+    if let Some(correspondence) = playing_state.correspondence() {
+        turn.check(playing_state, correspondence)?;
+    }
+    if let Some(correspondence) = playing_state.correspondence_mut() {
+        correspondence.incoming = Some(turn);
+    }
+    Ok(())
+}
+
+/// List where the match's randomness came from, so that a disputed roll can
+/// be checked against a replay or the other player's copy of the match
+fn rng_audit_ui(ui: &mut egui::Ui, rng: &MatchRng) {
+    egui::CollapsingHeader::new("Random draws").show(ui, |ui| {
+        ui.monospace(format!("Match seed {:016x}", rng.seed()));
+        egui::ScrollArea::vertical()
+            .max_height(120.)
+            .show(ui, |ui| {
+                for draw in rng.log() {
+                    ui.monospace(format!(
+                        "Turn {}: {} {:016x}",
+                        draw.turn_number,
+                        draw.stream.name(),
+                        draw.seed
+                    ));
+                }
+            });
+    });
+}
+
+/// Show the sharing code of a puzzle, with a button to copy it
+fn puzzle_code_ui(ui: &mut egui::Ui, puzzle: &Puzzle) {
+    let code = puzzle.code();
+    ui.horizontal(|ui| {
+        ui.label("Share this puzzle:");
+        ui.monospace(&code);
+        if ui.button("Copy").clicked() {
+            ui.ctx().copy_text(code.clone());
+        }
+    });
+}
+
+/// Number of players of a versus match, more than two making it a
+/// free-for-all
+fn player_count_ui(ui: &mut egui::Ui, players: &mut Vec<PlayerConfig>) {
+    let mut count = players.len();
+    ui.horizontal(|ui| {
+        ui.label("Players:");
+        ui.add(
+            egui::widgets::DragValue::new(&mut count)
+                .range(2..=super::MAX_PLAYERS),
+        );
+    });
+    if count != players.len() {
+        let configs = (players.len() + 1..=count).map(PlayerConfig::new);
+        players.extend(configs);
+        players.truncate(count);
+    }
+}
+
+/// Let the computer play as player 2, at a chosen difficulty
+fn computer_setup_ui(ui: &mut egui::Ui, computer: &mut Option<u8>) {
+    let mut enabled = computer.is_some();
+    if ui.checkbox(&mut enabled, "Computer").changed() {
+        *computer = enabled.then_some(super::DEFAULT_AI_LEVEL);
+    }
+    if let Some(level) = computer {
+        ui.add(
+            egui::Slider::new(level, 1..=super::MAX_AI_LEVEL)
+                .text("Difficulty"),
+        );
+    }
 }
 
 fn setup_ui(
     context: &bevy_egui::egui::Context,
     state: &mut GameState,
+    ui_state: &mut UiState,
+    challenges: &Challenges,
+    tournament: &mut ActiveTournament,
+    kiosk: KioskMode,
     mut start_playing_events: EventWriter<StartPlaying>,
 ) {
-    #[cfg(debug_assertions)]
-    const MIN_SECONDS: usize = 2;
-    #[cfg(not(debug_assertions))]
-    const MIN_SECONDS: usize = 20;
     if state.setup_state().is_none() {
         return;
     };
@@ -50,98 +760,1730 @@ fn setup_ui(
             let Some(setup_state) = state.setup_state_mut() else {
                 return;
             };
-            ui.label(RichText::new("Player 1").heading());
-            ui.label("Starting soldiers:");
-            ui.add(
-                egui::widgets::DragValue::new(
-                    &mut setup_state.player_1.soldier_num,
-                )
-                .range(1..=4),
-            );
-            ui.label("Name:");
-            ui.text_edit_singleline(&mut setup_state.player_1.name);
+            egui::ComboBox::from_label("Mode")
+                .selected_text(setup_state.mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in MatchMode::ALL {
+                        // Turn codes are for sending to players elsewhere
+                        let remote = matches!(
+                            mode,
+                            MatchMode::Correspondence | MatchMode::Network
+                        );
+                        if kiosk.0 && remote {
+                            continue;
+                        }
+                        ui.selectable_value(
+                            &mut setup_state.mode,
+                            mode,
+                            mode.name(),
+                        );
+                    }
+                });
+            if setup_state.mode == MatchMode::Versus {
+                player_count_ui(ui, &mut setup_state.players);
+            }
+            // Layouts have two teams, so free-for-alls are played on random
+            // boards
+            let free_for_all = setup_state.mode == MatchMode::Versus
+                && setup_state.players.len() > 2;
+            match setup_state.mode {
+                MatchMode::Versus if free_for_all => {}
+                MatchMode::Versus => layout_setup_ui(ui, setup_state, ui_state),
+                MatchMode::Puzzle => puzzle_setup_ui(ui, setup_state, ui_state),
+                MatchMode::Correspondence => {
+                    correspondence_setup_ui(ui, setup_state, ui_state)
+                }
+                MatchMode::Replay => replay_setup_ui(ui, setup_state, ui_state),
+                _ => {}
+            }
+            // Layouts place the soldiers and obstacles themselves
+            let on_layout = setup_state.mode == MatchMode::Versus
+                && setup_state.layout.is_some()
+                && !free_for_all;
+            // Puzzle targets aren't a player to set up, only versus matches
+            // have more than two players, and replays bring their own
+            let shown = match setup_state.mode {
+                MatchMode::Versus => setup_state.players.len(),
+                MatchMode::Puzzle => 1,
+                MatchMode::Replay => 0,
+                _ => 2,
+            };
+            for (index, player) in
+                setup_state.players.iter_mut().enumerate().take(shown)
+            {
+                ui.separator();
+                let heading = match (setup_state.mode, index) {
+                    (MatchMode::Horde, 1) => "Teammate".to_string(),
+                    _ => format!("Player {}", index + 1),
+                };
+                ui.label(RichText::new(heading).heading());
+                if !on_layout {
+                    ui.label("Starting soldiers:");
+                    ui.add(
+                        egui::widgets::DragValue::new(&mut player.soldier_num)
+                            .range(1..=4),
+                    );
+                }
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut player.name);
+                skin_select(
+                    ui,
+                    &format!("p{}_skin", index + 1),
+                    &mut player.skin,
+                );
+                // Only players sharing this machine need controls of their
+                // own
+                if matches!(
+                    setup_state.mode,
+                    MatchMode::Versus | MatchMode::Horde
+                ) {
+                    input_select(
+                        ui,
+                        &format!("p{}_input", index + 1),
+                        &mut player.input,
+                    );
+                }
+                if setup_state.mode == MatchMode::Versus && index == 1 {
+                    computer_setup_ui(ui, &mut setup_state.computer);
+                }
+            }
+
+            // Replays are played by the rules they were recorded with
+            if setup_state.mode != MatchMode::Replay {
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Seconds per turn:");
+                    ui.add(
+                        egui::widgets::DragValue::new(
+                            &mut setup_state.turn_seconds,
+                        )
+                        .range(
+                            super::MIN_TURN_SECONDS..=super::MAX_TURN_SECONDS,
+                        ),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    if !on_layout {
+                        ui.label("Obstacles:");
+                        ui.add(
+                            egui::widgets::DragValue::new(
+                                &mut setup_state.obstacles,
+                            )
+                            .range(0..=super::MAX_OBSTACLES),
+                        );
+                    }
+                    ui.label("Gravity wells:");
+                    ui.add(
+                        egui::widgets::DragValue::new(
+                            &mut setup_state.gravity_wells,
+                        )
+                        .range(0..=super::MAX_GRAVITY_WELLS),
+                    );
+                });
+                ui.checkbox(
+                    &mut setup_state.roulette,
+                    "Mutator roulette (new mutators every round)",
+                );
+                ui.horizontal(|ui| {
+                    let mut limited = setup_state.max_turns.is_some();
+                    ui.checkbox(&mut limited, "Turn limit");
+                    let mut turns = setup_state
+                        .max_turns
+                        .map_or(super::DEFAULT_MAX_TURNS, NonZeroU32::get);
+                    ui.add_enabled(
+                        limited,
+                        egui::widgets::DragValue::new(&mut turns)
+                            .range(2..=200),
+                    );
+                    setup_state.max_turns =
+                        limited.then(|| NonZeroU32::new(turns)).flatten();
+                });
+                if setup_state.mode == MatchMode::Network {
+                    ui.horizontal(|ui| {
+                        let mut limited = setup_state.idle_forfeit.is_some();
+                        ui.checkbox(&mut limited, "Forfeit after missed turns")
+                            .on_hover_text(
+                                "Turns that run out are skipped, and a player \
+                             who misses this many in a row forfeits",
+                            );
+                        let mut turns = setup_state.idle_forfeit.map_or(
+                            super::DEFAULT_IDLE_FORFEIT_TURNS,
+                            NonZeroU8::get,
+                        );
+                        ui.add_enabled(
+                            limited,
+                            egui::widgets::DragValue::new(&mut turns)
+                                .range(1..=10),
+                        );
+                        setup_state.idle_forfeit =
+                            limited.then(|| NonZeroU8::new(turns)).flatten();
+                    });
+                }
+                ui.horizontal(|ui| {
+                    let mut limited = setup_state.budget.is_some();
+                    ui.checkbox(&mut limited, "Equation budget").on_hover_text(
+                        "Numbers, x and operators cost a point, powers two and \
+                     functions three",
+                    );
+                    let mut points = setup_state
+                        .budget
+                        .unwrap_or(super::DEFAULT_EQUATION_BUDGET);
+                    ui.add_enabled(
+                        limited,
+                        egui::widgets::DragValue::new(&mut points)
+                            .range(3..=100)
+                            .suffix(" points"),
+                    );
+                    setup_state.budget = limited.then_some(points);
+                });
+                ui.horizontal(|ui| {
+                    let mut limited = setup_state.max_shot_length.is_some();
+                    ui.checkbox(&mut limited, "Shot range").on_hover_text(
+                        "Shots fizzle once they have travelled this far along \
+                     their path. The board is 20 units across.",
+                    );
+                    let mut length = setup_state
+                        .max_shot_length
+                        .unwrap_or(super::DEFAULT_MAX_SHOT_LENGTH);
+                    ui.add_enabled(
+                        limited,
+                        egui::widgets::DragValue::new(&mut length)
+                            .range(5..=200)
+                            .suffix(" units"),
+                    );
+                    setup_state.max_shot_length = limited.then_some(length);
+                });
+                ui.add_enabled(
+                    setup_state.max_shot_length.is_some(),
+                    egui::Checkbox::new(
+                        &mut setup_state.energy,
+                        "Bank unused range as energy for longer shots",
+                    ),
+                );
+                ui.horizontal(|ui| {
+                    let mut limited = setup_state.cooldown.is_some();
+                    ui.checkbox(&mut limited, "No repeats").on_hover_text(
+                    "Players can't shoot an equation they shot in their last \
+                     few turns",
+                );
+                    let mut turns = setup_state.cooldown.map_or(
+                        super::DEFAULT_EQUATION_COOLDOWN,
+                        NonZeroU8::get,
+                    );
+                    ui.add_enabled(
+                        limited,
+                        egui::widgets::DragValue::new(&mut turns)
+                            .range(1..=10)
+                            .suffix(" turns"),
+                    );
+                    setup_state.cooldown =
+                        limited.then(|| NonZeroU8::new(turns)).flatten();
+                });
+                ui.horizontal(|ui| {
+                    let mut blitz = setup_state.blitz.is_some();
+                    ui.checkbox(&mut blitz, "Blitz")
+                        .on_hover_text("Shorten the turns after every round");
+                    let mut settings = setup_state.blitz.unwrap_or_default();
+                    ui.add_enabled_ui(blitz, |ui| {
+                        ui.add(
+                            egui::widgets::DragValue::new(
+                                &mut settings.step_seconds,
+                            )
+                            .range(1..=60)
+                            .prefix("-")
+                            .suffix(" s/round"),
+                        );
+                        ui.label("down to");
+                        ui.add(
+                            egui::widgets::DragValue::new(
+                                &mut settings.floor_seconds,
+                            )
+                            .range(
+                                super::MIN_TURN_SECONDS
+                                    ..=super::MAX_TURN_SECONDS,
+                            )
+                            .suffix(" s"),
+                        );
+                    });
+                    setup_state.blitz = blitz.then_some(settings);
+                });
+                restriction_select(ui, &mut setup_state.restriction);
+                ui.checkbox(
+                    &mut setup_state.practice,
+                    "Practice (preview shots while typing)",
+                );
+                ui.add_enabled(
+                    !setup_state.practice,
+                    egui::Checkbox::new(
+                        &mut setup_state.aim_preview,
+                        "Aim preview (the start of each shot)",
+                    ),
+                );
+            }
+            ui.horizontal(|ui| {
+                if ui.button(RichText::new("Start").size(20.)).clicked() {
+                    start_playing_events.send(StartPlaying);
+                }
+                if let Some(error) = &ui_state.setup_error {
+                    ui.colored_label(egui::Color32::RED, error.to_string());
+                }
+            });
+            if !kiosk.0 {
+                ui.horizontal(|ui| {
+                    ui.label("Saved game:");
+                    ui.text_edit_singleline(&mut ui_state.save_file);
+                    if ui.button("Load game").clicked() {
+                        match load_game(&ui_state.save_file) {
+                            Ok(game) => {
+                                ui_state.resume = Some(game);
+                                ui_state.save_error = None;
+                                start_playing_events.send(StartPlaying);
+                            }
+                            Err(e) => ui_state.save_error = Some(e),
+                        }
+                    }
+                });
+                if let Some(error) = &ui_state.save_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            }
             ui.separator();
-            ui.label(RichText::new("Player 2").heading());
-            ui.label("Starting soldiers:");
-            ui.add(
-                egui::widgets::DragValue::new(
-                    &mut setup_state.player_2.soldier_num,
-                )
-                .range(1..=4),
-            );
-            ui.label("Name:");
-            ui.text_edit_singleline(&mut setup_state.player_2.name);
+            if !kiosk.0 && ui.button("Settings").clicked() {
+                ui_state.settings_open = true;
+            }
+            if ui.button("Records").clicked() {
+                ui_state.records_open = true;
+            }
+            if ui.button("Weekly challenges").clicked() {
+                ui_state.challenges_open = true;
+            }
+            if ui.button("Tournament").clicked() {
+                ui_state.tournament_open = true;
+            }
+            if ui.button("Replays").clicked() {
+                ui_state.replays_open = true;
+            }
+            if ui.button("Accuracy drill").clicked() {
+                let now = ui.input(|i| i.time);
+                ui_state.drill = Some(Drill::new(&mut rand::thread_rng(), now));
+                ui_state.drill_error = None;
+                ui_state.drill_best = None;
+            }
+        },
+    );
+    if let Some(setup_state) = state.setup_state() {
+        tournament_ui(context, ui_state, tournament, setup_state);
+    }
+    if let Some(setup_state) = state.setup_state_mut()
+        && challenges_ui(context, ui_state, challenges, setup_state)
+    {
+        start_playing_events.send(StartPlaying);
+    }
+    if let Some(setup_state) = state.setup_state_mut() {
+        replays_ui(context, ui_state, setup_state);
+    }
+    replay_analysis_ui(context, ui_state);
+}
 
+/// An accuracy drill: the board with its target, the time spent on the
+/// round, and the player's best times once it is over
+fn drill_ui(
+    context: &bevy_egui::egui::Context,
+    ui_state: &mut UiState,
+    records: &mut ResMut<Records>,
+    player: &str,
+) {
+    let UiState {
+        drill: Some(drill),
+        drill_equation: equation,
+        drill_error: error,
+        drill_best: best,
+        ..
+    } = ui_state
+    else {
+        return;
+    };
+    let now = context.input(|i| i.time);
+    let mut open = true;
+    let mut restart = false;
+    egui::Window::new("Accuracy drill")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(context, |ui| {
+            if drill.is_over() {
+                ui.label(format!(
+                    "{} targets hit in {:.1} s",
+                    super::DRILL_ROUNDS,
+                    drill.total_seconds()
+                ));
+            } else {
+                ui.label(format!(
+                    "Round {} of {}: {} target, {:.1} s",
+                    drill.round(),
+                    super::DRILL_ROUNDS,
+                    drill.band().name().to_lowercase(),
+                    drill.elapsed(now)
+                ));
+                // Keep the round's time counting up
+                ui.ctx().request_repaint();
+            }
+            drill_board(ui, drill);
+            if drill.is_over() {
+                drill_rounds_ui(ui, drill);
+                restart = ui.button("Start over").clicked();
+            } else {
+                ui.horizontal(|ui| {
+                    let response = ui.text_edit_singleline(equation);
+                    let entered = response.lost_focus()
+                        && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Shoot").clicked() || entered {
+                        let rng = &mut rand::thread_rng();
+                        match drill.shoot(equation, rng, now) {
+                            Ok(round) => {
+                                *error = None;
+                                if let Some(round) = round
+                                    && records.record_drill(player, &round)
+                                {
+                                    *best = Some(format!(
+                                        "New best for {} targets: {:.1} s",
+                                        round.band.name().to_lowercase(),
+                                        round.seconds
+                                    ));
+                                }
+                            }
+                            Err(e) => *error = Some(e),
+                        }
+                    }
+                });
+            }
+            if let Some(error) = error {
+                ui.colored_label(egui::Color32::RED, &*error);
+            }
+            if let Some(best) = best {
+                ui.label(&*best);
+            }
             ui.separator();
+            ui.strong(format!("{player}'s best rounds"));
+            let bests = records.players.get(player).map(|i| &i.drill_bests);
+            egui::Grid::new("drill_bests").show(ui, |ui| {
+                for band in DistanceBand::ALL {
+                    ui.label(band.name());
+                    match bests.and_then(|i| i.get(&band)) {
+                        Some(seconds) => ui.label(format!("{seconds:.1} s")),
+                        None => ui.weak("–"),
+                    };
+                    ui.end_row();
+                }
+            });
+        });
+    if !open {
+        ui_state.drill = None;
+    } else if restart {
+        ui_state.drill = Some(Drill::new(&mut rand::thread_rng(), now));
+        ui_state.drill_best = None;
+    }
+}
+
+/// Board of an accuracy drill, with the shooter, the target and the path of
+/// the latest shot
+fn drill_board(ui: &mut egui::Ui, drill: &Drill) {
+    let size = super::DRILL_BOARD_SIZE;
+    let (rect, _) =
+        ui.allocate_exact_size(egui::Vec2::splat(size), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2., color32(super::SUMMARY_BOARD_COLOR));
+    let scale = size / 20.;
+    let to_screen =
+        |point: Vec2| rect.center() + egui::vec2(point.x, -point.y) * scale;
+    let [shooter_color, target_color, ..] = super::SUMMARY_SOLDIER_COLORS;
+    if let Some(shot) = &drill.last_shot {
+        let color = if shot.hit {
+            color32(shooter_color)
+        } else {
+            egui::Color32::GRAY
+        };
+        let points = shot.points.iter().map(|&i| to_screen(i)).collect();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(2., color)));
+    }
+    let radius = super::SOLDIER_RADIUS / 20. * scale;
+    painter.circle_filled(
+        to_screen(drill.shooter),
+        radius,
+        color32(shooter_color),
+    );
+    if !drill.is_over() {
+        painter.circle_filled(
+            to_screen(drill.target),
+            radius,
+            color32(target_color),
+        );
+    }
+}
+
+/// How long each round of a finished drill took
+fn drill_rounds_ui(ui: &mut egui::Ui, drill: &Drill) {
+    egui::Grid::new("drill_rounds")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Round");
+            ui.strong("Distance");
+            ui.strong("Shots");
+            ui.strong("Time");
+            ui.end_row();
+            for (number, round) in drill.rounds.iter().enumerate() {
+                ui.label((number + 1).to_string());
+                ui.label(round.band.name());
+                ui.label(round.shots.to_string());
+                ui.label(format!("{:.1} s", round.seconds));
+                ui.end_row();
+            }
+        });
+}
+
+/// What can be done with a replay listed in the replay library
+enum ReplayAction {
+    Watch,
+    Analyze,
+    ExportGif,
+    /// Ask whether to delete it
+    AskDelete,
+    Delete,
+    Keep,
+}
+
+/// The library of saved replays, newest first unless sorted otherwise, to
+/// watch, analyze, export or delete
+fn replays_ui(
+    context: &bevy_egui::egui::Context,
+    ui_state: &mut UiState,
+    setup_state: &mut SetupPhase,
+) {
+    if !ui_state.replays_open {
+        ui_state.saved_replays = None;
+        ui_state.replay_deleting = None;
+        ui_state.replay_exported = None;
+        return;
+    }
+    let replays = ui_state.saved_replays.get_or_insert_with(Replay::saved);
+    let filter = &mut ui_state.replay_filter;
+    let sort = &mut ui_state.replay_sort;
+    let deleting = &ui_state.replay_deleting;
+    let exported = &ui_state.replay_exported;
+    let mut action = None;
+    let mut refresh = false;
+    egui::Window::new("Replays")
+        .open(&mut ui_state.replays_open)
+        .collapsible(false)
+        .show(context, |ui| {
             ui.horizontal(|ui| {
-                ui.label("Seconds per turn:");
-                ui.add(
-                    egui::widgets::DragValue::new(
-                        &mut setup_state.turn_seconds,
-                    )
-                    .range(MIN_SECONDS..=300),
+                ui.label("Filter:");
+                ui.text_edit_singleline(filter).on_hover_text(
+                    "Players, mutators or the version of the game",
                 );
+                egui::ComboBox::from_id_salt("replay_sort")
+                    .selected_text(sort.name())
+                    .show_ui(ui, |ui| {
+                        for option in ReplaySort::ALL {
+                            ui.selectable_value(sort, option, option.name());
+                        }
+                    });
+                refresh = ui.button("Refresh").clicked();
             });
-            if ui.button(RichText::new("Start").size(20.)).clicked() {
-                start_playing_events.send(StartPlaying);
+            if let Some(exported) = exported {
+                ui.label(exported);
             }
-        },
+            sort.sort(replays);
+            if replays.is_empty() {
+                ui.label("No replays have been saved yet");
+                return;
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("replays_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Final board");
+                        ui.strong("Played");
+                        ui.strong("Players");
+                        ui.strong("Winner");
+                        ui.strong("Turns");
+                        ui.strong("Mutators");
+                        ui.strong("Version");
+                        ui.end_row();
+                        for (index, (file, replay)) in
+                            replays.iter().enumerate()
+                        {
+                            if !replay.matches(filter) {
+                                continue;
+                            }
+                            replay_thumbnail(ui, replay);
+                            replay_row(ui, replay);
+                            let asked = deleting.as_ref() == Some(file);
+                            if let Some(picked) = replay_actions(ui, asked) {
+                                action = Some((index, picked));
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+    if refresh {
+        ui_state.saved_replays = None;
+    }
+    let Some((index, action)) = action else {
+        return;
+    };
+    let Some((file, replay)) = (ui_state.saved_replays.as_mut())
+        .and_then(|replays| replays.get(index))
+    else {
+        return;
+    };
+    ui_state.replay_deleting = None;
+    match action {
+        ReplayAction::Watch => {
+            setup_state.mode = MatchMode::Replay;
+            setup_state.replay = Some(replay.clone().into());
+            ui_state.replay_file = file.clone();
+            ui_state.replay_error = None;
+            ui_state.replays_open = false;
+        }
+        ReplayAction::Analyze => {
+            ui_state.analyzed_replay = Some(replay.clone());
+        }
+        ReplayAction::ExportGif => {
+            ui_state.replay_exported = Some(match replay.export_gif(file) {
+                Ok(path) => format!("Exported to {}", path.display()),
+                Err(e) => format!("Couldn't export the replay: {e}"),
+            });
+        }
+        ReplayAction::AskDelete => {
+            ui_state.replay_deleting = Some(file.clone());
+        }
+        ReplayAction::Keep => {}
+        ReplayAction::Delete => {
+            if let Err(e) = Replay::delete(file) {
+                ui_state.replay_exported =
+                    Some(format!("Couldn't delete the replay: {e}"));
+            }
+            ui_state.saved_replays = None;
+        }
+    }
+}
+
+/// Buttons for what can be done with a replay of the library, or for
+/// confirming that it should be deleted if that was `asked`
+fn replay_actions(ui: &mut egui::Ui, asked: bool) -> Option<ReplayAction> {
+    let mut action = None;
+    ui.horizontal(|ui| {
+        if asked {
+            ui.label("Delete it?");
+            if ui.button("Delete").clicked() {
+                action = Some(ReplayAction::Delete);
+            }
+            if ui.button("Keep").clicked() {
+                action = Some(ReplayAction::Keep);
+            }
+            return;
+        }
+        let buttons = [
+            ("Watch", ReplayAction::Watch),
+            ("Analyze", ReplayAction::Analyze),
+            ("Export GIF", ReplayAction::ExportGif),
+            ("Delete", ReplayAction::AskDelete),
+        ];
+        for (label, picked) in buttons {
+            if ui.button(label).clicked() {
+                action = Some(picked);
+            }
+        }
+    });
+    action
+}
+
+/// The board a replay ends on, drawn small
+fn replay_thumbnail(ui: &mut egui::Ui, replay: &Replay) {
+    let size = super::REPLAY_THUMBNAIL_SIZE;
+    let (rect, _) =
+        ui.allocate_exact_size(egui::Vec2::splat(size), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2., color32(super::SUMMARY_BOARD_COLOR));
+    let scale = size / 20.;
+    let radius = (super::SOLDIER_RADIUS / 20. * scale).max(1.5);
+    for soldier in replay.final_soldiers() {
+        let Some(&color) = super::SUMMARY_SOLDIER_COLORS.get(soldier.player)
+        else {
+            continue;
+        };
+        let center = rect.center() + egui::vec2(soldier.x, -soldier.y) * scale;
+        painter.circle_filled(center, radius, color32(color));
+    }
+}
+
+fn color32(color: Color) -> egui::Color32 {
+    let [r, g, b, a] = color.to_srgba().to_u8_array();
+    egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+}
+
+/// Every turn of a replay, and what each player did over it
+fn replay_analysis_ui(
+    context: &bevy_egui::egui::Context,
+    ui_state: &mut UiState,
+) {
+    let Some(replay) = &ui_state.analyzed_replay else {
+        return;
+    };
+    let players = &replay.header.setup.players;
+    let name = |player: usize| players.get(player).map_or("?", |i| &i.name);
+    let mut open = true;
+    egui::Window::new("Replay analysis")
+        .open(&mut open)
+        .collapsible(false)
+        .show(context, |ui| {
+            egui::Grid::new("replay_stats")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("Player");
+                    ui.strong("Shots");
+                    ui.strong("Hits");
+                    ui.strong("Destroyed");
+                    ui.strong("Turns missed");
+                    ui.end_row();
+                    for (player, stats) in replay.stats().iter().enumerate() {
+                        ui.label(name(player));
+                        ui.label(stats.shots.to_string());
+                        ui.label(stats.hits.to_string());
+                        ui.label(stats.kills.to_string());
+                        ui.label(stats.skipped.to_string());
+                        ui.end_row();
+                    }
+                });
+            ui.separator();
+            shot_types_ui(ui, replay);
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("replay_turns")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Turn");
+                        ui.strong("Player");
+                        ui.strong("Shot");
+                        ui.strong("Destroyed");
+                        ui.end_row();
+                        for turn in &replay.turns {
+                            ui.label(turn.turn_number.to_string());
+                            ui.label(format!(
+                                "{} ({})",
+                                name(turn.player),
+                                turn.soldier
+                            ));
+                            match &turn.equation {
+                                Some(equation) => ui.monospace(equation),
+                                None => ui.weak("Ran out of time"),
+                            };
+                            let destroyed = (turn.hits.iter())
+                                .map(|i| {
+                                    format!("{} ({})", name(i.player), i.number)
+                                })
+                                .collect::<Vec<_>>();
+                            ui.label(destroyed.join(", "));
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+    if !open {
+        ui_state.analyzed_replay = None;
+    }
+}
+
+/// How often each player of a replay hit with each type of shot, and the
+/// type each misses the most of
+fn shot_types_ui(ui: &mut egui::Ui, replay: &Replay) {
+    let players = &replay.header.setup.players;
+    let name = |player: usize| players.get(player).map_or("?", |i| &i.name);
+    let stats = replay.stats();
+    egui::Grid::new("shot_types").striped(true).show(ui, |ui| {
+        ui.strong("Hits by shot type");
+        for shot_type in ShotType::ALL {
+            let mut heading = shot_type.name().to_string();
+            heading[..1].make_ascii_uppercase();
+            ui.strong(heading);
+        }
+        ui.end_row();
+        for (player, stats) in stats.iter().enumerate() {
+            ui.label(name(player));
+            for by_type in stats.by_type {
+                if by_type.shots == 0 {
+                    ui.weak("–");
+                } else {
+                    ui.label(format!("{}/{}", by_type.hits, by_type.shots));
+                }
+            }
+            ui.end_row();
+        }
+    });
+    for (player, stats) in stats.iter().enumerate() {
+        if let Some((shot_type, missed)) = stats.worst_shot_type() {
+            ui.label(format!(
+                "{} misses {:.0}% of {} shots",
+                name(player),
+                missed * 100.,
+                shot_type.name()
+            ));
+        }
+    }
+}
+
+/// Cells of a replay's row in the replay browser, with the rest of its
+/// header shown on hover
+fn replay_row(ui: &mut egui::Ui, replay: &Replay) {
+    let header = &replay.header;
+    let setup = &header.setup;
+    let names = (setup.players.iter())
+        .map(|i| i.name.as_str())
+        .collect::<Vec<_>>();
+    let ended = header.ended.map_or("unfinished".to_string(), format_time);
+    ui.label(format_time(header.started)).on_hover_text(format!(
+        "Ended {ended}\n{}\nSeed {}",
+        header.map, setup.seed
+    ));
+    ui.label(names.join(" vs. "));
+    ui.label(header.winner.as_deref().unwrap_or("-"));
+    ui.label(replay.turns.len().to_string());
+    let mutators = (header.mutators.iter())
+        .map(|i| i.name())
+        .collect::<Vec<_>>();
+    ui.label(if mutators.is_empty() {
+        "-".to_string()
+    } else {
+        mutators.join(", ")
+    });
+    ui.label(&header.version);
+}
+
+/// Enter players into a tournament, or follow the bracket of the one being
+/// played
+fn tournament_ui(
+    context: &bevy_egui::egui::Context,
+    ui_state: &mut UiState,
+    tournament: &mut ActiveTournament,
+    setup_state: &SetupPhase,
+) {
+    let entrants = &mut ui_state.entrants;
+    if entrants.is_empty() {
+        *entrants = (1..=4).map(|i| format!("Player {i}")).collect();
+    }
+    egui::Window::new("Tournament")
+        .open(&mut ui_state.tournament_open)
+        .collapsible(false)
+        .show(context, |ui| {
+            if let Some(current) = &tournament.0 {
+                bracket_ui(ui, current);
+                if ui.button("End tournament").clicked() {
+                    tournament.0 = None;
+                }
+                return;
+            }
+            ui.label("Players:");
+            let mut remove = None;
+            for (i, name) in entrants.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(name);
+                    if ui.small_button("✖").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                entrants.remove(i);
+            }
+            if entrants.len() < MAX_ENTRANTS
+                && ui.button("Add player").clicked()
+            {
+                entrants.push(format!("Player {}", entrants.len() + 1));
+            }
+            ui.label("Matches are played with the current settings.");
+            let start = ui.add_enabled(
+                entrants.len() >= 2,
+                egui::Button::new("Start tournament"),
+            );
+            if start.clicked() {
+                tournament.0 = Tournament::new(
+                    &mut rand::thread_rng(),
+                    entrants.clone(),
+                    setup_state.clone(),
+                );
+            }
+        });
+}
+
+/// Show each round of the bracket as a column, with the next match and the
+/// champion below
+fn bracket_ui(ui: &mut egui::Ui, tournament: &Tournament) {
+    ui.horizontal_top(|ui| {
+        for (round, slots) in tournament.rounds().iter().enumerate() {
+            ui.vertical(|ui| {
+                ui.strong(format!("Round {}", round + 1));
+                for slot in slots {
+                    match slot {
+                        Some(name) => ui.label(name),
+                        None if round == 0 => ui.weak("(bye)"),
+                        None => ui.weak("…"),
+                    };
+                }
+            });
+        }
+    });
+    ui.separator();
+    tournament_status_ui(ui, tournament);
+}
+
+fn tournament_status_ui(ui: &mut egui::Ui, tournament: &Tournament) {
+    if let Some(champion) = tournament.champion() {
+        ui.heading(format!("🏆 {champion} is the champion!"));
+    } else if let Some(next) = tournament.next_match() {
+        let [player_1, player_2] = &next.players;
+        ui.label(format!("Next match: {player_1} vs {player_2}"));
+    }
+}
+
+/// Show this week's challenge puzzles and a calendar of how many were solved
+/// in past weeks, by the player set up as player 1. Returns whether one of
+/// the puzzles was picked to be played.
+fn challenges_ui(
+    context: &bevy_egui::egui::Context,
+    ui_state: &mut UiState,
+    challenges: &Challenges,
+    setup_state: &mut SetupPhase,
+) -> bool {
+    if !ui_state.challenges_open {
+        ui_state.weekly_puzzles = None;
+        return false;
+    }
+    let week = IsoWeek::current();
+    let puzzles = ui_state
+        .weekly_puzzles
+        .get_or_insert_with(|| weekly_puzzles(week));
+    let player = &setup_state.players[0].name;
+    let mut picked = None;
+    egui::Window::new("Weekly challenges")
+        .open(&mut ui_state.challenges_open)
+        .collapsible(false)
+        .show(context, |ui| {
+            ui.heading(format!("This week ({week})"));
+            egui::Grid::new("weekly_puzzles").show(ui, |ui| {
+                for puzzle in puzzles.iter() {
+                    let solved = puzzle
+                        .challenge
+                        .is_some_and(|id| challenges.is_solved(player, id));
+                    ui.label(puzzle.difficulty().name());
+                    ui.label(if solved { "★" } else { "☆" });
+                    if ui.button("Play").clicked() {
+                        picked = Some(puzzle.clone());
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            ui.label(format!("Solved by {player}:"));
+            egui::Grid::new("challenge_calendar").striped(true).show(
+                ui,
+                |ui| {
+                    for week in calendar() {
+                        ui.label(week.to_string());
+                        ui.label(badge(challenges.solved(player, week)));
+                        ui.end_row();
+                    }
+                },
+            );
+        });
+    let Some(puzzle) = picked else {
+        return false;
+    };
+    setup_state.mode = MatchMode::Puzzle;
+    setup_state.custom_puzzle = Some(puzzle);
+    ui_state.challenges_open = false;
+    true
+}
+
+fn restriction_select(ui: &mut egui::Ui, restriction: &mut Restriction) {
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_label("Allowed functions")
+            .selected_text(restriction.name())
+            .show_ui(ui, |ui| {
+                for option in Restriction::ALL {
+                    let selected = std::mem::discriminant(restriction)
+                        == std::mem::discriminant(&option);
+                    if ui.selectable_label(selected, option.name()).clicked()
+                        && !selected
+                    {
+                        *restriction = option;
+                    }
+                }
+            });
+        if let Restriction::MaxDegree(max) = restriction {
+            ui.label("up to degree");
+            ui.add(egui::widgets::DragValue::new(max).range(1..=9));
+        }
+    });
+}
+
+fn skin_select(ui: &mut egui::Ui, id: &str, skin: &mut SoldierSkin) {
+    ui.label("Skin:");
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(skin.name())
+        .show_ui(ui, |ui| {
+            for option in SoldierSkin::ALL {
+                ui.selectable_value(skin, option, option.name());
+            }
+        });
+}
+
+fn input_select(ui: &mut egui::Ui, id: &str, input: &mut InputDevice) {
+    ui.label("Controls:").on_hover_text(
+        "Keys and gamepad buttons of other players are ignored on this \
+         player's turns",
     );
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(input.name())
+        .show_ui(ui, |ui| {
+            for option in InputDevice::all() {
+                ui.selectable_value(input, option, option.name());
+            }
+        });
 }
 
 fn play_ui(
     context: &bevy_egui::egui::Context,
     state: &mut GameState,
-    mut gizmos: Gizmos,
     mut start_graphing_events: EventWriter<StartGraphingEvent>,
+    resources: &mut UiResources,
+    input_context: Option<&egui::Context>,
 ) {
+    let presentation_mode = resources.settings.presentation_mode;
+    let overlay = resources.overlay.0;
+    let decimal_comma = resources.settings.decimal_comma.enabled();
+    let palette = resources.settings.palette_input;
+    // Only written back when it changes, as that saves the settings
+    let mut layout = resources.settings.panels.clone();
+    let params = &mut resources.params;
+    let UiState {
+        draft_tabs,
+        edit_histories: histories,
+        parsed_equation,
+        turn_code,
+        turn_code_error,
+        game_saved,
+        ..
+    } = &mut *resources.ui_state;
     let Some(playing_state) = state.playing_state_mut() else {
         return;
     };
-    let data = PlayUiData::new(playing_state);
-    gizmos.circle_2d(
-        Isometry2d {
-            rotation: Rot2::IDENTITY,
-            translation: data.soldier_loc * 20.,
-        },
-        super::SOLDIER_RADIUS,
-        super::ACTIVE_SOLDIER_OUTLINE_COLOR,
+    if overlay {
+        nameplates_ui(
+            context,
+            playing_state,
+            super::OVERLAY_NAMEPLATE_TEXT_SIZE,
+        );
+    } else if presentation_mode {
+        nameplates_ui(context, playing_state, super::NAMEPLATE_TEXT_SIZE);
+    }
+    if !overlay {
+        turn_counter_ui(context, playing_state);
+        idle_warning_ui(context, playing_state);
+    }
+    let player_name = playing_state.current_player().name.clone();
+    let (restriction, budget) =
+        (playing_state.restriction(), playing_state.budget());
+    let cooldown = playing_state.cooldown();
+    let recent = playing_state.current_player().recent_equations().to_vec();
+    let range = playing_state.max_shot_length();
+    let energy = playing_state.banked_energy();
+    if playing_state.current_player().ai
+        && playing_state.turn_phase().is_input()
+        && !overlay
+    {
+        egui::Area::new(egui::Id::new("ai_thinking"))
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0., -20.))
+            .interactable(false)
+            .show(context, |ui| {
+                ui.label(RichText::new(format!("{player_name} is aiming…")))
+            });
+    }
+    if playing_state.is_remote_turn()
+        && playing_state.turn_phase().is_input()
+        && !overlay
+    {
+        correspondence_ui(context, playing_state, turn_code, turn_code_error);
+    }
+    if playing_state.turn_phase().is_handoff() {
+        handoff_ui(input_context.unwrap_or(context), playing_state);
+        return;
+    }
+    // The board is all that is shown in overlay mode, so the input waits
+    // for the input window to open
+    if overlay && input_context.is_none() {
+        return;
+    }
+    let can_save = playing_state.can_save();
+    let mut save = false;
+    let mut data = PlayUiData::new(playing_state);
+    if let Some(prepare_data) = &mut data.prepare_ui {
+        let title = format!("{}: prepare your next shot", prepare_data.name);
+        let draft_tab = &mut draft_tabs[prepare_data.player.index()];
+        let prepare_ui = |ui: &mut egui::Ui| {
+            let active = prepare_data.active_soldier;
+            let drafts = &mut prepare_data.drafts;
+            if let Some(draft) = drafts_ui(ui, drafts, active, draft_tab) {
+                slots_ui(ui, draft.slots);
+                let key = (draft.soldier_id, draft.slots.selected());
+                let history = histories.entry(key).or_default();
+                let equation = draft.slots.current_mut();
+                if palette {
+                    ui.monospace(equation.as_str());
+                    palette_ui(ui, equation);
+                } else {
+                    equation_edit(ui, equation, history, decimal_comma);
+                }
+                parse_error_ui(ui, equation, parsed_equation);
+                let function = parsed_equation.get(equation);
+                params_ui(ui, function, equation, params);
+            }
+        };
+        input_panel(
+            context,
+            input_context,
+            presentation_mode,
+            &title,
+            (&mut layout, true),
+            prepare_ui,
+        );
+        if layout != resources.settings.panels {
+            resources.settings.panels = layout;
+        }
+        return;
+    }
+    let Some(mut input_data) = data.input_ui else {
+        return;
+    };
+    let draft_tab = &mut draft_tabs[input_data.player.index()];
+    let input_ui = |ui: &mut egui::Ui| {
+        let active = input_data.active_soldier;
+        let Some(draft) =
+            drafts_ui(ui, &mut input_data.drafts, active, draft_tab)
+        else {
+            return;
+        };
+        let is_active = draft.soldier_id == active;
+        slots_ui(ui, draft.slots);
+        let key = (draft.soldier_id, draft.slots.selected());
+        let equation = draft.slots.current_mut();
+        ui.horizontal(|ui| {
+            let history = histories.entry(key).or_default();
+            let entered = !palette && {
+                let response =
+                    equation_edit(ui, equation, history, decimal_comma);
+                response.lost_focus()
+                    && ui.input(|i| i.key_pressed(egui::Key::Enter))
+            };
+            if palette {
+                ui.monospace(equation.as_str());
+            }
+            let valid = parsed_equation.get(equation).is_some();
+            let done = ui
+                .add_enabled(is_active && valid, egui::Button::new("Done"))
+                .on_disabled_hover_text(if is_active {
+                    "The function has to be fixed before it can be shot."
+                } else {
+                    "Only the active soldier can shoot. Switch back to its \
+                     tab, or select this soldier on the board."
+                });
+            if done.clicked() || entered && is_active && valid {
+                if let Ok(mut func) = equation.parse() {
+                    params.bind(&mut func);
+                    start_graphing_events.send(StartGraphingEvent(func));
+                }
+            }
+            timer_ui(ui, input_data.timer.remaining());
+            if let Some(range) = range {
+                range_ui(ui, range, energy);
+            }
+            // Saved games are resumed from the setup screen
+            save = can_save && ui.button("Save game").clicked();
+        });
+        parse_error_ui(ui, equation, parsed_equation);
+        if let Some(saved) = game_saved.as_ref() {
+            ui.label(saved);
+        }
+        if palette {
+            palette_ui(ui, equation);
+        }
+        let function = parsed_equation.get(equation);
+        rules_ui(ui, function, params, restriction, budget, cooldown, &recent);
+        params_ui(ui, function, equation, params);
+    };
+    let title = format!("{player_name}'s turn");
+    input_panel(
+        context,
+        input_context,
+        presentation_mode,
+        &title,
+        (&mut layout, false),
+        input_ui,
     );
-    if let Some(input_data) = data.input_ui {
+    if layout != resources.settings.panels {
+        resources.settings.panels = layout;
+    }
+    if save {
+        *game_saved = Some(match save_game(playing_state) {
+            Ok(path) => format!("Saved to {}", path.display()),
+            Err(e) => format!("Couldn't save the game: {e}"),
+        });
+    }
+}
+
+/// Why the equation doesn't parse, pointing at the character it stops at
+/// if it is one the tokenizer doesn't know. Nothing is said about an empty
+/// equation, though it can't be shot either.
+fn parse_error_ui(
+    ui: &mut egui::Ui,
+    equation: &str,
+    parsed_equation: &mut ParsedEquation,
+) {
+    if equation.trim().is_empty() {
+        return;
+    }
+    let Some(error) = parsed_equation.error(equation) else {
+        return;
+    };
+    let text = match error {
+        ParseError::Tokenizer(e) => {
+            let c = equation.chars().nth(e.failure_idx).unwrap_or('?');
+            format!("Unexpected “{c}” at character {}", e.failure_idx + 1)
+        }
+        ParseError::TreeBuild(e) => e.to_string(),
+        ParseError::ShuntingYard(e) => e.to_string(),
+    };
+    ui.colored_label(egui::Color32::RED, text);
+}
+
+/// Keys to build the equation with, in place of typing it
+fn palette_ui(ui: &mut egui::Ui, equation: &mut String) {
+    let (width, height) = super::PALETTE_KEY_SIZE;
+    egui::Grid::new("equation_palette").show(ui, |ui| {
+        for row in PALETTE {
+            for key in row {
+                let button = egui::Button::new(key.label())
+                    .min_size(egui::vec2(width, height));
+                if ui.add(button).clicked() {
+                    key.press(equation);
+                }
+            }
+            ui.end_row();
+        }
+    });
+}
+
+/// How far the shot may travel, with the energy banked towards it
+fn range_ui(ui: &mut egui::Ui, range: f32, energy: Option<f32>) {
+    let text = match energy {
+        Some(energy) if energy >= 1. => {
+            format!("Range {range:.0} ({energy:.0} banked)")
+        }
+        _ => format!("Range {range:.0}"),
+    };
+    ui.label(text).on_hover_text(
+        "Shots fizzle once they have travelled this far along their path",
+    );
+}
+
+/// Time left in the turn as m:ss, pulsing red when it is running out
+fn timer_ui(ui: &mut egui::Ui, remaining: Duration) {
+    let secs = remaining.as_secs();
+    let text = RichText::new(format!("{}:{:02}", secs / 60, secs % 60));
+    if secs >= super::TURN_WARNING_SECONDS {
+        ui.label(text);
+        return;
+    }
+    // Brightest at the start of each second, as it ticks over
+    let pulse = remaining.as_secs_f32().fract();
+    let red = egui::Color32::RED.gamma_multiply(0.4 + 0.6 * pulse);
+    ui.label(text.color(red).strong().size(16. + 4. * pulse));
+}
+
+/// Show the equation input in the separate input window if there is one, or
+/// in the main window otherwise. The title is only shown in the input
+/// window, where there is room for it, or in place of the panel while it is
+/// collapsed. The panel at the bottom of the main window can be resized, and
+/// collapsed if `collapsible`, which is remembered in `layout`.
+fn input_panel(
+    context: &egui::Context,
+    input_context: Option<&egui::Context>,
+    presentation_mode: bool,
+    title: &str,
+    (layout, collapsible): (&mut PanelLayout, bool),
+    mut add_contents: impl FnMut(&mut egui::Ui),
+) {
+    if let Some(input_context) = input_context {
+        egui::CentralPanel::default().show(input_context, |ui| {
+            ui.heading(title);
+            add_contents(ui);
+        });
+    } else if presentation_mode {
+        // Keep the input out of the way of the enlarged board
+        egui::Window::new("Equation")
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0., -10.))
+            .title_bar(false)
+            .resizable(false)
+            .show(context, add_contents);
+    } else if collapsible && layout.input_collapsed {
         egui::TopBottomPanel::new(
             egui::panel::TopBottomSide::Bottom,
-            "playing_input_panel",
+            "playing_input_panel_collapsed",
         )
         .show(context, |ui| {
             ui.horizontal(|ui| {
-                ui.text_edit_singleline(input_data.current_input);
-                if ui.button("Done").clicked() {
-                    if let Ok(func) = input_data.current_input.parse() {
-                        start_graphing_events.send(StartGraphingEvent(func));
-                    }
+                if ui
+                    .small_button("⏶")
+                    .on_hover_text("Show the equation panel")
+                    .clicked()
+                {
+                    layout.input_collapsed = false;
+                }
+                ui.label(title);
+            });
+        });
+    } else {
+        let (min_height, max_height) = super::INPUT_PANEL_HEIGHT_RANGE;
+        let panel = egui::TopBottomPanel::new(
+            egui::panel::TopBottomSide::Bottom,
+            "playing_input_panel",
+        )
+        .resizable(true)
+        .default_height(layout.input_height)
+        .height_range(min_height..=max_height)
+        .show(context, |ui| {
+            if collapsible
+                && ui
+                    .small_button("⏷")
+                    .on_hover_text(
+                        "Hide the equation panel while the shot is shown",
+                    )
+                    .clicked()
+            {
+                layout.input_collapsed = true;
+            }
+            // Scroll what doesn't fit instead of growing past the height
+            // it was resized to
+            egui::ScrollArea::vertical()
+                .auto_shrink(false)
+                .show(ui, &mut add_contents);
+        });
+        // Remember the height once the resize handle is let go
+        let height = panel.response.rect.height().round();
+        let dragging = context.input(|i| i.pointer.any_down());
+        if !dragging && height != layout.input_height {
+            layout.input_height = height;
+        }
+    }
+}
+
+/// Single line editor for an equation. Scrolling while the text cursor is in
+/// a number nudges that number (Shift for finer steps, Alt for coarser ones).
+fn equation_edit(
+    ui: &mut egui::Ui,
+    equation: &mut String,
+    history: &mut EditHistory,
+    decimal_comma: bool,
+) -> egui::Response {
+    // Record edits made anywhere, such as by the sliders or the computer,
+    // before handling undo and redo
+    history.record(equation);
+    let id = ui.next_auto_id();
+    if ui.memory(|memory| memory.has_focus(id)) {
+        let restored = ui.input_mut(|input| {
+            let redo = input.consume_key(
+                egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+                egui::Key::Z,
+            ) || input
+                .consume_key(egui::Modifiers::COMMAND, egui::Key::Y);
+            if redo {
+                history.redo()
+            } else if input.consume_key(egui::Modifiers::COMMAND, egui::Key::Z)
+            {
+                history.undo()
+            } else {
+                None
+            }
+        });
+        if let Some(restored) = restored {
+            *equation = restored;
+        }
+    }
+    let mut output = egui::TextEdit::singleline(equation).id(id).show(ui);
+    // Swapping a comma for a point keeps the length, and so the cursor
+    if decimal_comma && output.response.changed() {
+        *equation = with_decimal_points(equation);
+    }
+    let (scroll, modifiers) = ui.input(|i| {
+        // egui turns Shift+scroll into horizontal scrolling
+        (i.raw_scroll_delta.x + i.raw_scroll_delta.y, i.modifiers)
+    });
+    let Some(cursor) = output
+        .cursor_range
+        .filter(|_| scroll != 0. && output.response.hovered())
+    else {
+        return output.response;
+    };
+    let scale = if modifiers.shift {
+        NudgeScale::Fine
+    } else if modifiers.alt {
+        NudgeScale::Coarse
+    } else {
+        NudgeScale::Normal
+    };
+    let nudged = nudge_number(
+        equation,
+        cursor.primary.ccursor.index,
+        scroll.signum(),
+        scale,
+    );
+    if let Some((nudged, new_cursor)) = nudged {
+        *equation = nudged;
+        output.state.cursor.set_char_range(Some(
+            egui::text::CCursorRange::one(egui::text::CCursor::new(new_cursor)),
+        ));
+        output.state.store(ui.ctx(), output.response.id);
+        output.response.mark_changed();
+    }
+    output.response
+}
+
+/// Tabs to switch between a soldier's candidate equations, and add or remove
+/// them. The selected one is the one that gets shot.
+fn slots_ui(ui: &mut egui::Ui, slots: &mut EquationSlots) {
+    ui.horizontal(|ui| {
+        for slot in 0..slots.equations().len() {
+            let selected = slots.selected() == slot;
+            let label = ui
+                .selectable_label(selected, format!("Equation {}", slot + 1))
+                .on_hover_text(&slots.equations()[slot]);
+            if label.clicked() {
+                slots.select(slot);
+            }
+        }
+        let room = slots.equations().len() < super::EQUATION_SLOTS;
+        if ui
+            .add_enabled(room, egui::Button::new("+"))
+            .on_hover_text("Try another equation, starting from this one")
+            .clicked()
+        {
+            slots.add();
+        }
+        let removable = slots.equations().len() > 1;
+        if ui
+            .add_enabled(removable, egui::Button::new("−"))
+            .on_hover_text("Throw away this equation")
+            .clicked()
+        {
+            slots.remove_current();
+        }
+    });
+}
+
+/// Tabs to switch between the drafts of a player's soldiers, with the active
+/// soldier's tab marked. Returns the draft that is selected.
+fn drafts_ui<'a, 'b>(
+    ui: &mut egui::Ui,
+    drafts: &'a mut [Draft<'b>],
+    active: SoldierId,
+    selected: &mut Option<SoldierId>,
+) -> Option<&'a mut Draft<'b>> {
+    // Fall back to the active soldier if the selected one has died
+    let shown = selected
+        .filter(|id| drafts.iter().any(|draft| draft.soldier_id == *id))
+        .unwrap_or(active);
+    if drafts.len() > 1 {
+        ui.horizontal(|ui| {
+            for draft in drafts.iter() {
+                let id = draft.soldier_id;
+                let label = if id == active {
+                    format!("● Soldier {}", id.number + 1)
+                } else {
+                    format!("Soldier {}", id.number + 1)
+                };
+                if ui.selectable_label(shown == id, label).clicked() {
+                    *selected = (id != active).then_some(id);
                 }
-                ui.label(input_data.timer.remaining().as_secs().to_string());
-            })
+            }
         });
     }
+    drafts.iter_mut().find(|draft| draft.soldier_id == shown)
 }
 
-fn finished_ui(context: &bevy_egui::egui::Context, state: &mut GameState) {
-    let Some(finished_state) = state.finished_state_mut() else {
+/// Sliders for the parameters used in `equation`, each with an editable range,
+/// and the equation with their values filled in
+fn params_ui(
+    ui: &mut egui::Ui,
+    function: Option<&ParsedFunction>,
+    equation: &str,
+    params: &mut EquationParams,
+) {
+    let used = function.map(ParsedFunction::variables).unwrap_or_default();
+    let mut any_used = false;
+    for (name, param) in PARAM_NAMES.iter().zip(&mut params.0) {
+        if !used.contains(name) {
+            continue;
+        }
+        any_used = true;
+        ui.horizontal(|ui| {
+            ui.label(name.to_string());
+            ui.add(egui::DragValue::new(&mut param.min).speed(0.1));
+            ui.add(
+                egui::Slider::new(&mut param.value, param.min..=param.max)
+                    .step_by(0.01),
+            );
+            ui.add(egui::DragValue::new(&mut param.max).speed(0.1));
+            param.max = param.max.max(param.min);
+        });
+    }
+    if any_used {
+        ui.label(format!("y = {}", params.substitute(equation)));
+    }
+}
+
+/// Show how much of the budget the equation uses, if there is one, and
+/// explain why it won't be shot if it breaks the match's rules: its
+/// restriction, budget and cooldown on repeats
+fn rules_ui(
+    ui: &mut egui::Ui,
+    function: Option<&ParsedFunction>,
+    params: &EquationParams,
+    restriction: Restriction,
+    budget: Option<u32>,
+    cooldown: Option<NonZeroU8>,
+    recent: &[String],
+) {
+    if let Some(budget) = budget {
+        let cost = function.map_or(0, ParsedFunction::cost);
+        let mut meter =
+            egui::ProgressBar::new((cost as f32 / budget as f32).min(1.))
+                .text(format!("{cost}/{budget} points"));
+        if cost > budget {
+            meter = meter.fill(egui::Color32::DARK_RED);
+        }
+        ui.add(meter);
+    }
+    let Some(mut function) = function.cloned() else {
         return;
     };
+    params.bind(&mut function);
+    let checked = function
+        .check(restriction)
+        .and_then(|()| function.check_budget(budget))
+        .and_then(|()| function.check_repeat(cooldown, recent));
+    if let Err(e) = checked {
+        ui.colored_label(egui::Color32::RED, e.to_string());
+    }
+}
 
-    let winner = match finished_state.winner {
-        PlayerSelect::Player1 => 1,
-        PlayerSelect::Player2 => 2,
+/// Big player names and remaining soldier counts along the top of the window,
+/// with the player whose turn it is highlighted
+fn nameplates_ui(
+    context: &bevy_egui::egui::Context,
+    state: &PlayPhase,
+    text_size: f32,
+) {
+    let nameplate = |ui: &mut egui::Ui, player: &PlayerState, turn| {
+        let mut text = RichText::new(format!(
+            "{} ({})",
+            player.name,
+            player.soldiers().len()
+        ))
+        .size(text_size)
+        .strong();
+        if state.turn() == turn {
+            text = text.color(egui::Color32::from_rgb(0, 160, 0));
+        }
+        ui.label(text);
     };
+    // Players take the left and right corners in turn, a row further down
+    // for each pair
+    for (&turn, player) in PlayerSelect::ALL.iter().zip(state.players()) {
+        let row = (turn.index() / 2) as f32 * text_size * 1.5;
+        let (anchor, offset) = if turn.index() % 2 == 0 {
+            (egui::Align2::LEFT_TOP, egui::vec2(20., 10. + row))
+        } else {
+            (egui::Align2::RIGHT_TOP, egui::vec2(-20., 10. + row))
+        };
+        egui::Area::new(egui::Id::new(("nameplate", turn.index())))
+            .anchor(anchor, offset)
+            .show(context, |ui| nameplate(ui, player, turn));
+    }
+}
+
+/// Between the turns of players sharing the machine, wait for the next one
+/// to take it before showing their equations
+fn handoff_ui(context: &egui::Context, playing_state: &mut PlayPhase) {
+    let name = playing_state.current_player().name.clone();
+    egui::Window::new("Next turn")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0., 0.))
+        .collapsible(false)
+        .resizable(false)
+        .title_bar(false)
+        .show(context, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    RichText::new(format!("Pass the keyboard to {name}"))
+                        .heading(),
+                );
+                if ui.button(format!("I'm {name}, start my turn")).clicked() {
+                    playing_state.end_handoff();
+                }
+            });
+        });
+}
+
+/// Warn about players of a network match who are close to forfeiting by
+/// letting their turns run out
+fn idle_warning_ui(context: &egui::Context, state: &PlayPhase) {
+    let Some(limit) = state.idle_forfeit() else {
+        return;
+    };
+    let warnings = (state.players().iter())
+        .filter(|i| i.idle_turns > 0)
+        .map(|i| {
+            let left = limit.get().saturating_sub(i.idle_turns);
+            let missed = match i.idle_turns {
+                1 => "turn".to_string(),
+                n => format!("{n} turns"),
+            };
+            format!(
+                "{} missed their last {missed} and forfeits after {left} more",
+                i.name,
+            )
+        })
+        .collect::<Vec<_>>();
+    if warnings.is_empty() {
+        return;
+    }
+    egui::Area::new(egui::Id::new("idle_warnings"))
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0., 40.))
+        .interactable(false)
+        .show(context, |ui| {
+            for warning in warnings {
+                ui.colored_label(egui::Color32::ORANGE, warning);
+            }
+        });
+}
+
+/// Show the turn number against the turn limit, or that the match is in
+/// sudden death
+fn turn_counter_ui(context: &bevy_egui::egui::Context, state: &PlayPhase) {
+    let text = if let Some(puzzle) = state.puzzle() {
+        RichText::new(format!(
+            "{} puzzle: hit every target with one shot",
+            puzzle.difficulty().name()
+        ))
+    } else if let Some(score) = state.horde_score() {
+        RichText::new(format!("Wave {} · {} kills", score.wave, score.kills))
+    } else if state.is_sudden_death() {
+        RichText::new("Sudden death!").color(egui::Color32::RED)
+    } else if let Some(max_turns) = state.max_turns() {
+        RichText::new(format!("Turn {}/{max_turns}", state.turn_number()))
+    } else {
+        return;
+    };
+    egui::Area::new(egui::Id::new("turn_counter"))
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0., 10.))
+        .interactable(false)
+        .show(context, |ui| ui.label(text.size(20.).strong()));
+}
+
+fn finished_ui(
+    context: &bevy_egui::egui::Context,
+    state: &mut GameState,
+    tournament: &ActiveTournament,
+    summary: &MatchSummary,
+    summary_saved: &mut Option<String>,
+    replay_saved: &mut Option<String>,
+) {
+    let Some(finished_state) = state.finished_state_mut() else {
+        *summary_saved = None;
+        *replay_saved = None;
+        return;
+    };
+
+    let horde = finished_state.horde;
+    let puzzle = finished_state.puzzle.clone();
+    let turn_code = finished_state.turn_code.clone();
+    let rng = finished_state.rng.clone();
+    let winner = finished_state.winner.index() + 1;
 
     egui::Window::new("Game Over!")
         .movable(false)
         .resizable(false)
         .collapsible(false)
         .show(context, |ui| {
-            ui.label(format!("Player {} wins!", winner));
+            if let Some(puzzle) = &puzzle {
+                if winner == 1 {
+                    ui.label("Puzzle solved!");
+                } else {
+                    ui.label("Missed! One solution is:");
+                    ui.monospace(&puzzle.solution);
+                }
+                puzzle_code_ui(ui, puzzle);
+            } else if let Some(score) = horde {
+                ui.label(format!(
+                    "Your team reached wave {} with {} kills",
+                    score.wave, score.kills
+                ));
+            } else {
+                ui.label(format!("Player {} wins!", winner));
+            }
+            if let Some(code) = &turn_code {
+                ui.horizontal(|ui| {
+                    ui.label("Send the winning turn to your opponent:");
+                    ui.monospace(code);
+                    if ui.button("Copy").clicked() {
+                        ui.ctx().copy_text(code.clone());
+                    }
+                });
+            }
+            if let Some(tournament) = &tournament.0 {
+                tournament_status_ui(ui, tournament);
+            }
+            if let Some(rng) = &rng {
+                rng_audit_ui(ui, rng);
+            }
+            if ui.button("Save summary image").clicked()
+                && let Some(finished) = state.finished_state()
+            {
+                *summary_saved = Some(match summary.save(finished) {
+                    Ok(path) => format!("Saved to {}", path.display()),
+                    Err(e) => format!("Couldn't save the summary: {e}"),
+                });
+            }
+            if let Some(saved) = summary_saved {
+                ui.label(&*saved);
+            }
+            if let Some(replay) =
+                (state.finished_state()).and_then(|i| i.replay.as_ref())
+                && ui.button("Save replay").clicked()
+            {
+                *replay_saved = Some(match replay.save() {
+                    Ok(path) => format!("Saved to {}", path.display()),
+                    Err(e) => format!("Couldn't save the replay: {e}"),
+                });
+            }
+            if let Some(saved) = replay_saved {
+                ui.label(&*saved);
+            }
+            if let Some(replay) =
+                (state.finished_state()).and_then(|i| i.replay.as_ref())
+            {
+                ui.collapsing("Shot types", |ui| shot_types_ui(ui, replay));
+            }
             if ui.button("Restart").clicked() {
                 *state = GameState::default();
             }
@@ -7,3 +7,75 @@ pub fn smoothstep(x: f32) -> f32 {
         x * x * (3. - 2. * x)
     }
 }
+
+/// Directory used for persistent files (settings, profiles, ...). Uses
+/// `$GRAPHWARS_DATA_DIR` if set, otherwise the platform's usual per-user data
+/// location, falling back to the working directory.
+pub fn data_dir() -> std::path::PathBuf {
+    use std::path::PathBuf;
+    if let Some(dir) = std::env::var_os("GRAPHWARS_DATA_DIR") {
+        return PathBuf::from(dir);
+    }
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME")
+            .map(|h| PathBuf::from(h).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .map(|h| PathBuf::from(h).join(".local/share"))
+            })
+    };
+    base.map(|b| b.join("graphwars")).unwrap_or_default()
+}
+
+/// 64-bit FNV-1a hash, which is stable across platforms and versions
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Fewer points along the same polyline, none of which strays further than
+/// `tolerance` from the original (Ramer–Douglas–Peucker)
+pub fn simplify_polyline(
+    points: &[bevy::math::Vec2],
+    tolerance: f32,
+) -> Vec<bevy::math::Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut spans = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = spans.pop() {
+        let (a, b) = (points[start], points[end]);
+        let distance = |point: bevy::math::Vec2| {
+            let along = b - a;
+            if along.length_squared() == 0. {
+                point.distance(a)
+            } else {
+                let t = ((point - a).dot(along) / along.length_squared())
+                    .clamp(0., 1.);
+                point.distance(a + along * t)
+            }
+        };
+        let furthest = (start + 1..end)
+            .map(|i| (i, distance(points[i])))
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        if let Some((i, distance)) = furthest
+            && distance > tolerance
+        {
+            keep[i] = true;
+            spans.push((start, i));
+            spans.push((i, end));
+        }
+    }
+    (points.iter().zip(keep))
+        .filter_map(|(&point, keep)| keep.then_some(point))
+        .collect()
+}